@@ -0,0 +1,283 @@
+//! Backup archives of all trees of a database
+//!
+//! An archive is a magic header, a version, the creation time, a sequence of tree and
+//! entry records and an entry count, followed by a CRC32 checksum of everything before it.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::error::StorageError;
+use serde::{Deserialize, Serialize};
+
+use super::{Result, TimestampMillis, timestamp_millis};
+
+/// Magic bytes at the start of every backup archive
+const BACKUP_MAGIC: &[u8; 8] = b"KVSTBAK\0";
+/// Version of the backup archive format
+const BACKUP_VERSION: u32 = 1;
+/// Record marker, starts a tree
+const RECORD_TREE: u8 = 1;
+/// Record marker, a key-value entry of the current tree
+const RECORD_ENTRY: u8 = 2;
+/// Record marker, end of the archive body
+const RECORD_END: u8 = 0;
+
+/// Summary of a backup archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    /// Path of the archive file
+    pub path: PathBuf,
+    /// When the backup was taken
+    pub created_at: TimestampMillis,
+    /// Number of trees in the archive
+    pub trees: usize,
+    /// Number of key-value entries in the archive
+    pub entries: u64,
+    /// CRC32 checksum of the archive body
+    pub checksum: u32,
+}
+
+/// Writer that updates a CRC32 checksum with everything written
+struct CrcWriter<W: Write> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> CrcWriter<W> {
+    #[inline]
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    #[inline]
+    fn put(&mut self, buf: &[u8]) -> Result<()> {
+        self.hasher.update(buf);
+        self.inner.write_all(buf)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn put_bytes(&mut self, buf: &[u8]) -> Result<()> {
+        self.put(&(buf.len() as u32).to_be_bytes())?;
+        self.put(buf)
+    }
+}
+
+/// Reader that updates a CRC32 checksum with everything read
+struct CrcReader<R: Read> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+}
+
+impl<R: Read> CrcReader<R> {
+    #[inline]
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    #[inline]
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.inner.read_exact(&mut buf)?;
+        self.hasher.update(&buf);
+        Ok(buf)
+    }
+
+    #[inline]
+    fn take_bytes(&mut self) -> Result<Vec<u8>> {
+        let len = u32::from_be_bytes(self.take::<4>()?) as u64;
+        //grows with the bytes actually read, a corrupt length cannot allocate up front
+        let mut buf = Vec::new();
+        (&mut self.inner).take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            return Err(StorageError::codec("backup archive is truncated"));
+        }
+        self.hasher.update(&buf);
+        Ok(buf)
+    }
+}
+
+/// Resolves the archive file, a directory gets a timestamped file inside it
+#[inline]
+fn archive_path(path: &Path, created_at: TimestampMillis) -> PathBuf {
+    if path.is_dir() {
+        path.join(format!("kv-storage-{}.bak", created_at))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Writes the trees `names` of `db`, in that order, to a backup archive at `path`
+///
+/// The archive is written to a temporary file first and renamed once complete.
+pub(crate) fn write_backup(
+    db: &sled::Db,
+    names: Vec<sled::IVec>,
+    path: &Path,
+) -> Result<BackupInfo> {
+    let created_at = timestamp_millis();
+    let path = archive_path(path, created_at);
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let mut w = CrcWriter::new(BufWriter::new(File::create(&tmp_path)?));
+    w.put(BACKUP_MAGIC)?;
+    w.put(&BACKUP_VERSION.to_be_bytes())?;
+    w.put(&created_at.to_be_bytes())?;
+
+    let mut trees = 0;
+    let mut entries = 0u64;
+    for name in names {
+        let tree = db.open_tree(&name)?;
+        w.put(&[RECORD_TREE])?;
+        w.put_bytes(&name)?;
+        trees += 1;
+        for item in tree.iter() {
+            let (k, v) = item?;
+            w.put(&[RECORD_ENTRY])?;
+            w.put_bytes(&k)?;
+            w.put_bytes(&v)?;
+            entries += 1;
+        }
+    }
+    w.put(&[RECORD_END])?;
+    w.put(&entries.to_be_bytes())?;
+
+    let checksum = w.hasher.clone().finalize();
+    let mut inner = w.inner;
+    inner.write_all(&checksum.to_be_bytes())?;
+    inner.flush()?;
+    inner.get_ref().sync_all()?;
+    drop(inner);
+    std::fs::rename(&tmp_path, &path)?;
+
+    Ok(BackupInfo {
+        path,
+        created_at,
+        trees,
+        entries,
+        checksum,
+    })
+}
+
+/// Reads a backup archive, calling `f` for each tree name and then for each of its entries
+///
+/// Returns an error if the archive is truncated or its checksum does not match.
+fn read_backup<F>(path: &Path, mut f: F) -> Result<BackupInfo>
+where
+    F: FnMut(&[u8], Option<(Vec<u8>, Vec<u8>)>) -> Result<()>,
+{
+    let mut r = CrcReader::new(BufReader::new(File::open(path)?));
+    if &r.take::<8>()? != BACKUP_MAGIC {
+        return Err(StorageError::codec("not a backup archive"));
+    }
+    let version = u32::from_be_bytes(r.take::<4>()?);
+    if version != BACKUP_VERSION {
+        return Err(StorageError::codec(format!(
+            "unsupported backup version: {}",
+            version
+        )));
+    }
+    let created_at = i64::from_be_bytes(r.take::<8>()?);
+
+    let mut trees = 0;
+    let mut entries = 0u64;
+    let mut tree_name: Option<Vec<u8>> = None;
+    loop {
+        match r.take::<1>()?[0] {
+            RECORD_TREE => {
+                let name = r.take_bytes()?;
+                f(&name, None)?;
+                tree_name = Some(name);
+                trees += 1;
+            }
+            RECORD_ENTRY => {
+                let k = r.take_bytes()?;
+                let v = r.take_bytes()?;
+                match tree_name.as_ref() {
+                    Some(name) => f(name, Some((k, v)))?,
+                    None => return Err(StorageError::codec("backup entry outside of a tree")),
+                }
+                entries += 1;
+            }
+            RECORD_END => break,
+            c => return Err(StorageError::codec(format!("invalid backup record: {}", c))),
+        }
+    }
+    let count = u64::from_be_bytes(r.take::<8>()?);
+    let checksum = r.hasher.clone().finalize();
+    let mut expected = [0u8; 4];
+    r.inner.read_exact(&mut expected)?;
+    if u32::from_be_bytes(expected) != checksum {
+        return Err(StorageError::codec("backup checksum mismatch"));
+    }
+    if count != entries {
+        return Err(StorageError::codec(format!(
+            "backup entry count mismatch, expected {}, found {}",
+            count, entries
+        )));
+    }
+
+    Ok(BackupInfo {
+        path: path.to_path_buf(),
+        created_at,
+        trees,
+        entries,
+        checksum,
+    })
+}
+
+/// Verifies the checksum of a backup archive without restoring it
+pub fn verify_backup<P: AsRef<Path>>(path: P) -> Result<BackupInfo> {
+    read_backup(path.as_ref(), |_, _| Ok(()))
+}
+
+/// Restores a verified backup archive into the empty database `db`
+pub(crate) fn restore_backup(db: &sled::Db, path: &Path) -> Result<BackupInfo> {
+    verify_backup(path)?;
+    if db.tree_names().iter().any(|name| {
+        db.open_tree(name)
+            .map(|tree| !tree.is_empty())
+            .unwrap_or(true)
+    }) {
+        return Err(StorageError::invalid("restore target is not empty"));
+    }
+    let mut tree: Option<sled::Tree> = None;
+    let mut batch = sled::Batch::default();
+    let mut batch_len = 0;
+    let info = read_backup(path, |name, entry| {
+        match entry {
+            None => {
+                if let Some(tree) = tree.as_ref() {
+                    tree.apply_batch(std::mem::take(&mut batch))?;
+                    batch_len = 0;
+                }
+                tree = Some(db.open_tree(name)?);
+            }
+            Some((k, v)) => {
+                batch.insert(k, v);
+                batch_len += 1;
+                if batch_len >= 1000 {
+                    if let Some(tree) = tree.as_ref() {
+                        tree.apply_batch(std::mem::take(&mut batch))?;
+                    }
+                    batch_len = 0;
+                }
+            }
+        }
+        Ok(())
+    })?;
+    if let Some(tree) = tree.as_ref() {
+        tree.apply_batch(batch)?;
+    }
+    db.flush()?;
+    Ok(info)
+}
@@ -0,0 +1,334 @@
+//! Background cleanup task
+//!
+//! The cleanup task removes expired entries, evicts keys over the storage limit
+//! and trims streams. `CleanupPolicy` sets when and how much it runs, a custom
+//! task can replace it through `Config::cleanup_f`, and the `CleanupHandle`
+//! returned by `StorageDB::cleanup_handle` pauses, triggers or stops it.
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::task::spawn_blocking;
+use tokio::time::sleep;
+
+use super::StorageDB;
+use super::sled_storage::SledStorageDB;
+
+/// When and how much the default cleanup task runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CleanupPolicy {
+    /// Runs rounds periodically, when disabled the task starts paused and only
+    /// runs rounds requested with `CleanupHandle::trigger`
+    pub enabled: bool,
+    /// Time between two rounds in milliseconds
+    pub interval_ms: u64,
+    /// Number of entries removed per batch
+    pub batch_size: usize,
+    /// Stops a round after this many milliseconds, the rest is left to the next round
+    pub max_round_ms: Option<u64>,
+    /// Number of active commands above which the task waits between batches
+    pub backoff_active_count: isize,
+    /// Time waited between batches while the database is busy, in milliseconds
+    pub backoff_ms: u64,
+    /// Time an expiration hook may run before it counts as failed, in milliseconds
+    pub hook_timeout_ms: u64,
+}
+
+impl Default for CleanupPolicy {
+    fn default() -> Self {
+        CleanupPolicy {
+            enabled: true,
+            interval_ms: 10_000,
+            batch_size: 200,
+            max_round_ms: None,
+            backoff_active_count: 50,
+            backoff_ms: 500,
+            hook_timeout_ms: 30_000,
+        }
+    }
+}
+
+impl CleanupPolicy {
+    /// Checks if the round started at `started` ran out of time
+    #[inline]
+    fn is_over(&self, started: Instant) -> bool {
+        self.max_round_ms
+            .is_some_and(|ms| started.elapsed() >= Duration::from_millis(ms))
+    }
+}
+
+/// Starts the cleanup task of a database
+pub trait Cleanup: Send + Sync {
+    /// Spawns the task for `db`, which should follow the requests made through `handle`
+    fn start(&self, db: &StorageDB, handle: CleanupHandle);
+}
+
+impl<F> Cleanup for F
+where
+    F: Fn(&StorageDB, CleanupHandle) + Send + Sync,
+{
+    #[inline]
+    fn start(&self, db: &StorageDB, handle: CleanupHandle) {
+        self(db, handle)
+    }
+}
+
+/// Cleanup task started when a database is opened
+#[derive(Clone)]
+pub struct CleanupFun(Arc<dyn Cleanup>);
+
+impl CleanupFun {
+    /// Creates the task from a closure
+    #[inline]
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: Fn(&StorageDB, CleanupHandle) + Send + Sync + 'static,
+    {
+        CleanupFun(Arc::new(f))
+    }
+
+    /// Creates the task from a `Cleanup` implementation
+    #[inline]
+    pub fn new<C: Cleanup + 'static>(cleanup: C) -> Self {
+        CleanupFun(Arc::new(cleanup))
+    }
+
+    /// Starts the task
+    #[inline]
+    pub(crate) fn start(&self, db: &StorageDB, handle: CleanupHandle) {
+        self.0.start(db, handle)
+    }
+}
+
+impl Default for CleanupFun {
+    fn default() -> Self {
+        CleanupFun::from_fn(def_cleanup)
+    }
+}
+
+impl From<Box<dyn Cleanup>> for CleanupFun {
+    fn from(cleanup: Box<dyn Cleanup>) -> Self {
+        CleanupFun(Arc::from(cleanup))
+    }
+}
+
+impl From<Arc<dyn Cleanup>> for CleanupFun {
+    fn from(cleanup: Arc<dyn Cleanup>) -> Self {
+        CleanupFun(cleanup)
+    }
+}
+
+impl fmt::Debug for CleanupFun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CleanupFun")
+    }
+}
+
+/// Controls the cleanup task of a database
+#[derive(Debug, Clone)]
+pub struct CleanupHandle {
+    inner: Arc<CleanupControl>,
+}
+
+/// State shared between a cleanup task and its handles
+#[derive(Debug, Default)]
+struct CleanupControl {
+    paused: AtomicBool,
+    triggered: AtomicBool,
+    stopped: AtomicBool,
+    notify: Notify,
+}
+
+impl CleanupHandle {
+    /// Creates a handle, `paused` if periodic rounds are disabled
+    #[inline]
+    pub(crate) fn new(paused: bool) -> Self {
+        let inner = CleanupControl {
+            paused: AtomicBool::new(paused),
+            ..Default::default()
+        };
+        CleanupHandle {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Pauses periodic rounds, triggered rounds still run
+    #[inline]
+    pub fn pause(&self) {
+        self.inner.paused.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_one();
+    }
+
+    /// Resumes periodic rounds
+    #[inline]
+    pub fn resume(&self) {
+        self.inner.paused.store(false, Ordering::SeqCst);
+        self.inner.notify.notify_one();
+    }
+
+    /// Checks if periodic rounds are paused
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::SeqCst)
+    }
+
+    /// Runs a round as soon as possible, even while paused
+    #[inline]
+    pub fn trigger(&self) {
+        self.inner.triggered.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_one();
+    }
+
+    /// Stops the task after its current round, it cannot be restarted
+    #[inline]
+    pub fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_one();
+    }
+
+    /// Checks if the task has been stopped
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the next round, returns false once the task should stop
+    ///
+    /// A round is due `interval` after the call unless paused, or right away when triggered.
+    pub async fn tick(&self, interval: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + interval;
+        loop {
+            if self.is_stopped() {
+                return false;
+            }
+            if self.inner.triggered.swap(false, Ordering::SeqCst) {
+                return true;
+            }
+            if !self.is_paused() && tokio::time::Instant::now() >= deadline {
+                return true;
+            }
+            let notified = self.inner.notify.notified();
+            if self.is_paused() {
+                notified.await;
+            } else {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline) => {}
+                    _ = notified => {}
+                }
+            }
+        }
+    }
+}
+
+/// Default cleanup task, runs rounds according to `Config::cleanup`
+fn def_cleanup(db: &StorageDB, handle: CleanupHandle) {
+    let StorageDB::Sled(db) = db.clone();
+
+    tokio::spawn(async move {
+        let policy = db.cleanup_policy().clone();
+        let interval = Duration::from_millis(policy.interval_ms);
+        loop {
+            tokio::select! {
+                run = handle.tick(interval) => if !run { break },
+                _ = db.closed() => break,
+            }
+            run_round(&db, &policy).await;
+        }
+    });
+}
+
+/// Runs one cleanup round over all namespaces
+async fn run_round(db: &SledStorageDB, policy: &CleanupPolicy) {
+    let limit = policy.batch_size.max(1);
+    let started = Instant::now();
+    //every namespace keeps its own expiration index and stream trim queue
+    let handles = match db.namespace_handles() {
+        Ok(handles) => handles,
+        Err(e) => {
+            log::error!("{:?}", e);
+            vec![db.clone()]
+        }
+    };
+    #[cfg(feature = "ttl")]
+    {
+        let mut total_cleanups = 0;
+        for db in handles.iter() {
+            loop {
+                let now = Instant::now();
+                //hooked entries are removed once their hooks were called
+                let count = db.run_expire_hooks(limit).await + db.cleanup(limit);
+                total_cleanups += count;
+                if count > 0 {
+                    log::debug!(
+                        "def_cleanup: {}, total cleanups: {}, active_count(): {}, cost time: {:?}",
+                        count,
+                        total_cleanups,
+                        db.active_count(),
+                        now.elapsed()
+                    );
+                }
+                if count < limit || policy.is_over(started) {
+                    break;
+                }
+                if db.active_count() > policy.backoff_active_count {
+                    sleep(Duration::from_millis(policy.backoff_ms)).await;
+                } else {
+                    sleep(Duration::from_millis(0)).await;
+                }
+            }
+            if policy.is_over(started) {
+                break;
+            }
+        }
+        db.cleanup_stats().record(total_cleanups, started.elapsed());
+        if started.elapsed().as_secs() > 3 {
+            log::info!(
+                "total cleanups: {}, cost time: {:?}",
+                total_cleanups,
+                started.elapsed()
+            );
+        }
+    }
+    //evicting samples every namespace, it runs on a blocking thread in batches
+    while !db.is_closed() && !policy.is_over(started) {
+        let evicting = db.clone();
+        match spawn_blocking(move || evicting.evict_batch(limit)).await {
+            Ok(Ok(count)) => {
+                if count > 0 {
+                    log::debug!("def_cleanup: evicted {} keys", count);
+                }
+                if count < limit {
+                    break;
+                }
+                sleep(Duration::from_millis(0)).await;
+            }
+            Ok(Err(e)) => {
+                log::error!("{:?}", e);
+                break;
+            }
+            Err(e) => {
+                log::error!("{:?}", e);
+                break;
+            }
+        }
+    }
+    for db in handles.iter() {
+        while !db.is_closed() && !policy.is_over(started) {
+            match db.trim_streams(limit).await {
+                Ok(count) if count >= limit => {
+                    sleep(Duration::from_millis(0)).await;
+                }
+                Ok(_) => break,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Time source of the database
+//!
+//! Every expiration time and time-to-live is computed from `Config::clock`, as are
+//! eviction access times, generated stream IDs, consumer group delivery times,
+//! Pub/Sub message times and the TTLs of imported keys. The default `SystemClock`
+//! reads the system time, a `ManualClock` only moves when advanced, so expirations
+//! can be tested without waiting.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+use super::{TimestampMillis, timestamp_millis};
+
+/// Source of the current time used for expiration
+pub trait Clock: Debug + Send + Sync {
+    /// Current time in milliseconds since the Unix epoch
+    fn now(&self) -> TimestampMillis;
+}
+
+/// Clock reading the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> TimestampMillis {
+        timestamp_millis()
+    }
+}
+
+/// Clock that only moves when set or advanced, clones share the same time
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicI64>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at `now`
+    #[inline]
+    pub fn new(now: TimestampMillis) -> Self {
+        ManualClock {
+            now: Arc::new(AtomicI64::new(now)),
+        }
+    }
+
+    /// Sets the current time
+    #[inline]
+    pub fn set(&self, now: TimestampMillis) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Moves the current time forward by `dur`
+    #[inline]
+    pub fn advance(&self, dur: Duration) {
+        self.now
+            .fetch_add(dur.as_millis() as TimestampMillis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    #[inline]
+    fn now(&self) -> TimestampMillis {
+        self.now.load(Ordering::SeqCst)
+    }
+}
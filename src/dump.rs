@@ -0,0 +1,467 @@
+//! JSON-lines dumps
+//!
+//! A dump holds one JSON object per key-value pair, map, list and stream with its
+//! type, name, time-to-live and content. Names and map fields are written as text
+//! when printable UTF-8 and as base64 otherwise. Values are decoded with bincode as
+//! the value type chosen by the caller and written as JSON, values of another type
+//! or written raw, like counters, fall back to text or base64.
+
+use super::error::StorageError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::sled_storage::{KeyType, StreamId, StreamPendingEntry};
+use super::{Key, Result, TimestampMillis};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as standard, padded base64
+pub(crate) fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - i * 6)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes standard, padded base64
+pub(crate) fn base64_decode(data: &str) -> Result<Vec<u8>> {
+    let data = data.as_bytes();
+    if !data.len().is_multiple_of(4) {
+        return Err(StorageError::codec("invalid base64 length"));
+    }
+    let mut out = Vec::with_capacity(data.len() / 4 * 3);
+    for chunk in data.chunks(4) {
+        let mut n = 0u32;
+        let mut pad = 0;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                b'=' if i >= 2 => {
+                    pad += 1;
+                    0
+                }
+                _ => return Err(StorageError::codec("invalid base64 character")),
+            };
+            if pad > 0 && *c != b'=' {
+                return Err(StorageError::codec("invalid base64 padding"));
+            }
+            n = (n << 6) | v as u32;
+        }
+        let bytes = n.to_be_bytes();
+        out.extend_from_slice(&bytes[1..4 - pad]);
+    }
+    Ok(out)
+}
+
+/// Bytes in a dump, plain text when printable UTF-8, base64 otherwise, or a value
+/// decoded as JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum DumpBytes {
+    Text(String),
+    Raw { base64: String },
+    Value { value: serde_json::Value },
+}
+
+impl DumpBytes {
+    #[inline]
+    fn encode(data: &[u8]) -> Self {
+        match std::str::from_utf8(data) {
+            Ok(s) if !s.chars().any(|c| c.is_control()) => DumpBytes::Text(s.to_string()),
+            _ => DumpBytes::Raw {
+                base64: base64_encode(data),
+            },
+        }
+    }
+
+    /// Encodes a stored value, decoded as `V` if it encodes back to the same bytes
+    #[inline]
+    fn encode_value<V>(data: &[u8]) -> Self
+    where
+        V: Serialize + DeserializeOwned,
+    {
+        if let Ok(v) = bincode::deserialize::<V>(data)
+            && bincode::serialize(&v).is_ok_and(|encoded| encoded == data)
+            && let Ok(value) = serde_json::to_value(&v)
+        {
+            return DumpBytes::Value { value };
+        }
+        Self::encode(data)
+    }
+
+    #[inline]
+    fn decode(self) -> Result<Vec<u8>> {
+        match self {
+            DumpBytes::Text(s) => Ok(s.into_bytes()),
+            DumpBytes::Raw { base64 } => base64_decode(&base64),
+            DumpBytes::Value { .. } => Err(StorageError::codec("a value where bytes are expected")),
+        }
+    }
+
+    /// Decodes a stored value, encoding decoded values as `V`
+    #[inline]
+    fn decode_value<V>(self) -> Result<Vec<u8>>
+    where
+        V: Serialize + DeserializeOwned,
+    {
+        match self {
+            DumpBytes::Value { value } => {
+                Ok(bincode::serialize(&serde_json::from_value::<V>(value)?)?)
+            }
+            bytes => bytes.decode(),
+        }
+    }
+}
+
+/// Consumer group of a dumped stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DumpGroupRecord {
+    name: DumpBytes,
+    last_delivered: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pending: Vec<DumpPendingRecord>,
+}
+
+/// Entry delivered to a consumer group but not yet acknowledged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DumpPendingRecord {
+    id: String,
+    consumer: DumpBytes,
+    delivered_at: TimestampMillis,
+    delivery_count: u64,
+}
+
+/// One line of a JSON-lines dump
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum DumpRecord {
+    Kv {
+        key: DumpBytes,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<TimestampMillis>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sliding: Option<TimestampMillis>,
+        value: DumpBytes,
+    },
+    Map {
+        name: DumpBytes,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<TimestampMillis>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sliding: Option<TimestampMillis>,
+        fields: Vec<(DumpBytes, DumpBytes)>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        field_ttls: Vec<(DumpBytes, TimestampMillis)>,
+    },
+    List {
+        name: DumpBytes,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<TimestampMillis>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sliding: Option<TimestampMillis>,
+        values: Vec<DumpBytes>,
+    },
+    Stream {
+        name: DumpBytes,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<TimestampMillis>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sliding: Option<TimestampMillis>,
+        last_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maxlen: Option<u64>,
+        entries: Vec<(String, DumpBytes)>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        groups: Vec<DumpGroupRecord>,
+    },
+}
+
+/// Consumer group of a dumped stream, its last delivered ID and pending entries
+#[derive(Debug, Clone)]
+pub(crate) struct DumpGroup {
+    pub(crate) name: Key,
+    pub(crate) last_delivered: StreamId,
+    pub(crate) pending: Vec<StreamPendingEntry>,
+}
+
+/// A dumped key, map, list or stream with its raw stored values
+#[derive(Debug, Clone)]
+pub(crate) enum DumpEntry {
+    KV {
+        key: Key,
+        ttl: Option<TimestampMillis>,
+        /// Time-to-live restarted by every read, if the expiration slides
+        sliding: Option<TimestampMillis>,
+        value: Vec<u8>,
+    },
+    Map {
+        name: Key,
+        ttl: Option<TimestampMillis>,
+        /// Time-to-live restarted by every read, if the expiration slides
+        sliding: Option<TimestampMillis>,
+        fields: Vec<(Key, Vec<u8>)>,
+        /// Remaining time-to-live of fields that expire
+        field_ttls: Vec<(Key, TimestampMillis)>,
+    },
+    List {
+        name: Key,
+        ttl: Option<TimestampMillis>,
+        /// Time-to-live restarted by every read, if the expiration slides
+        sliding: Option<TimestampMillis>,
+        values: Vec<Vec<u8>>,
+    },
+    Stream {
+        name: Key,
+        ttl: Option<TimestampMillis>,
+        /// Time-to-live restarted by every read, if the expiration slides
+        sliding: Option<TimestampMillis>,
+        last_id: StreamId,
+        maxlen: Option<u64>,
+        entries: Vec<(StreamId, Vec<u8>)>,
+        groups: Vec<DumpGroup>,
+    },
+}
+
+impl DumpEntry {
+    /// Returns the key, map, list or stream name
+    #[inline]
+    pub(crate) fn name(&self) -> &[u8] {
+        match self {
+            DumpEntry::KV { key, .. } => key,
+            DumpEntry::Map { name, .. }
+            | DumpEntry::List { name, .. }
+            | DumpEntry::Stream { name, .. } => name,
+        }
+    }
+
+    /// Returns the key type
+    #[inline]
+    pub(crate) fn key_type(&self) -> KeyType {
+        match self {
+            DumpEntry::KV { .. } => KeyType::KV,
+            DumpEntry::Map { .. } => KeyType::Map,
+            DumpEntry::List { .. } => KeyType::List,
+            DumpEntry::Stream { .. } => KeyType::Stream,
+        }
+    }
+
+    /// Returns the remaining time-to-live in milliseconds
+    #[inline]
+    pub(crate) fn ttl(&self) -> Option<TimestampMillis> {
+        match self {
+            DumpEntry::KV { ttl, .. }
+            | DumpEntry::Map { ttl, .. }
+            | DumpEntry::List { ttl, .. }
+            | DumpEntry::Stream { ttl, .. } => *ttl,
+        }
+    }
+
+    /// Returns the time-to-live restarted by every read, if the expiration slides
+    #[inline]
+    pub(crate) fn sliding(&self) -> Option<TimestampMillis> {
+        match self {
+            DumpEntry::KV { sliding, .. }
+            | DumpEntry::Map { sliding, .. }
+            | DumpEntry::List { sliding, .. }
+            | DumpEntry::Stream { sliding, .. } => *sliding,
+        }
+    }
+
+    /// Converts the entry into a dump line, decoding values as `V`
+    pub(crate) fn encode<V>(self) -> DumpRecord
+    where
+        V: Serialize + DeserializeOwned,
+    {
+        let value = |v: &[u8]| DumpBytes::encode_value::<V>(v);
+        match self {
+            DumpEntry::KV {
+                key,
+                ttl,
+                sliding,
+                value: v,
+            } => DumpRecord::Kv {
+                key: DumpBytes::encode(&key),
+                ttl,
+                sliding,
+                value: value(&v),
+            },
+            DumpEntry::Map {
+                name,
+                ttl,
+                sliding,
+                fields,
+                field_ttls,
+            } => DumpRecord::Map {
+                name: DumpBytes::encode(&name),
+                ttl,
+                sliding,
+                fields: fields
+                    .iter()
+                    .map(|(f, v)| (DumpBytes::encode(f), value(v)))
+                    .collect(),
+                field_ttls: field_ttls
+                    .iter()
+                    .map(|(f, ttl)| (DumpBytes::encode(f), *ttl))
+                    .collect(),
+            },
+            DumpEntry::List {
+                name,
+                ttl,
+                sliding,
+                values,
+            } => DumpRecord::List {
+                name: DumpBytes::encode(&name),
+                ttl,
+                sliding,
+                values: values.iter().map(|v| value(v)).collect(),
+            },
+            DumpEntry::Stream {
+                name,
+                ttl,
+                sliding,
+                last_id,
+                maxlen,
+                entries,
+                groups,
+            } => DumpRecord::Stream {
+                name: DumpBytes::encode(&name),
+                ttl,
+                sliding,
+                last_id: last_id.to_string(),
+                maxlen,
+                entries: entries
+                    .iter()
+                    .map(|(id, v)| (id.to_string(), value(v)))
+                    .collect(),
+                groups: groups
+                    .into_iter()
+                    .map(|g| DumpGroupRecord {
+                        name: DumpBytes::encode(&g.name),
+                        last_delivered: g.last_delivered.to_string(),
+                        pending: g
+                            .pending
+                            .into_iter()
+                            .map(|p| DumpPendingRecord {
+                                id: p.id.to_string(),
+                                consumer: DumpBytes::encode(&p.consumer),
+                                delivered_at: p.delivered_at,
+                                delivery_count: p.delivery_count,
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// Converts a dump line into an entry, encoding decoded values as `V`
+    pub(crate) fn decode<V>(record: DumpRecord) -> Result<Self>
+    where
+        V: Serialize + DeserializeOwned,
+    {
+        Ok(match record {
+            DumpRecord::Kv {
+                key,
+                ttl,
+                sliding,
+                value,
+            } => DumpEntry::KV {
+                key: key.decode()?,
+                ttl,
+                sliding,
+                value: value.decode_value::<V>()?,
+            },
+            DumpRecord::Map {
+                name,
+                ttl,
+                sliding,
+                fields,
+                field_ttls,
+            } => DumpEntry::Map {
+                name: name.decode()?,
+                ttl,
+                sliding,
+                fields: fields
+                    .into_iter()
+                    .map(|(f, v)| Ok((f.decode()?, v.decode_value::<V>()?)))
+                    .collect::<Result<Vec<_>>>()?,
+                field_ttls: field_ttls
+                    .into_iter()
+                    .map(|(f, ttl)| Ok((f.decode()?, ttl)))
+                    .collect::<Result<Vec<_>>>()?,
+            },
+            DumpRecord::List {
+                name,
+                ttl,
+                sliding,
+                values,
+            } => DumpEntry::List {
+                name: name.decode()?,
+                ttl,
+                sliding,
+                values: values
+                    .into_iter()
+                    .map(DumpBytes::decode_value::<V>)
+                    .collect::<Result<Vec<_>>>()?,
+            },
+            DumpRecord::Stream {
+                name,
+                ttl,
+                sliding,
+                last_id,
+                maxlen,
+                entries,
+                groups,
+            } => DumpEntry::Stream {
+                name: name.decode()?,
+                ttl,
+                sliding,
+                last_id: last_id.parse()?,
+                maxlen,
+                entries: entries
+                    .into_iter()
+                    .map(|(id, v)| Ok((id.parse()?, v.decode_value::<V>()?)))
+                    .collect::<Result<Vec<_>>>()?,
+                groups: groups
+                    .into_iter()
+                    .map(|g| {
+                        Ok(DumpGroup {
+                            name: g.name.decode()?,
+                            last_delivered: g.last_delivered.parse()?,
+                            pending: g
+                                .pending
+                                .into_iter()
+                                .map(|p| {
+                                    Ok(StreamPendingEntry {
+                                        id: p.id.parse()?,
+                                        consumer: p.consumer.decode()?,
+                                        delivered_at: p.delivered_at,
+                                        delivery_count: p.delivery_count,
+                                    })
+                                })
+                                .collect::<Result<Vec<_>>>()?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            },
+        })
+    }
+}
@@ -0,0 +1,195 @@
+//! Storage errors
+//!
+//! Every operation returns `StorageError`. Errors of sled, the file system and the
+//! runtime are wrapped as `Backend`, decoding failures become `Codec`, and a dropped
+//! command worker or channel is reported as `Closed`.
+
+use std::fmt;
+
+use sled::transaction::TransactionError;
+
+use super::Key;
+use super::eviction::MaxMemoryError;
+use super::quota::QuotaError;
+use super::sled_storage::KeyType;
+
+/// Error returned by all storage operations
+#[derive(Debug)]
+pub enum StorageError {
+    /// `List::push_limit` found the list full and was not asked to pop
+    ListFull,
+    /// The name is used by a different type
+    WrongType {
+        key: Key,
+        expected: KeyType,
+        actual: KeyType,
+    },
+    /// A required entry, such as a stream consumer group, does not exist
+    NotFound(String),
+    /// Stored or imported data could not be encoded or decoded
+    Codec(String),
+    /// A transaction was aborted
+    Conflict,
+    /// A write exceeded a namespace quota
+    QuotaExceeded(QuotaError),
+    /// A write was refused under `EvictionPolicy::NoEviction`
+    MaxMemory(MaxMemoryError),
+    /// The database was closed
+    Closed,
+    /// An argument or the configuration is invalid
+    Invalid(String),
+    /// A subscriber fell behind and this many messages were dropped
+    Lagged(u64),
+    /// Error from sled, the file system or the runtime
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl StorageError {
+    #[inline]
+    pub(crate) fn codec<T: fmt::Display>(msg: T) -> Self {
+        StorageError::Codec(msg.to_string())
+    }
+
+    #[inline]
+    pub(crate) fn invalid<T: fmt::Display>(msg: T) -> Self {
+        StorageError::Invalid(msg.to_string())
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::ListFull => write!(f, "list is full"),
+            StorageError::WrongType {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "WRONGTYPE {} holds a {:?}, not a {:?}",
+                String::from_utf8_lossy(key),
+                actual,
+                expected
+            ),
+            StorageError::NotFound(msg) => write!(f, "not found: {}", msg),
+            StorageError::Codec(msg) => write!(f, "codec error: {}", msg),
+            StorageError::Conflict => write!(f, "transaction aborted"),
+            StorageError::QuotaExceeded(e) => e.fmt(f),
+            StorageError::MaxMemory(e) => e.fmt(f),
+            StorageError::Closed => write!(f, "storage is closed"),
+            StorageError::Invalid(msg) => write!(f, "invalid argument: {}", msg),
+            StorageError::Lagged(n) => write!(f, "subscriber lagged, {} messages lost", n),
+            StorageError::Backend(e) => write!(f, "backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::QuotaExceeded(e) => Some(e),
+            StorageError::MaxMemory(e) => Some(e),
+            StorageError::Backend(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<sled::Error> for StorageError {
+    #[inline]
+    fn from(e: sled::Error) -> Self {
+        StorageError::Backend(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    #[inline]
+    fn from(e: std::io::Error) -> Self {
+        StorageError::Backend(Box::new(e))
+    }
+}
+
+impl From<tokio::task::JoinError> for StorageError {
+    #[inline]
+    fn from(e: tokio::task::JoinError) -> Self {
+        StorageError::Backend(Box::new(e))
+    }
+}
+
+impl From<tokio::sync::oneshot::error::RecvError> for StorageError {
+    #[inline]
+    fn from(_: tokio::sync::oneshot::error::RecvError) -> Self {
+        StorageError::Closed
+    }
+}
+
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for StorageError {
+    #[inline]
+    fn from(_: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        StorageError::Closed
+    }
+}
+
+impl From<bincode::Error> for StorageError {
+    #[inline]
+    fn from(e: bincode::Error) -> Self {
+        StorageError::codec(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    #[inline]
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::codec(e)
+    }
+}
+
+impl From<std::array::TryFromSliceError> for StorageError {
+    #[inline]
+    fn from(e: std::array::TryFromSliceError) -> Self {
+        StorageError::codec(e)
+    }
+}
+
+impl From<QuotaError> for StorageError {
+    #[inline]
+    fn from(e: QuotaError) -> Self {
+        StorageError::QuotaExceeded(e)
+    }
+}
+
+impl From<MaxMemoryError> for StorageError {
+    #[inline]
+    fn from(e: MaxMemoryError) -> Self {
+        StorageError::MaxMemory(e)
+    }
+}
+
+impl From<TransactionError<StorageError>> for StorageError {
+    #[inline]
+    fn from(e: TransactionError<StorageError>) -> Self {
+        match e {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => e.into(),
+        }
+    }
+}
+
+impl From<TransactionError<sled::Error>> for StorageError {
+    #[inline]
+    fn from(e: TransactionError<sled::Error>) -> Self {
+        match e {
+            TransactionError::Abort(e) | TransactionError::Storage(e) => e.into(),
+        }
+    }
+}
+
+impl From<TransactionError<()>> for StorageError {
+    #[inline]
+    fn from(e: TransactionError<()>) -> Self {
+        match e {
+            TransactionError::Abort(()) => StorageError::Conflict,
+            TransactionError::Storage(e) => e.into(),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Bounded key-value storage
+//!
+//! Key-value entries can be limited by stored bytes or by key count. Once the
+//! limit is exceeded the cleanup task evicts keys according to the configured
+//! policy, or, under `noeviction`, writes are refused with `MaxMemoryError`.
+//! Maps, lists and streams are neither counted nor evicted.
+//!
+//! The usage is counted when the database is opened and then kept up to date by
+//! writes and removals. Eviction approximates the policy: every evicted key is the
+//! best ranked of a small sample per namespace, taken behind the previous sample.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use convert::Bytesize;
+use serde::{Deserialize, Serialize};
+use sled::IVec;
+
+use super::{Key, TimestampMillis};
+
+/// LFU hit counters are halved once per elapsed period
+const LFU_DECAY_PERIOD_MS: TimestampMillis = 60_000;
+
+/// Keys sampled per namespace for every evicted key
+pub(crate) const EVICTION_SAMPLES: usize = 16;
+
+/// Limit on stored key-value entries
+///
+/// Only key-value pairs count towards the limit, the entries of maps, lists and
+/// streams are not limited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaxMemory {
+    /// No limit
+    #[default]
+    Unlimited,
+    /// Total size of keys and values in bytes
+    Bytes { limit: Bytesize },
+    /// Number of keys
+    Keys { limit: u64 },
+}
+
+impl MaxMemory {
+    /// Returns the limit, `None` when unlimited
+    #[inline]
+    pub fn limit(&self) -> Option<u64> {
+        match self {
+            MaxMemory::Unlimited => None,
+            MaxMemory::Bytes { limit } => Some(limit.as_u64()),
+            MaxMemory::Keys { limit } => Some(*limit),
+        }
+    }
+}
+
+/// Which keys are evicted once `max_memory` is exceeded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvictionPolicy {
+    /// Least recently used keys first
+    AllkeysLru,
+    /// Least frequently used keys first
+    AllkeysLfu,
+    /// Least recently used keys with a TTL first
+    VolatileLru,
+    /// Keys with the nearest expiration time first
+    VolatileTtl,
+    /// Nothing is evicted, writes are refused
+    #[default]
+    NoEviction,
+}
+
+impl EvictionPolicy {
+    /// Checks if the policy needs access times or counts
+    #[inline]
+    pub(crate) fn tracks_access(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::AllkeysLru | EvictionPolicy::AllkeysLfu | EvictionPolicy::VolatileLru
+        )
+    }
+}
+
+/// Error returned for writes refused under `EvictionPolicy::NoEviction`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaxMemoryError {
+    /// Stored bytes or keys
+    pub used: u64,
+    /// Configured limit
+    pub limit: u64,
+}
+
+impl fmt::Display for MaxMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "write refused, storage limit reached ({} of {})",
+            self.used, self.limit
+        )
+    }
+}
+
+impl std::error::Error for MaxMemoryError {}
+
+/// Eviction statistics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvictionInfo {
+    /// Configured limit
+    pub max_memory: MaxMemory,
+    /// Configured policy
+    pub policy: EvictionPolicy,
+    /// Stored bytes or keys
+    pub used: u64,
+    /// Number of evicted keys
+    pub evicted: u64,
+}
+
+/// Access time and decaying hit count of a key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct AccessMeta {
+    pub(crate) last_access: TimestampMillis,
+    pub(crate) hits: u32,
+}
+
+impl AccessMeta {
+    /// Decodes access metadata, unreadable metadata counts as never accessed
+    #[inline]
+    pub(crate) fn decode(data: &[u8]) -> Self {
+        match (data.get(..8), data.get(8..12)) {
+            (Some(at), Some(hits)) => AccessMeta {
+                last_access: TimestampMillis::from_be_bytes(at.try_into().unwrap_or_default()),
+                hits: u32::from_be_bytes(hits.try_into().unwrap_or_default()),
+            },
+            _ => AccessMeta::default(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn encode(&self) -> [u8; 12] {
+        let mut data = [0; 12];
+        data[..8].copy_from_slice(&self.last_access.to_be_bytes());
+        data[8..].copy_from_slice(&self.hits.to_be_bytes());
+        data
+    }
+
+    /// Records an access at `now`
+    #[inline]
+    pub(crate) fn touch(mut self, now: TimestampMillis) -> Self {
+        self.hits = self.decayed_hits(now).saturating_add(1);
+        self.last_access = now;
+        self
+    }
+
+    /// Hit count halved for every decay period since the last access
+    #[inline]
+    pub(crate) fn decayed_hits(&self, now: TimestampMillis) -> u32 {
+        let periods = (now - self.last_access).max(0) / LFU_DECAY_PERIOD_MS;
+        self.hits.checked_shr(periods as u32).unwrap_or(0)
+    }
+}
+
+/// Limit, policy and usage of the key-value storage
+pub(crate) struct Eviction {
+    pub(crate) max_memory: MaxMemory,
+    pub(crate) policy: EvictionPolicy,
+    used: AtomicU64,
+    /// Whether `used` was counted since it was invalidated
+    counted: AtomicBool,
+    evicted: AtomicU64,
+    /// Last key sampled in every namespace, the next sample starts behind it
+    cursors: Mutex<HashMap<Option<Key>, IVec>>,
+}
+
+impl Eviction {
+    #[inline]
+    pub(crate) fn new(max_memory: MaxMemory, policy: EvictionPolicy) -> Self {
+        Self {
+            max_memory,
+            policy,
+            used: AtomicU64::new(0),
+            counted: AtomicBool::new(false),
+            evicted: AtomicU64::new(0),
+            cursors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks if a limit is configured
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_memory.limit().is_some()
+    }
+
+    /// Checks if key accesses must be recorded
+    #[inline]
+    pub(crate) fn tracks_access(&self) -> bool {
+        self.is_enabled() && self.policy.tracks_access()
+    }
+
+    /// Adds a write to the usage, refusing it under `noeviction` if it exceeds the limit
+    ///
+    /// `added` is negative for overwrites with smaller values. The usage is given back
+    /// unless the returned reservation is committed.
+    #[inline]
+    pub(crate) fn reserve(&self, added: i64) -> Result<EvictionReservation<'_>, MaxMemoryError> {
+        let Some(limit) = self.max_memory.limit() else {
+            return Ok(EvictionReservation::default());
+        };
+        let used = self.used.load(Ordering::Relaxed);
+        if self.policy == EvictionPolicy::NoEviction
+            && added > 0
+            && used.saturating_add(added as u64) > limit
+        {
+            return Err(MaxMemoryError { used, limit });
+        }
+        self.adjust(added);
+        Ok(EvictionReservation {
+            eviction: Some(self),
+            added,
+        })
+    }
+
+    #[inline]
+    fn adjust(&self, added: i64) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_add_signed(added))
+            });
+    }
+
+    /// Takes removed entries off the usage
+    #[inline]
+    pub(crate) fn release(&self, removed: u64) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(removed))
+            });
+    }
+
+    #[inline]
+    pub(crate) fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Sets the usage counted by an eviction pass
+    #[inline]
+    pub(crate) fn set_used(&self, used: u64) {
+        self.used.store(used, Ordering::Relaxed);
+        self.counted.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn is_counted(&self) -> bool {
+        self.counted.load(Ordering::Relaxed)
+    }
+
+    /// Makes the next eviction pass count the usage again
+    #[inline]
+    pub(crate) fn invalidate(&self) {
+        self.counted.store(false, Ordering::Relaxed);
+    }
+
+    /// Gets the last key sampled in a namespace
+    #[inline]
+    pub(crate) fn cursor(&self, namespace: Option<&[u8]>) -> Option<IVec> {
+        let cursors = self.cursors.lock().unwrap_or_else(|e| e.into_inner());
+        cursors.get(&namespace.map(|name| name.to_vec())).cloned()
+    }
+
+    #[inline]
+    pub(crate) fn set_cursor(&self, namespace: Option<&[u8]>, key: IVec) {
+        let mut cursors = self.cursors.lock().unwrap_or_else(|e| e.into_inner());
+        cursors.insert(namespace.map(|name| name.to_vec()), key);
+    }
+
+    #[inline]
+    pub(crate) fn record_evicted(&self, count: u64) {
+        self.evicted.fetch_add(count, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn info(&self) -> EvictionInfo {
+        EvictionInfo {
+            max_memory: self.max_memory.clone(),
+            policy: self.policy,
+            used: self.used.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Usage reserved for a write, given back when dropped before the write committed
+#[must_use]
+#[derive(Default)]
+pub(crate) struct EvictionReservation<'a> {
+    eviction: Option<&'a Eviction>,
+    added: i64,
+}
+
+impl EvictionReservation<'_> {
+    /// Keeps the reserved usage, the write succeeded
+    #[inline]
+    pub(crate) fn commit(mut self) {
+        self.eviction = None;
+    }
+}
+
+impl Drop for EvictionReservation<'_> {
+    fn drop(&mut self) {
+        if let Some(eviction) = self.eviction.take() {
+            eviction.adjust(-self.added);
+        }
+    }
+}
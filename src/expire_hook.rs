@@ -0,0 +1,192 @@
+//! Expiration hooks
+//!
+//! Hooks registered with `on_expire` are called by the cleanup task for every
+//! expired key, map, list or stream of their namespace whose name matches their
+//! pattern. An entry is only removed once all its hooks have completed, so a
+//! hook interrupted by a restart or a panic is called again on a later pass.
+//! Entries whose hooks failed or ran longer than `CleanupPolicy::hook_timeout_ms`
+//! are retried with a growing delay, meanwhile the cleanup task moves on to the
+//! entries behind them.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use super::sled_storage::{KeyType, Pattern, is_match};
+use super::{Key, TimestampMillis};
+
+/// Delay before the hooks of an entry are called again after their first failure
+const RETRY_DELAY_MIN: TimestampMillis = 1_000;
+/// Longest delay before the hooks of an entry are called again
+const RETRY_DELAY_MAX: TimestampMillis = 60_000;
+
+/// Value of an expired key, map or list, as passed to expiration hooks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpiredValue {
+    /// Serialized value of a key
+    KV(Vec<u8>),
+    /// Keys and serialized values of the map items
+    Map(Vec<(Key, Vec<u8>)>),
+    /// Serialized list values, front first
+    List(Vec<Vec<u8>>),
+}
+
+/// Future returned by an expiration hook
+type HookFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Type-erased expiration hook
+type HookFn = Arc<dyn Fn(Key, KeyType, Option<ExpiredValue>) -> HookFuture + Send + Sync>;
+
+/// Hook registered for a pattern in a namespace
+struct ExpireHook {
+    pattern: Pattern,
+    namespace: Option<Key>,
+    f: HookFn,
+}
+
+/// Retry state of an entry whose hooks failed
+struct HookRetry {
+    failures: u32,
+    retry_at: TimestampMillis,
+}
+
+/// Expiration hooks of all namespaces
+#[derive(Default)]
+pub(crate) struct ExpireHooks {
+    hooks: RwLock<Vec<ExpireHook>>,
+    /// Entries whose hooks failed, by namespace and expiration entry
+    retries: Mutex<HashMap<(Option<Key>, Key), HookRetry>>,
+}
+
+impl ExpireHooks {
+    /// Registers `f` for keys of `namespace` matching `pattern`
+    #[inline]
+    pub(crate) fn register<F, Fut>(&self, pattern: &[u8], namespace: Option<&[u8]>, f: F)
+    where
+        F: Fn(Key, KeyType, Option<ExpiredValue>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let hook = ExpireHook {
+            pattern: Pattern::parse(pattern),
+            namespace: namespace.map(|ns| ns.to_vec()),
+            f: Arc::new(move |key, key_type, value| Box::pin(f(key, key_type, value))),
+        };
+        self.hooks.write().unwrap().push(hook);
+    }
+
+    /// Checks if any hook is registered, the cleanup task skips the hook pass otherwise
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.hooks.read().unwrap().is_empty()
+    }
+
+    /// Checks if a hook is registered for `key` of `namespace`
+    #[inline]
+    pub(crate) fn matches(&self, key: &[u8], namespace: Option<&[u8]>) -> bool {
+        self.hooks
+            .read()
+            .unwrap()
+            .iter()
+            .any(|hook| hook.is_match(key, namespace))
+    }
+
+    /// Calls the hooks registered for `key` of `namespace`, one after the other
+    ///
+    /// Every hook runs in its own task, returns false if one of them panicked or
+    /// did not complete within `timeout`. Hooks that time out are aborted.
+    pub(crate) async fn call(
+        &self,
+        key: &[u8],
+        key_type: KeyType,
+        value: Option<ExpiredValue>,
+        namespace: Option<&[u8]>,
+        timeout: Duration,
+    ) -> bool {
+        let hooks = self
+            .hooks
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|hook| hook.is_match(key, namespace))
+            .map(|hook| hook.f.clone())
+            .collect::<Vec<_>>();
+        let mut delivered = true;
+        for f in hooks {
+            let mut task = tokio::spawn(f(key.to_vec(), key_type, value.clone()));
+            match tokio::time::timeout(timeout, &mut task).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    log::error!("expiration hook failed: {:?}", e);
+                    delivered = false;
+                }
+                Err(_) => {
+                    task.abort();
+                    log::error!("expiration hook timed out after {:?}", timeout);
+                    delivered = false;
+                }
+            }
+        }
+        delivered
+    }
+
+    /// Checks if the hooks of an entry failed and must not be called again before `now`
+    #[inline]
+    pub(crate) fn is_delayed(
+        &self,
+        at_key: &[u8],
+        namespace: Option<&[u8]>,
+        now: TimestampMillis,
+    ) -> bool {
+        self.retries
+            .lock()
+            .unwrap()
+            .get(&(namespace.map(|ns| ns.to_vec()), at_key.to_vec()))
+            .is_some_and(|retry| retry.retry_at > now)
+    }
+
+    /// Delays the next call of the hooks of an entry, doubling the delay per failure
+    #[inline]
+    pub(crate) fn failed(&self, at_key: &[u8], namespace: Option<&[u8]>, now: TimestampMillis) {
+        let mut retries = self.retries.lock().unwrap();
+        let retry = retries
+            .entry((namespace.map(|ns| ns.to_vec()), at_key.to_vec()))
+            .or_insert(HookRetry {
+                failures: 0,
+                retry_at: now,
+            });
+        let delay = RETRY_DELAY_MIN.saturating_mul(1 << retry.failures.min(16));
+        retry.failures += 1;
+        retry.retry_at = now + delay.min(RETRY_DELAY_MAX);
+    }
+
+    /// Forgets the failures of an entry whose hooks completed
+    #[inline]
+    pub(crate) fn delivered(&self, at_key: &[u8], namespace: Option<&[u8]>) {
+        let mut retries = self.retries.lock().unwrap();
+        if !retries.is_empty() {
+            retries.remove(&(namespace.map(|ns| ns.to_vec()), at_key.to_vec()));
+        }
+    }
+
+    /// Forgets the failures of the entries of `namespace` that no longer exist
+    #[inline]
+    pub(crate) fn retain_retries<F>(&self, namespace: Option<&[u8]>, mut exists: F)
+    where
+        F: FnMut(&[u8]) -> bool,
+    {
+        self.retries
+            .lock()
+            .unwrap()
+            .retain(|(ns, at_key), _| ns.as_deref() != namespace || exists(at_key));
+    }
+}
+
+impl ExpireHook {
+    /// Checks if the hook is registered for `key` of `namespace`
+    #[inline]
+    fn is_match(&self, key: &[u8], namespace: Option<&[u8]>) -> bool {
+        self.namespace.as_deref() == namespace && is_match(self.pattern.clone(), key)
+    }
+}
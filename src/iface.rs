@@ -1,6 +1,10 @@
-use super::{IterItem, Key, Result, StorageList, StorageMap, TimestampMillis};
+use super::{
+    IterItem, Key, KeyType, Result, StorageList, StorageMap, StreamEntry, StreamId,
+    StreamPendingEntry, TimestampMillis,
+};
 use async_trait::async_trait;
 use serde::de::DeserializeOwned;
+use std::time::Duration;
 /// Asynchronous iterator trait for storage operations
 #[async_trait]
 pub trait AsyncIterator {
@@ -33,6 +37,9 @@ pub trait IStorageDB: Send + Sync {
     /// Concrete List type for this storage
     type ListType: List;
 
+    /// Concrete Stream type for this storage
+    type StreamType: Stream;
+
     /// Creates or accesses a named map
     async fn map<N: AsRef<[u8]> + Sync + Send>(
         &self,
@@ -40,6 +47,14 @@ pub trait IStorageDB: Send + Sync {
         expire: Option<TimestampMillis>,
     ) -> Result<Self::MapType>;
 
+    /// Creates or accesses a named map whose time-to-live restarts on every read (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn map_sliding<N: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: N,
+        ttl: TimestampMillis,
+    ) -> Result<Self::MapType>;
+
     /// Removes an entire map
     async fn map_remove<K>(&self, name: K) -> Result<()>
     where
@@ -55,6 +70,14 @@ pub trait IStorageDB: Send + Sync {
         expire: Option<TimestampMillis>,
     ) -> Result<Self::ListType>;
 
+    /// Creates or accesses a named list whose time-to-live restarts on every pop (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn list_sliding<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        ttl: TimestampMillis,
+    ) -> Result<Self::ListType>;
+
     /// Removes an entire list
     async fn list_remove<K>(&self, name: K) -> Result<()>
     where
@@ -63,12 +86,37 @@ pub trait IStorageDB: Send + Sync {
     /// Checks if a list exists
     async fn list_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool>;
 
+    /// Creates or accesses a named stream
+    async fn stream<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        expire: Option<TimestampMillis>,
+    ) -> Result<Self::StreamType>;
+
+    /// Removes an entire stream
+    async fn stream_remove<K>(&self, name: K) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send;
+
+    /// Checks if a stream exists
+    async fn stream_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool>;
+
+    /// Gets the type of the key, map, list or stream stored under a name
+    async fn type_of<K: AsRef<[u8]> + Sync + Send>(&self, name: K) -> Result<Option<KeyType>>;
+
     /// Inserts a key-value pair
     async fn insert<K, V>(&self, key: K, val: &V) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send,
         V: serde::ser::Serialize + Sync + Send;
 
+    /// Inserts a key-value pair whose time-to-live restarts on every read (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn insert_sliding<K, V>(&self, key: K, val: &V, ttl: TimestampMillis) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+        V: serde::ser::Serialize + Sync + Send;
+
     /// Retrieves a value by key
     async fn get<K, V>(&self, key: K) -> Result<Option<V>>
     where
@@ -89,22 +137,22 @@ pub trait IStorageDB: Send + Sync {
     async fn batch_remove(&self, keys: Vec<Key>) -> Result<()>;
 
     /// Increments a counter value
-    async fn counter_incr<K>(&self, key: K, increment: isize) -> Result<()>
+    async fn counter_incr<K>(&self, key: K, increment: i64) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send;
 
     /// Decrements a counter value
-    async fn counter_decr<K>(&self, key: K, increment: isize) -> Result<()>
+    async fn counter_decr<K>(&self, key: K, increment: i64) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send;
 
     /// Gets current counter value
-    async fn counter_get<K>(&self, key: K) -> Result<Option<isize>>
+    async fn counter_get<K>(&self, key: K) -> Result<Option<i64>>
     where
         K: AsRef<[u8]> + Sync + Send;
 
     /// Sets counter to specific value
-    async fn counter_set<K>(&self, key: K, val: isize) -> Result<()>
+    async fn counter_set<K>(&self, key: K, val: i64) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send;
 
@@ -245,6 +293,26 @@ pub trait Map: Sync + Send {
     /// Gets remaining time-to-live for the map (requires "ttl" feature)
     #[cfg(feature = "ttl")]
     async fn ttl(&self) -> Result<Option<TimestampMillis>>;
+
+    /// Sets expiration duration for a single item, false if the item does not exist (requires "ttl" feature)
+    ///
+    /// Inserting a new value for the item drops its expiration.
+    #[cfg(feature = "ttl")]
+    async fn expire_field<K>(&self, key: K, dur: TimestampMillis) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send;
+
+    /// Gets remaining time-to-live for an item, `None` if it does not exist or has none (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn field_ttl<K>(&self, key: K) -> Result<Option<TimestampMillis>>
+    where
+        K: AsRef<[u8]> + Sync + Send;
+
+    /// Removes the expiration of an item, false if it does not exist or has none (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn persist_field<K>(&self, key: K) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send;
 }
 
 /// List storage operations
@@ -317,3 +385,140 @@ pub trait List: Sync + Send {
     #[cfg(feature = "ttl")]
     async fn ttl(&self) -> Result<Option<TimestampMillis>>;
 }
+
+/// Append-only stream operations, modelled on Redis streams
+#[async_trait]
+pub trait Stream: Sync + Send {
+    /// Gets the name of this stream
+    fn name(&self) -> &[u8];
+
+    /// Appends a value with an auto-generated ID (XADD *)
+    ///
+    /// With `maxlen` the stream is trimmed to about `maxlen` entries by the
+    /// cleanup task, using the limit given with the most recent add.
+    async fn add<V>(&self, val: &V, maxlen: Option<usize>) -> Result<StreamId>
+    where
+        V: serde::ser::Serialize + Sync + Send;
+
+    /// Appends a value with an explicit ID, which must be greater than the last ID
+    async fn add_with_id<V>(
+        &self,
+        id: StreamId,
+        val: &V,
+        maxlen: Option<usize>,
+    ) -> Result<StreamId>
+    where
+        V: serde::ser::Serialize + Sync + Send;
+
+    /// Gets entries with IDs in `start..=end`, oldest first (XRANGE)
+    async fn range<V>(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        V: DeserializeOwned + Sync + Send;
+
+    /// Gets entries with IDs in `start..=end`, newest first (XREVRANGE)
+    async fn rev_range<V>(
+        &self,
+        end: StreamId,
+        start: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        V: DeserializeOwned + Sync + Send;
+
+    /// Gets entries with IDs greater than `after` (XREAD)
+    ///
+    /// With `block` waits up to the given duration for new entries if there are none.
+    async fn read<V>(
+        &self,
+        after: StreamId,
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        V: DeserializeOwned + Sync + Send;
+
+    /// Gets number of entries in the stream
+    async fn len(&self) -> Result<usize>;
+
+    /// Checks if stream is empty
+    async fn is_empty(&self) -> Result<bool>;
+
+    /// Gets the ID of the last added entry
+    async fn last_id(&self) -> Result<Option<StreamId>>;
+
+    /// Removes entries by ID, returns the number of removed entries (XDEL)
+    async fn delete(&self, ids: Vec<StreamId>) -> Result<usize>;
+
+    /// Removes the oldest entries down to `maxlen`, returns the number of removed entries (XTRIM)
+    async fn trim(&self, maxlen: usize) -> Result<usize>;
+
+    /// Removes all entries and consumer groups
+    async fn clear(&self) -> Result<()>;
+
+    /// Creates a consumer group delivering entries after `start`, or only new entries if `None`
+    ///
+    /// Returns false if the group already exists.
+    async fn group_create<G>(&self, group: G, start: Option<StreamId>) -> Result<bool>
+    where
+        G: AsRef<[u8]> + Sync + Send;
+
+    /// Removes a consumer group and its pending entries
+    async fn group_destroy<G>(&self, group: G) -> Result<bool>
+    where
+        G: AsRef<[u8]> + Sync + Send;
+
+    /// Delivers entries not yet delivered to the group and adds them to its pending entries (XREADGROUP)
+    ///
+    /// With `block` waits up to the given duration for new entries if there are none.
+    async fn read_group<G, C, V>(
+        &self,
+        group: G,
+        consumer: C,
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+        C: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send;
+
+    /// Removes entries from the pending entries of a group (XACK)
+    async fn ack<G>(&self, group: G, ids: Vec<StreamId>) -> Result<usize>
+    where
+        G: AsRef<[u8]> + Sync + Send;
+
+    /// Gets the pending entries of a group (XPENDING)
+    async fn pending<G>(&self, group: G) -> Result<Vec<StreamPendingEntry>>
+    where
+        G: AsRef<[u8]> + Sync + Send;
+
+    /// Transfers pending entries idle for at least `min_idle` milliseconds to `consumer` (XCLAIM)
+    async fn claim<G, C, V>(
+        &self,
+        group: G,
+        consumer: C,
+        min_idle: TimestampMillis,
+        ids: Vec<StreamId>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+        C: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send;
+
+    /// Sets expiration timestamp for the entire stream (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn expire_at(&self, at: TimestampMillis) -> Result<bool>;
+
+    /// Sets expiration duration for the entire stream (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn expire(&self, dur: TimestampMillis) -> Result<bool>;
+
+    /// Gets remaining time-to-live for the stream (requires "ttl" feature)
+    #[cfg(feature = "ttl")]
+    async fn ttl(&self) -> Result<Option<TimestampMillis>>;
+}
@@ -0,0 +1,142 @@
+//! Keyspace change notifications
+//!
+//! Successful write commands and expirations applied by the cleanup task are
+//! published as [`KeyspaceEvent`]s on a broadcast channel, subscribers filter
+//! them by a key pattern and only see events of their own namespace.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::error::StorageError;
+use super::sled_storage::{KeyType, Pattern, is_match};
+use super::{AsyncIterator, Key, Result};
+
+/// Kind of change applied to a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyspaceEventKind {
+    /// A key, map field or list item was written
+    Inserted,
+    /// A key, map field or list item was removed
+    Removed,
+    /// A key, map or list was removed by the expiration cleanup
+    Expired,
+    /// All fields of a map or all items of a list were removed
+    Cleared,
+    /// A key was evicted to stay within the storage limit
+    Evicted,
+}
+
+/// Change notification for a key, map or list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyspaceEvent {
+    /// Kind of change
+    pub kind: KeyspaceEventKind,
+    /// Type of the changed key
+    pub key_type: KeyType,
+    /// Key, map name or list name
+    pub key: Key,
+    /// Map field, or the removed prefix for `Map::remove_with_prefix`
+    pub field: Option<Key>,
+    /// Namespace of the key, `None` for the default namespace
+    pub namespace: Option<Key>,
+}
+
+impl KeyspaceEvent {
+    /// Creates an event without a field
+    #[inline]
+    pub(crate) fn new(kind: KeyspaceEventKind, key_type: KeyType, key: &[u8]) -> Self {
+        Self {
+            kind,
+            key_type,
+            key: key.to_vec(),
+            field: None,
+            namespace: None,
+        }
+    }
+
+    /// Creates a map field event
+    #[inline]
+    pub(crate) fn field(kind: KeyspaceEventKind, map: &[u8], field: &[u8]) -> Self {
+        Self {
+            kind,
+            key_type: KeyType::Map,
+            key: map.to_vec(),
+            field: Some(field.to_vec()),
+            namespace: None,
+        }
+    }
+}
+
+/// Publisher side of the keyspace notifications
+pub(crate) struct Keyspace {
+    tx: broadcast::Sender<KeyspaceEvent>,
+}
+
+impl Keyspace {
+    /// Creates a publisher keeping up to `capacity` undelivered events per subscriber
+    #[inline]
+    pub(crate) fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self { tx }
+    }
+
+    /// Checks if anyone listens, events are only built when this returns true
+    #[inline]
+    pub(crate) fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    /// Publishes events
+    #[inline]
+    pub(crate) fn publish(&self, events: Vec<KeyspaceEvent>) {
+        for event in events {
+            //an error only means that all subscribers are gone
+            let _ = self.tx.send(event);
+        }
+    }
+
+    /// Creates a subscriber for keys of `namespace` matching `pattern`
+    #[inline]
+    pub(crate) fn subscribe(&self, pattern: &[u8], namespace: Option<&[u8]>) -> KeyspaceSubscriber {
+        KeyspaceSubscriber {
+            rx: self.tx.subscribe(),
+            pattern: Pattern::parse(pattern),
+            namespace: namespace.map(|ns| ns.to_vec()),
+        }
+    }
+}
+
+/// Stream of keyspace events matching a pattern
+///
+/// Yields an error when the subscriber fell behind and events were dropped,
+/// after which it continues with the oldest retained event.
+pub struct KeyspaceSubscriber {
+    rx: broadcast::Receiver<KeyspaceEvent>,
+    pattern: Pattern,
+    namespace: Option<Key>,
+}
+
+#[async_trait]
+impl AsyncIterator for KeyspaceSubscriber {
+    type Item = Result<KeyspaceEvent>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.rx.recv().await {
+                Ok(event) => {
+                    if event.namespace == self.namespace
+                        && is_match(self.pattern.clone(), event.key.as_slice())
+                    {
+                        return Some(Ok(event));
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    return Some(Err(StorageError::Lagged(n)));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
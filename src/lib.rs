@@ -1,25 +1,63 @@
 //http://sled.rs/
 
 #![allow(dead_code)]
+mod backup;
+mod cleanup;
+mod clock;
+mod dump;
+mod error;
+mod eviction;
+#[cfg(feature = "ttl")]
+mod expire_hook;
 mod iface;
+mod keyspace;
+mod metrics;
+mod migrate;
+mod pubsub;
+mod quota;
+mod rdb;
 mod sled_config;
 mod sled_storage;
+mod slowlog;
 mod test;
 mod test_kv;
 mod test_list;
 mod test_map;
+mod test_rdb;
+mod test_stream;
+mod verify;
 
 use async_trait::async_trait;
+pub use backup::{BackupInfo, verify_backup};
+pub use cleanup::{Cleanup, CleanupFun, CleanupHandle, CleanupPolicy};
+pub use clock::{Clock, ManualClock, SystemClock};
 use core::fmt;
+pub use error::StorageError;
+pub use eviction::{EvictionInfo, EvictionPolicy, MaxMemory, MaxMemoryError};
+#[cfg(feature = "ttl")]
+pub use expire_hook::ExpiredValue;
 use iface::*;
-pub use iface::{List, Map};
+pub use iface::{List, Map, Stream};
+pub use keyspace::{KeyspaceEvent, KeyspaceEventKind, KeyspaceSubscriber};
+pub use metrics::{CommandMetrics, HistogramSnapshot, MetricsSnapshot};
+pub use migrate::{FORMAT_VERSION, MigrationProgress, MigrationReport, MigrationStep};
+pub use pubsub::{PubSub, PubSubMessage, PubSubSubscriber};
+pub use quota::{Quota, QuotaError, QuotaLimit, QuotaUsage};
+pub use rdb::RdbImportReport;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
-pub use sled_config::Config;
-use sled_storage::{SledStorageDB, SledStorageList, SledStorageMap};
+pub use sled_config::{Config, Durability, StorageMode};
+pub use sled_storage::{
+    CleanupInfo, CommandKind, KeyType, NamespaceInfo, StorageInfo, StreamEntry, StreamId,
+    StreamPendingEntry,
+};
+use sled_storage::{SledStorageDB, SledStorageList, SledStorageMap, SledStorageStream};
+pub use slowlog::SlowLogEntry;
+use std::time::Duration;
+pub use verify::{Inconsistency, VerifyReport};
 
 type TimestampMillis = i64;
-type Result<T> = anyhow::Result<T>;
+type Result<T> = std::result::Result<T, StorageError>;
 #[inline]
 fn timestamp_millis() -> TimestampMillis {
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -54,6 +92,31 @@ pub async fn init_db(cfg: &Config) -> Result<StorageDB> {
     Ok(db)
 }
 
+/// Restores a backup archive into a fresh database at `cfg.path` and opens it
+///
+/// The archive checksum is verified first and the target database must be empty.
+pub async fn restore_db<P: AsRef<std::path::Path>>(backup: P, cfg: &Config) -> Result<StorageDB> {
+    let db = cfg.to_sled_config()?.open()?;
+    backup::restore_backup(&db, backup.as_ref())?;
+    let db = SledStorageDB::from_db(db, cfg.clone()).await?;
+    Ok(StorageDB::Sled(db))
+}
+
+/// Migrates the database at `cfg.path` to the current format version without opening it
+///
+/// Opening a database migrates it as well, this allows a dry run first, which only
+/// counts the keys each step would change, and reports progress while running. A dry
+/// run stops counting after the first step that would change keys.
+pub async fn migrate_db<F>(cfg: &Config, dry_run: bool, mut progress: F) -> Result<MigrationReport>
+where
+    F: FnMut(&MigrationProgress),
+{
+    let db = cfg.to_sled_config()?.open()?;
+    let report = migrate::run_migrations(&db, dry_run, &mut progress)?;
+    db.flush_async().await?;
+    Ok(report)
+}
+
 #[derive(Clone)]
 pub enum StorageDB {
     Sled(SledStorageDB),
@@ -72,6 +135,19 @@ impl StorageDB {
         })
     }
 
+    /// Creates or accesses a named map whose time-to-live restarts on every read (requires "ttl" feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    pub async fn map_sliding<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        ttl: TimestampMillis,
+    ) -> Result<StorageMap> {
+        Ok(match self {
+            StorageDB::Sled(db) => StorageMap::Sled(db.map_sliding(name, ttl).await?),
+        })
+    }
+
     /// Removes a named map
     #[inline]
     pub async fn map_remove<K>(&self, name: K) -> Result<()>
@@ -103,6 +179,19 @@ impl StorageDB {
         })
     }
 
+    /// Creates or accesses a named list whose time-to-live restarts on every pop (requires "ttl" feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    pub async fn list_sliding<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        ttl: TimestampMillis,
+    ) -> Result<StorageList> {
+        Ok(match self {
+            StorageDB::Sled(db) => StorageList::Sled(db.list_sliding(name, ttl).await?),
+        })
+    }
+
     /// Removes a named list
     #[inline]
     pub async fn list_remove<K>(&self, name: K) -> Result<()>
@@ -122,6 +211,45 @@ impl StorageDB {
         }
     }
 
+    /// Accesses a named stream
+    #[inline]
+    pub async fn stream<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        expire: Option<TimestampMillis>,
+    ) -> Result<StorageStream> {
+        Ok(match self {
+            StorageDB::Sled(db) => StorageStream::Sled(db.stream(name, expire).await?),
+        })
+    }
+
+    /// Removes a named stream
+    #[inline]
+    pub async fn stream_remove<K>(&self, name: K) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageDB::Sled(db) => db.stream_remove(name).await,
+        }
+    }
+
+    /// Checks if stream exists
+    #[inline]
+    pub async fn stream_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+        match self {
+            StorageDB::Sled(db) => db.stream_contains_key(key).await,
+        }
+    }
+
+    /// Gets the type stored under a name
+    #[inline]
+    pub async fn type_of<K: AsRef<[u8]> + Sync + Send>(&self, name: K) -> Result<Option<KeyType>> {
+        match self {
+            StorageDB::Sled(db) => db.type_of(name).await,
+        }
+    }
+
     /// Inserts a key-value pair
     #[inline]
     pub async fn insert<K, V>(&self, key: K, val: &V) -> Result<()>
@@ -134,6 +262,19 @@ impl StorageDB {
         }
     }
 
+    /// Inserts a key-value pair whose time-to-live restarts on every read (requires "ttl" feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    pub async fn insert_sliding<K, V>(&self, key: K, val: &V, ttl: TimestampMillis) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+        V: Serialize + Sync + Send,
+    {
+        match self {
+            StorageDB::Sled(db) => db.insert_sliding(key, val, ttl).await,
+        }
+    }
+
     /// Retrieves a value by key
     #[inline]
     pub async fn get<K, V>(&self, key: K) -> Result<Option<V>>
@@ -178,7 +319,7 @@ impl StorageDB {
 
     /// Increments a counter
     #[inline]
-    pub async fn counter_incr<K>(&self, key: K, increment: isize) -> Result<()>
+    pub async fn counter_incr<K>(&self, key: K, increment: i64) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send,
     {
@@ -189,7 +330,7 @@ impl StorageDB {
 
     /// Decrements a counter
     #[inline]
-    pub async fn counter_decr<K>(&self, key: K, decrement: isize) -> Result<()>
+    pub async fn counter_decr<K>(&self, key: K, decrement: i64) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send,
     {
@@ -200,7 +341,7 @@ impl StorageDB {
 
     /// Gets counter value
     #[inline]
-    pub async fn counter_get<K>(&self, key: K) -> Result<Option<isize>>
+    pub async fn counter_get<K>(&self, key: K) -> Result<Option<i64>>
     where
         K: AsRef<[u8]> + Sync + Send,
     {
@@ -211,7 +352,7 @@ impl StorageDB {
 
     /// Sets counter value
     #[inline]
-    pub async fn counter_set<K>(&self, key: K, val: isize) -> Result<()>
+    pub async fn counter_set<K>(&self, key: K, val: i64) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send,
     {
@@ -322,6 +463,232 @@ impl StorageDB {
             StorageDB::Sled(db) => db.info().await,
         }
     }
+
+    /// Takes a snapshot of the per-command metrics
+    #[inline]
+    pub fn metrics(&self) -> MetricsSnapshot {
+        match self {
+            StorageDB::Sled(db) => db.metrics(),
+        }
+    }
+
+    /// Gets the handle to pause, trigger or stop the cleanup task
+    #[inline]
+    pub fn cleanup_handle(&self) -> CleanupHandle {
+        match self {
+            StorageDB::Sled(db) => db.cleanup_handle(),
+        }
+    }
+
+    /// Subscribes to changes of keys, maps and lists whose name matches `pattern`
+    #[inline]
+    pub fn subscribe<P: AsRef<[u8]>>(&self, pattern: P) -> KeyspaceSubscriber {
+        match self {
+            StorageDB::Sled(db) => db.subscribe(pattern),
+        }
+    }
+
+    /// Registers a hook called with the value of expired keys, maps and lists whose name
+    /// matches `pattern` before the cleanup task removes them (requires "ttl" feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    pub fn on_expire<P, F, Fut>(&self, pattern: P, hook: F)
+    where
+        P: AsRef<[u8]>,
+        F: Fn(Key, KeyType, Option<ExpiredValue>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        match self {
+            StorageDB::Sled(db) => db.on_expire(pattern, hook),
+        }
+    }
+
+    /// Gets a Pub/Sub handle for publishing and subscribing to channels
+    #[inline]
+    pub fn pubsub(&self) -> PubSub {
+        match self {
+            StorageDB::Sled(db) => PubSub::new(db.clone()),
+        }
+    }
+
+    /// Gets up to `n` slow-operation log entries, newest first
+    #[inline]
+    pub fn slowlog_get(&self, n: usize) -> Vec<SlowLogEntry> {
+        match self {
+            StorageDB::Sled(db) => db.slowlog_get(n),
+        }
+    }
+
+    /// Gets the number of slow-operation log entries
+    #[inline]
+    pub fn slowlog_len(&self) -> usize {
+        match self {
+            StorageDB::Sled(db) => db.slowlog_len(),
+        }
+    }
+
+    /// Clears the slow-operation log
+    #[inline]
+    pub fn slowlog_reset(&self) {
+        match self {
+            StorageDB::Sled(db) => db.slowlog_reset(),
+        }
+    }
+
+    /// Gets storage statistics as a typed struct
+    #[inline]
+    pub async fn storage_info(&self) -> Result<StorageInfo> {
+        match self {
+            StorageDB::Sled(db) => db.storage_info().await,
+        }
+    }
+
+    /// Flushes all dirty data to disk, returns the number of bytes flushed
+    #[inline]
+    pub async fn flush(&self) -> Result<usize> {
+        match self {
+            StorageDB::Sled(db) => db.flush().await,
+        }
+    }
+
+    /// Writes every key, map, list and stream to `writer` as JSON lines, values decoded as `V`
+    #[inline]
+    pub async fn dump_jsonl<V, W>(&self, writer: W) -> Result<u64>
+    where
+        V: Serialize + DeserializeOwned + 'static,
+        W: std::io::Write + Send + 'static,
+    {
+        match self {
+            StorageDB::Sled(db) => db.dump_jsonl::<V, W>(writer).await,
+        }
+    }
+
+    /// Loads a JSON-lines dump written by `dump_jsonl` with the same value type `V`
+    #[inline]
+    pub async fn load_jsonl<V, R>(&self, reader: R) -> Result<u64>
+    where
+        V: Serialize + DeserializeOwned + 'static,
+        R: std::io::BufRead + Send + 'static,
+    {
+        match self {
+            StorageDB::Sled(db) => db.load_jsonl::<V, R>(reader).await,
+        }
+    }
+
+    /// Imports a Redis RDB file, see `RdbImportReport` for what was imported
+    #[inline]
+    pub async fn import_rdb<R>(&self, reader: R) -> Result<RdbImportReport>
+    where
+        R: std::io::Read + Send + 'static,
+    {
+        match self {
+            StorageDB::Sled(db) => db.import_rdb(reader).await,
+        }
+    }
+
+    /// Checks map counts, list bounds, stream metadata and expiration entries for inconsistencies
+    #[inline]
+    pub async fn verify(&self) -> Result<VerifyReport> {
+        match self {
+            StorageDB::Sled(db) => db.verify().await,
+        }
+    }
+
+    /// Repairs the inconsistencies `verify` reports
+    #[inline]
+    pub async fn repair(&self) -> Result<VerifyReport> {
+        match self {
+            StorageDB::Sled(db) => db.repair().await,
+        }
+    }
+
+    /// Writes a point-in-time backup of all trees to a file or directory
+    #[inline]
+    pub async fn backup<P: AsRef<std::path::Path>>(&self, path: P) -> Result<BackupInfo> {
+        match self {
+            StorageDB::Sled(db) => db.backup(path).await,
+        }
+    }
+
+    /// Opens a handle on the namespace `name`, isolated from all other namespaces
+    #[inline]
+    pub fn namespace<N: AsRef<[u8]>>(&self, name: N) -> Result<StorageDB> {
+        match self {
+            StorageDB::Sled(db) => Ok(StorageDB::Sled(db.namespace(name)?)),
+        }
+    }
+
+    /// Returns the namespace of this handle, `None` for the default namespace
+    #[inline]
+    pub fn namespace_name(&self) -> Option<&[u8]> {
+        match self {
+            StorageDB::Sled(db) => db.namespace_name(),
+        }
+    }
+
+    /// Lists the namespaces that were ever opened, without the default namespace
+    #[inline]
+    pub fn namespace_names(&self) -> Vec<Key> {
+        match self {
+            StorageDB::Sled(db) => db.namespace_names(),
+        }
+    }
+
+    /// Gets the key counts of the default namespace and all other namespaces
+    #[inline]
+    pub async fn namespaces(&self) -> Result<Vec<NamespaceInfo>> {
+        match self {
+            StorageDB::Sled(db) => db.namespaces().await,
+        }
+    }
+
+    /// Sets or removes the quota of this handle's namespace
+    #[inline]
+    pub fn set_quota(&self, quota: Option<Quota>) -> Result<()> {
+        match self {
+            StorageDB::Sled(db) => db.set_quota(quota),
+        }
+    }
+
+    /// Gets the quota of this handle's namespace
+    #[inline]
+    pub fn quota(&self) -> Option<Quota> {
+        match self {
+            StorageDB::Sled(db) => db.quota(),
+        }
+    }
+
+    /// Gets the entries and value bytes of this handle's namespace that quotas apply to
+    #[inline]
+    pub async fn quota_usage(&self) -> Result<QuotaUsage> {
+        match self {
+            StorageDB::Sled(db) => db.quota_usage().await,
+        }
+    }
+
+    /// Removes all keys, maps, lists and streams of this handle's namespace
+    #[inline]
+    pub async fn flush_namespace(&self) -> Result<()> {
+        match self {
+            StorageDB::Sled(db) => db.flush_namespace().await,
+        }
+    }
+
+    /// Closes the database, queued commands are executed and data is flushed first
+    #[inline]
+    pub async fn close(&self) -> Result<()> {
+        match self {
+            StorageDB::Sled(db) => db.close().await,
+        }
+    }
+
+    /// Checks if the database has been closed
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        match self {
+            StorageDB::Sled(db) => db.is_closed(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -478,6 +845,36 @@ impl Map for StorageMap {
             StorageMap::Sled(m) => m.ttl().await,
         }
     }
+
+    #[cfg(feature = "ttl")]
+    async fn expire_field<K>(&self, key: K, dur: TimestampMillis) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageMap::Sled(m) => m.expire_field(key, dur).await,
+        }
+    }
+
+    #[cfg(feature = "ttl")]
+    async fn field_ttl<K>(&self, key: K) -> Result<Option<TimestampMillis>>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageMap::Sled(m) => m.field_ttl(key).await,
+        }
+    }
+
+    #[cfg(feature = "ttl")]
+    async fn persist_field<K>(&self, key: K) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageMap::Sled(m) => m.persist_field(key).await,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -614,3 +1011,218 @@ impl List for StorageList {
         }
     }
 }
+
+#[derive(Clone)]
+pub enum StorageStream {
+    /// Sled stream implementation
+    Sled(SledStorageStream),
+}
+
+impl fmt::Debug for StorageStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StorageStream::Sled(stream) => stream.name(),
+        };
+        f.debug_tuple(&format!(
+            "StorageStream({:?})",
+            String::from_utf8_lossy(name)
+        ))
+        .finish()
+    }
+}
+
+#[async_trait]
+impl Stream for StorageStream {
+    fn name(&self) -> &[u8] {
+        match self {
+            StorageStream::Sled(s) => s.name(),
+        }
+    }
+
+    async fn add<V>(&self, val: &V, maxlen: Option<usize>) -> Result<StreamId>
+    where
+        V: Serialize + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.add(val, maxlen).await,
+        }
+    }
+
+    async fn add_with_id<V>(&self, id: StreamId, val: &V, maxlen: Option<usize>) -> Result<StreamId>
+    where
+        V: Serialize + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.add_with_id(id, val, maxlen).await,
+        }
+    }
+
+    async fn range<V>(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        V: DeserializeOwned + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.range(start, end, count).await,
+        }
+    }
+
+    async fn rev_range<V>(
+        &self,
+        end: StreamId,
+        start: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        V: DeserializeOwned + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.rev_range(end, start, count).await,
+        }
+    }
+
+    async fn read<V>(
+        &self,
+        after: StreamId,
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        V: DeserializeOwned + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.read(after, count, block).await,
+        }
+    }
+
+    async fn len(&self) -> Result<usize> {
+        match self {
+            StorageStream::Sled(s) => s.len().await,
+        }
+    }
+
+    async fn is_empty(&self) -> Result<bool> {
+        match self {
+            StorageStream::Sled(s) => s.is_empty().await,
+        }
+    }
+
+    async fn last_id(&self) -> Result<Option<StreamId>> {
+        match self {
+            StorageStream::Sled(s) => s.last_id().await,
+        }
+    }
+
+    async fn delete(&self, ids: Vec<StreamId>) -> Result<usize> {
+        match self {
+            StorageStream::Sled(s) => s.delete(ids).await,
+        }
+    }
+
+    async fn trim(&self, maxlen: usize) -> Result<usize> {
+        match self {
+            StorageStream::Sled(s) => s.trim(maxlen).await,
+        }
+    }
+
+    async fn clear(&self) -> Result<()> {
+        match self {
+            StorageStream::Sled(s) => s.clear().await,
+        }
+    }
+
+    async fn group_create<G>(&self, group: G, start: Option<StreamId>) -> Result<bool>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.group_create(group, start).await,
+        }
+    }
+
+    async fn group_destroy<G>(&self, group: G) -> Result<bool>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.group_destroy(group).await,
+        }
+    }
+
+    async fn read_group<G, C, V>(
+        &self,
+        group: G,
+        consumer: C,
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+        C: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.read_group(group, consumer, count, block).await,
+        }
+    }
+
+    async fn ack<G>(&self, group: G, ids: Vec<StreamId>) -> Result<usize>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.ack(group, ids).await,
+        }
+    }
+
+    async fn pending<G>(&self, group: G) -> Result<Vec<StreamPendingEntry>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.pending(group).await,
+        }
+    }
+
+    async fn claim<G, C, V>(
+        &self,
+        group: G,
+        consumer: C,
+        min_idle: TimestampMillis,
+        ids: Vec<StreamId>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+        C: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
+    {
+        match self {
+            StorageStream::Sled(s) => s.claim(group, consumer, min_idle, ids).await,
+        }
+    }
+
+    #[cfg(feature = "ttl")]
+    async fn expire_at(&self, at: TimestampMillis) -> Result<bool> {
+        match self {
+            StorageStream::Sled(s) => s.expire_at(at).await,
+        }
+    }
+
+    #[cfg(feature = "ttl")]
+    async fn expire(&self, dur: TimestampMillis) -> Result<bool> {
+        match self {
+            StorageStream::Sled(s) => s.expire(dur).await,
+        }
+    }
+
+    #[cfg(feature = "ttl")]
+    async fn ttl(&self) -> Result<Option<TimestampMillis>> {
+        match self {
+            StorageStream::Sled(s) => s.ttl().await,
+        }
+    }
+}
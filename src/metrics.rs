@@ -0,0 +1,336 @@
+//! Command metrics for the storage layer
+//!
+//! Counts, errors and latency histograms are recorded per [`CommandKind`] by the
+//! background command processor, together with the time commands spend waiting
+//! in the command channel.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::sled_storage::CommandKind;
+
+/// Upper bounds of the latency histogram buckets in microseconds
+const BUCKET_BOUNDS_MICROS: [u64; 19] = [
+    10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000,
+    500_000, 1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// Number of histogram buckets, including the overflow bucket
+const BUCKETS: usize = BUCKET_BOUNDS_MICROS.len() + 1;
+
+/// Prefix of all exported metric names
+const METRIC_PREFIX: &str = "kv_storage";
+
+/// Latency histogram with fixed buckets
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [AtomicU64; BUCKETS],
+    /// Sum of all observations in microseconds
+    sum_micros: AtomicU64,
+    /// Number of observations
+    count: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    /// Records one observation
+    #[inline]
+    fn observe(&self, dur: Duration) {
+        let micros = dur.as_micros() as u64;
+        let idx = BUCKET_BOUNDS_MICROS.partition_point(|bound| *bound < micros);
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Takes a snapshot of the histogram
+    #[inline]
+    fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            bounds_micros: BUCKET_BOUNDS_MICROS.to_vec(),
+            counts: self
+                .buckets
+                .iter()
+                .map(|b| b.load(Ordering::Relaxed))
+                .collect(),
+            sum_micros: self.sum_micros.load(Ordering::Relaxed),
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters and latency histogram of one command kind
+#[derive(Default)]
+struct CommandStats {
+    /// Number of processed commands
+    count: AtomicU64,
+    /// Number of commands that returned an error
+    errors: AtomicU64,
+    /// Execution time of the commands
+    latency: Histogram,
+}
+
+/// Metrics recorded by the background command processor
+pub(crate) struct Metrics {
+    /// Statistics per command kind, indexed by `CommandKind as usize`
+    commands: [CommandStats; CommandKind::COUNT],
+    /// Time commands spent in the command channel
+    queue_wait: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            commands: std::array::from_fn(|_| CommandStats::default()),
+            queue_wait: Histogram::default(),
+        }
+    }
+}
+
+impl Metrics {
+    /// Records a processed command
+    #[inline]
+    pub(crate) fn observe_command(&self, kind: CommandKind, dur: Duration, failed: bool) {
+        let stats = &self.commands[kind as usize];
+        stats.count.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        stats.latency.observe(dur);
+    }
+
+    /// Records the time a command waited in the command channel
+    #[inline]
+    pub(crate) fn observe_queue_wait(&self, dur: Duration) {
+        self.queue_wait.observe(dur);
+    }
+
+    /// Gets the number of processed commands of a kind
+    #[inline]
+    pub(crate) fn count(&self, kind: CommandKind) -> u64 {
+        self.commands[kind as usize].count.load(Ordering::Relaxed)
+    }
+
+    /// Takes a snapshot of all metrics
+    #[inline]
+    pub(crate) fn snapshot(&self, active_count: isize, queue_depth: usize) -> MetricsSnapshot {
+        let commands = CommandKind::ALL
+            .iter()
+            .map(|kind| {
+                let stats = &self.commands[*kind as usize];
+                CommandMetrics {
+                    kind: *kind,
+                    count: stats.count.load(Ordering::Relaxed),
+                    errors: stats.errors.load(Ordering::Relaxed),
+                    latency: stats.latency.snapshot(),
+                }
+            })
+            .collect();
+        MetricsSnapshot {
+            active_count,
+            queue_depth,
+            commands,
+            queue_wait: self.queue_wait.snapshot(),
+        }
+    }
+}
+
+/// Snapshot of a latency histogram
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistogramSnapshot {
+    /// Upper bounds of the buckets in microseconds, the last bucket is unbounded
+    pub bounds_micros: Vec<u64>,
+    /// Observations per bucket, not cumulative, one more than `bounds_micros`
+    pub counts: Vec<u64>,
+    /// Sum of all observations in microseconds
+    pub sum_micros: u64,
+    /// Number of observations
+    pub count: u64,
+}
+
+impl HistogramSnapshot {
+    /// Gets the mean of all observations
+    #[inline]
+    pub fn mean(&self) -> Duration {
+        self.sum_micros
+            .checked_div(self.count)
+            .map(Duration::from_micros)
+            .unwrap_or_default()
+    }
+
+    /// Gets the upper bound of the bucket containing the given quantile (0.0 - 1.0)
+    ///
+    /// Returns `None` if there are no observations or the quantile falls into the
+    /// unbounded bucket.
+    #[inline]
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((self.count as f64) * q.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (idx, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return self
+                    .bounds_micros
+                    .get(idx)
+                    .map(|micros| Duration::from_micros(*micros));
+            }
+        }
+        None
+    }
+}
+
+/// Metrics of one command kind
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandMetrics {
+    /// Command kind
+    pub kind: CommandKind,
+    /// Number of processed commands
+    pub count: u64,
+    /// Number of commands that returned an error
+    pub errors: u64,
+    /// Execution time of the commands
+    pub latency: HistogramSnapshot,
+}
+
+/// Snapshot of the storage metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    /// Number of commands sent but not yet completed
+    pub active_count: isize,
+    /// Number of commands waiting in the command channel
+    pub queue_depth: usize,
+    /// Metrics per command kind
+    pub commands: Vec<CommandMetrics>,
+    /// Time commands spent in the command channel
+    pub queue_wait: HistogramSnapshot,
+}
+
+impl MetricsSnapshot {
+    /// Gets the metrics of a command kind
+    #[inline]
+    pub fn command(&self, kind: CommandKind) -> &CommandMetrics {
+        &self.commands[kind as usize]
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format
+    ///
+    /// Command kinds that were never executed are omitted.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let used = || self.commands.iter().filter(|c| c.count > 0);
+
+        write_header(
+            &mut out,
+            "commands_total",
+            "counter",
+            "Number of processed commands.",
+        );
+        for c in used() {
+            let _ = writeln!(
+                out,
+                "{METRIC_PREFIX}_commands_total{{command=\"{}\"}} {}",
+                c.kind, c.count
+            );
+        }
+
+        write_header(
+            &mut out,
+            "command_errors_total",
+            "counter",
+            "Number of commands that returned an error.",
+        );
+        for c in used() {
+            let _ = writeln!(
+                out,
+                "{METRIC_PREFIX}_command_errors_total{{command=\"{}\"}} {}",
+                c.kind, c.errors
+            );
+        }
+
+        write_header(
+            &mut out,
+            "command_duration_seconds",
+            "histogram",
+            "Command execution time in the background processor.",
+        );
+        for c in used() {
+            let label = format!("command=\"{}\"", c.kind);
+            write_histogram(&mut out, "command_duration_seconds", &label, &c.latency);
+        }
+
+        write_header(
+            &mut out,
+            "queue_wait_seconds",
+            "histogram",
+            "Time commands spent waiting in the command channel.",
+        );
+        write_histogram(&mut out, "queue_wait_seconds", "", &self.queue_wait);
+
+        write_header(
+            &mut out,
+            "active_commands",
+            "gauge",
+            "Number of commands sent but not yet completed.",
+        );
+        let _ = writeln!(out, "{METRIC_PREFIX}_active_commands {}", self.active_count);
+
+        write_header(
+            &mut out,
+            "queue_depth",
+            "gauge",
+            "Number of commands waiting in the command channel.",
+        );
+        let _ = writeln!(out, "{METRIC_PREFIX}_queue_depth {}", self.queue_depth);
+        out
+    }
+}
+
+/// Writes the HELP and TYPE lines of a metric
+#[inline]
+fn write_header(out: &mut String, name: &str, typ: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {METRIC_PREFIX}_{name} {help}");
+    let _ = writeln!(out, "# TYPE {METRIC_PREFIX}_{name} {typ}");
+}
+
+/// Writes the bucket, sum and count series of a histogram
+fn write_histogram(out: &mut String, name: &str, label: &str, h: &HistogramSnapshot) {
+    let sep = if label.is_empty() { "" } else { "," };
+    let mut cumulative = 0;
+    for (idx, c) in h.counts.iter().enumerate() {
+        cumulative += c;
+        let le = match h.bounds_micros.get(idx) {
+            Some(micros) => format!("{}", *micros as f64 / 1_000_000.0),
+            None => "+Inf".into(),
+        };
+        let _ = writeln!(
+            out,
+            "{METRIC_PREFIX}_{name}_bucket{{{label}{sep}le=\"{le}\"}} {cumulative}"
+        );
+    }
+    let braced = if label.is_empty() {
+        String::new()
+    } else {
+        format!("{{{label}}}")
+    };
+    let _ = writeln!(
+        out,
+        "{METRIC_PREFIX}_{name}_sum{braced} {}",
+        h.sum_micros as f64 / 1_000_000.0
+    );
+    let _ = writeln!(out, "{METRIC_PREFIX}_{name}_count{braced} {}", h.count);
+}
@@ -0,0 +1,321 @@
+//! On-disk format versioning
+//!
+//! The format version is kept in a metadata tree. Opening a database runs the
+//! registered migration steps from the stored version up to `FORMAT_VERSION`,
+//! in order, and refuses databases written by a newer release.
+//!
+//! Steps that rewrite keys write the migrated content into staging trees and
+//! leave the original trees untouched. Once a step completes, the staging trees
+//! replace the original ones in bounded batches, and only then is the new version
+//! stored. An interrupted step is run again from scratch, an interrupted
+//! replacement is finished when the database is opened next.
+
+use super::error::StorageError;
+use serde::{Deserialize, Serialize};
+use sled::Batch;
+
+use super::Result;
+use super::sled_storage::SledStorageDB;
+
+/// Tree for database metadata
+pub(crate) const META_TREE: &[u8] = b"__meta_tree@";
+/// Metadata key holding the format version
+const FORMAT_VERSION_KEY: &[u8] = b"format_version";
+/// Format version written by this release
+pub const FORMAT_VERSION: u32 = 5;
+/// Metadata key set while staging trees replace the original ones, holds the version they produce
+const REPLACE_KEY: &[u8] = b"migration_replace";
+/// Prefix of staging trees, followed by the name of the tree they replace
+const STAGING_PREFIX: &[u8] = b"__migrate@";
+/// Number of keys between two progress reports
+const PROGRESS_INTERVAL: u64 = 10_000;
+/// Number of keys written per batch while replacing a tree or rewriting keys in place
+pub(crate) const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+/// A step migrating the database from one format version to the next
+pub(crate) struct Migration {
+    /// Version this step migrates from, it produces `from + 1`
+    pub(crate) from: u32,
+    /// Short description of the step
+    pub(crate) name: &'static str,
+    /// Runs the step, returns the number of changed keys
+    pub(crate) run: fn(&mut MigrationContext) -> Result<u64>,
+}
+
+/// Registered migration steps, ordered by version
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        name: "length-prefixed map and list names",
+        run: SledStorageDB::migrate_key_layout,
+    },
+    Migration {
+        from: 1,
+        name: "fixed-width map counts and list indexes",
+        run: SledStorageDB::migrate_fixed_width,
+    },
+    Migration {
+        from: 2,
+        name: "expiration entries keyed by type",
+        run: SledStorageDB::migrate_expire_keys,
+    },
+    Migration {
+        from: 3,
+        name: "length-prefixed stream names",
+        run: SledStorageDB::migrate_stream_names,
+    },
+    Migration {
+        from: 4,
+        name: "type index of keys, maps, lists and streams",
+        run: SledStorageDB::migrate_type_index,
+    },
+];
+
+/// Progress of a running migration step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationProgress {
+    /// Step description
+    pub step: String,
+    /// Version the step migrates from
+    pub from: u32,
+    /// Number of keys processed so far
+    pub processed: u64,
+}
+
+/// A migration step that was run, or would run in a dry run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationStep {
+    /// Step description
+    pub name: String,
+    /// Version the step migrates from
+    pub from: u32,
+    /// Version the step migrates to
+    pub to: u32,
+    /// Number of changed keys
+    pub changed: u64,
+}
+
+/// Result of running the migrations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationReport {
+    /// Format version found on disk
+    pub from_version: u32,
+    /// Format version after the migration
+    pub to_version: u32,
+    /// Whether nothing was written
+    pub dry_run: bool,
+    /// Steps in the order they ran
+    pub steps: Vec<MigrationStep>,
+    /// Steps a dry run did not count, they depend on keys an earlier step would change
+    pub uncounted: Vec<String>,
+}
+
+/// State handed to a migration step
+pub(crate) struct MigrationContext<'a> {
+    /// Database being migrated
+    pub(crate) db: &'a sled::Db,
+    /// Whether changes must only be counted
+    pub(crate) dry_run: bool,
+    step: &'static Migration,
+    processed: u64,
+    progress: &'a mut dyn FnMut(&MigrationProgress),
+}
+
+impl MigrationContext<'_> {
+    /// Version the running step produces
+    #[inline]
+    pub(crate) fn to_version(&self) -> u32 {
+        self.step.from + 1
+    }
+
+    /// Opens an empty staging tree, which replaces `tree` once the step completes
+    #[inline]
+    pub(crate) fn staging_tree(&self, tree: &[u8]) -> Result<sled::Tree> {
+        let name = [STAGING_PREFIX, tree].concat();
+        //left over by an interrupted run of the step
+        self.db.drop_tree(&name)?;
+        Ok(self.db.open_tree(name)?)
+    }
+
+    /// Counts a processed key, reporting progress every `PROGRESS_INTERVAL` keys
+    #[inline]
+    pub(crate) fn tick(&mut self) {
+        self.processed += 1;
+        if self.processed.is_multiple_of(PROGRESS_INTERVAL) {
+            self.report();
+        }
+    }
+
+    #[inline]
+    fn report(&mut self) {
+        (self.progress)(&MigrationProgress {
+            step: self.step.name.to_string(),
+            from: self.step.from,
+            processed: self.processed,
+        });
+    }
+}
+
+/// Reads the stored format version
+///
+/// Databases without a version are either new, or written before versioning
+/// was introduced if any tree holds data.
+#[inline]
+fn stored_version(db: &sled::Db, meta_tree: &sled::Tree) -> Result<u32> {
+    if let Some(v) = meta_tree.get(FORMAT_VERSION_KEY)? {
+        let v = v
+            .as_ref()
+            .try_into()
+            .map_err(|_| StorageError::codec("invalid format version"))?;
+        return Ok(u32::from_be_bytes(v));
+    }
+    for name in db.tree_names() {
+        if name.as_ref() != META_TREE && !db.open_tree(&name)?.is_empty() {
+            return Ok(0);
+        }
+    }
+    Ok(FORMAT_VERSION)
+}
+
+/// Replaces every tree that has a staging tree by the staging tree's content
+fn replace_staged(db: &sled::Db) -> Result<()> {
+    for staging_name in db.tree_names() {
+        let Some(name) = staging_name.strip_prefix(STAGING_PREFIX) else {
+            continue;
+        };
+        let tree = db.open_tree(name)?;
+        loop {
+            let mut batch = Batch::default();
+            let mut len = 0;
+            for key in tree.iter().keys().take(MIGRATION_BATCH_SIZE) {
+                batch.remove(key?);
+                len += 1;
+            }
+            if len == 0 {
+                break;
+            }
+            tree.apply_batch(batch)?;
+        }
+        let mut batch = Batch::default();
+        let mut len = 0;
+        for item in db.open_tree(&staging_name)?.iter() {
+            let (key, value) = item?;
+            batch.insert(key, value);
+            len += 1;
+            if len == MIGRATION_BATCH_SIZE {
+                tree.apply_batch(std::mem::take(&mut batch))?;
+                len = 0;
+            }
+        }
+        tree.apply_batch(batch)?;
+        tree.flush()?;
+        db.drop_tree(&staging_name)?;
+    }
+    Ok(())
+}
+
+/// Stores the version a step produced, replacing the original trees by its staging trees first
+fn complete_step(db: &sled::Db, meta_tree: &sled::Tree, version: u32) -> Result<()> {
+    let staged = db
+        .tree_names()
+        .iter()
+        .any(|name| name.starts_with(STAGING_PREFIX));
+    if staged {
+        meta_tree.insert(REPLACE_KEY, version.to_be_bytes().as_slice())?;
+        db.flush()?;
+        replace_staged(db)?;
+    }
+    let mut batch = Batch::default();
+    batch.insert(FORMAT_VERSION_KEY, version.to_be_bytes().as_slice());
+    batch.remove(REPLACE_KEY);
+    meta_tree.apply_batch(batch)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// Finishes a tree replacement interrupted by a crash, or drops the staging trees of an
+/// interrupted step
+fn recover(db: &sled::Db, meta_tree: &sled::Tree) -> Result<()> {
+    match meta_tree.get(REPLACE_KEY)? {
+        Some(v) => {
+            let v = v
+                .as_ref()
+                .try_into()
+                .map_err(|_| StorageError::codec("invalid migration version"))?;
+            complete_step(db, meta_tree, u32::from_be_bytes(v))
+        }
+        None => {
+            for name in db.tree_names() {
+                if name.starts_with(STAGING_PREFIX) {
+                    db.drop_tree(&name)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Runs the migration steps from the stored format version up to `FORMAT_VERSION`
+///
+/// In a dry run the steps only count the keys they would change. Steps after one that
+/// would change keys are not counted, they are listed in `MigrationReport::uncounted`.
+pub(crate) fn run_migrations(
+    db: &sled::Db,
+    dry_run: bool,
+    progress: &mut dyn FnMut(&MigrationProgress),
+) -> Result<MigrationReport> {
+    let meta_tree = db.open_tree(META_TREE)?;
+    if !dry_run {
+        recover(db, &meta_tree)?;
+    }
+    let from_version = stored_version(db, &meta_tree)?;
+    if from_version > FORMAT_VERSION {
+        return Err(StorageError::codec(format!(
+            "database format version {} is newer than the supported version {}",
+            from_version, FORMAT_VERSION
+        )));
+    }
+
+    let mut report = MigrationReport {
+        from_version,
+        to_version: from_version,
+        dry_run,
+        steps: Vec::new(),
+        uncounted: Vec::new(),
+    };
+    for step in MIGRATIONS.iter().filter(|m| m.from >= from_version) {
+        //counting a step against keys an earlier step would have rewritten is meaningless
+        if report
+            .steps
+            .last()
+            .is_some_and(|s| dry_run && s.changed > 0)
+        {
+            report.uncounted.push(step.name.to_string());
+            continue;
+        }
+        let mut ctx = MigrationContext {
+            db,
+            dry_run,
+            step,
+            processed: 0,
+            progress: &mut *progress,
+        };
+        let changed = (step.run)(&mut ctx)?;
+        ctx.report();
+        if !dry_run {
+            complete_step(db, &meta_tree, ctx.to_version())?;
+        }
+        report.to_version = ctx.to_version();
+        report.steps.push(MigrationStep {
+            name: step.name.to_string(),
+            from: step.from,
+            to: ctx.to_version(),
+            changed,
+        });
+    }
+    if !dry_run {
+        meta_tree.insert(FORMAT_VERSION_KEY, FORMAT_VERSION.to_be_bytes().as_slice())?;
+        db.flush()?;
+    }
+    Ok(report)
+}
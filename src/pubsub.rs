@@ -0,0 +1,279 @@
+//! Redis-style Pub/Sub between tasks sharing a storage handle
+//!
+//! Messages are fanned out over a broadcast channel. Optionally the last N
+//! messages per channel are kept in an internal tree, so late subscribers can
+//! read the history.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::error::StorageError;
+use super::sled_storage::{Pattern, SledStorageDB, is_match};
+use super::{AsyncIterator, Key, Result, TimestampMillis};
+
+/// Message published to a channel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PubSubMessage {
+    /// Channel the message was published to
+    pub channel: Key,
+    /// Encoded message value
+    pub payload: Vec<u8>,
+    /// Publish time
+    pub timestamp: TimestampMillis,
+}
+
+impl PubSubMessage {
+    /// Decodes the message value
+    #[inline]
+    pub fn value<V: DeserializeOwned>(&self) -> Result<V> {
+        Ok(bincode::deserialize::<V>(self.payload.as_ref())?)
+    }
+}
+
+/// Broadcast channel shared by all handles of a database
+pub(crate) struct PubSubHub {
+    tx: broadcast::Sender<PubSubMessage>,
+    history_len: usize,
+    /// Internal tree holding the channel history, keyed by length-prefixed channel and sequence
+    history: sled::Tree,
+    /// Channel selection of the live subscribers, by subscriber ID
+    filters: RwLock<HashMap<u64, ChannelFilter>>,
+    next_id: AtomicU64,
+}
+
+impl PubSubHub {
+    /// Creates a hub buffering up to `capacity` messages per subscriber
+    #[inline]
+    pub(crate) fn new(capacity: usize, history_len: usize, history: sled::Tree) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self {
+            tx,
+            history_len,
+            history,
+            filters: RwLock::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a subscriber receiving the messages selected by `filter`
+    #[inline]
+    fn subscribe(self: &Arc<Self>, filter: ChannelFilter) -> PubSubSubscriber {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.filters
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, filter.clone());
+        PubSubSubscriber {
+            rx: self.tx.subscribe(),
+            filter,
+            hub: self.clone(),
+            id,
+        }
+    }
+
+    /// Counts the live subscribers whose channel or pattern matches `channel`
+    #[inline]
+    fn subscribers(&self, channel: &[u8]) -> usize {
+        self.filters
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .filter(|filter| filter.matches(channel))
+            .count()
+    }
+
+    /// Creates the history key prefix of a channel
+    #[inline]
+    fn history_prefix(channel: &[u8]) -> Key {
+        [(channel.len() as u32).to_be_bytes().as_slice(), channel].concat()
+    }
+
+    /// Appends a message to the history of its channel, dropping the oldest beyond the limit
+    #[inline]
+    pub(crate) fn _history_append(&self, msg: &PubSubMessage) -> Result<()> {
+        let prefix = Self::history_prefix(&msg.channel);
+        let keys = self
+            .history
+            .scan_prefix(prefix.as_slice())
+            .keys()
+            .collect::<sled::Result<Vec<_>>>()?;
+        let seq = match keys.last() {
+            Some(key) => u64::from_be_bytes(key[prefix.len()..].try_into()?) + 1,
+            None => 0,
+        };
+        let mut batch = sled::Batch::default();
+        for key in keys
+            .iter()
+            .take((keys.len() + 1).saturating_sub(self.history_len))
+        {
+            batch.remove(key);
+        }
+        batch.insert(
+            [prefix.as_slice(), seq.to_be_bytes().as_slice()].concat(),
+            bincode::serialize(msg)?,
+        );
+        self.history.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Gets the retained messages of a channel, oldest first
+    #[inline]
+    pub(crate) fn _history(&self, channel: &[u8]) -> Result<Vec<PubSubMessage>> {
+        self.history
+            .scan_prefix(Self::history_prefix(channel))
+            .values()
+            .map(|v| Ok(bincode::deserialize(v?.as_ref())?))
+            .collect()
+    }
+
+    /// Removes the retained messages of a channel
+    #[inline]
+    pub(crate) fn _history_clear(&self, channel: &[u8]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for key in self
+            .history
+            .scan_prefix(Self::history_prefix(channel))
+            .keys()
+        {
+            batch.remove(key?);
+        }
+        self.history.apply_batch(batch)?;
+        Ok(())
+    }
+}
+
+/// Pub/Sub handle, obtained with [`StorageDB::pubsub`](super::StorageDB::pubsub)
+#[derive(Clone)]
+pub struct PubSub {
+    hub: Arc<PubSubHub>,
+    db: SledStorageDB,
+}
+
+impl PubSub {
+    /// Creates a handle
+    #[inline]
+    pub(crate) fn new(db: SledStorageDB) -> Self {
+        Self {
+            hub: db.pubsub_hub(),
+            db,
+        }
+    }
+
+    /// Publishes a value to a channel
+    ///
+    /// Returns the number of subscribers whose channel or pattern matches.
+    pub async fn publish<C, V>(&self, channel: C, val: &V) -> Result<usize>
+    where
+        C: AsRef<[u8]> + Sync + Send,
+        V: Serialize + Sync + Send,
+    {
+        let msg = PubSubMessage {
+            channel: channel.as_ref().to_vec(),
+            payload: bincode::serialize(val)?,
+            timestamp: self.db.now(),
+        };
+        if self.hub.history_len > 0 {
+            self.db.pubsub_append(msg.clone()).await?;
+        }
+        let receivers = self.hub.subscribers(channel.as_ref());
+        let _ = self.hub.tx.send(msg);
+        Ok(receivers)
+    }
+
+    /// Subscribes to a single channel
+    #[inline]
+    pub fn subscribe<C: AsRef<[u8]>>(&self, channel: C) -> PubSubSubscriber {
+        self.hub
+            .subscribe(ChannelFilter::Channel(channel.as_ref().to_vec()))
+    }
+
+    /// Subscribes to all channels matching a pattern (supports * and ? wildcards)
+    #[inline]
+    pub fn psubscribe<P: AsRef<[u8]>>(&self, pattern: P) -> PubSubSubscriber {
+        self.hub
+            .subscribe(ChannelFilter::Pattern(Pattern::parse(pattern.as_ref())))
+    }
+
+    /// Gets the retained messages of a channel, oldest first
+    pub async fn history<C: AsRef<[u8]> + Sync + Send>(
+        &self,
+        channel: C,
+    ) -> Result<Vec<PubSubMessage>> {
+        if self.hub.history_len == 0 {
+            return Ok(Vec::new());
+        }
+        self.db.pubsub_history(channel.as_ref()).await
+    }
+
+    /// Removes the retained messages of a channel
+    pub async fn clear_history<C: AsRef<[u8]> + Sync + Send>(&self, channel: C) -> Result<()> {
+        self.db.pubsub_clear_history(channel.as_ref()).await
+    }
+}
+
+/// Channel selection of a subscriber
+#[derive(Clone)]
+enum ChannelFilter {
+    Channel(Key),
+    Pattern(Pattern),
+}
+
+impl ChannelFilter {
+    /// Checks if a channel is selected
+    #[inline]
+    fn matches(&self, channel: &[u8]) -> bool {
+        match self {
+            ChannelFilter::Channel(c) => c.as_slice() == channel,
+            ChannelFilter::Pattern(p) => is_match(p.clone(), channel),
+        }
+    }
+}
+
+/// Stream of messages published to the subscribed channels
+///
+/// Yields an error when the subscriber fell behind and messages were dropped,
+/// after which it continues with the oldest retained message.
+pub struct PubSubSubscriber {
+    rx: broadcast::Receiver<PubSubMessage>,
+    filter: ChannelFilter,
+    hub: Arc<PubSubHub>,
+    id: u64,
+}
+
+impl Drop for PubSubSubscriber {
+    fn drop(&mut self) {
+        self.hub
+            .filters
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+#[async_trait]
+impl AsyncIterator for PubSubSubscriber {
+    type Item = Result<PubSubMessage>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.rx.recv().await {
+                Ok(msg) => {
+                    if self.filter.matches(msg.channel.as_slice()) {
+                        return Some(Ok(msg));
+                    }
+                }
+                Err(RecvError::Lagged(n)) => {
+                    return Some(Err(StorageError::Lagged(n)));
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
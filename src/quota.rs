@@ -0,0 +1,266 @@
+//! Namespace quotas
+//!
+//! A quota limits the entries of one namespace: key-value pairs, map fields and
+//! list items count as entries, their values towards the stored bytes. Writes
+//! that would exceed a quota are refused with `QuotaError`.
+//!
+//! Usage is counted by the first write checked after the namespace was opened or
+//! its quota set, writes and removals then keep it exact. Writes are refused from
+//! that usage alone, a write that fails gives its reservation back.
+
+use std::fmt;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Key, Result};
+
+/// Limits on the entries of a namespace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    /// Number of key-value pairs, map fields and list items
+    pub max_keys: Option<u64>,
+    /// Total size of their values in bytes
+    pub max_bytes: Option<u64>,
+    /// Size of a single value in bytes
+    pub max_value_size: Option<u64>,
+}
+
+/// Entries and value bytes stored in a namespace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    /// Number of key-value pairs, map fields and list items
+    pub keys: u64,
+    /// Total size of their values in bytes
+    pub bytes: u64,
+}
+
+/// Which quota limit a refused write exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaLimit {
+    /// `max_keys`
+    Keys,
+    /// `max_bytes`
+    Bytes,
+    /// `max_value_size`
+    ValueSize,
+}
+
+/// Error returned for writes exceeding a namespace quota
+///
+/// `QuotaLimit::ValueSize` rejects the request itself, the other limits mean the
+/// namespace is full until entries are removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaError {
+    /// Namespace of the write, `None` for the default namespace
+    pub namespace: Option<Key>,
+    /// Exceeded limit
+    pub limit: QuotaLimit,
+    /// Stored entries or bytes, or the size of the refused value
+    pub used: u64,
+    /// Configured maximum
+    pub max: u64,
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let namespace = match &self.namespace {
+            Some(name) => String::from_utf8_lossy(name).into_owned(),
+            None => "default".into(),
+        };
+        let what = match self.limit {
+            QuotaLimit::Keys => "key",
+            QuotaLimit::Bytes => "byte",
+            QuotaLimit::ValueSize => "value size",
+        };
+        write!(
+            f,
+            "write refused, {} quota of namespace {} exceeded ({} of {})",
+            what, namespace, self.used, self.max
+        )
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// Quota and usage of a namespace, shared by all handles on it
+pub(crate) struct NamespaceQuota {
+    namespace: Option<Key>,
+    quota: RwLock<Option<Quota>>,
+    keys: AtomicU64,
+    bytes: AtomicU64,
+    /// Whether `keys` and `bytes` were counted since the quota was set
+    counted: AtomicBool,
+}
+
+impl NamespaceQuota {
+    #[inline]
+    pub(crate) fn new(namespace: Option<Key>, quota: Option<Quota>) -> Self {
+        Self {
+            namespace,
+            quota: RwLock::new(quota),
+            keys: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            counted: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self) -> Option<Quota> {
+        *self.quota.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replaces the quota, usage is counted again by the next write
+    #[inline]
+    pub(crate) fn set(&self, quota: Option<Quota>) {
+        *self.quota.write().unwrap_or_else(|e| e.into_inner()) = quota;
+        self.invalidate();
+    }
+
+    /// Makes the next write count the namespace again
+    #[inline]
+    pub(crate) fn invalidate(&self) {
+        self.counted.store(false, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.get().is_some()
+    }
+
+    #[inline]
+    fn set_usage(&self, usage: QuotaUsage) {
+        self.keys.store(usage.keys, Ordering::Relaxed);
+        self.bytes.store(usage.bytes, Ordering::Relaxed);
+        self.counted.store(true, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn usage(&self) -> QuotaUsage {
+        QuotaUsage {
+            keys: self.keys.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Adds a write to the usage, refusing it if it exceeds the quota
+    ///
+    /// `keys` and `bytes` are the changes the write makes, `value_size` is its
+    /// largest value. `count` counts the namespace, it is only called by the first
+    /// write after the usage was invalidated. The reservation is given back when
+    /// dropped without `QuotaReservation::commit`.
+    pub(crate) fn reserve<F>(
+        &self,
+        keys: i64,
+        bytes: i64,
+        value_size: u64,
+        count: F,
+    ) -> Result<QuotaReservation<'_>>
+    where
+        F: Fn() -> Result<QuotaUsage>,
+    {
+        let Some(quota) = self.get() else {
+            return Ok(QuotaReservation::default());
+        };
+        let error = |limit, used, max| QuotaError {
+            namespace: self.namespace.clone(),
+            limit,
+            used,
+            max,
+        };
+        if let Some(max) = quota.max_value_size
+            && value_size > max
+        {
+            return Err(error(QuotaLimit::ValueSize, value_size, max).into());
+        }
+        if !self.counted.load(Ordering::Relaxed) {
+            self.set_usage(count()?);
+        }
+        let usage = self.usage();
+        let over = |used: u64, added: i64, max: Option<u64>| match max {
+            Some(max) if added > 0 && used.saturating_add(added as u64) > max => Some(max),
+            _ => None,
+        };
+        if let Some(max) = over(usage.keys, keys, quota.max_keys) {
+            return Err(error(QuotaLimit::Keys, usage.keys, max).into());
+        }
+        if let Some(max) = over(usage.bytes, bytes, quota.max_bytes) {
+            return Err(error(QuotaLimit::Bytes, usage.bytes, max).into());
+        }
+        self.adjust(keys, bytes);
+        Ok(QuotaReservation {
+            quota: Some(self),
+            keys,
+            bytes,
+        })
+    }
+
+    /// Takes removed entries off the usage
+    #[inline]
+    pub(crate) fn release(&self, removed: QuotaUsage) {
+        if removed != QuotaUsage::default() {
+            self.adjust(
+                -(removed.keys.min(i64::MAX as u64) as i64),
+                -(removed.bytes.min(i64::MAX as u64) as i64),
+            );
+        }
+    }
+
+    /// Checks if removals have to be taken off the usage
+    #[inline]
+    pub(crate) fn is_counted(&self) -> bool {
+        self.counted.load(Ordering::Relaxed)
+    }
+
+    /// Changes the usage, unless it still has to be counted
+    #[inline]
+    fn adjust(&self, keys: i64, bytes: i64) {
+        if !self.is_counted() {
+            return;
+        }
+        let add = |counter: &AtomicU64, n: i64| {
+            let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_add_signed(n))
+            });
+        };
+        add(&self.keys, keys);
+        add(&self.bytes, bytes);
+    }
+
+    /// Counts the namespace again and returns its usage
+    #[inline]
+    pub(crate) fn current<F>(&self, count: F) -> Result<QuotaUsage>
+    where
+        F: Fn() -> Result<QuotaUsage>,
+    {
+        self.set_usage(count()?);
+        Ok(self.usage())
+    }
+}
+
+/// Usage reserved for a write, given back when dropped before the write committed
+#[must_use]
+#[derive(Default)]
+pub(crate) struct QuotaReservation<'a> {
+    quota: Option<&'a NamespaceQuota>,
+    keys: i64,
+    bytes: i64,
+}
+
+impl QuotaReservation<'_> {
+    /// Keeps the reserved usage, the write succeeded
+    #[inline]
+    pub(crate) fn commit(mut self) {
+        self.quota = None;
+    }
+}
+
+impl Drop for QuotaReservation<'_> {
+    fn drop(&mut self) {
+        if let Some(quota) = self.quota.take() {
+            quota.adjust(-self.keys, -self.bytes);
+        }
+    }
+}
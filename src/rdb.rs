@@ -0,0 +1,891 @@
+//! Redis RDB file parser
+//!
+//! Reads RDB versions 6 to 11 (Redis 3.2 to 7.2) for `import_rdb`. Strings, lists,
+//! sets, sorted sets and hashes are read in their plain encodings as well as the
+//! zipmap, ziplist, intset, quicklist and listpack encodings, LZF-compressed and
+//! integer-encoded strings included. Streams and module values are skipped, the
+//! CRC64 checksum at the end of the file is verified.
+
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read};
+
+use super::error::StorageError;
+use serde::{Deserialize, Serialize};
+
+use super::dump::DumpEntry;
+use super::{Key, Result, TimestampMillis};
+
+/// Oldest supported RDB version (Redis 3.2)
+const RDB_VERSION_MIN: u32 = 6;
+/// Newest supported RDB version (Redis 7.2)
+const RDB_VERSION_MAX: u32 = 11;
+
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_ZSET: u8 = 3;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+const RDB_TYPE_MODULE_2: u8 = 7;
+const RDB_TYPE_HASH_ZIPMAP: u8 = 9;
+const RDB_TYPE_LIST_ZIPLIST: u8 = 10;
+const RDB_TYPE_SET_INTSET: u8 = 11;
+const RDB_TYPE_ZSET_ZIPLIST: u8 = 12;
+const RDB_TYPE_HASH_ZIPLIST: u8 = 13;
+const RDB_TYPE_LIST_QUICKLIST: u8 = 14;
+const RDB_TYPE_STREAM_LISTPACKS: u8 = 15;
+const RDB_TYPE_HASH_LISTPACK: u8 = 16;
+const RDB_TYPE_ZSET_LISTPACK: u8 = 17;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+const RDB_TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const RDB_TYPE_SET_LISTPACK: u8 = 20;
+const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+const RDB_OPCODE_FUNCTION2: u8 = 245;
+const RDB_OPCODE_MODULE_AUX: u8 = 247;
+const RDB_OPCODE_IDLE: u8 = 248;
+const RDB_OPCODE_FREQ: u8 = 249;
+const RDB_OPCODE_AUX: u8 = 250;
+const RDB_OPCODE_RESIZEDB: u8 = 251;
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
+const RDB_OPCODE_EXPIRETIME: u8 = 253;
+const RDB_OPCODE_SELECTDB: u8 = 254;
+const RDB_OPCODE_EOF: u8 = 255;
+
+const RDB_ENC_INT8: u8 = 0;
+const RDB_ENC_INT16: u8 = 1;
+const RDB_ENC_INT32: u8 = 2;
+const RDB_ENC_LZF: u8 = 3;
+
+const RDB_MODULE_OPCODE_EOF: u64 = 0;
+const RDB_MODULE_OPCODE_SINT: u64 = 1;
+const RDB_MODULE_OPCODE_UINT: u64 = 2;
+const RDB_MODULE_OPCODE_FLOAT: u64 = 3;
+const RDB_MODULE_OPCODE_DOUBLE: u64 = 4;
+const RDB_MODULE_OPCODE_STRING: u64 = 5;
+
+const QUICKLIST_NODE_CONTAINER_PLAIN: u64 = 1;
+
+/// Most elements or bytes allocated ahead of reading them, lengths come from the file
+const RDB_PREALLOC_MAX: usize = 4096;
+
+/// Lookup table of the CRC64 (Jones) checksum used by Redis
+const CRC64_TABLE: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x95ac_9329_ac4b_c9b5
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Updates a Redis CRC64 checksum with `data`
+#[inline]
+pub(crate) fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    for b in data {
+        crc = CRC64_TABLE[((crc ^ *b as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Summary of an RDB import
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RdbImportReport {
+    /// RDB format version of the file
+    pub version: u32,
+    /// Number of strings imported as key-values
+    pub strings: u64,
+    /// Number of lists imported as lists
+    pub lists: u64,
+    /// Number of sets imported as maps with empty values
+    pub sets: u64,
+    /// Number of sorted sets imported as maps of member to score
+    pub sorted_sets: u64,
+    /// Number of hashes imported as maps
+    pub hashes: u64,
+    /// Number of keys skipped because they were already expired
+    pub expired: u64,
+    /// Number of keys that were also imported from an earlier Redis database and overwrote it
+    pub merged: u64,
+    /// Number of keys skipped per unsupported type
+    pub unsupported: BTreeMap<String, u64>,
+}
+
+impl RdbImportReport {
+    /// Returns the total number of imported keys
+    #[inline]
+    pub fn imported(&self) -> u64 {
+        self.strings + self.lists + self.sets + self.sorted_sets + self.hashes
+    }
+}
+
+/// A value read from an RDB file
+#[derive(Debug, Clone)]
+pub(crate) enum RdbValue {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    SortedSet(Vec<(Vec<u8>, f64)>),
+    Hash(Vec<(Vec<u8>, Vec<u8>)>),
+    /// A value that was skipped, with its type name
+    Unsupported(&'static str),
+}
+
+/// A key read from an RDB file
+#[derive(Debug, Clone)]
+pub(crate) struct RdbRecord {
+    pub(crate) db: u64,
+    pub(crate) key: Key,
+    pub(crate) expire_at: Option<TimestampMillis>,
+    pub(crate) value: RdbValue,
+}
+
+impl RdbRecord {
+    /// Converts the record into a dump entry and records it in `report`
+    ///
+    /// TTLs are relative to `now`. Returns `None` for expired and unsupported keys.
+    pub(crate) fn into_entry(
+        self,
+        now: TimestampMillis,
+        report: &mut RdbImportReport,
+    ) -> Option<DumpEntry> {
+        let ttl = self.expire_at.map(|at| at - now);
+        if matches!(ttl, Some(ttl) if ttl <= 0) {
+            report.expired += 1;
+            return None;
+        }
+        let key = self.key;
+        Some(match self.value {
+            RdbValue::String(value) => {
+                report.strings += 1;
+                DumpEntry::KV {
+                    key,
+                    ttl,
+                    sliding: None,
+                    value,
+                }
+            }
+            RdbValue::List(values) => {
+                report.lists += 1;
+                DumpEntry::List {
+                    name: key,
+                    ttl,
+                    sliding: None,
+                    values,
+                }
+            }
+            RdbValue::Set(members) => {
+                report.sets += 1;
+                let fields = members.into_iter().map(|m| (m, Vec::new())).collect();
+                DumpEntry::Map {
+                    name: key,
+                    ttl,
+                    sliding: None,
+                    fields,
+                    field_ttls: Vec::new(),
+                }
+            }
+            RdbValue::SortedSet(members) => {
+                report.sorted_sets += 1;
+                let fields = members
+                    .into_iter()
+                    .map(|(m, score)| (m, score.to_le_bytes().to_vec()))
+                    .collect();
+                DumpEntry::Map {
+                    name: key,
+                    ttl,
+                    sliding: None,
+                    fields,
+                    field_ttls: Vec::new(),
+                }
+            }
+            RdbValue::Hash(fields) => {
+                report.hashes += 1;
+                DumpEntry::Map {
+                    name: key,
+                    ttl,
+                    sliding: None,
+                    fields,
+                    field_ttls: Vec::new(),
+                }
+            }
+            RdbValue::Unsupported(type_name) => {
+                log::warn!(
+                    "rdb import: skipped {} key {:?}",
+                    type_name,
+                    String::from_utf8_lossy(&key)
+                );
+                *report.unsupported.entry(type_name.to_string()).or_default() += 1;
+                return None;
+            }
+        })
+    }
+}
+
+/// Length or special string encoding
+enum RdbLen {
+    Len(u64),
+    Encoded(u8),
+}
+
+/// Streaming parser for Redis RDB files
+pub(crate) struct RdbParser<R: Read> {
+    reader: R,
+    crc: u64,
+    version: u32,
+    db: u64,
+    done: bool,
+}
+
+impl<R: Read> RdbParser<R> {
+    /// Reads the RDB header
+    pub(crate) fn new(reader: R) -> Result<Self> {
+        let mut parser = Self {
+            reader,
+            crc: 0,
+            version: 0,
+            db: 0,
+            done: false,
+        };
+        let header = parser.read_bytes(9)?;
+        if &header[..5] != b"REDIS" {
+            return Err(StorageError::codec("not an RDB file"));
+        }
+        let version = std::str::from_utf8(&header[5..])
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(|| StorageError::codec("invalid RDB version"))?;
+        if !(RDB_VERSION_MIN..=RDB_VERSION_MAX).contains(&version) {
+            return Err(StorageError::codec(format!(
+                "unsupported RDB version: {}",
+                version
+            )));
+        }
+        parser.version = version;
+        Ok(parser)
+    }
+
+    /// Returns the RDB format version
+    #[inline]
+    pub(crate) fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Reads the next key, returns `None` at the end of the file
+    pub(crate) fn next_record(&mut self) -> Result<Option<RdbRecord>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut expire_at = None;
+        loop {
+            match self.read_u8()? {
+                RDB_OPCODE_EOF => {
+                    self.verify_checksum()?;
+                    self.done = true;
+                    return Ok(None);
+                }
+                RDB_OPCODE_SELECTDB => self.db = self.read_length()?,
+                RDB_OPCODE_EXPIRETIME => {
+                    let secs = u32::from_le_bytes(self.read_array()?);
+                    expire_at = Some(secs as TimestampMillis * 1000);
+                }
+                RDB_OPCODE_EXPIRETIME_MS => {
+                    expire_at = Some(i64::from_le_bytes(self.read_array()?));
+                }
+                RDB_OPCODE_RESIZEDB => {
+                    self.read_length()?;
+                    self.read_length()?;
+                }
+                RDB_OPCODE_AUX => {
+                    self.read_string()?;
+                    self.read_string()?;
+                }
+                RDB_OPCODE_FREQ => {
+                    self.read_u8()?;
+                }
+                RDB_OPCODE_IDLE => {
+                    self.read_length()?;
+                }
+                RDB_OPCODE_MODULE_AUX => {
+                    //module id, when opcode and when
+                    self.read_length()?;
+                    self.read_length()?;
+                    self.read_length()?;
+                    self.skip_module_value()?;
+                }
+                RDB_OPCODE_FUNCTION2 => {
+                    self.read_string()?;
+                }
+                value_type => {
+                    let key = self.read_string()?;
+                    let value = self.read_value(value_type)?;
+                    return Ok(Some(RdbRecord {
+                        db: self.db,
+                        key,
+                        expire_at,
+                        value,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Checks the trailing CRC64, a zero checksum means checksums were disabled
+    fn verify_checksum(&mut self) -> Result<()> {
+        let crc = self.crc;
+        let mut expected = [0u8; 8];
+        match self.reader.read_exact(&mut expected) {
+            Ok(()) => {}
+            //some tools strip the checksum
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        let expected = u64::from_le_bytes(expected);
+        if expected != 0 && expected != crc {
+            return Err(StorageError::codec("RDB checksum mismatch"));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_bytes(&mut self, n: usize) -> Result<Vec<u8>> {
+        //grows with the bytes actually read, a corrupt length cannot allocate up front
+        let mut buf = Vec::with_capacity(n.min(RDB_PREALLOC_MAX));
+        (&mut self.reader).take(n as u64).read_to_end(&mut buf)?;
+        if buf.len() != n {
+            return Err(StorageError::codec("RDB file is truncated"));
+        }
+        self.crc = crc64(self.crc, &buf);
+        Ok(buf)
+    }
+
+    /// Reads `n` strings
+    fn read_strings(&mut self, n: usize) -> Result<Vec<Vec<u8>>> {
+        let mut values = Vec::with_capacity(n.min(RDB_PREALLOC_MAX));
+        for _ in 0..n {
+            values.push(self.read_string()?);
+        }
+        Ok(values)
+    }
+
+    #[inline]
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.reader.read_exact(&mut buf)?;
+        self.crc = crc64(self.crc, &buf);
+        Ok(buf)
+    }
+
+    #[inline]
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    fn read_len(&mut self) -> Result<RdbLen> {
+        let b = self.read_u8()?;
+        Ok(match b >> 6 {
+            0 => RdbLen::Len((b & 0x3f) as u64),
+            1 => RdbLen::Len((((b & 0x3f) as u64) << 8) | self.read_u8()? as u64),
+            2 => match b {
+                0x80 => RdbLen::Len(u32::from_be_bytes(self.read_array()?) as u64),
+                0x81 => RdbLen::Len(u64::from_be_bytes(self.read_array()?)),
+                _ => {
+                    return Err(StorageError::codec(format!(
+                        "invalid RDB length encoding: {}",
+                        b
+                    )));
+                }
+            },
+            _ => RdbLen::Encoded(b & 0x3f),
+        })
+    }
+
+    #[inline]
+    fn read_length(&mut self) -> Result<u64> {
+        match self.read_len()? {
+            RdbLen::Len(len) => Ok(len),
+            RdbLen::Encoded(_) => Err(StorageError::codec("unexpected encoded RDB length")),
+        }
+    }
+
+    #[inline]
+    fn read_count(&mut self) -> Result<usize> {
+        Ok(self.read_length()? as usize)
+    }
+
+    fn read_string(&mut self) -> Result<Vec<u8>> {
+        match self.read_len()? {
+            RdbLen::Len(len) => self.read_bytes(len as usize),
+            RdbLen::Encoded(RDB_ENC_INT8) => Ok((self.read_u8()? as i8).to_string().into_bytes()),
+            RdbLen::Encoded(RDB_ENC_INT16) => Ok(i16::from_le_bytes(self.read_array()?)
+                .to_string()
+                .into_bytes()),
+            RdbLen::Encoded(RDB_ENC_INT32) => Ok(i32::from_le_bytes(self.read_array()?)
+                .to_string()
+                .into_bytes()),
+            RdbLen::Encoded(RDB_ENC_LZF) => {
+                let clen = self.read_count()?;
+                let len = self.read_count()?;
+                let data = self.read_bytes(clen)?;
+                lzf_decompress(&data, len)
+            }
+            RdbLen::Encoded(enc) => Err(StorageError::codec(format!(
+                "invalid RDB string encoding: {}",
+                enc
+            ))),
+        }
+    }
+
+    /// Reads a sorted set score stored as a string
+    fn read_string_double(&mut self) -> Result<f64> {
+        match self.read_u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => parse_double(&self.read_bytes(len as usize)?),
+        }
+    }
+
+    fn read_value(&mut self, value_type: u8) -> Result<RdbValue> {
+        Ok(match value_type {
+            RDB_TYPE_STRING => RdbValue::String(self.read_string()?),
+            RDB_TYPE_LIST => {
+                let n = self.read_count()?;
+                RdbValue::List(self.read_strings(n)?)
+            }
+            RDB_TYPE_SET => {
+                let n = self.read_count()?;
+                RdbValue::Set(self.read_strings(n)?)
+            }
+            RDB_TYPE_ZSET => {
+                let n = self.read_count()?;
+                let mut members = Vec::with_capacity(n.min(RDB_PREALLOC_MAX));
+                for _ in 0..n {
+                    members.push((self.read_string()?, self.read_string_double()?));
+                }
+                RdbValue::SortedSet(members)
+            }
+            RDB_TYPE_ZSET_2 => {
+                let n = self.read_count()?;
+                let mut members = Vec::with_capacity(n.min(RDB_PREALLOC_MAX));
+                for _ in 0..n {
+                    let member = self.read_string()?;
+                    members.push((member, f64::from_le_bytes(self.read_array()?)));
+                }
+                RdbValue::SortedSet(members)
+            }
+            RDB_TYPE_HASH => {
+                let n = self.read_count()?;
+                let mut fields = Vec::with_capacity(n.min(RDB_PREALLOC_MAX));
+                for _ in 0..n {
+                    fields.push((self.read_string()?, self.read_string()?));
+                }
+                RdbValue::Hash(fields)
+            }
+            RDB_TYPE_MODULE_2 => {
+                self.read_length()?;
+                self.skip_module_value()?;
+                RdbValue::Unsupported("module")
+            }
+            RDB_TYPE_HASH_ZIPMAP => RdbValue::Hash(zipmap_entries(&self.read_string()?)?),
+            RDB_TYPE_LIST_ZIPLIST => RdbValue::List(ziplist_entries(&self.read_string()?)?),
+            RDB_TYPE_SET_INTSET => RdbValue::Set(intset_entries(&self.read_string()?)?),
+            RDB_TYPE_ZSET_ZIPLIST => {
+                RdbValue::SortedSet(scored(ziplist_entries(&self.read_string()?)?)?)
+            }
+            RDB_TYPE_HASH_ZIPLIST => RdbValue::Hash(pairs(ziplist_entries(&self.read_string()?)?)?),
+            RDB_TYPE_LIST_QUICKLIST => {
+                let n = self.read_count()?;
+                let mut values = Vec::new();
+                for _ in 0..n {
+                    values.extend(ziplist_entries(&self.read_string()?)?);
+                }
+                RdbValue::List(values)
+            }
+            RDB_TYPE_HASH_LISTPACK => {
+                RdbValue::Hash(pairs(listpack_entries(&self.read_string()?)?)?)
+            }
+            RDB_TYPE_ZSET_LISTPACK => {
+                RdbValue::SortedSet(scored(listpack_entries(&self.read_string()?)?)?)
+            }
+            RDB_TYPE_LIST_QUICKLIST_2 => {
+                let n = self.read_count()?;
+                let mut values = Vec::new();
+                for _ in 0..n {
+                    let container = self.read_length()?;
+                    let data = self.read_string()?;
+                    if container == QUICKLIST_NODE_CONTAINER_PLAIN {
+                        values.push(data);
+                    } else {
+                        values.extend(listpack_entries(&data)?);
+                    }
+                }
+                RdbValue::List(values)
+            }
+            RDB_TYPE_SET_LISTPACK => RdbValue::Set(listpack_entries(&self.read_string()?)?),
+            RDB_TYPE_STREAM_LISTPACKS
+            | RDB_TYPE_STREAM_LISTPACKS_2
+            | RDB_TYPE_STREAM_LISTPACKS_3 => {
+                self.skip_stream(value_type)?;
+                RdbValue::Unsupported("stream")
+            }
+            t => {
+                return Err(StorageError::codec(format!(
+                    "unsupported RDB value type {} cannot be skipped",
+                    t
+                )));
+            }
+        })
+    }
+
+    /// Skips a module value written with the self-describing module opcodes
+    fn skip_module_value(&mut self) -> Result<()> {
+        loop {
+            match self.read_length()? {
+                RDB_MODULE_OPCODE_EOF => return Ok(()),
+                RDB_MODULE_OPCODE_SINT | RDB_MODULE_OPCODE_UINT => {
+                    self.read_length()?;
+                }
+                RDB_MODULE_OPCODE_FLOAT => {
+                    self.read_array::<4>()?;
+                }
+                RDB_MODULE_OPCODE_DOUBLE => {
+                    self.read_array::<8>()?;
+                }
+                RDB_MODULE_OPCODE_STRING => {
+                    self.read_string()?;
+                }
+                op => {
+                    return Err(StorageError::codec(format!(
+                        "invalid RDB module opcode: {}",
+                        op
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Skips a stream with its consumer groups
+    fn skip_stream(&mut self, value_type: u8) -> Result<()> {
+        for _ in 0..self.read_count()? {
+            self.read_string()?;
+            self.read_string()?;
+        }
+        //length and last id
+        self.read_length()?;
+        self.read_length()?;
+        self.read_length()?;
+        if value_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+            //first id, max deleted id and entries added
+            for _ in 0..5 {
+                self.read_length()?;
+            }
+        }
+        for _ in 0..self.read_count()? {
+            self.read_string()?;
+            self.read_length()?;
+            self.read_length()?;
+            if value_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+                self.read_length()?;
+            }
+            for _ in 0..self.read_count()? {
+                //id, delivery time and delivery count
+                self.read_array::<16>()?;
+                self.read_array::<8>()?;
+                self.read_length()?;
+            }
+            for _ in 0..self.read_count()? {
+                self.read_string()?;
+                self.read_array::<8>()?;
+                if value_type >= RDB_TYPE_STREAM_LISTPACKS_3 {
+                    self.read_array::<8>()?;
+                }
+                for _ in 0..self.read_count()? {
+                    self.read_array::<16>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a score stored as a decimal string
+#[inline]
+fn parse_double(data: &[u8]) -> Result<f64> {
+    match data {
+        b"inf" | b"+inf" => Ok(f64::INFINITY),
+        b"-inf" => Ok(f64::NEG_INFINITY),
+        b"nan" => Ok(f64::NAN),
+        _ => Ok(std::str::from_utf8(data)
+            .map_err(StorageError::codec)?
+            .parse::<f64>()
+            .map_err(StorageError::codec)?),
+    }
+}
+
+/// Groups a flat list of entries into field-value pairs
+#[inline]
+fn pairs(entries: Vec<Vec<u8>>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(StorageError::codec("odd number of RDB hash entries"));
+    }
+    let mut iter = entries.into_iter();
+    let mut out = Vec::new();
+    while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+        out.push((k, v));
+    }
+    Ok(out)
+}
+
+/// Groups a flat list of entries into member-score pairs
+#[inline]
+fn scored(entries: Vec<Vec<u8>>) -> Result<Vec<(Vec<u8>, f64)>> {
+    pairs(entries)?
+        .into_iter()
+        .map(|(member, score)| Ok((member, parse_double(&score)?)))
+        .collect()
+}
+
+/// Returns `len` bytes of `buf` starting at `pos`
+#[inline]
+fn slice(buf: &[u8], pos: usize, len: usize) -> Result<&[u8]> {
+    buf.get(pos..pos + len)
+        .ok_or_else(|| StorageError::codec("truncated RDB encoded value"))
+}
+
+/// Returns the byte of `buf` at `pos`
+#[inline]
+fn byte(buf: &[u8], pos: usize) -> Result<u8> {
+    buf.get(pos)
+        .copied()
+        .ok_or_else(|| StorageError::codec("truncated RDB encoded value"))
+}
+
+/// Sign-extends a 24-bit little endian integer
+#[inline]
+fn int24(data: &[u8]) -> i64 {
+    ((((data[0] as u32) | ((data[1] as u32) << 8) | ((data[2] as u32) << 16)) << 8) as i32 >> 8)
+        as i64
+}
+
+/// Decodes the entries of a ziplist, integers are returned as decimal strings
+fn ziplist_entries(buf: &[u8]) -> Result<Vec<Vec<u8>>> {
+    //zlbytes, zltail and zllen
+    let mut pos = 10;
+    let mut out = Vec::new();
+    loop {
+        let b = byte(buf, pos)?;
+        if b == 0xff {
+            break;
+        }
+        //previous entry length
+        pos += if b == 0xfe { 5 } else { 1 };
+        let enc = byte(buf, pos)?;
+        let (value, len) = match enc >> 6 {
+            0 => {
+                let len = (enc & 0x3f) as usize;
+                (slice(buf, pos + 1, len)?.to_vec(), 1 + len)
+            }
+            1 => {
+                let len = (((enc & 0x3f) as usize) << 8) | byte(buf, pos + 1)? as usize;
+                (slice(buf, pos + 2, len)?.to_vec(), 2 + len)
+            }
+            2 => {
+                let len = u32::from_be_bytes(slice(buf, pos + 1, 4)?.try_into()?) as usize;
+                (slice(buf, pos + 5, len)?.to_vec(), 5 + len)
+            }
+            _ => {
+                let (n, len) = match enc {
+                    0xc0 => (
+                        i16::from_le_bytes(slice(buf, pos + 1, 2)?.try_into()?) as i64,
+                        3,
+                    ),
+                    0xd0 => (
+                        i32::from_le_bytes(slice(buf, pos + 1, 4)?.try_into()?) as i64,
+                        5,
+                    ),
+                    0xe0 => (i64::from_le_bytes(slice(buf, pos + 1, 8)?.try_into()?), 9),
+                    0xf0 => (int24(slice(buf, pos + 1, 3)?), 4),
+                    0xfe => (byte(buf, pos + 1)? as i8 as i64, 2),
+                    0xf1..=0xfd => (((enc & 0x0f) as i64) - 1, 1),
+                    _ => {
+                        return Err(StorageError::codec(format!(
+                            "invalid ziplist encoding: {}",
+                            enc
+                        )));
+                    }
+                };
+                (n.to_string().into_bytes(), len)
+            }
+        };
+        out.push(value);
+        pos += len;
+    }
+    Ok(out)
+}
+
+/// Returns the size of a listpack entry's trailing back length
+#[inline]
+fn listpack_backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..16383 => 2,
+        16383..2097151 => 3,
+        2097151..268435455 => 4,
+        _ => 5,
+    }
+}
+
+/// Decodes the entries of a listpack, integers are returned as decimal strings
+fn listpack_entries(buf: &[u8]) -> Result<Vec<Vec<u8>>> {
+    //total bytes and number of elements
+    let mut pos = 6;
+    let mut out = Vec::new();
+    loop {
+        let b = byte(buf, pos)?;
+        if b == 0xff {
+            break;
+        }
+        let int = |n: i64| n.to_string().into_bytes();
+        let (value, len) = if b & 0x80 == 0 {
+            (int((b & 0x7f) as i64), 1)
+        } else if b & 0xc0 == 0x80 {
+            let len = (b & 0x3f) as usize;
+            (slice(buf, pos + 1, len)?.to_vec(), 1 + len)
+        } else if b & 0xe0 == 0xc0 {
+            let mut n = (((b & 0x1f) as i64) << 8) | byte(buf, pos + 1)? as i64;
+            if n >= 1 << 12 {
+                n -= 1 << 13;
+            }
+            (int(n), 2)
+        } else if b & 0xf0 == 0xe0 {
+            let len = (((b & 0x0f) as usize) << 8) | byte(buf, pos + 1)? as usize;
+            (slice(buf, pos + 2, len)?.to_vec(), 2 + len)
+        } else {
+            match b {
+                0xf0 => {
+                    let len = u32::from_le_bytes(slice(buf, pos + 1, 4)?.try_into()?) as usize;
+                    (slice(buf, pos + 5, len)?.to_vec(), 5 + len)
+                }
+                0xf1 => (
+                    int(i16::from_le_bytes(slice(buf, pos + 1, 2)?.try_into()?) as i64),
+                    3,
+                ),
+                0xf2 => (int(int24(slice(buf, pos + 1, 3)?)), 4),
+                0xf3 => (
+                    int(i32::from_le_bytes(slice(buf, pos + 1, 4)?.try_into()?) as i64),
+                    5,
+                ),
+                0xf4 => (
+                    int(i64::from_le_bytes(slice(buf, pos + 1, 8)?.try_into()?)),
+                    9,
+                ),
+                _ => {
+                    return Err(StorageError::codec(format!(
+                        "invalid listpack encoding: {}",
+                        b
+                    )));
+                }
+            }
+        };
+        out.push(value);
+        pos += len + listpack_backlen_size(len);
+    }
+    Ok(out)
+}
+
+/// Decodes the members of an intset as decimal strings
+fn intset_entries(buf: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let enc = u32::from_le_bytes(slice(buf, 0, 4)?.try_into()?) as usize;
+    let len = u32::from_le_bytes(slice(buf, 4, 4)?.try_into()?) as usize;
+    (0..len)
+        .map(|i| {
+            let data = slice(buf, 8 + i * enc, enc)?;
+            let n = match enc {
+                2 => i16::from_le_bytes(data.try_into()?) as i64,
+                4 => i32::from_le_bytes(data.try_into()?) as i64,
+                8 => i64::from_le_bytes(data.try_into()?),
+                _ => {
+                    return Err(StorageError::codec(format!(
+                        "invalid intset encoding: {}",
+                        enc
+                    )));
+                }
+            };
+            Ok(n.to_string().into_bytes())
+        })
+        .collect()
+}
+
+/// Decodes the field-value pairs of a zipmap
+fn zipmap_entries(buf: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let zipmap_len = |pos: usize| -> Result<(usize, usize)> {
+        match byte(buf, pos)? {
+            b if b < 254 => Ok((b as usize, 1)),
+            254 => Ok((
+                u32::from_le_bytes(slice(buf, pos + 1, 4)?.try_into()?) as usize,
+                5,
+            )),
+            b => Err(StorageError::codec(format!("invalid zipmap length: {}", b))),
+        }
+    };
+    //zmlen
+    let mut pos = 1;
+    let mut out = Vec::new();
+    while byte(buf, pos)? != 0xff {
+        let (klen, n) = zipmap_len(pos)?;
+        let key = slice(buf, pos + n, klen)?.to_vec();
+        pos += n + klen;
+        let (vlen, n) = zipmap_len(pos)?;
+        let free = byte(buf, pos + n)? as usize;
+        let value = slice(buf, pos + n + 1, vlen)?.to_vec();
+        pos += n + 1 + vlen + free;
+        out.push((key, value));
+    }
+    Ok(out)
+}
+
+/// Decompresses LZF data as written by Redis
+fn lzf_decompress(input: &[u8], out_len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(out_len.min(RDB_PREALLOC_MAX));
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            let run = slice(input, i, ctrl + 1)?;
+            out.extend_from_slice(run);
+            i += ctrl + 1;
+        } else {
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += byte(input, i)? as usize;
+                i += 1;
+            }
+            let back = ((ctrl & 0x1f) << 8) + byte(input, i)? as usize + 1;
+            i += 1;
+            if back > out.len() {
+                return Err(StorageError::codec("invalid LZF back reference"));
+            }
+            let start = out.len() - back;
+            for k in 0..len + 2 {
+                out.push(out[start + k]);
+            }
+        }
+    }
+    if out.len() != out_len {
+        return Err(StorageError::codec("invalid LZF data length"));
+    }
+    Ok(out)
+}
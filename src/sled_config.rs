@@ -1,9 +1,11 @@
 use super::Result;
-use super::sled_storage::{CleanupFun, SledStorageDB};
-use anyhow::Error;
+use super::cleanup::{CleanupFun, CleanupPolicy};
+use super::clock::{Clock, SystemClock};
+use super::error::StorageError;
+use super::eviction::{EvictionPolicy, MaxMemory};
 use convert::Bytesize;
 use serde::{Deserialize, Serialize};
-use tokio::time::sleep;
+use std::sync::Arc;
 /// Configuration for Sled storage backend
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -11,9 +13,59 @@ pub struct Config {
     pub path: String,
     /// Cache capacity in bytes
     pub cache_capacity: Bytesize,
-    /// Cleanup function for expired keys
-    #[serde(skip, default = "Config::cleanup_f_default")]
+    /// Commands running longer than this (in microseconds) are recorded in the slow log,
+    /// a negative value disables the slow log
+    #[serde(default = "Config::slowlog_slower_than_default")]
+    pub slowlog_slower_than: i64,
+    /// Maximum number of slow log entries
+    #[serde(default = "Config::slowlog_max_len_default")]
+    pub slowlog_max_len: usize,
+    /// Number of keyspace events buffered per subscriber before it lags
+    #[serde(default = "Config::keyspace_events_capacity_default")]
+    pub keyspace_events_capacity: usize,
+    /// Number of Pub/Sub messages buffered per subscriber before it lags
+    #[serde(default = "Config::pubsub_channel_capacity_default")]
+    pub pubsub_channel_capacity: usize,
+    /// Number of messages kept per Pub/Sub channel for late subscribers, 0 disables the history
+    #[serde(default)]
+    pub pubsub_history_len: usize,
+    /// When written data is flushed to disk, `Periodic` sets sled's `flush_every_ms`
+    #[serde(default)]
+    pub durability: Durability,
+    /// Whether sled optimizes for disk space or write throughput
+    #[serde(default)]
+    pub mode: StorageMode,
+    /// Enables zstd compression, requires this crate's `compression` feature
+    #[serde(default)]
+    pub use_compression: bool,
+    /// zstd compression level, from 1 to 22
+    #[serde(default = "Config::compression_factor_default")]
+    pub compression_factor: i32,
+    /// Size of sled's log segments in bytes, must be a power of two
+    #[serde(default = "Config::segment_size_default")]
+    pub segment_size: usize,
+    /// Deletes the database directory when it is dropped
+    #[serde(default)]
+    pub temporary: bool,
+    /// Number of commands that can be queued for the background processor
+    #[serde(default = "Config::command_channel_capacity_default")]
+    pub command_channel_capacity: usize,
+    /// Limit on stored key-value data, in bytes or keys
+    #[serde(default)]
+    pub max_memory: MaxMemory,
+    /// What happens once `max_memory` is exceeded
+    #[serde(default)]
+    pub eviction_policy: EvictionPolicy,
+    /// When and how much the default cleanup task runs
+    #[serde(default)]
+    pub cleanup: CleanupPolicy,
+    /// Cleanup task started when the database is opened, `CleanupFun::default()` runs
+    /// the default task
+    #[serde(skip)]
     pub cleanup_f: CleanupFun,
+    /// Time source for expiration and other timestamps, a `ManualClock` makes them testable without waiting
+    #[serde(skip, default = "Config::clock_default")]
+    pub clock: Arc<dyn Clock>,
 }
 
 impl Default for Config {
@@ -21,7 +73,23 @@ impl Default for Config {
         Config {
             path: String::default(),
             cache_capacity: Bytesize::from(1024 * 1024 * 1024),
-            cleanup_f: def_cleanup,
+            slowlog_slower_than: Config::slowlog_slower_than_default(),
+            slowlog_max_len: Config::slowlog_max_len_default(),
+            keyspace_events_capacity: Config::keyspace_events_capacity_default(),
+            pubsub_channel_capacity: Config::pubsub_channel_capacity_default(),
+            pubsub_history_len: 0,
+            durability: Durability::default(),
+            mode: StorageMode::default(),
+            use_compression: false,
+            compression_factor: Config::compression_factor_default(),
+            segment_size: Config::segment_size_default(),
+            temporary: false,
+            command_channel_capacity: Config::command_channel_capacity_default(),
+            max_memory: MaxMemory::default(),
+            eviction_policy: EvictionPolicy::default(),
+            cleanup: CleanupPolicy::default(),
+            cleanup_f: CleanupFun::default(),
+            clock: Config::clock_default(),
         }
     }
 }
@@ -31,65 +99,119 @@ impl Config {
     #[inline]
     pub fn to_sled_config(&self) -> Result<sled::Config> {
         if self.path.trim().is_empty() {
-            return Err(Error::msg("storage dir is empty"));
+            return Err(StorageError::invalid("storage dir is empty"));
+        }
+        if self.use_compression && !cfg!(feature = "compression") {
+            return Err(StorageError::invalid(
+                "use_compression requires the `compression` feature",
+            ));
         }
         let sled_cfg = sled::Config::default()
             .path(self.path.trim())
             .cache_capacity(self.cache_capacity.as_u64())
-            .flush_every_ms(Some(3000))
-            .mode(sled::Mode::LowSpace);
+            .flush_every_ms(self.durability.flush_every_ms())
+            .mode(self.mode.into())
+            .use_compression(self.use_compression)
+            .compression_factor(self.compression_factor)
+            .segment_size(self.segment_size)
+            .temporary(self.temporary);
         Ok(sled_cfg)
     }
 
-    /// Returns default cleanup function
+    /// Returns default slow log threshold (10ms)
+    #[inline]
+    fn slowlog_slower_than_default() -> i64 {
+        10_000
+    }
+
+    /// Returns default slow log length
+    #[inline]
+    fn slowlog_max_len_default() -> usize {
+        128
+    }
+
+    /// Returns default keyspace event buffer size
+    #[inline]
+    fn keyspace_events_capacity_default() -> usize {
+        1024
+    }
+
+    /// Returns default Pub/Sub message buffer size
     #[inline]
-    fn cleanup_f_default() -> CleanupFun {
-        def_cleanup
+    fn pubsub_channel_capacity_default() -> usize {
+        1024
+    }
+
+    /// Returns default zstd compression level
+    #[inline]
+    fn compression_factor_default() -> i32 {
+        5
+    }
+
+    /// Returns default log segment size (512KB)
+    #[inline]
+    fn segment_size_default() -> usize {
+        512 * 1024
+    }
+
+    /// Returns default command channel capacity
+    #[inline]
+    fn command_channel_capacity_default() -> usize {
+        300_000
+    }
+
+    /// Returns default clock, the system time
+    #[inline]
+    fn clock_default() -> Arc<dyn Clock> {
+        Arc::new(SystemClock)
     }
 }
 
-/// Default cleanup function that runs in background thread
-fn def_cleanup(_db: &SledStorageDB) {
-    #[cfg(feature = "ttl")]
-    {
-        let db = _db.clone();
+/// Storage mode, trades disk space against write throughput
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMode {
+    /// Favors small on-disk size
+    #[default]
+    LowSpace,
+    /// Favors fast writes at the cost of more disk space
+    HighThroughput,
+}
+
+impl From<StorageMode> for sled::Mode {
+    fn from(mode: StorageMode) -> Self {
+        match mode {
+            StorageMode::LowSpace => sled::Mode::LowSpace,
+            StorageMode::HighThroughput => sled::Mode::HighThroughput,
+        }
+    }
+}
 
-        tokio::spawn(async move {
-            let limit = 200;
-            loop {
-                sleep(std::time::Duration::from_secs(10)).await;
-                let mut total_cleanups = 0;
-                let now = std::time::Instant::now();
-                loop {
-                    let now = std::time::Instant::now();
-                    let count = db.cleanup(limit);
-                    total_cleanups += count;
-                    if count > 0 {
-                        log::debug!(
-                            "def_cleanup: {}, total cleanups: {}, active_count(): {}, cost time: {:?}",
-                            count,
-                            total_cleanups,
-                            db.active_count(),
-                            now.elapsed()
-                        );
-                    }
-                    if count < limit {
-                        break;
-                    }
-                    if db.active_count() > 50 {
-                        sleep(std::time::Duration::from_millis(500)).await;
-                    } else {
-                        sleep(std::time::Duration::from_millis(0)).await;
-                    }
-                }
-                if now.elapsed().as_secs() > 3 {
-                    log::info!(
-                        "total cleanups: {}, cost time: {:?}",
-                        total_cleanups,
-                        now.elapsed()
-                    );
-                }
-            }
-        });
+/// Durability mode, controls when written data is flushed to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Durability {
+    /// Only flush on explicit `flush()`/`close()` or when sled decides to
+    None,
+    /// Flush in the background every `interval_ms` milliseconds
+    Periodic { interval_ms: u64 },
+    /// Flush after every write command before replying
+    PerWrite,
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Durability::Periodic { interval_ms: 3000 }
+    }
+}
+
+impl Durability {
+    /// Returns the background flush interval for sled
+    #[inline]
+    fn flush_every_ms(&self) -> Option<u64> {
+        match self {
+            Durability::Periodic { interval_ms } => Some(*interval_ms),
+            Durability::None | Durability::PerWrite => None,
+        }
     }
 }
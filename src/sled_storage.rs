@@ -83,14 +83,16 @@ Sled 持久化存储实现的功能解释
 #![allow(dead_code)]
 use core::fmt;
 use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
-use std::io;
-use std::io::{ErrorKind, Read};
-use std::ops::Deref;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::io::{BufRead, Read, Write};
+use std::ops::{Bound, Deref};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicIsize, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use anyhow::anyhow;
 use async_trait::async_trait;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
@@ -105,13 +107,32 @@ use sled::transaction::{
     TransactionalTree,
 };
 use sled::{Batch, IVec, Tree};
+use tokio::sync::Notify;
+use tokio::sync::OnceCell;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 use tokio::task::spawn_blocking;
 
+use super::backup::{BackupInfo, write_backup};
+use super::cleanup::{CleanupHandle, CleanupPolicy};
+use super::dump::{DumpEntry, DumpGroup, DumpRecord};
+use super::error::StorageError;
+use super::eviction::{
+    AccessMeta, EVICTION_SAMPLES, Eviction, EvictionInfo, EvictionPolicy, MaxMemory,
+};
+#[cfg(feature = "ttl")]
+use super::expire_hook::{ExpireHooks, ExpiredValue};
 use super::iface::IStorageDB;
-use super::sled_config::Config;
-use super::{AsyncIterator, IterItem, Key, List, Map};
+use super::keyspace::{Keyspace, KeyspaceEvent, KeyspaceEventKind, KeyspaceSubscriber};
+use super::metrics::{Metrics, MetricsSnapshot};
+use super::migrate::{META_TREE, MIGRATION_BATCH_SIZE, MigrationContext, run_migrations};
+use super::pubsub::{PubSubHub, PubSubMessage};
+use super::quota::{NamespaceQuota, Quota, QuotaReservation, QuotaUsage};
+use super::rdb::{RdbImportReport, RdbParser};
+use super::sled_config::{Config, Durability};
+use super::slowlog::{SlowLog, SlowLogEntry};
+use super::verify::{Inconsistency, VerifyReport};
+use super::{AsyncIterator, IterItem, Key, List, Map, StorageDB, Stream};
 use super::{Result, StorageList, StorageMap};
 #[allow(unused_imports)]
 use super::{TimestampMillis, timestamp_millis};
@@ -128,6 +149,10 @@ const LIST_TREE: &[u8] = b"__list_tree@";
 const EXPIRE_KEYS_TREE: &[u8] = b"__expire_key_tree@";
 /// Tree for tracking key expiration (key => expire_at)
 const KEY_EXPIRE_TREE: &[u8] = b"__key_expire_tree@";
+/// Tree for key-value access metadata used by eviction (key => last access and hits)
+const KV_ACCESS_TREE: &[u8] = b"__kv_access_tree@";
+/// Tree indexing the type stored under each name
+const TYPE_TREE: &[u8] = b"__type_tree@";
 /// Prefix for map keys
 const MAP_NAME_PREFIX: &[u8] = b"__map@";
 /// Separator between map name and item key
@@ -135,6 +160,12 @@ const MAP_KEY_SEPARATOR: &[u8] = b"@__item@";
 #[allow(dead_code)]
 /// Suffix for map count keys
 const MAP_KEY_COUNT_SUFFIX: &[u8] = b"@__count@";
+/// Separator between map name and the expiration time of an item
+#[cfg(feature = "ttl")]
+const MAP_FIELD_EXPIRE_SEPARATOR: &[u8] = b"@__expire@";
+/// Prefix for map items waiting to expire, ordered by expiration time
+#[cfg(feature = "ttl")]
+const MAP_FIELD_EXPIRE_PREFIX: &[u8] = b"__map_expire@";
 
 /// Prefix for list keys
 const LIST_NAME_PREFIX: &[u8] = b"__list@";
@@ -142,23 +173,43 @@ const LIST_NAME_PREFIX: &[u8] = b"__list@";
 const LIST_KEY_COUNT_SUFFIX: &[u8] = b"@__count@";
 /// Suffix for list content keys
 const LIST_KEY_CONTENT_SUFFIX: &[u8] = b"@__content@";
-
-/// Type alias for cleanup function signature
-pub(crate) type CleanupFun = fn(&SledStorageDB);
+/// Tree for streams
+const STREAM_TREE: &[u8] = b"__stream_tree@";
+/// Prefix for stream names
+const STREAM_NAME_PREFIX: &[u8] = b"__stream@";
+/// Suffix for stream metadata keys
+const STREAM_KEY_META_SUFFIX: &[u8] = b"@__meta@";
+/// Suffix for stream entry keys
+const STREAM_KEY_ENTRY_SUFFIX: &[u8] = b"@__entry@";
+/// Suffix for stream consumer group keys
+const STREAM_KEY_GROUP_SUFFIX: &[u8] = b"@__group@";
+/// Prefix for streams waiting to be trimmed by the cleanup task
+const STREAM_TRIM_PREFIX: &[u8] = b"__stream_trim@";
+/// Tree for the retained Pub/Sub messages of each channel
+const PUBSUB_TREE: &[u8] = b"__pubsub_tree@";
+/// Prefix for tree names of a namespace, followed by the length-prefixed namespace name
+const NAMESPACE_PREFIX: &[u8] = b"__ns@";
+/// Metadata key of the default namespace quota, other namespaces append their length-prefixed name
+const QUOTA_PREFIX: &[u8] = b"quota@";
 
 /// Enum representing different key types in storage
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-enum KeyType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum KeyType {
     /// Key-value pair
     KV,
     /// Map structure
     Map,
     /// List structure
     List,
+    /// Stream structure
+    Stream,
 }
 
 impl KeyType {
+    /// All key types
+    pub const ALL: [KeyType; 4] = [KeyType::KV, KeyType::Map, KeyType::List, KeyType::Stream];
+
     /// Encodes key type to a single byte
     #[inline]
     #[allow(dead_code)]
@@ -167,6 +218,7 @@ impl KeyType {
             KeyType::KV => &[1],
             KeyType::Map => &[2],
             KeyType::List => &[3],
+            KeyType::Stream => &[4],
         }
     }
 
@@ -175,28 +227,101 @@ impl KeyType {
     #[allow(dead_code)]
     fn decode(v: &[u8]) -> Result<Self> {
         if v.is_empty() {
-            Err(anyhow!("invalid data"))
+            Err(StorageError::codec("invalid key type"))
         } else {
             match v[0] {
                 1 => Ok(KeyType::KV),
                 2 => Ok(KeyType::Map),
                 3 => Ok(KeyType::List),
-                _ => Err(anyhow!("invalid data")),
+                4 => Ok(KeyType::Stream),
+                _ => Err(StorageError::codec("invalid key type")),
             }
         }
     }
 }
 
+/// Expiration metadata of a key, stored in the key expiration tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ExpireMeta {
+    /// Expiration time
+    at: TimestampMillis,
+    /// Time-to-live restarted by every read, `None` for a fixed expiration time
+    sliding: Option<TimestampMillis>,
+}
+
+impl ExpireMeta {
+    /// Expires `ttl` after `now`, sliding on reads if `sliding` is set
+    #[inline]
+    fn new(now: TimestampMillis, ttl: TimestampMillis, sliding: bool) -> Self {
+        if sliding {
+            Self::sliding(now, ttl)
+        } else {
+            Self::fixed(now + ttl)
+        }
+    }
+
+    /// Expires at a fixed time
+    #[inline]
+    fn fixed(at: TimestampMillis) -> Self {
+        ExpireMeta { at, sliding: None }
+    }
+
+    /// Expires `ttl` after `now`, the deadline moves on every read
+    #[inline]
+    fn sliding(now: TimestampMillis, ttl: TimestampMillis) -> Self {
+        ExpireMeta {
+            at: now + ttl,
+            sliding: Some(ttl),
+        }
+    }
+
+    /// Encodes as the big-endian expiration time, followed by the sliding time-to-live if any
+    #[inline]
+    fn encode(&self) -> Vec<u8> {
+        let mut v = self.at.to_be_bytes().to_vec();
+        if let Some(ttl) = self.sliding {
+            v.extend_from_slice(&ttl.to_be_bytes());
+        }
+        v
+    }
+
+    /// Decodes expiration metadata, a bare expiration time is a fixed expiration
+    #[inline]
+    fn decode(v: &[u8]) -> Result<Self> {
+        let at = TimestampMillis::from_be_bytes(Self::at_bytes(v).try_into()?);
+        let sliding = match v.get(8..) {
+            Some(ttl) if !ttl.is_empty() => Some(TimestampMillis::from_be_bytes(ttl.try_into()?)),
+            _ => None,
+        };
+        Ok(ExpireMeta { at, sliding })
+    }
+
+    /// Expiration time part of encoded metadata, as used in the expire key tree
+    #[inline]
+    fn at_bytes(v: &[u8]) -> &[u8] {
+        &v[..v.len().min(8)]
+    }
+}
+
 /// Enum representing all possible storage operations
 enum Command {
     // Database operations
     DBInsert(SledStorageDB, Key, Vec<u8>, oneshot::Sender<Result<()>>),
+    #[cfg(feature = "ttl")]
+    DBInsertSliding(
+        SledStorageDB,
+        Key,
+        Vec<u8>,
+        TimestampMillis,
+        oneshot::Sender<Result<()>>,
+    ),
     DBGet(SledStorageDB, IVec, oneshot::Sender<Result<Option<IVec>>>),
     DBRemove(SledStorageDB, IVec, oneshot::Sender<Result<()>>),
     DBMapNew(
         SledStorageDB,
         IVec,
         Option<TimestampMillis>,
+        bool,
         oneshot::Sender<Result<SledStorageMap>>,
     ),
     DBMapRemove(SledStorageDB, IVec, oneshot::Sender<Result<()>>),
@@ -205,16 +330,17 @@ enum Command {
         SledStorageDB,
         IVec,
         Option<TimestampMillis>,
+        bool,
         oneshot::Sender<Result<SledStorageList>>,
     ),
     DBListRemove(SledStorageDB, IVec, oneshot::Sender<Result<()>>),
     DBListContainsKey(SledStorageDB, IVec, oneshot::Sender<Result<bool>>),
     DBBatchInsert(SledStorageDB, Vec<(Key, IVec)>, oneshot::Sender<Result<()>>),
     DBBatchRemove(SledStorageDB, Vec<Key>, oneshot::Sender<Result<()>>),
-    DBCounterIncr(SledStorageDB, IVec, isize, oneshot::Sender<Result<()>>),
-    DBCounterDecr(SledStorageDB, IVec, isize, oneshot::Sender<Result<()>>),
-    DBCounterGet(SledStorageDB, IVec, oneshot::Sender<Result<Option<isize>>>),
-    DBCounterSet(SledStorageDB, IVec, isize, oneshot::Sender<Result<()>>),
+    DBCounterIncr(SledStorageDB, IVec, i64, oneshot::Sender<Result<()>>),
+    DBCounterDecr(SledStorageDB, IVec, i64, oneshot::Sender<Result<()>>),
+    DBCounterGet(SledStorageDB, IVec, oneshot::Sender<Result<Option<i64>>>),
+    DBCounterSet(SledStorageDB, IVec, i64, oneshot::Sender<Result<()>>),
     DBContainsKey(SledStorageDB, IVec, oneshot::Sender<Result<bool>>),
     #[cfg(feature = "ttl")]
     DBExpireAt(
@@ -235,6 +361,33 @@ enum Command {
     #[allow(dead_code)]
     DBLen(SledStorageDB, oneshot::Sender<usize>),
     DBSize(SledStorageDB, oneshot::Sender<usize>),
+    DBStreamNew(
+        SledStorageDB,
+        IVec,
+        Option<TimestampMillis>,
+        oneshot::Sender<Result<SledStorageStream>>,
+    ),
+    DBStreamRemove(SledStorageDB, IVec, oneshot::Sender<Result<()>>),
+    DBStreamContainsKey(SledStorageDB, IVec, oneshot::Sender<Result<bool>>),
+    DBStreamTrim(SledStorageDB, usize, oneshot::Sender<usize>),
+    DBFlush(SledStorageDB, oneshot::Sender<Result<usize>>),
+    DBClose(SledStorageDB, oneshot::Sender<Result<()>>),
+    DBBackup(SledStorageDB, PathBuf, oneshot::Sender<Result<BackupInfo>>),
+    DBLoad(SledStorageDB, DumpEntry, oneshot::Sender<Result<()>>),
+    DBVerify(SledStorageDB, bool, oneshot::Sender<Result<VerifyReport>>),
+    DBFlushNamespace(SledStorageDB, oneshot::Sender<Result<()>>),
+    DBTypeOf(
+        SledStorageDB,
+        IVec,
+        oneshot::Sender<Result<Option<KeyType>>>,
+    ),
+    DBPublish(SledStorageDB, PubSubMessage, oneshot::Sender<Result<()>>),
+    DBPubSubHistory(
+        SledStorageDB,
+        IVec,
+        oneshot::Sender<Result<Vec<PubSubMessage>>>,
+    ),
+    DBPubSubClearHistory(SledStorageDB, IVec, oneshot::Sender<Result<()>>),
 
     // Map operations
     MapInsert(SledStorageMap, IVec, IVec, oneshot::Sender<Result<()>>),
@@ -264,8 +417,29 @@ enum Command {
         SledStorageMap,
         oneshot::Sender<Result<Option<TimestampMillis>>>,
     ),
+    #[cfg(feature = "ttl")]
+    MapExpireField(
+        SledStorageMap,
+        IVec,
+        TimestampMillis,
+        oneshot::Sender<Result<bool>>,
+    ),
+    #[cfg(feature = "ttl")]
+    MapFieldTTL(
+        SledStorageMap,
+        IVec,
+        oneshot::Sender<Result<Option<TimestampMillis>>>,
+    ),
+    #[cfg(feature = "ttl")]
+    MapPersistField(SledStorageMap, IVec, oneshot::Sender<Result<bool>>),
     MapIsExpired(SledStorageMap, oneshot::Sender<Result<bool>>),
     MapPrefixIter(SledStorageMap, Option<IVec>, oneshot::Sender<sled::Iter>),
+    #[allow(clippy::type_complexity)]
+    MapIterNext(
+        SledStorageMap,
+        sled::Iter,
+        oneshot::Sender<(sled::Iter, Option<Result<(IVec, IVec)>>)>,
+    ),
 
     // List operations
     ListPush(SledStorageList, IVec, oneshot::Sender<Result<()>>),
@@ -301,6 +475,76 @@ enum Command {
     ListIsExpired(SledStorageList, oneshot::Sender<Result<bool>>),
     ListPrefixIter(SledStorageList, oneshot::Sender<sled::Iter>),
 
+    // Stream operations
+    StreamAdd(
+        SledStorageStream,
+        Option<StreamId>,
+        IVec,
+        Option<usize>,
+        oneshot::Sender<Result<StreamId>>,
+    ),
+    StreamRange(
+        SledStorageStream,
+        StreamId,
+        StreamId,
+        Option<usize>,
+        bool,
+        oneshot::Sender<Result<StreamItems>>,
+    ),
+    StreamLen(SledStorageStream, oneshot::Sender<Result<usize>>),
+    StreamLastId(SledStorageStream, oneshot::Sender<Result<Option<StreamId>>>),
+    StreamDelete(
+        SledStorageStream,
+        Vec<StreamId>,
+        oneshot::Sender<Result<usize>>,
+    ),
+    StreamTrim(SledStorageStream, usize, oneshot::Sender<Result<usize>>),
+    StreamClear(SledStorageStream, oneshot::Sender<Result<()>>),
+    StreamGroupCreate(
+        SledStorageStream,
+        Key,
+        Option<StreamId>,
+        oneshot::Sender<Result<bool>>,
+    ),
+    StreamGroupDestroy(SledStorageStream, Key, oneshot::Sender<Result<bool>>),
+    StreamReadGroup(
+        SledStorageStream,
+        Key,
+        Key,
+        Option<usize>,
+        oneshot::Sender<Result<StreamItems>>,
+    ),
+    StreamAck(
+        SledStorageStream,
+        Key,
+        Vec<StreamId>,
+        oneshot::Sender<Result<usize>>,
+    ),
+    StreamPending(
+        SledStorageStream,
+        Key,
+        oneshot::Sender<Result<Vec<StreamPendingEntry>>>,
+    ),
+    StreamClaim(
+        SledStorageStream,
+        Key,
+        Key,
+        TimestampMillis,
+        Vec<StreamId>,
+        oneshot::Sender<Result<StreamItems>>,
+    ),
+    #[cfg(feature = "ttl")]
+    StreamExpireAt(
+        SledStorageStream,
+        TimestampMillis,
+        oneshot::Sender<Result<bool>>,
+    ),
+    #[cfg(feature = "ttl")]
+    StreamTTL(
+        SledStorageStream,
+        oneshot::Sender<Result<Option<TimestampMillis>>>,
+    ),
+
     // Iterator operation
     #[allow(clippy::type_complexity)]
     IterNext(
@@ -309,37 +553,1039 @@ enum Command {
     ),
 }
 
-/// Increments a counter value stored in bytes
-fn _increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
-    let number = match old {
-        Some(bytes) => {
-            if let Ok(array) = bytes.try_into() {
-                let number = isize::from_be_bytes(array);
-                number + 1
-            } else {
-                1
+/// Fieldless mirror of [`Command`], used to label statistics per operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommandKind {
+    DBInsert,
+    DBInsertSliding,
+    DBGet,
+    DBRemove,
+    DBMapNew,
+    DBMapRemove,
+    DBMapContainsKey,
+    DBListNew,
+    DBListRemove,
+    DBListContainsKey,
+    DBBatchInsert,
+    DBBatchRemove,
+    DBCounterIncr,
+    DBCounterDecr,
+    DBCounterGet,
+    DBCounterSet,
+    DBContainsKey,
+    DBExpireAt,
+    DBTtl,
+    DBMapPrefixIter,
+    DBListPrefixIter,
+    DBScanIter,
+    DBLen,
+    DBSize,
+    DBStreamNew,
+    DBStreamRemove,
+    DBStreamContainsKey,
+    DBStreamTrim,
+    DBFlush,
+    DBClose,
+    DBBackup,
+    DBLoad,
+    DBVerify,
+    DBFlushNamespace,
+    DBTypeOf,
+    DBPublish,
+    DBPubSubHistory,
+    DBPubSubClearHistory,
+    MapInsert,
+    MapGet,
+    MapRemove,
+    MapContainsKey,
+    MapLen,
+    MapIsEmpty,
+    MapClear,
+    MapRemoveAndFetch,
+    MapRemoveWithPrefix,
+    MapBatchInsert,
+    MapBatchRemove,
+    MapExpireAt,
+    MapTTL,
+    MapExpireField,
+    MapFieldTTL,
+    MapPersistField,
+    MapIsExpired,
+    MapPrefixIter,
+    MapIterNext,
+    ListPush,
+    ListPushs,
+    ListPushLimit,
+    ListPop,
+    ListAll,
+    ListGetIndex,
+    ListLen,
+    ListIsEmpty,
+    ListClear,
+    ListExpireAt,
+    ListTTL,
+    ListIsExpired,
+    ListPrefixIter,
+    StreamAdd,
+    StreamRange,
+    StreamLen,
+    StreamLastId,
+    StreamDelete,
+    StreamTrim,
+    StreamClear,
+    StreamGroupCreate,
+    StreamGroupDestroy,
+    StreamReadGroup,
+    StreamAck,
+    StreamPending,
+    StreamClaim,
+    StreamExpireAt,
+    StreamTTL,
+    IterNext,
+}
+
+impl CommandKind {
+    /// Number of command kinds, `IterNext` must stay the last variant
+    pub const COUNT: usize = CommandKind::IterNext as usize + 1;
+
+    /// All command kinds, in declaration order
+    pub const ALL: [CommandKind; CommandKind::COUNT] = [
+        CommandKind::DBInsert,
+        CommandKind::DBInsertSliding,
+        CommandKind::DBGet,
+        CommandKind::DBRemove,
+        CommandKind::DBMapNew,
+        CommandKind::DBMapRemove,
+        CommandKind::DBMapContainsKey,
+        CommandKind::DBListNew,
+        CommandKind::DBListRemove,
+        CommandKind::DBListContainsKey,
+        CommandKind::DBBatchInsert,
+        CommandKind::DBBatchRemove,
+        CommandKind::DBCounterIncr,
+        CommandKind::DBCounterDecr,
+        CommandKind::DBCounterGet,
+        CommandKind::DBCounterSet,
+        CommandKind::DBContainsKey,
+        CommandKind::DBExpireAt,
+        CommandKind::DBTtl,
+        CommandKind::DBMapPrefixIter,
+        CommandKind::DBListPrefixIter,
+        CommandKind::DBScanIter,
+        CommandKind::DBLen,
+        CommandKind::DBSize,
+        CommandKind::DBStreamNew,
+        CommandKind::DBStreamRemove,
+        CommandKind::DBStreamContainsKey,
+        CommandKind::DBStreamTrim,
+        CommandKind::DBFlush,
+        CommandKind::DBClose,
+        CommandKind::DBBackup,
+        CommandKind::DBLoad,
+        CommandKind::DBVerify,
+        CommandKind::DBFlushNamespace,
+        CommandKind::DBTypeOf,
+        CommandKind::DBPublish,
+        CommandKind::DBPubSubHistory,
+        CommandKind::DBPubSubClearHistory,
+        CommandKind::MapInsert,
+        CommandKind::MapGet,
+        CommandKind::MapRemove,
+        CommandKind::MapContainsKey,
+        CommandKind::MapLen,
+        CommandKind::MapIsEmpty,
+        CommandKind::MapClear,
+        CommandKind::MapRemoveAndFetch,
+        CommandKind::MapRemoveWithPrefix,
+        CommandKind::MapBatchInsert,
+        CommandKind::MapBatchRemove,
+        CommandKind::MapExpireAt,
+        CommandKind::MapTTL,
+        CommandKind::MapExpireField,
+        CommandKind::MapFieldTTL,
+        CommandKind::MapPersistField,
+        CommandKind::MapIsExpired,
+        CommandKind::MapPrefixIter,
+        CommandKind::MapIterNext,
+        CommandKind::ListPush,
+        CommandKind::ListPushs,
+        CommandKind::ListPushLimit,
+        CommandKind::ListPop,
+        CommandKind::ListAll,
+        CommandKind::ListGetIndex,
+        CommandKind::ListLen,
+        CommandKind::ListIsEmpty,
+        CommandKind::ListClear,
+        CommandKind::ListExpireAt,
+        CommandKind::ListTTL,
+        CommandKind::ListIsExpired,
+        CommandKind::ListPrefixIter,
+        CommandKind::StreamAdd,
+        CommandKind::StreamRange,
+        CommandKind::StreamLen,
+        CommandKind::StreamLastId,
+        CommandKind::StreamDelete,
+        CommandKind::StreamTrim,
+        CommandKind::StreamClear,
+        CommandKind::StreamGroupCreate,
+        CommandKind::StreamGroupDestroy,
+        CommandKind::StreamReadGroup,
+        CommandKind::StreamAck,
+        CommandKind::StreamPending,
+        CommandKind::StreamClaim,
+        CommandKind::StreamExpireAt,
+        CommandKind::StreamTTL,
+        CommandKind::IterNext,
+    ];
+
+    /// Returns the command kind name
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandKind::DBInsert => "DBInsert",
+            CommandKind::DBInsertSliding => "DBInsertSliding",
+            CommandKind::DBGet => "DBGet",
+            CommandKind::DBRemove => "DBRemove",
+            CommandKind::DBMapNew => "DBMapNew",
+            CommandKind::DBMapRemove => "DBMapRemove",
+            CommandKind::DBMapContainsKey => "DBMapContainsKey",
+            CommandKind::DBListNew => "DBListNew",
+            CommandKind::DBListRemove => "DBListRemove",
+            CommandKind::DBListContainsKey => "DBListContainsKey",
+            CommandKind::DBBatchInsert => "DBBatchInsert",
+            CommandKind::DBBatchRemove => "DBBatchRemove",
+            CommandKind::DBCounterIncr => "DBCounterIncr",
+            CommandKind::DBCounterDecr => "DBCounterDecr",
+            CommandKind::DBCounterGet => "DBCounterGet",
+            CommandKind::DBCounterSet => "DBCounterSet",
+            CommandKind::DBContainsKey => "DBContainsKey",
+            CommandKind::DBExpireAt => "DBExpireAt",
+            CommandKind::DBTtl => "DBTtl",
+            CommandKind::DBMapPrefixIter => "DBMapPrefixIter",
+            CommandKind::DBListPrefixIter => "DBListPrefixIter",
+            CommandKind::DBScanIter => "DBScanIter",
+            CommandKind::DBLen => "DBLen",
+            CommandKind::DBSize => "DBSize",
+            CommandKind::DBStreamNew => "DBStreamNew",
+            CommandKind::DBStreamRemove => "DBStreamRemove",
+            CommandKind::DBStreamContainsKey => "DBStreamContainsKey",
+            CommandKind::DBStreamTrim => "DBStreamTrim",
+            CommandKind::DBFlush => "DBFlush",
+            CommandKind::DBClose => "DBClose",
+            CommandKind::DBBackup => "DBBackup",
+            CommandKind::DBLoad => "DBLoad",
+            CommandKind::DBVerify => "DBVerify",
+            CommandKind::DBFlushNamespace => "DBFlushNamespace",
+            CommandKind::DBTypeOf => "DBTypeOf",
+            CommandKind::DBPublish => "DBPublish",
+            CommandKind::DBPubSubHistory => "DBPubSubHistory",
+            CommandKind::DBPubSubClearHistory => "DBPubSubClearHistory",
+            CommandKind::MapInsert => "MapInsert",
+            CommandKind::MapGet => "MapGet",
+            CommandKind::MapRemove => "MapRemove",
+            CommandKind::MapContainsKey => "MapContainsKey",
+            CommandKind::MapLen => "MapLen",
+            CommandKind::MapIsEmpty => "MapIsEmpty",
+            CommandKind::MapClear => "MapClear",
+            CommandKind::MapRemoveAndFetch => "MapRemoveAndFetch",
+            CommandKind::MapRemoveWithPrefix => "MapRemoveWithPrefix",
+            CommandKind::MapBatchInsert => "MapBatchInsert",
+            CommandKind::MapBatchRemove => "MapBatchRemove",
+            CommandKind::MapExpireAt => "MapExpireAt",
+            CommandKind::MapTTL => "MapTTL",
+            CommandKind::MapExpireField => "MapExpireField",
+            CommandKind::MapFieldTTL => "MapFieldTTL",
+            CommandKind::MapPersistField => "MapPersistField",
+            CommandKind::MapIsExpired => "MapIsExpired",
+            CommandKind::MapPrefixIter => "MapPrefixIter",
+            CommandKind::MapIterNext => "MapIterNext",
+            CommandKind::ListPush => "ListPush",
+            CommandKind::ListPushs => "ListPushs",
+            CommandKind::ListPushLimit => "ListPushLimit",
+            CommandKind::ListPop => "ListPop",
+            CommandKind::ListAll => "ListAll",
+            CommandKind::ListGetIndex => "ListGetIndex",
+            CommandKind::ListLen => "ListLen",
+            CommandKind::ListIsEmpty => "ListIsEmpty",
+            CommandKind::ListClear => "ListClear",
+            CommandKind::ListExpireAt => "ListExpireAt",
+            CommandKind::ListTTL => "ListTTL",
+            CommandKind::ListIsExpired => "ListIsExpired",
+            CommandKind::ListPrefixIter => "ListPrefixIter",
+            CommandKind::StreamAdd => "StreamAdd",
+            CommandKind::StreamRange => "StreamRange",
+            CommandKind::StreamLen => "StreamLen",
+            CommandKind::StreamLastId => "StreamLastId",
+            CommandKind::StreamDelete => "StreamDelete",
+            CommandKind::StreamTrim => "StreamTrim",
+            CommandKind::StreamClear => "StreamClear",
+            CommandKind::StreamGroupCreate => "StreamGroupCreate",
+            CommandKind::StreamGroupDestroy => "StreamGroupDestroy",
+            CommandKind::StreamReadGroup => "StreamReadGroup",
+            CommandKind::StreamAck => "StreamAck",
+            CommandKind::StreamPending => "StreamPending",
+            CommandKind::StreamClaim => "StreamClaim",
+            CommandKind::StreamExpireAt => "StreamExpireAt",
+            CommandKind::StreamTTL => "StreamTTL",
+            CommandKind::IterNext => "IterNext",
+        }
+    }
+}
+
+impl CommandKind {
+    /// Checks if commands of this kind modify the storage
+    #[inline]
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            CommandKind::DBInsert
+                | CommandKind::DBInsertSliding
+                | CommandKind::DBRemove
+                | CommandKind::DBMapNew
+                | CommandKind::DBMapRemove
+                | CommandKind::DBListNew
+                | CommandKind::DBListRemove
+                | CommandKind::DBBatchInsert
+                | CommandKind::DBBatchRemove
+                | CommandKind::DBCounterIncr
+                | CommandKind::DBCounterDecr
+                | CommandKind::DBCounterSet
+                | CommandKind::DBExpireAt
+                | CommandKind::DBStreamNew
+                | CommandKind::DBStreamRemove
+                | CommandKind::DBStreamTrim
+                | CommandKind::DBLoad
+                | CommandKind::DBVerify
+                | CommandKind::DBFlushNamespace
+                | CommandKind::DBPublish
+                | CommandKind::DBPubSubClearHistory
+                | CommandKind::MapInsert
+                | CommandKind::MapRemove
+                | CommandKind::MapClear
+                | CommandKind::MapRemoveAndFetch
+                | CommandKind::MapRemoveWithPrefix
+                | CommandKind::MapBatchInsert
+                | CommandKind::MapBatchRemove
+                | CommandKind::MapExpireAt
+                | CommandKind::MapExpireField
+                | CommandKind::MapPersistField
+                | CommandKind::ListPush
+                | CommandKind::ListPushs
+                | CommandKind::ListPushLimit
+                | CommandKind::ListPop
+                | CommandKind::ListClear
+                | CommandKind::ListExpireAt
+                | CommandKind::StreamAdd
+                | CommandKind::StreamDelete
+                | CommandKind::StreamTrim
+                | CommandKind::StreamClear
+                | CommandKind::StreamGroupCreate
+                | CommandKind::StreamGroupDestroy
+                | CommandKind::StreamReadGroup
+                | CommandKind::StreamAck
+                | CommandKind::StreamClaim
+                | CommandKind::StreamExpireAt
+        )
+    }
+}
+
+impl fmt::Display for CommandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Command {
+    /// Returns the kind of this command
+    #[inline]
+    fn kind(&self) -> CommandKind {
+        match self {
+            Command::DBInsert(..) => CommandKind::DBInsert,
+            #[cfg(feature = "ttl")]
+            Command::DBInsertSliding(..) => CommandKind::DBInsertSliding,
+            Command::DBGet(..) => CommandKind::DBGet,
+            Command::DBRemove(..) => CommandKind::DBRemove,
+            Command::DBMapNew(..) => CommandKind::DBMapNew,
+            Command::DBMapRemove(..) => CommandKind::DBMapRemove,
+            Command::DBMapContainsKey(..) => CommandKind::DBMapContainsKey,
+            Command::DBListNew(..) => CommandKind::DBListNew,
+            Command::DBListRemove(..) => CommandKind::DBListRemove,
+            Command::DBListContainsKey(..) => CommandKind::DBListContainsKey,
+            Command::DBBatchInsert(..) => CommandKind::DBBatchInsert,
+            Command::DBBatchRemove(..) => CommandKind::DBBatchRemove,
+            Command::DBCounterIncr(..) => CommandKind::DBCounterIncr,
+            Command::DBCounterDecr(..) => CommandKind::DBCounterDecr,
+            Command::DBCounterGet(..) => CommandKind::DBCounterGet,
+            Command::DBCounterSet(..) => CommandKind::DBCounterSet,
+            Command::DBContainsKey(..) => CommandKind::DBContainsKey,
+            #[cfg(feature = "ttl")]
+            Command::DBExpireAt(..) => CommandKind::DBExpireAt,
+            #[cfg(feature = "ttl")]
+            Command::DBTtl(..) => CommandKind::DBTtl,
+            Command::DBMapPrefixIter(..) => CommandKind::DBMapPrefixIter,
+            Command::DBListPrefixIter(..) => CommandKind::DBListPrefixIter,
+            Command::DBScanIter(..) => CommandKind::DBScanIter,
+            Command::DBLen(..) => CommandKind::DBLen,
+            Command::DBSize(..) => CommandKind::DBSize,
+            Command::DBStreamNew(..) => CommandKind::DBStreamNew,
+            Command::DBStreamRemove(..) => CommandKind::DBStreamRemove,
+            Command::DBStreamContainsKey(..) => CommandKind::DBStreamContainsKey,
+            Command::DBStreamTrim(..) => CommandKind::DBStreamTrim,
+            Command::DBFlush(..) => CommandKind::DBFlush,
+            Command::DBClose(..) => CommandKind::DBClose,
+            Command::DBBackup(..) => CommandKind::DBBackup,
+            Command::DBLoad(..) => CommandKind::DBLoad,
+            Command::DBVerify(..) => CommandKind::DBVerify,
+            Command::DBFlushNamespace(..) => CommandKind::DBFlushNamespace,
+            Command::DBPublish(..) => CommandKind::DBPublish,
+            Command::DBPubSubHistory(..) => CommandKind::DBPubSubHistory,
+            Command::DBPubSubClearHistory(..) => CommandKind::DBPubSubClearHistory,
+            Command::DBTypeOf(..) => CommandKind::DBTypeOf,
+            Command::MapInsert(..) => CommandKind::MapInsert,
+            Command::MapGet(..) => CommandKind::MapGet,
+            Command::MapRemove(..) => CommandKind::MapRemove,
+            Command::MapContainsKey(..) => CommandKind::MapContainsKey,
+            #[cfg(feature = "map_len")]
+            Command::MapLen(..) => CommandKind::MapLen,
+            Command::MapIsEmpty(..) => CommandKind::MapIsEmpty,
+            Command::MapClear(..) => CommandKind::MapClear,
+            Command::MapRemoveAndFetch(..) => CommandKind::MapRemoveAndFetch,
+            Command::MapRemoveWithPrefix(..) => CommandKind::MapRemoveWithPrefix,
+            Command::MapBatchInsert(..) => CommandKind::MapBatchInsert,
+            Command::MapBatchRemove(..) => CommandKind::MapBatchRemove,
+            #[cfg(feature = "ttl")]
+            Command::MapExpireAt(..) => CommandKind::MapExpireAt,
+            #[cfg(feature = "ttl")]
+            Command::MapTTL(..) => CommandKind::MapTTL,
+            #[cfg(feature = "ttl")]
+            Command::MapExpireField(..) => CommandKind::MapExpireField,
+            #[cfg(feature = "ttl")]
+            Command::MapFieldTTL(..) => CommandKind::MapFieldTTL,
+            #[cfg(feature = "ttl")]
+            Command::MapPersistField(..) => CommandKind::MapPersistField,
+            Command::MapIsExpired(..) => CommandKind::MapIsExpired,
+            Command::MapPrefixIter(..) => CommandKind::MapPrefixIter,
+            Command::MapIterNext(..) => CommandKind::MapIterNext,
+            Command::ListPush(..) => CommandKind::ListPush,
+            Command::ListPushs(..) => CommandKind::ListPushs,
+            Command::ListPushLimit(..) => CommandKind::ListPushLimit,
+            Command::ListPop(..) => CommandKind::ListPop,
+            Command::ListAll(..) => CommandKind::ListAll,
+            Command::ListGetIndex(..) => CommandKind::ListGetIndex,
+            Command::ListLen(..) => CommandKind::ListLen,
+            Command::ListIsEmpty(..) => CommandKind::ListIsEmpty,
+            Command::ListClear(..) => CommandKind::ListClear,
+            #[cfg(feature = "ttl")]
+            Command::ListExpireAt(..) => CommandKind::ListExpireAt,
+            #[cfg(feature = "ttl")]
+            Command::ListTTL(..) => CommandKind::ListTTL,
+            Command::ListIsExpired(..) => CommandKind::ListIsExpired,
+            Command::ListPrefixIter(..) => CommandKind::ListPrefixIter,
+            Command::StreamAdd(..) => CommandKind::StreamAdd,
+            Command::StreamRange(..) => CommandKind::StreamRange,
+            Command::StreamLen(..) => CommandKind::StreamLen,
+            Command::StreamLastId(..) => CommandKind::StreamLastId,
+            Command::StreamDelete(..) => CommandKind::StreamDelete,
+            Command::StreamTrim(..) => CommandKind::StreamTrim,
+            Command::StreamClear(..) => CommandKind::StreamClear,
+            Command::StreamGroupCreate(..) => CommandKind::StreamGroupCreate,
+            Command::StreamGroupDestroy(..) => CommandKind::StreamGroupDestroy,
+            Command::StreamReadGroup(..) => CommandKind::StreamReadGroup,
+            Command::StreamAck(..) => CommandKind::StreamAck,
+            Command::StreamPending(..) => CommandKind::StreamPending,
+            Command::StreamClaim(..) => CommandKind::StreamClaim,
+            #[cfg(feature = "ttl")]
+            Command::StreamExpireAt(..) => CommandKind::StreamExpireAt,
+            #[cfg(feature = "ttl")]
+            Command::StreamTTL(..) => CommandKind::StreamTTL,
+            Command::IterNext(..) => CommandKind::IterNext,
+        }
+    }
+
+    /// Returns the key, map name or list name the command operates on
+    #[inline]
+    fn key(&self) -> Option<&[u8]> {
+        match self {
+            Command::DBInsert(_, key, ..) => Some(key.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::DBInsertSliding(_, key, ..) => Some(key.as_slice()),
+            Command::DBGet(_, key, ..) => Some(key.as_ref()),
+            Command::DBRemove(_, key, ..) => Some(key.as_ref()),
+            Command::DBMapNew(_, key, ..) => Some(key.as_ref()),
+            Command::DBMapRemove(_, key, ..) => Some(key.as_ref()),
+            Command::DBMapContainsKey(_, key, ..) => Some(key.as_ref()),
+            Command::DBListNew(_, key, ..) => Some(key.as_ref()),
+            Command::DBListRemove(_, key, ..) => Some(key.as_ref()),
+            Command::DBListContainsKey(_, key, ..) => Some(key.as_ref()),
+            Command::DBBatchInsert(..) => None,
+            Command::DBBatchRemove(..) => None,
+            Command::DBCounterIncr(_, key, ..) => Some(key.as_ref()),
+            Command::DBCounterDecr(_, key, ..) => Some(key.as_ref()),
+            Command::DBCounterGet(_, key, ..) => Some(key.as_ref()),
+            Command::DBCounterSet(_, key, ..) => Some(key.as_ref()),
+            Command::DBContainsKey(_, key, ..) => Some(key.as_ref()),
+            #[cfg(feature = "ttl")]
+            Command::DBExpireAt(_, key, ..) => Some(key.as_ref()),
+            #[cfg(feature = "ttl")]
+            Command::DBTtl(_, key, ..) => Some(key.as_ref()),
+            Command::DBMapPrefixIter(..) => None,
+            Command::DBListPrefixIter(..) => None,
+            Command::DBScanIter(_, key, ..) => Some(key.as_ref()),
+            Command::DBLen(..) => None,
+            Command::DBSize(..) => None,
+            Command::DBStreamNew(_, key, ..) => Some(key.as_ref()),
+            Command::DBStreamRemove(_, key, ..) => Some(key.as_ref()),
+            Command::DBStreamContainsKey(_, key, ..) => Some(key.as_ref()),
+            Command::DBStreamTrim(..) => None,
+            Command::DBFlush(..) => None,
+            Command::DBClose(..) => None,
+            Command::DBBackup(..) => None,
+            Command::DBLoad(_, entry, ..) => Some(entry.name()),
+            Command::DBVerify(..) => None,
+            Command::DBFlushNamespace(..) => None,
+            Command::DBTypeOf(_, key, ..) => Some(key.as_ref()),
+            Command::DBPublish(_, msg, ..) => Some(msg.channel.as_slice()),
+            Command::DBPubSubHistory(_, channel, ..) => Some(channel.as_ref()),
+            Command::DBPubSubClearHistory(_, channel, ..) => Some(channel.as_ref()),
+            Command::MapInsert(map, ..) => Some(map.name.as_slice()),
+            Command::MapGet(map, ..) => Some(map.name.as_slice()),
+            Command::MapRemove(map, ..) => Some(map.name.as_slice()),
+            Command::MapContainsKey(map, ..) => Some(map.name.as_slice()),
+            #[cfg(feature = "map_len")]
+            Command::MapLen(map, ..) => Some(map.name.as_slice()),
+            Command::MapIsEmpty(map, ..) => Some(map.name.as_slice()),
+            Command::MapClear(map, ..) => Some(map.name.as_slice()),
+            Command::MapRemoveAndFetch(map, ..) => Some(map.name.as_slice()),
+            Command::MapRemoveWithPrefix(map, ..) => Some(map.name.as_slice()),
+            Command::MapBatchInsert(map, ..) => Some(map.name.as_slice()),
+            Command::MapBatchRemove(map, ..) => Some(map.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::MapExpireAt(map, ..) => Some(map.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::MapTTL(map, ..) => Some(map.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::MapExpireField(map, ..) => Some(map.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::MapFieldTTL(map, ..) => Some(map.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::MapPersistField(map, ..) => Some(map.name.as_slice()),
+            Command::MapIsExpired(map, ..) => Some(map.name.as_slice()),
+            Command::MapPrefixIter(map, ..) => Some(map.name.as_slice()),
+            Command::MapIterNext(map, ..) => Some(map.name.as_slice()),
+            Command::ListPush(list, ..) => Some(list.name.as_slice()),
+            Command::ListPushs(list, ..) => Some(list.name.as_slice()),
+            Command::ListPushLimit(list, ..) => Some(list.name.as_slice()),
+            Command::ListPop(list, ..) => Some(list.name.as_slice()),
+            Command::ListAll(list, ..) => Some(list.name.as_slice()),
+            Command::ListGetIndex(list, ..) => Some(list.name.as_slice()),
+            Command::ListLen(list, ..) => Some(list.name.as_slice()),
+            Command::ListIsEmpty(list, ..) => Some(list.name.as_slice()),
+            Command::ListClear(list, ..) => Some(list.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::ListExpireAt(list, ..) => Some(list.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::ListTTL(list, ..) => Some(list.name.as_slice()),
+            Command::ListIsExpired(list, ..) => Some(list.name.as_slice()),
+            Command::ListPrefixIter(list, ..) => Some(list.name.as_slice()),
+            Command::StreamAdd(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamRange(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamLen(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamLastId(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamDelete(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamTrim(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamClear(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamGroupCreate(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamGroupDestroy(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamReadGroup(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamAck(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamPending(stream, ..) => Some(stream.name.as_slice()),
+            Command::StreamClaim(stream, ..) => Some(stream.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::StreamExpireAt(stream, ..) => Some(stream.name.as_slice()),
+            #[cfg(feature = "ttl")]
+            Command::StreamTTL(stream, ..) => Some(stream.name.as_slice()),
+            Command::IterNext(..) => None,
+        }
+    }
+}
+
+impl Command {
+    /// Executes the command and sends the result back to the caller
+    ///
+    /// With `flush` successful results are only sent after the database was flushed.
+    /// With `notify` the outcome carries the keyspace events of the changes made.
+    #[inline]
+    fn execute(self, flush: Option<&sled::Db>, notify: bool) -> CommandOutcome {
+        use KeyspaceEventKind::*;
+        match self {
+            Command::DBInsert(db, key, val, res_tx) => {
+                let res = db._insert(key.as_slice(), val.as_slice());
+                let events = db._events(notify, &res, |_| {
+                    vec![KeyspaceEvent::new(Inserted, KeyType::KV, &key)]
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            #[cfg(feature = "ttl")]
+            Command::DBInsertSliding(db, key, val, ttl, res_tx) => {
+                let res = db._insert_sliding(key.as_slice(), val.as_slice(), ttl);
+                let events = db._events(notify, &res, |_| {
+                    vec![KeyspaceEvent::new(Inserted, KeyType::KV, &key)]
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::DBGet(db, key, res_tx) => reply(res_tx, db._get(key.as_ref()), flush),
+            Command::DBRemove(db, key, res_tx) => {
+                let res = db._kv_remove(key.as_ref());
+                let events = db._events(notify, &res, |removed| {
+                    removed
+                        .then(|| KeyspaceEvent::new(Removed, KeyType::KV, &key))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::DBMapNew(db, name, expire_ms, sliding, res_tx) => {
+                let name = name.as_ref().to_vec();
+                let map = SledStorageMap::_new_expire(name, expire_ms, sliding, db);
+                reply(res_tx, map, flush)
+            }
+            Command::DBMapRemove(db, name, res_tx) => {
+                let res = db._map_remove(name.as_ref());
+                let events = db._events(notify, &res, |existed| {
+                    existed
+                        .then(|| KeyspaceEvent::new(Cleared, KeyType::Map, &name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::DBMapContainsKey(db, key, res_tx) => {
+                reply(res_tx, db._self_map_contains_key(key.as_ref()), flush)
+            }
+            Command::DBListNew(db, name, expire_ms, sliding, res_tx) => {
+                let name = name.as_ref().to_vec();
+                let list = SledStorageList::_new_expire(name, expire_ms, sliding, db);
+                reply(res_tx, list, flush)
+            }
+            Command::DBListRemove(db, name, res_tx) => {
+                let res = db._list_remove(name.as_ref());
+                let events = db._events(notify, &res, |existed| {
+                    existed
+                        .then(|| KeyspaceEvent::new(Cleared, KeyType::List, &name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::DBListContainsKey(db, key, res_tx) => {
+                reply(res_tx, db._self_list_contains_key(key.as_ref()), flush)
+            }
+            Command::DBBatchInsert(db, key_vals, res_tx) => {
+                let inserted = key_vals
+                    .iter()
+                    .filter(|_| notify)
+                    .map(|(key, _)| KeyspaceEvent::new(Inserted, KeyType::KV, key))
+                    .collect();
+                let res = db._batch_insert(key_vals);
+                let events = db._events(notify, &res, |_| inserted);
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::DBBatchRemove(db, keys, res_tx) => {
+                let res = db._batch_remove(keys);
+                let events = db._events(notify, &res, |removed| {
+                    removed
+                        .iter()
+                        .map(|key| KeyspaceEvent::new(Removed, KeyType::KV, key))
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::DBCounterIncr(db, key, increment, res_tx) => {
+                let res = db._counter_incr(key.as_ref(), increment);
+                let events = db._events(notify, &res, |_| {
+                    vec![KeyspaceEvent::new(Inserted, KeyType::KV, &key)]
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::DBCounterDecr(db, key, increment, res_tx) => {
+                let res = db._counter_decr(key.as_ref(), increment);
+                let events = db._events(notify, &res, |_| {
+                    vec![KeyspaceEvent::new(Inserted, KeyType::KV, &key)]
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::DBCounterGet(db, key, res_tx) => {
+                reply(res_tx, db._counter_get(key.as_ref()), flush)
+            }
+            Command::DBCounterSet(db, key, val, res_tx) => {
+                let res = db._counter_set(key.as_ref(), val);
+                let events = db._events(notify, &res, |_| {
+                    vec![KeyspaceEvent::new(Inserted, KeyType::KV, &key)]
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::DBContainsKey(db, key, res_tx) => {
+                reply(res_tx, db._self_contains_key(key.as_ref()), flush)
+            }
+            #[cfg(feature = "ttl")]
+            Command::DBExpireAt(db, key, at, res_tx) => {
+                reply(res_tx, db._expire_at(key.as_ref(), at, KeyType::KV), flush)
+            }
+            #[cfg(feature = "ttl")]
+            Command::DBTtl(db, key, res_tx) => reply(res_tx, db._self_ttl(key.as_ref()), flush),
+            Command::DBMapPrefixIter(db, res_tx) => reply_ok(res_tx, db._map_scan_prefix()),
+            Command::DBListPrefixIter(db, res_tx) => reply_ok(res_tx, db._list_scan_prefix()),
+            Command::DBScanIter(db, pattern, res_tx) => {
+                reply_ok(res_tx, db._db_scan_prefix(pattern))
+            }
+            Command::DBLen(db, res_tx) => reply_ok(res_tx, db._kv_len()),
+            Command::DBSize(db, res_tx) => reply_ok(res_tx, db._db_size()),
+            Command::DBStreamNew(db, name, expire_ms, res_tx) => {
+                let stream = SledStorageStream::_new_expire(name.as_ref().to_vec(), expire_ms, db);
+                reply(res_tx, stream, flush)
+            }
+            Command::DBStreamRemove(db, name, res_tx) => {
+                let res = db._stream_remove(name.as_ref());
+                let events = db._events(notify, &res, |existed| {
+                    existed
+                        .then(|| KeyspaceEvent::new(Cleared, KeyType::Stream, &name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::DBStreamContainsKey(db, key, res_tx) => {
+                reply(res_tx, db._self_stream_contains_key(key.as_ref()), flush)
+            }
+            Command::DBStreamTrim(db, limit, res_tx) => reply_ok(res_tx, db._trim_streams(limit)),
+            Command::DBFlush(db, res_tx) => reply(res_tx, db._flush(), None),
+            Command::DBBackup(db, path, res_tx) => reply(
+                res_tx,
+                write_backup(&db.db, db._backup_tree_names(), &path),
+                None,
+            ),
+            Command::DBLoad(db, entry, res_tx) => {
+                let loaded =
+                    notify.then(|| KeyspaceEvent::new(Inserted, entry.key_type(), entry.name()));
+                let res = db._load(entry);
+                let events = db._events(notify, &res, |_| loaded.into_iter().collect());
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::DBVerify(db, repair, res_tx) => reply(res_tx, db._verify(repair), flush),
+            Command::DBFlushNamespace(db, res_tx) => reply(res_tx, db._flush_namespace(), flush),
+            Command::DBTypeOf(db, name, res_tx) => reply(res_tx, db._type_of(name.as_ref()), flush),
+            Command::DBPublish(db, msg, res_tx) => {
+                reply(res_tx, db.pubsub._history_append(&msg), flush)
+            }
+            Command::DBPubSubHistory(db, channel, res_tx) => {
+                reply(res_tx, db.pubsub._history(channel.as_ref()), flush)
+            }
+            Command::DBPubSubClearHistory(db, channel, res_tx) => {
+                reply(res_tx, db.pubsub._history_clear(channel.as_ref()), flush)
+            }
+            Command::DBClose(_, res_tx) => reply(res_tx, Err(StorageError::Closed), None),
+
+            Command::MapInsert(map, key, val, res_tx) => {
+                let event = notify.then(|| KeyspaceEvent::field(Inserted, &map.name, &key));
+                let res = map._insert(key, val);
+                let events = map
+                    .db
+                    ._events(notify, &res, |_| event.into_iter().collect());
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::MapGet(map, key, res_tx) => reply(res_tx, map._get(key), flush),
+            Command::MapRemove(map, key, res_tx) => {
+                let event = notify.then(|| KeyspaceEvent::field(Removed, &map.name, &key));
+                let res = map._remove(key);
+                let events = map.db._events(notify, &res, |removed| {
+                    event.filter(|_| *removed).into_iter().collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::MapContainsKey(map, key, res_tx) => {
+                reply(res_tx, map._contains_key(key), flush)
+            }
+            #[cfg(feature = "map_len")]
+            Command::MapLen(map, res_tx) => reply(res_tx, map._len(), flush),
+            Command::MapIsEmpty(map, res_tx) => reply(res_tx, map._is_empty(), flush),
+            Command::MapClear(map, res_tx) => {
+                let res = map
+                    .db
+                    ._contains_key(&map.name, KeyType::Map)
+                    .and_then(|existed| map._clear().map(|_| existed));
+                let events = map.db._events(notify, &res, |existed| {
+                    existed
+                        .then(|| KeyspaceEvent::new(Cleared, KeyType::Map, &map.name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::MapRemoveAndFetch(map, key, res_tx) => {
+                let event = notify.then(|| KeyspaceEvent::field(Removed, &map.name, &key));
+                let res = map._remove_and_fetch(key);
+                let events = map.db._events(notify, &res, |removed| {
+                    event.filter(|_| removed.is_some()).into_iter().collect()
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::MapRemoveWithPrefix(map, key, res_tx) => {
+                let event = notify.then(|| KeyspaceEvent::field(Removed, &map.name, &key));
+                let res = map._remove_with_prefix(key);
+                let events = map.db._events(notify, &res, |removed| {
+                    event.filter(|_| *removed > 0).into_iter().collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::MapBatchInsert(map, key_vals, res_tx) => {
+                let inserted = key_vals
+                    .iter()
+                    .filter(|_| notify)
+                    .map(|(key, _)| KeyspaceEvent::field(Inserted, &map.name, key))
+                    .collect();
+                let res = map._batch_insert(key_vals);
+                let events = map.db._events(notify, &res, |_| inserted);
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::MapBatchRemove(map, keys, res_tx) => {
+                let res = map._batch_remove(keys);
+                let events = map.db._events(notify, &res, |removed| {
+                    removed
+                        .iter()
+                        .map(|key| KeyspaceEvent::field(Removed, &map.name, key))
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            #[cfg(feature = "ttl")]
+            Command::MapExpireAt(map, at, res_tx) => reply(res_tx, map._expire_at(at), flush),
+            #[cfg(feature = "ttl")]
+            Command::MapTTL(map, res_tx) => reply(res_tx, map._ttl(), flush),
+            #[cfg(feature = "ttl")]
+            Command::MapExpireField(map, key, at, res_tx) => {
+                reply(res_tx, map._expire_field_at(key, at), flush)
+            }
+            #[cfg(feature = "ttl")]
+            Command::MapFieldTTL(map, key, res_tx) => reply(res_tx, map._field_ttl(key), flush),
+            #[cfg(feature = "ttl")]
+            Command::MapPersistField(map, key, res_tx) => {
+                reply(res_tx, map._persist_field(key), flush)
+            }
+            Command::MapIsExpired(map, res_tx) => reply(res_tx, map._is_expired(), flush),
+            Command::MapPrefixIter(map, prefix, res_tx) => {
+                reply_ok(res_tx, map._prefix_iter(prefix))
+            }
+            Command::MapIterNext(map, mut iter, res_tx) => {
+                let item = map._iter_next(&mut iter);
+                reply_ok(res_tx, (iter, item))
+            }
+
+            Command::ListPush(list, val, res_tx) => {
+                let res = list._push(val);
+                let events = list.db._events(notify, &res, |_| {
+                    vec![KeyspaceEvent::new(Inserted, KeyType::List, &list.name)]
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::ListPushs(list, vals, res_tx) => {
+                let pushed = !vals.is_empty();
+                let res = list._pushs(vals);
+                let events = list.db._events(notify, &res, |_| {
+                    pushed
+                        .then(|| KeyspaceEvent::new(Inserted, KeyType::List, &list.name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::ListPushLimit(list, data, limit, pop_front_if_limited, res_tx) => {
+                let res = list._push_limit(data, limit, pop_front_if_limited);
+                let events = list.db._events(notify, &res, |popped| {
+                    let mut events = vec![KeyspaceEvent::new(Inserted, KeyType::List, &list.name)];
+                    if popped.is_some() {
+                        events.push(KeyspaceEvent::new(Removed, KeyType::List, &list.name));
+                    }
+                    events
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::ListPop(list, res_tx) => {
+                let res = list._pop();
+                let events = list.db._events(notify, &res, |popped| {
+                    popped
+                        .is_some()
+                        .then(|| KeyspaceEvent::new(Removed, KeyType::List, &list.name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::ListAll(list, res_tx) => reply(res_tx, list._all(), flush),
+            Command::ListGetIndex(list, idx, res_tx) => reply(res_tx, list._get_index(idx), flush),
+            Command::ListLen(list, res_tx) => reply(res_tx, list._len(), flush),
+            Command::ListIsEmpty(list, res_tx) => reply(res_tx, list._is_empty(), flush),
+            Command::ListClear(list, res_tx) => {
+                let res = list
+                    .db
+                    ._contains_key(&list.name, KeyType::List)
+                    .and_then(|existed| list._clear().map(|_| existed));
+                let events = list.db._events(notify, &res, |existed| {
+                    existed
+                        .then(|| KeyspaceEvent::new(Cleared, KeyType::List, &list.name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            #[cfg(feature = "ttl")]
+            Command::ListExpireAt(list, at, res_tx) => reply(res_tx, list._expire_at(at), flush),
+            #[cfg(feature = "ttl")]
+            Command::ListTTL(list, res_tx) => reply(res_tx, list._ttl(), flush),
+            Command::ListIsExpired(list, res_tx) => reply(res_tx, list._is_expired(), flush),
+            Command::ListPrefixIter(list, res_tx) => reply_ok(res_tx, list._prefix_iter()),
+
+            Command::StreamAdd(stream, id, data, maxlen, res_tx) => {
+                let res = stream._add(id, data, maxlen);
+                let events = stream.db._events(notify, &res, |_| {
+                    vec![KeyspaceEvent::new(Inserted, KeyType::Stream, &stream.name)]
+                });
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::StreamRange(stream, start, end, count, rev, res_tx) => {
+                reply(res_tx, stream._range(start, end, count, rev), flush)
+            }
+            Command::StreamLen(stream, res_tx) => reply(res_tx, stream._len(), flush),
+            Command::StreamLastId(stream, res_tx) => reply(res_tx, stream._last_id(), flush),
+            Command::StreamDelete(stream, ids, res_tx) => {
+                let res = stream._delete(ids);
+                let events = stream._removed_events(notify, &res);
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::StreamTrim(stream, maxlen, res_tx) => {
+                let res = stream._trim(maxlen);
+                let events = stream._removed_events(notify, &res);
+                reply(res_tx, res, flush).with_events(events)
+            }
+            Command::StreamClear(stream, res_tx) => {
+                let res = stream
+                    .db
+                    ._contains_key(&stream.name, KeyType::Stream)
+                    .and_then(|existed| stream._clear().map(|_| existed));
+                let events = stream.db._events(notify, &res, |existed| {
+                    existed
+                        .then(|| KeyspaceEvent::new(Cleared, KeyType::Stream, &stream.name))
+                        .into_iter()
+                        .collect()
+                });
+                reply(res_tx, res.map(|_| ()), flush).with_events(events)
+            }
+            Command::StreamGroupCreate(stream, group, start, res_tx) => {
+                reply(res_tx, stream._group_create(&group, start), flush)
+            }
+            Command::StreamGroupDestroy(stream, group, res_tx) => {
+                reply(res_tx, stream._group_destroy(&group), flush)
+            }
+            Command::StreamReadGroup(stream, group, consumer, count, res_tx) => {
+                reply(res_tx, stream._read_group(&group, &consumer, count), flush)
+            }
+            Command::StreamAck(stream, group, ids, res_tx) => {
+                reply(res_tx, stream._ack(&group, ids), flush)
+            }
+            Command::StreamPending(stream, group, res_tx) => {
+                reply(res_tx, stream._pending(&group), flush)
+            }
+            Command::StreamClaim(stream, group, consumer, min_idle, ids, res_tx) => reply(
+                res_tx,
+                stream._claim(&group, &consumer, min_idle, ids),
+                flush,
+            ),
+            #[cfg(feature = "ttl")]
+            Command::StreamExpireAt(stream, at, res_tx) => {
+                reply(res_tx, stream._expire_at(at), flush)
+            }
+            #[cfg(feature = "ttl")]
+            Command::StreamTTL(stream, res_tx) => reply(res_tx, stream._ttl(), flush),
+
+            Command::IterNext(mut iter, res_tx) => {
+                let item = iter.next();
+                reply_ok(res_tx, (iter, item))
             }
         }
-        None => 1,
+    }
+}
+
+/// Result of executing a command in the background processor
+struct CommandOutcome {
+    /// Whether the command returned an error
+    failed: bool,
+    /// Whether the result reached the caller
+    delivered: bool,
+    /// Keyspace events of the changes the command made
+    events: Vec<KeyspaceEvent>,
+}
+
+impl CommandOutcome {
+    /// Attaches the keyspace events of the changes the command made
+    #[inline]
+    fn with_events(mut self, events: Vec<KeyspaceEvent>) -> Self {
+        self.events = events;
+        self
+    }
+}
+
+/// Sends a fallible command result back to the caller
+#[inline]
+fn reply<T>(
+    res_tx: oneshot::Sender<Result<T>>,
+    res: Result<T>,
+    flush: Option<&sled::Db>,
+) -> CommandOutcome {
+    let res = match (res, flush) {
+        (Ok(v), Some(db)) => db.flush().map(|_| v).map_err(StorageError::from),
+        (res, _) => res,
     };
+    let failed = res.is_err();
+    CommandOutcome {
+        failed,
+        delivered: res_tx.send(res).is_ok(),
+        events: Vec::new(),
+    }
+}
+
+/// Sends an infallible command result back to the caller
+#[inline]
+fn reply_ok<T>(res_tx: oneshot::Sender<T>, res: T) -> CommandOutcome {
+    CommandOutcome {
+        failed: false,
+        delivered: res_tx.send(res).is_ok(),
+        events: Vec::new(),
+    }
+}
+
+/// Decodes a counter, stored as a big-endian `i64`
+///
+/// Counters written as a 4 byte `isize` by 32-bit hosts are still accepted, they
+/// are rewritten as `i64` on their next update.
+#[inline]
+pub(crate) fn decode_counter(bytes: &[u8]) -> Option<i64> {
+    if let Ok(array) = bytes.try_into() {
+        Some(i64::from_be_bytes(array))
+    } else if let Ok(array) = bytes.try_into() {
+        Some(i32::from_be_bytes(array) as i64)
+    } else {
+        None
+    }
+}
 
+/// Increments a counter value stored in bytes
+fn _increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
+    let number = old.and_then(decode_counter).map(|n| n + 1).unwrap_or(1);
     Some(number.to_be_bytes().to_vec())
 }
 
 /// Decrements a counter value stored in bytes
 fn _decrement(old: Option<&[u8]>) -> Option<Vec<u8>> {
-    let number = match old {
-        Some(bytes) => {
-            if let Ok(array) = bytes.try_into() {
-                let number = isize::from_be_bytes(array);
-                number - 1
-            } else {
-                -1
-            }
-        }
-        None => -1,
-    };
-
+    let number = old.and_then(decode_counter).map(|n| n - 1).unwrap_or(-1);
     Some(number.to_be_bytes().to_vec())
 }
 
@@ -410,7 +1656,7 @@ impl Pattern {
 }
 
 /// Checks if text matches the given pattern
-fn is_match<P: Into<Pattern>>(pattern: P, text: &[u8]) -> bool {
+pub(crate) fn is_match<P: Into<Pattern>>(pattern: P, text: &[u8]) -> bool {
     let pattern = pattern.into();
     let text_chars = text;
     let pattern_len = pattern.len();
@@ -466,6 +1712,115 @@ impl BytesReplace for &[u8] {
     }
 }
 
+/// Statistics recorded by the background cleanup task
+#[derive(Default)]
+pub struct CleanupStats {
+    /// Number of completed cleanup rounds
+    rounds: AtomicU64,
+    /// Total number of expired entries removed
+    total_removed: AtomicU64,
+    /// Entries removed by the last round
+    last_removed: AtomicU64,
+    /// Duration of the last round in milliseconds
+    last_cost_ms: AtomicU64,
+    /// Timestamp of the last round
+    last_run_at: AtomicI64,
+}
+
+impl CleanupStats {
+    /// Records a finished cleanup round
+    #[inline]
+    pub fn record(&self, removed: usize, cost: std::time::Duration) {
+        self.rounds.fetch_add(1, Ordering::Relaxed);
+        self.total_removed
+            .fetch_add(removed as u64, Ordering::Relaxed);
+        self.last_removed.store(removed as u64, Ordering::Relaxed);
+        self.last_cost_ms
+            .store(cost.as_millis() as u64, Ordering::Relaxed);
+        self.last_run_at
+            .store(timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// Takes a snapshot of the current values
+    #[inline]
+    pub fn snapshot(&self) -> CleanupInfo {
+        CleanupInfo {
+            rounds: self.rounds.load(Ordering::Relaxed),
+            total_removed: self.total_removed.load(Ordering::Relaxed),
+            last_removed: self.last_removed.load(Ordering::Relaxed),
+            last_cost_ms: self.last_cost_ms.load(Ordering::Relaxed),
+            last_run_at: self.last_run_at.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Snapshot of the cleanup task statistics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupInfo {
+    /// Number of completed cleanup rounds
+    pub rounds: u64,
+    /// Total number of expired entries removed
+    pub total_removed: u64,
+    /// Entries removed by the last round
+    pub last_removed: u64,
+    /// Duration of the last round in milliseconds
+    pub last_cost_ms: u64,
+    /// Timestamp of the last round, 0 if none ran yet
+    pub last_run_at: TimestampMillis,
+}
+
+/// Storage statistics, similar to Redis INFO
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageInfo {
+    /// Storage engine name
+    pub storage_engine: String,
+    /// Number of commands sent but not yet completed
+    pub active_count: isize,
+    /// Number of commands waiting in the command channel
+    pub queue_depth: usize,
+    /// Capacity of the command channel
+    pub queue_capacity: usize,
+    /// Namespace the statistics were taken from, `None` for the default namespace
+    pub namespace: Option<Key>,
+    /// Number of key-value entries, including counters
+    pub kv_keys: usize,
+    /// Number of maps
+    pub maps: usize,
+    /// Number of lists
+    pub lists: usize,
+    /// Number of keys, maps and lists with a TTL
+    pub ttl_keys: usize,
+    /// Size of the database files on disk in bytes
+    pub size_on_disk: u64,
+    /// Whether the database was recovered from a previous process
+    pub was_recovered: bool,
+    /// Configured page cache capacity in bytes
+    pub cache_capacity: u64,
+    /// Number of processed commands per command kind
+    pub commands: BTreeMap<String, u64>,
+    /// Cleanup task statistics
+    pub cleanup: CleanupInfo,
+    /// Storage limit, usage and evicted keys
+    pub eviction: EvictionInfo,
+}
+
+/// Key counts of a namespace
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NamespaceInfo {
+    /// Namespace name, `None` for the default namespace
+    pub name: Option<Key>,
+    /// Number of key-value entries, including counters
+    pub kv_keys: usize,
+    /// Number of maps
+    pub maps: usize,
+    /// Number of lists
+    pub lists: usize,
+    /// Number of keys, maps and lists with a TTL
+    pub ttl_keys: usize,
+    /// Quota of the namespace, if one is set
+    pub quota: Option<Quota>,
+}
+
 /// Main database handle for Sled storage
 #[derive(Clone)]
 pub struct SledStorageDB {
@@ -477,220 +1832,161 @@ pub struct SledStorageDB {
     pub(crate) map_tree: sled::Tree,
     /// Tree for list metadata
     pub(crate) list_tree: sled::Tree,
+    /// Tree for streams
+    pub(crate) stream_tree: sled::Tree,
     /// Tree for tracking expiration times
     #[allow(dead_code)]
     pub(crate) expire_key_tree: sled::Tree,
     /// Tree for tracking key expiration
     #[allow(dead_code)]
     pub(crate) key_expire_tree: sled::Tree,
-    /// Channel sender for commands
-    cmd_tx: mpsc::Sender<Command>,
+    /// Tree for key-value access metadata
+    pub(crate) access_tree: sled::Tree,
+    /// Tree for the type stored under each name
+    pub(crate) type_tree: sled::Tree,
+    /// Namespace of this handle, `None` for the default namespace
+    namespace: Option<Arc<Key>>,
+    /// Quota and usage of this handle's namespace
+    quota: Arc<NamespaceQuota>,
+    /// Quota state of every namespace opened so far
+    quotas: Arc<Mutex<HashMap<Option<Key>, Arc<NamespaceQuota>>>>,
+    /// Channel sender for commands, tagged with the time they were queued
+    cmd_tx: mpsc::Sender<(Instant, Command)>,
     /// Count of active commands
     active_count: Arc<AtomicIsize>,
+    /// Command metrics
+    metrics: Arc<Metrics>,
+    /// Slow-operation log
+    slowlog: Arc<SlowLog>,
+    /// Keyspace change notifications
+    keyspace: Arc<Keyspace>,
+    /// Pub/Sub message hub
+    pubsub: Arc<PubSubHub>,
+    /// Wakes up blocked stream readers when entries are added
+    stream_notify: Arc<Notify>,
+    /// Cleanup task statistics
+    cleanup_stats: Arc<CleanupStats>,
+    /// Pauses, triggers and stops the cleanup task
+    cleanup_handle: CleanupHandle,
+    /// Key-value storage limit and usage
+    eviction: Arc<Eviction>,
+    /// Hooks called for expired entries
+    #[cfg(feature = "ttl")]
+    expire_hooks: Arc<ExpireHooks>,
+    /// Set once the database has been closed
+    closed: Arc<AtomicBool>,
+    /// Wakes up background tasks when the database is closed
+    close_notify: Arc<Notify>,
+    /// Outcome of closing the database, shared by every `close` call
+    close_result: Arc<OnceCell<std::result::Result<(), String>>>,
+    /// Configuration the database was opened with
+    cfg: Arc<Config>,
 }
 
 impl SledStorageDB {
     /// Creates a new SledStorageDB instance
     #[inline]
     pub(crate) async fn new(cfg: Config) -> Result<Self> {
-        let sled_cfg = cfg.to_sled_config()?;
-
-        let (db, kv_tree, map_tree, list_tree, expire_key_tree, key_expire_tree) =
-            sled_cfg.open().map(|db| {
-                // Open or create a new disk-backed Tree with its own keyspace,
-                let kv_tree = db.open_tree(KV_TREE);
-                let map_tree = db.open_tree(MAP_TREE);
-                let list_tree = db.open_tree(LIST_TREE);
-                let expire_key_tree = db.open_tree(EXPIRE_KEYS_TREE);
-                let key_expire_tree = db.open_tree(KEY_EXPIRE_TREE);
-                (
-                    Arc::new(db),
-                    kv_tree,
-                    map_tree,
-                    list_tree,
-                    expire_key_tree,
-                    key_expire_tree,
-                )
-            })?;
-        let kv_tree = kv_tree?;
-        let map_tree = map_tree?;
-        let list_tree = list_tree?;
-        let expire_key_tree = expire_key_tree?;
-        let key_expire_tree = key_expire_tree?;
+        let db = cfg.to_sled_config()?.open()?;
+        Self::from_db(db, cfg).await
+    }
+
+    /// Creates a new SledStorageDB instance on an already opened sled database
+    #[inline]
+    pub(crate) async fn from_db(db: sled::Db, cfg: Config) -> Result<Self> {
+        // Open or create a new disk-backed Tree with its own keyspace,
+        let kv_tree = db.open_tree(KV_TREE)?;
+        let map_tree = db.open_tree(MAP_TREE)?;
+        let list_tree = db.open_tree(LIST_TREE)?;
+        let expire_key_tree = db.open_tree(EXPIRE_KEYS_TREE)?;
+        let key_expire_tree = db.open_tree(KEY_EXPIRE_TREE)?;
+        let access_tree = db.open_tree(KV_ACCESS_TREE)?;
+        let type_tree = db.open_tree(TYPE_TREE)?;
+        let report = run_migrations(&db, false, &mut |p| {
+            log::info!(
+                "migrating from format {}, {}: {} keys",
+                p.from,
+                p.step,
+                p.processed
+            )
+        })?;
+        if !report.steps.is_empty() {
+            log::info!(
+                "migrated database format from {} to {}",
+                report.from_version,
+                report.to_version
+            );
+        }
+        let db = Arc::new(db);
+        let stream_tree = db.open_tree(STREAM_TREE)?;
+        let quotas = Arc::new(Mutex::new(HashMap::new()));
+        let quota = Self::namespace_quota(&db, &quotas, None)?;
         let active_count = Arc::new(AtomicIsize::new(0));
         let active_count1 = active_count.clone();
-
-        let (cmd_tx, mut cmd_rx) = tokio::sync::mpsc::channel::<Command>(300_000);
+        let metrics = Arc::new(Metrics::default());
+        let metrics1 = metrics.clone();
+        let slowlog = Arc::new(SlowLog::new(cfg.slowlog_slower_than, cfg.slowlog_max_len));
+        let slowlog1 = slowlog.clone();
+        let keyspace = Arc::new(Keyspace::new(cfg.keyspace_events_capacity));
+        let keyspace1 = keyspace.clone();
+        let pubsub = Arc::new(PubSubHub::new(
+            cfg.pubsub_channel_capacity,
+            cfg.pubsub_history_len,
+            db.open_tree(PUBSUB_TREE)?,
+        ));
+
+        let flush_db = matches!(cfg.durability, Durability::PerWrite).then(|| db.clone());
+
+        let (cmd_tx, mut cmd_rx) =
+            tokio::sync::mpsc::channel::<(Instant, Command)>(cfg.command_channel_capacity.max(1));
         spawn_blocking(move || {
             tokio::runtime::Handle::current().block_on(async move {
-                while let Some(cmd) = cmd_rx.recv().await {
-                    let err = anyhow::Error::msg("send result fail");
-                    let snd_res = match cmd {
-                        Command::DBInsert(db, key, val, res_tx) => res_tx
-                            .send(db._insert(key.as_slice(), val.as_slice()))
-                            .map_err(|_| err),
-                        Command::DBGet(db, key, res_tx) => {
-                            res_tx.send(db._get(key.as_ref())).map_err(|_| err)
-                        }
-                        Command::DBRemove(db, key, res_tx) => {
-                            res_tx.send(db._kv_remove(key.as_ref())).map_err(|_| err)
-                        }
-                        Command::DBMapNew(db, name, expire_ms, res_tx) => {
-                            let map =
-                                SledStorageMap::_new_expire(name.as_ref().to_vec(), expire_ms, db);
-                            res_tx.send(map).map_err(|_| err)
-                        }
-                        Command::DBMapRemove(db, name, res_tx) => {
-                            res_tx.send(db._map_remove(name.as_ref())).map_err(|_| err)
-                        }
-                        Command::DBMapContainsKey(db, key, res_tx) => res_tx
-                            .send(db._self_map_contains_key(key.as_ref()))
-                            .map_err(|_| err),
-                        Command::DBListNew(db, name, expire_ms, res_tx) => {
-                            let list =
-                                SledStorageList::_new_expire(name.as_ref().to_vec(), expire_ms, db);
-                            res_tx.send(list).map_err(|_| err)
-                        }
-                        Command::DBListRemove(db, name, res_tx) => {
-                            res_tx.send(db._list_remove(name.as_ref())).map_err(|_| err)
-                        }
-                        Command::DBListContainsKey(db, key, res_tx) => res_tx
-                            .send(db._self_list_contains_key(key.as_ref()))
-                            .map_err(|_| err),
-                        Command::DBBatchInsert(db, key_vals, res_tx) => {
-                            res_tx.send(db._batch_insert(key_vals)).map_err(|_| err)
-                        }
-                        Command::DBBatchRemove(db, keys, res_tx) => {
-                            res_tx.send(db._batch_remove(keys)).map_err(|_| err)
-                        }
-                        Command::DBCounterIncr(db, key, increment, res_tx) => res_tx
-                            .send(db._counter_incr(key.as_ref(), increment))
-                            .map_err(|_| err),
-                        Command::DBCounterDecr(db, key, increment, res_tx) => res_tx
-                            .send(db._counter_decr(key.as_ref(), increment))
-                            .map_err(|_| err),
-                        Command::DBCounterGet(db, key, res_tx) => {
-                            res_tx.send(db._counter_get(key.as_ref())).map_err(|_| err)
-                        }
-                        Command::DBCounterSet(db, key, val, res_tx) => res_tx
-                            .send(db._counter_set(key.as_ref(), val))
-                            .map_err(|_| err),
-                        Command::DBContainsKey(db, key, res_tx) => res_tx
-                            .send(db._self_contains_key(key.as_ref()))
-                            .map_err(|_| err),
-                        #[cfg(feature = "ttl")]
-                        Command::DBExpireAt(db, key, at, res_tx) => res_tx
-                            .send(db._expire_at(key.as_ref(), at, KeyType::KV))
-                            .map_err(|_| err),
-                        #[cfg(feature = "ttl")]
-                        Command::DBTtl(db, key, res_tx) => {
-                            res_tx.send(db._self_ttl(key.as_ref())).map_err(|_| err)
-                        }
-                        Command::DBMapPrefixIter(db, res_tx) => {
-                            res_tx.send(db._map_scan_prefix()).map_err(|_| err)
-                        }
-                        Command::DBListPrefixIter(db, res_tx) => {
-                            res_tx.send(db._list_scan_prefix()).map_err(|_| err)
-                        }
-                        Command::DBScanIter(db, pattern, res_tx) => {
-                            res_tx.send(db._db_scan_prefix(pattern)).map_err(|_| err)
-                        }
-                        Command::DBLen(db, res_tx) => res_tx.send(db._kv_len()).map_err(|_| err),
-                        Command::DBSize(db, res_tx) => res_tx.send(db._db_size()).map_err(|_| err),
-
-                        Command::MapInsert(map, key, val, res_tx) => {
-                            res_tx.send(map._insert(key, val)).map_err(|_| err)
-                        }
-                        Command::MapGet(map, key, res_tx) => {
-                            res_tx.send(map._get(key)).map_err(|_| err)
-                        }
-                        Command::MapRemove(map, key, res_tx) => {
-                            res_tx.send(map._remove(key)).map_err(|_| err)
-                        }
-                        Command::MapContainsKey(map, key, res_tx) => {
-                            res_tx.send(map._contains_key(key)).map_err(|_| err)
-                        }
-                        #[cfg(feature = "map_len")]
-                        Command::MapLen(map, res_tx) => res_tx.send(map._len()).map_err(|_| err),
-                        Command::MapIsEmpty(map, res_tx) => {
-                            res_tx.send(map._is_empty()).map_err(|_| err)
-                        }
-                        Command::MapClear(map, res_tx) => {
-                            res_tx.send(map._clear()).map_err(|_| err)
-                        }
-                        Command::MapRemoveAndFetch(map, key, res_tx) => {
-                            res_tx.send(map._remove_and_fetch(key)).map_err(|_| err)
-                        }
-                        Command::MapRemoveWithPrefix(map, key, res_tx) => {
-                            res_tx.send(map._remove_with_prefix(key)).map_err(|_| err)
-                        }
-                        Command::MapBatchInsert(map, key_vals, res_tx) => {
-                            res_tx.send(map._batch_insert(key_vals)).map_err(|_| err)
-                        }
-                        Command::MapBatchRemove(map, keys, res_tx) => {
-                            res_tx.send(map._batch_remove(keys)).map_err(|_| err)
-                        }
-                        #[cfg(feature = "ttl")]
-                        Command::MapExpireAt(map, at, res_tx) => {
-                            res_tx.send(map._expire_at(at)).map_err(|_| err)
-                        }
-                        #[cfg(feature = "ttl")]
-                        Command::MapTTL(map, res_tx) => res_tx.send(map._ttl()).map_err(|_| err),
-                        Command::MapIsExpired(map, res_tx) => {
-                            res_tx.send(map._is_expired()).map_err(|_| err)
-                        }
-                        Command::MapPrefixIter(map, prefix, res_tx) => {
-                            res_tx.send(map._prefix_iter(prefix)).map_err(|_| err)
-                        }
-
-                        Command::ListPush(list, val, res_tx) => {
-                            res_tx.send(list._push(val)).map_err(|_| err)
-                        }
-                        Command::ListPushs(list, vals, res_tx) => {
-                            res_tx.send(list._pushs(vals)).map_err(|_| err)
-                        }
-                        Command::ListPushLimit(list, data, limit, pop_front_if_limited, res_tx) => {
-                            res_tx
-                                .send(list._push_limit(data, limit, pop_front_if_limited))
-                                .map_err(|_| err)
-                        }
-                        Command::ListPop(list, res_tx) => res_tx.send(list._pop()).map_err(|_| err),
-                        Command::ListAll(list, res_tx) => res_tx.send(list._all()).map_err(|_| err),
-                        Command::ListGetIndex(list, idx, res_tx) => {
-                            res_tx.send(list._get_index(idx)).map_err(|_| err)
-                        }
-                        Command::ListLen(list, res_tx) => res_tx.send(list._len()).map_err(|_| err),
-                        Command::ListIsEmpty(list, res_tx) => {
-                            res_tx.send(list._is_empty()).map_err(|_| err)
-                        }
-                        Command::ListClear(list, res_tx) => {
-                            res_tx.send(list._clear()).map_err(|_| err)
-                        }
-                        #[cfg(feature = "ttl")]
-                        Command::ListExpireAt(list, at, res_tx) => {
-                            res_tx.send(list._expire_at(at)).map_err(|_| err)
-                        }
-                        #[cfg(feature = "ttl")]
-                        Command::ListTTL(list, res_tx) => res_tx.send(list._ttl()).map_err(|_| err),
-                        Command::ListIsExpired(list, res_tx) => {
-                            res_tx.send(list._is_expired()).map_err(|_| err)
-                        }
-                        Command::ListPrefixIter(list, res_tx) => {
-                            res_tx.send(list._prefix_iter()).map_err(|_| err)
-                        }
-
-                        Command::IterNext(mut iter, res_tx) => {
-                            let item = iter.next();
-                            res_tx.send((iter, item)).map_err(|_| err)
+                let mut closing = None;
+                while let Some((queued_at, cmd)) = cmd_rx.recv().await {
+                    let cmd = match cmd {
+                        Command::DBClose(db, res_tx) => {
+                            //stop accepting commands, the ones already queued are still executed
+                            cmd_rx.close();
+                            closing = Some((db, res_tx));
+                            active_count1.fetch_sub(1, Ordering::Relaxed);
+                            continue;
                         }
+                        cmd => cmd,
                     };
-
-                    if let Err(e) = snd_res {
-                        log::error!("{:?}", e);
+                    metrics1.observe_queue_wait(queued_at.elapsed());
+                    let kind = cmd.kind();
+                    let key = if slowlog1.is_enabled() {
+                        cmd.key().map(SlowLog::truncate_key)
+                    } else {
+                        None
+                    };
+                    let notify = keyspace1.has_subscribers();
+                    let started = Instant::now();
+                    let flush = if kind.is_write() {
+                        flush_db.as_deref()
+                    } else {
+                        None
+                    };
+                    let outcome = cmd.execute(flush, notify);
+                    let elapsed = started.elapsed();
+                    metrics1.observe_command(kind, elapsed, outcome.failed);
+                    slowlog1.record(kind, key, elapsed);
+                    if !outcome.failed {
+                        keyspace1.publish(outcome.events);
+                    }
+                    if !outcome.delivered {
+                        log::error!("send result fail");
                     }
 
                     active_count1.fetch_sub(1, Ordering::Relaxed);
                 }
+                if let Some((db, res_tx)) = closing {
+                    let res = db._flush().map(|_| ());
+                    drop(db);
+                    if res_tx.send(res).is_err() {
+                        log::error!("send result fail");
+                    }
+                }
             })
         });
 
@@ -699,14 +1995,41 @@ impl SledStorageDB {
             kv_tree,
             map_tree,
             list_tree,
+            stream_tree,
             expire_key_tree,
             key_expire_tree,
+            access_tree,
+            type_tree,
+            namespace: None,
+            quota,
+            quotas,
             cmd_tx,
             active_count,
+            metrics,
+            slowlog,
+            keyspace,
+            pubsub,
+            stream_notify: Arc::new(Notify::new()),
+            cleanup_stats: Arc::new(CleanupStats::default()),
+            cleanup_handle: CleanupHandle::new(!cfg.cleanup.enabled),
+            eviction: Arc::new(Eviction::new(cfg.max_memory.clone(), cfg.eviction_policy)),
+            #[cfg(feature = "ttl")]
+            expire_hooks: Arc::new(ExpireHooks::default()),
+            closed: Arc::new(AtomicBool::new(false)),
+            close_notify: Arc::new(Notify::new()),
+            close_result: Arc::new(OnceCell::new()),
+            cfg: Arc::new(cfg),
         };
 
+        //count the stored data, evicting right away if the limit is exceeded
+        if db.eviction.is_enabled() {
+            db.evict()?;
+        }
+
         //执行清理任务
-        (cfg.cleanup_f)(&db);
+        db.cfg
+            .cleanup_f
+            .start(&StorageDB::Sled(db.clone()), db.cleanup_handle.clone());
 
         Ok(db)
     }
@@ -715,24 +2038,10 @@ impl SledStorageDB {
     #[cfg(feature = "ttl")]
     #[inline]
     pub fn cleanup(&self, limit: usize) -> usize {
-        let rmeove = |typ: &KeyType, key: &[u8]| -> Result<()> {
-            match typ {
-                KeyType::Map => {
-                    self._map(key)._clear()?;
-                }
-                KeyType::List => {
-                    self._list(key)._clear()?;
-                }
-                KeyType::KV => {
-                    self.kv_tree.remove(key)?;
-                }
-            }
-            Ok(())
-        };
         let mut count = 0;
         let mut expire_at_key_types = Vec::new();
         for item in self.expire_key_tree.iter() {
-            if count > limit {
+            if count >= limit {
                 break;
             }
             let (expire_at_key, key_type) = match item {
@@ -753,7 +2062,7 @@ impl SledStorageDB {
                 }
             };
 
-            if expire_at > timestamp_millis() {
+            if expire_at > self.now() {
                 break;
             }
 
@@ -765,6 +2074,11 @@ impl SledStorageDB {
                 }
             };
 
+            //left to the expiration hooks, they remove it once delivered
+            if self._has_expire_hook(expire_at_key.get(9..).unwrap_or_default()) {
+                continue;
+            }
+
             expire_at_key_types.push((expire_at_key, key_type));
             count += 1;
         }
@@ -774,16 +2088,23 @@ impl SledStorageDB {
         let keys: Vec<(&[u8], &KeyType)> = expire_at_key_types
             .iter()
             .map(|(expire_at_key, key_type)| {
-                let (_, key) = expire_at_key.as_ref().split_at(8);
-                key_expire_batch.remove(key);
+                let (_, typed_key) = expire_at_key.as_ref().split_at(8);
+                key_expire_batch.remove(typed_key);
                 expire_key_batch.remove(expire_at_key);
-                (key, key_type)
+                (typed_key.get(1..).unwrap_or_default(), key_type)
             })
             .collect();
 
+        let mut events = Vec::new();
         for (key, key_type) in keys {
-            if let Err(e) = rmeove(key_type, key) {
+            if let Err(e) = self._remove_expired_data(*key_type, key) {
                 log::error!("{:?}", e);
+            } else if self.keyspace.has_subscribers() {
+                events.push(KeyspaceEvent::new(
+                    KeyspaceEventKind::Expired,
+                    *key_type,
+                    key,
+                ));
             }
         }
 
@@ -796,9 +2117,470 @@ impl SledStorageDB {
         ) {
             log::error!("{:?}", e);
         }
+        self.keyspace.publish(self.in_namespace(events));
+        count + self.cleanup_map_fields(limit.saturating_sub(count))
+    }
+
+    /// Removes the data of an expired key, map, list or stream (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _remove_expired_data(&self, key_type: KeyType, key: &[u8]) -> Result<()> {
+        match key_type {
+            KeyType::Map => {
+                self._map(key)._clear()?;
+            }
+            KeyType::List => {
+                self._list(key)._clear()?;
+            }
+            KeyType::Stream => {
+                self._stream(key)._clear()?;
+            }
+            KeyType::KV => {
+                let removed = self.kv_tree.remove(key)?;
+                return self._kv_removed(key, removed.as_ref());
+            }
+        }
+        self._type_unset(key, key_type)
+    }
+
+    /// Registers a hook called for every expired key, map, list or stream of this namespace
+    /// whose name matches `pattern` (TTL feature)
+    ///
+    /// The cleanup task calls the hook with the name, type and value before removing
+    /// the expired data, streams are passed without a value. Delivery is at least once:
+    /// the data is only removed after all matching hooks completed, a hook that panicked
+    /// or was interrupted by a restart is called again on a later pass. After a panic the
+    /// entry is retried with a delay doubling from one second up to a minute, later entries
+    /// are handled meanwhile. Hooks are not
+    /// persisted, register them right after opening the database so that no expired
+    /// entry is removed before. Expired data replaced by a write before the cleanup task
+    /// reaches it is not reported.
+    #[cfg(feature = "ttl")]
+    #[inline]
+    pub fn on_expire<P, F, Fut>(&self, pattern: P, hook: F)
+    where
+        P: AsRef<[u8]>,
+        F: Fn(Key, KeyType, Option<ExpiredValue>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.expire_hooks
+            .register(pattern.as_ref(), self.namespace_name(), hook);
+    }
+
+    /// Checks if an expiration hook is registered for a key of this namespace (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _has_expire_hook(&self, key: &[u8]) -> bool {
+        !self.expire_hooks.is_empty() && self.expire_hooks.matches(key, self.namespace_name())
+    }
+
+    /// Calls the expiration hooks of expired entries and removes the delivered ones,
+    /// returns the number of entries handled (TTL feature)
+    ///
+    /// Called by the cleanup task, at most `limit` entries are handled per call.
+    #[cfg(feature = "ttl")]
+    pub async fn run_expire_hooks(&self, limit: usize) -> usize {
+        if self.expire_hooks.is_empty() {
+            return 0;
+        }
+        let now = self.now();
+        let namespace = self.namespace_name();
+        let hook_timeout = Duration::from_millis(self.cfg.cleanup.hook_timeout_ms);
+        //entries replaced by a write no longer need their failures
+        self.expire_hooks.retain_retries(namespace, |at_key| {
+            self.expire_key_tree.contains_key(at_key).unwrap_or(true)
+        });
+        let mut due = Vec::new();
+        for item in self.expire_key_tree.iter().keys() {
+            if due.len() >= limit {
+                break;
+            }
+            let at_key = match item {
+                Ok(at_key) => at_key,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    break;
+                }
+            };
+            let Some(at) = at_key.get(..8).and_then(|at| at.try_into().ok()) else {
+                break;
+            };
+            if TimestampMillis::from_be_bytes(at) > now {
+                break;
+            }
+            //failed entries wait behind the others until their retry is due
+            if self._has_expire_hook(at_key.get(9..).unwrap_or_default())
+                && !self.expire_hooks.is_delayed(&at_key, namespace, now)
+            {
+                due.push(at_key);
+            }
+        }
+
+        let mut count = 0;
+        for at_key in due {
+            let (key_type, key) = match Self::decode_expire_key(&at_key[8..]) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    self.expire_hooks.failed(&at_key, namespace, now);
+                    continue;
+                }
+            };
+            let value = match self._expired_value(key_type, key) {
+                Ok(value) => value,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    self.expire_hooks.failed(&at_key, namespace, now);
+                    continue;
+                }
+            };
+            if !self
+                .expire_hooks
+                .call(key, key_type, value, namespace, hook_timeout)
+                .await
+            {
+                self.expire_hooks.failed(&at_key, namespace, now);
+                continue;
+            }
+            self.expire_hooks.delivered(&at_key, namespace);
+            match self._remove_delivered(&at_key, key_type, key) {
+                Ok(true) if self.keyspace.has_subscribers() => {
+                    let event = KeyspaceEvent::new(KeyspaceEventKind::Expired, key_type, key);
+                    self.keyspace.publish(self.in_namespace(vec![event]));
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("{:?}", e),
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Reads the value of an expired entry for the expiration hooks (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _expired_value(&self, key_type: KeyType, key: &[u8]) -> Result<Option<ExpiredValue>> {
+        Ok(match key_type {
+            KeyType::KV => self.kv_tree.get(key)?.map(|v| ExpiredValue::KV(v.to_vec())),
+            KeyType::Map => {
+                let map = self._map(key);
+                let prefix_len = map.map_item_prefix_name.len();
+                let mut iter = map._prefix_iter(None);
+                let mut items = Vec::new();
+                while let Some(item) = map._iter_next(&mut iter) {
+                    let (k, v) = item?;
+                    items.push((k[prefix_len..].to_vec(), v.to_vec()));
+                }
+                Some(ExpiredValue::Map(items))
+            }
+            KeyType::List => Some(ExpiredValue::List(
+                self._list(key)
+                    ._all()?
+                    .into_iter()
+                    .map(|v| v.to_vec())
+                    .collect(),
+            )),
+            KeyType::Stream => None,
+        })
+    }
+
+    /// Removes an expired entry after its hooks were called, unless a write gave it
+    /// another expiration time meanwhile (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _remove_delivered(&self, at_key: &[u8], key_type: KeyType, key: &[u8]) -> Result<bool> {
+        let (at, typed_key) = at_key.split_at(8);
+        let current = self
+            .key_expire_tree
+            .get(typed_key)?
+            .is_some_and(|v| ExpireMeta::at_bytes(&v) == at);
+        if current {
+            self._remove_expired_data(key_type, key)?;
+        }
+        (&self.key_expire_tree, &self.expire_key_tree).transaction(
+            |(key_expire_tx, expire_key_tx)| {
+                if current {
+                    key_expire_tx.remove(typed_key)?;
+                }
+                expire_key_tx.remove(at_key)?;
+                Ok::<_, ConflictableTransactionError<()>>(())
+            },
+        )?;
+        Ok(current)
+    }
+
+    /// Removes map items whose expiration time has passed (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn cleanup_map_fields(&self, limit: usize) -> usize {
+        let now = self.now();
+        let mut count = 0;
+        for item in self.map_tree.scan_prefix(MAP_FIELD_EXPIRE_PREFIX).keys() {
+            if count >= limit {
+                break;
+            }
+            let index_key = match item {
+                Ok(index_key) => index_key,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    break;
+                }
+            };
+            let Some((at, name, key)) = Self::decode_map_field_expire_index_key(&index_key) else {
+                log::error!("invalid map item expiration key: {:?}", index_key);
+                break;
+            };
+            if at > now {
+                break;
+            }
+            if let Err(e) = self._map(name)._remove_expired_field(key, at, &index_key) {
+                log::error!("{:?}", e);
+                break;
+            }
+            count += 1;
+        }
         count
     }
 
+    /// Evicts key-value pairs until the `max_memory` limit is met, returns the number evicted
+    ///
+    /// Returns right away while under the limit. Every evicted key is the best ranked of
+    /// `EVICTION_SAMPLES` keys sampled per namespace, the limit applies to all namespaces
+    /// together.
+    #[inline]
+    pub fn evict(&self) -> Result<usize> {
+        self.evict_batch(usize::MAX)
+    }
+
+    /// Evicts at most `max` key-value pairs towards the `max_memory` limit, returns the
+    /// number evicted
+    ///
+    /// The cleanup task evicts in batches so a round stays within its deadline.
+    pub(crate) fn evict_batch(&self, max: usize) -> Result<usize> {
+        let Some(limit) = self.eviction.max_memory.limit() else {
+            return Ok(0);
+        };
+        let policy = self.eviction.policy;
+        if !self.eviction.is_counted() {
+            let mut used = 0;
+            for db in self.namespace_handles()? {
+                used += db._evict_count()?;
+            }
+            self.eviction.set_used(used);
+        }
+        if self.eviction.used() <= limit || policy == EvictionPolicy::NoEviction {
+            return Ok(0);
+        }
+
+        let now = self.now();
+        let handles = self.namespace_handles()?;
+        let mut evicted = 0;
+        let mut events = vec![Vec::new(); handles.len()];
+        while evicted < max && self.eviction.used() > limit {
+            let mut candidates = Vec::new();
+            for (i, db) in handles.iter().enumerate() {
+                db._evict_sample(i, now, &mut candidates)?;
+            }
+            let Some((_, i, key)) = candidates.into_iter().min_by_key(|(rank, ..)| *rank) else {
+                //nothing the policy may evict, e.g. no key has a TTL under `volatile-*`
+                break;
+            };
+            let db = &handles[i];
+            db._kv_remove(&key)?;
+            evicted += 1;
+            if self.keyspace.has_subscribers() {
+                events[i].push(KeyspaceEvent::new(
+                    KeyspaceEventKind::Evicted,
+                    KeyType::KV,
+                    &key,
+                ));
+            }
+        }
+        self.eviction.record_evicted(evicted as u64);
+        for (db, events) in handles.iter().zip(events) {
+            self.keyspace.publish(db.in_namespace(events));
+        }
+        Ok(evicted)
+    }
+
+    /// Bytes or keys an entry counts towards the `max_memory` limit
+    #[inline]
+    fn _evict_size(&self, key: &[u8], val: &[u8]) -> u64 {
+        match self.eviction.max_memory {
+            MaxMemory::Bytes { .. } => (key.len() + val.len()) as u64,
+            _ => 1,
+        }
+    }
+
+    /// Counts the key-value usage of this namespace, dropping the access metadata of removed keys
+    fn _evict_count(&self) -> Result<u64> {
+        let mut used = 0;
+        let mut stale = Batch::default();
+        let mut access = self.access_tree.iter().peekable();
+        for item in self.kv_tree.iter() {
+            let (key, val) = item?;
+            used += self._evict_size(&key, &val);
+            //both trees are ordered by key
+            loop {
+                match access.peek() {
+                    Some(Ok((k, _))) if k.as_ref() < key.as_ref() => {
+                        stale.remove(k.clone());
+                        access.next();
+                    }
+                    Some(Ok((k, _))) if k.as_ref() == key.as_ref() => {
+                        access.next();
+                        break;
+                    }
+                    Some(Err(_)) => {
+                        access.next().transpose()?;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        for item in access {
+            stale.remove(item?.0);
+        }
+        self.access_tree.apply_batch(stale)?;
+        Ok(used)
+    }
+
+    /// Samples eviction candidates of this namespace
+    ///
+    /// Candidates are ranked by the policy and tagged with `handle`, the index of this
+    /// namespace in the pass.
+    fn _evict_sample(
+        &self,
+        handle: usize,
+        now: TimestampMillis,
+        candidates: &mut Vec<((i64, i64), usize, IVec)>,
+    ) -> Result<()> {
+        let policy = self.eviction.policy;
+        if policy == EvictionPolicy::VolatileTtl {
+            //expiration entries are ordered by time, the first ones are the best ranked
+            let mut sampled = 0;
+            for item in self.expire_key_tree.iter() {
+                if sampled >= EVICTION_SAMPLES {
+                    break;
+                }
+                let (at_key, key_type) = item?;
+                if at_key.len() < 9 || KeyType::decode(key_type.as_ref())? != KeyType::KV {
+                    continue;
+                }
+                let (at, typed_key) = at_key.split_at(8);
+                let at = TimestampMillis::from_be_bytes(at.try_into()?);
+                candidates.push(((at, 0), handle, IVec::from(&typed_key[1..])));
+                sampled += 1;
+            }
+            return Ok(());
+        }
+
+        //volatile policies only sample keys with a TTL
+        let (tree, prefix) = match policy {
+            EvictionPolicy::VolatileLru => (
+                &self.key_expire_tree,
+                Self::make_expire_key(KeyType::KV, &[]),
+            ),
+            _ => (&self.kv_tree, Vec::new()),
+        };
+        for sampled in self._evict_sample_keys(tree, &prefix)? {
+            let key = &sampled[prefix.len()..];
+            let meta = self
+                .access_tree
+                .get(key)?
+                .map(|v| AccessMeta::decode(&v))
+                .unwrap_or_default();
+            let rank = match policy {
+                EvictionPolicy::AllkeysLfu => (meta.decayed_hits(now) as i64, meta.last_access),
+                _ => (meta.last_access, 0),
+            };
+            candidates.push((rank, handle, IVec::from(key)));
+        }
+        Ok(())
+    }
+
+    /// Reads up to `EVICTION_SAMPLES` keys of `tree` starting with `prefix`, behind the
+    /// key sampled last in this namespace and wrapping around to the first key
+    fn _evict_sample_keys(&self, tree: &Tree, prefix: &[u8]) -> Result<Vec<IVec>> {
+        let cursor = self.eviction.cursor(self.namespace_name());
+        let start = match &cursor {
+            Some(cursor) => Bound::Excluded(cursor.to_vec()),
+            None => Bound::Included(prefix.to_vec()),
+        };
+        let mut keys = Vec::new();
+        for key in tree.range((start, Bound::Unbounded)).keys() {
+            let key = key?;
+            if keys.len() >= EVICTION_SAMPLES || !key.starts_with(prefix) {
+                break;
+            }
+            keys.push(key);
+        }
+        if let Some(cursor) = cursor {
+            for key in tree.scan_prefix(prefix).keys() {
+                let key = key?;
+                if keys.len() >= EVICTION_SAMPLES || key.as_ref() > cursor.as_ref() {
+                    break;
+                }
+                keys.push(key);
+            }
+        }
+        if let Some(last) = keys.last() {
+            self.eviction
+                .set_cursor(self.namespace_name(), last.clone());
+        }
+        Ok(keys)
+    }
+
+    /// Bytes or keys a key-value write adds towards the `max_memory` limit, overwrites
+    /// only add the change in size
+    #[inline]
+    fn _kv_added(&self, key: &[u8], val_len: usize) -> Result<i64> {
+        Ok(match self.eviction.max_memory {
+            MaxMemory::Unlimited => 0,
+            MaxMemory::Bytes { .. } => match self.kv_tree.get(key)? {
+                Some(old) => val_len as i64 - old.len() as i64,
+                None => (key.len() + val_len) as i64,
+            },
+            MaxMemory::Keys { .. } => !self.kv_tree.contains_key(key)? as i64,
+        })
+    }
+
+    /// Takes a removed key-value pair off the quota and `max_memory` usage and drops its
+    /// access metadata and type index entry
+    #[inline]
+    fn _kv_removed(&self, key: &[u8], removed: Option<&IVec>) -> Result<()> {
+        self._type_unset(key, KeyType::KV)?;
+        if let Some(val) = removed {
+            self._quota_release(Some(val));
+            if self.eviction.is_enabled() {
+                self.eviction.release(self._evict_size(key, val));
+            }
+            if self.eviction.tracks_access() {
+                self.access_tree.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if the values of removed key-value pairs are needed to account for them
+    #[inline]
+    fn _kv_tracks_removals(&self) -> bool {
+        self.quota.is_counted() || self.eviction.is_enabled()
+    }
+
+    /// Records a key access for the LRU and LFU eviction policies
+    #[inline]
+    fn _touch(&self, key: &[u8]) -> Result<()> {
+        if self.eviction.tracks_access() {
+            let now = self.now();
+            self.access_tree.fetch_and_update(key, |old| {
+                let meta = old.map(AccessMeta::decode).unwrap_or_default();
+                Some(meta.touch(now).encode().to_vec())
+            })?;
+        }
+        Ok(())
+    }
+
     /// Cleans up expired key-value pairs (TTL feature)
     #[cfg(feature = "ttl")]
     #[inline]
@@ -806,7 +2588,7 @@ impl SledStorageDB {
         let mut count = 0;
         let mut expire_at_key_types = Vec::new();
         for item in self.expire_key_tree.iter() {
-            if count > limit {
+            if count >= limit {
                 break;
             }
             let (expire_at_key, key_type) = match item {
@@ -827,7 +2609,7 @@ impl SledStorageDB {
                 }
             };
 
-            if expire_at > timestamp_millis() {
+            if expire_at > self.now() {
                 break;
             }
 
@@ -839,7 +2621,9 @@ impl SledStorageDB {
                 }
             };
 
-            if matches!(key_type, KeyType::KV) {
+            if matches!(key_type, KeyType::KV)
+                && !self._has_expire_hook(expire_at_key.get(9..).unwrap_or_default())
+            {
                 expire_at_key_types.push(expire_at_key);
                 count += 1;
             }
@@ -848,15 +2632,34 @@ impl SledStorageDB {
         let mut key_expire_batch = sled::Batch::default();
         let mut expire_key_batch = sled::Batch::default();
         let mut keys = Batch::default();
+        let mut removed = Vec::new();
+        let mut events = Vec::new();
         for expire_at_key in expire_at_key_types {
-            let (_, key) = expire_at_key.as_ref().split_at(8);
-            key_expire_batch.remove(key);
+            let (_, typed_key) = expire_at_key.as_ref().split_at(8);
+            let key = typed_key.get(1..).unwrap_or_default();
+            key_expire_batch.remove(typed_key);
             expire_key_batch.remove(expire_at_key.as_ref());
             keys.remove(key);
-        }
-
-        if let Err(e) = (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
-            |(kv_tx, key_expire_tx, expire_key_tx)| {
+            let val = if self._kv_tracks_removals() {
+                self.kv_tree.get(key).unwrap_or_else(|e| {
+                    log::error!("{:?}", e);
+                    None
+                })
+            } else {
+                None
+            };
+            removed.push((key.to_vec(), val));
+            if self.keyspace.has_subscribers() {
+                events.push(KeyspaceEvent::new(
+                    KeyspaceEventKind::Expired,
+                    KeyType::KV,
+                    key,
+                ));
+            }
+        }
+
+        if let Err(e) = (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+            |(kv_tx, key_expire_tx, expire_key_tx)| {
                 kv_tx.apply_batch(&keys)?;
                 key_expire_tx.apply_batch(&key_expire_batch)?;
                 expire_key_tx.apply_batch(&expire_key_batch)?;
@@ -864,6 +2667,13 @@ impl SledStorageDB {
             },
         ) {
             log::error!("{:?}", e);
+        } else {
+            for (key, val) in removed.iter() {
+                if let Err(e) = self._kv_removed(key, val.as_ref()) {
+                    log::error!("{:?}", e);
+                }
+            }
+            self.keyspace.publish(self.in_namespace(events));
         }
         count
     }
@@ -874,809 +2684,4142 @@ impl SledStorageDB {
         self.active_count.load(Ordering::Relaxed)
     }
 
-    // #[inline]
-    // pub fn map_size(&self) -> usize {
-    //     self.map_tree.len()
-    // }
-    //
-    // #[inline]
-    // pub fn list_size(&self) -> usize {
-    //     self.list_tree.len()
-    // }
+    /// Flushes all dirty data to disk, returns the number of bytes flushed
+    #[inline]
+    pub async fn flush(&self) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBFlush(self.clone(), tx)).await?;
+        rx.await?
+    }
+
+    /// Writes a point-in-time backup of all trees to `path`
+    ///
+    /// If `path` is a directory a timestamped archive is created inside it. The backup runs
+    /// on the command worker, so all other commands wait until it is written. The expiration
+    /// cleanup keeps running; it removes data before its expiration entries and those are
+    /// archived first, so an expired key is never restored without its TTL.
+    #[inline]
+    pub async fn backup<P: AsRef<Path>>(&self, path: P) -> Result<BackupInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBBackup(
+            self.clone(),
+            path.as_ref().to_path_buf(),
+            tx,
+        ))
+        .await?;
+        rx.await?
+    }
+
+    /// Names of all trees, the expiration indexes of every namespace first
+    #[inline]
+    fn _backup_tree_names(&self) -> Vec<IVec> {
+        let mut names = self.db.tree_names();
+        names.sort_by_key(|name| {
+            !(name.ends_with(KEY_EXPIRE_TREE) || name.ends_with(EXPIRE_KEYS_TREE))
+        });
+        names
+    }
 
-    /// Creates a map prefix name
+    /// Writes every key, map, list and stream as one JSON object per line
+    ///
+    /// Values that bincode decodes as `V` are written as JSON, others as text or base64.
+    /// Entries are streamed to `writer` on a blocking thread, returns the number of
+    /// lines written. Entries are read without holding back writes, a map, list or
+    /// stream written while it is dumped may be dumped with only part of that write.
     #[inline]
-    fn make_map_prefix_name<K>(name: K) -> Key
+    pub async fn dump_jsonl<V, W>(&self, mut writer: W) -> Result<u64>
     where
-        K: AsRef<[u8]>,
+        V: Serialize + DeserializeOwned + 'static,
+        W: Write + Send + 'static,
     {
-        [MAP_NAME_PREFIX, name.as_ref(), SEPARATOR].concat()
+        let this = self.clone();
+        spawn_blocking(move || {
+            let mut count = 0;
+            this._dump(|entry| {
+                serde_json::to_writer(&mut writer, &entry.encode::<V>())?;
+                writer.write_all(b"\n")?;
+                count += 1;
+                Ok(())
+            })?;
+            writer.flush()?;
+            Ok(count)
+        })
+        .await?
     }
 
-    /// Creates a map item prefix name
+    /// Loads a JSON-lines dump written by `dump_jsonl` with the same value type `V`,
+    /// returns the number of lines loaded
+    ///
+    /// `reader` is read on a blocking thread. Existing keys, maps, lists and streams
+    /// with the same name are replaced.
     #[inline]
-    fn make_map_item_prefix_name<K>(name: K) -> Key
+    pub async fn load_jsonl<V, R>(&self, reader: R) -> Result<u64>
     where
-        K: AsRef<[u8]>,
+        V: Serialize + DeserializeOwned + 'static,
+        R: BufRead + Send + 'static,
     {
-        [MAP_NAME_PREFIX, name.as_ref(), MAP_KEY_SEPARATOR].concat()
+        let (entry_tx, mut entry_rx) = mpsc::channel(64);
+        let read = spawn_blocking(move || {
+            for (n, line) in reader.lines().enumerate() {
+                let entry = line.map_err(StorageError::from).and_then(|line| {
+                    if line.trim().is_empty() {
+                        return Ok(None);
+                    }
+                    serde_json::from_str::<DumpRecord>(&line)
+                        .map_err(StorageError::from)
+                        .and_then(DumpEntry::decode::<V>)
+                        .map(Some)
+                        .map_err(|e| {
+                            StorageError::codec(format!("invalid dump line {}: {}", n + 1, e))
+                        })
+                });
+                let stop = entry.is_err();
+                if let Some(entry) = entry.transpose()
+                    && entry_tx.blocking_send(entry).is_err()
+                {
+                    break;
+                }
+                if stop {
+                    break;
+                }
+            }
+        });
+        let mut count = 0;
+        while let Some(entry) = entry_rx.recv().await {
+            let (tx, rx) = oneshot::channel();
+            self.cmd_send(Command::DBLoad(self.clone(), entry?, tx))
+                .await?;
+            rx.await??;
+            count += 1;
+        }
+        read.await?;
+        Ok(count)
     }
 
-    /// Creates a map count key name
+    /// Imports a Redis RDB file (versions 6 to 11)
+    ///
+    /// Strings become key-values, hashes become maps, lists become lists, sets become maps
+    /// with empty values and sorted sets become maps of member to `f64` score. Values are
+    /// stored raw, keys of all Redis databases are merged and a key imported again from a
+    /// later database overwrites the earlier one, those are counted as `merged`. Streams
+    /// and module values are skipped and counted in the report. `reader` is parsed on a
+    /// blocking thread and keys are written while the file is read, the checksum is
+    /// verified once the end of the file is reached.
     #[inline]
-    fn make_map_count_key_name<K>(name: K) -> Key
+    pub async fn import_rdb<R>(&self, reader: R) -> Result<RdbImportReport>
     where
-        K: AsRef<[u8]>,
+        R: Read + Send + 'static,
     {
-        [MAP_NAME_PREFIX, name.as_ref(), MAP_KEY_COUNT_SUFFIX].concat()
+        let (entry_tx, mut entry_rx) = mpsc::channel(64);
+        let this = self.clone();
+        let read = spawn_blocking(move || {
+            let mut parser = RdbParser::new(reader)?;
+            let mut report = RdbImportReport {
+                version: parser.version(),
+                ..Default::default()
+            };
+            //the database each imported key came from
+            let mut imported: HashMap<Key, u64> = HashMap::new();
+            while let Some(record) = parser.next_record()? {
+                let (db, key) = (record.db, record.key.clone());
+                if let Some(entry) = record.into_entry(this.now(), &mut report) {
+                    if imported.insert(key, db).is_some_and(|prev| prev != db) {
+                        report.merged += 1;
+                    }
+                    if entry_tx.blocking_send(entry).is_err() {
+                        break;
+                    }
+                }
+            }
+            Ok::<_, StorageError>(report)
+        });
+        while let Some(entry) = entry_rx.recv().await {
+            let (tx, rx) = oneshot::channel();
+            self.cmd_send(Command::DBLoad(self.clone(), entry, tx))
+                .await?;
+            rx.await??;
+        }
+        read.await?
     }
 
-    /// Extracts map name from count key
+    /// Checks map counts, list bounds, stream metadata and expiration entries for inconsistencies
     #[inline]
-    fn map_count_key_to_name(key: &[u8]) -> &[u8] {
-        key[MAP_NAME_PREFIX.len()..key.len() - MAP_KEY_COUNT_SUFFIX.len()].as_ref()
+    pub async fn verify(&self) -> Result<VerifyReport> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBVerify(self.clone(), false, tx))
+            .await?;
+        rx.await?
     }
 
-    /// Checks if a key is a map count key
+    /// Repairs the inconsistencies `verify` reports
+    ///
+    /// Map counts are recomputed, list bounds are rewritten and list content is
+    /// renumbered if it has gaps or starts at index 0, stream lengths and last IDs are
+    /// recomputed, orphaned items and expiration entries are purged.
     #[inline]
-    fn is_map_count_key(key: &[u8]) -> bool {
-        key.starts_with(MAP_NAME_PREFIX) && key.ends_with(MAP_KEY_COUNT_SUFFIX)
+    pub async fn repair(&self) -> Result<VerifyReport> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBVerify(self.clone(), true, tx))
+            .await?;
+        rx.await?
     }
 
-    /// Extracts map name from item key
-    #[allow(dead_code)]
+    /// Closes the database
+    ///
+    /// New commands are rejected, queued commands are executed, the cleanup task is
+    /// stopped and all data is flushed to disk before this returns. Concurrent and
+    /// later calls wait for the same close and return its outcome.
     #[inline]
-    fn map_item_key_to_name(key: &[u8]) -> Option<&[u8]> {
-        use super::SplitSubslice;
-        if let Some((prefix, _)) = key.split_subslice(MAP_KEY_SEPARATOR) {
-            if prefix.starts_with(MAP_NAME_PREFIX) {
-                return Some(
-                    prefix[MAP_NAME_PREFIX.len()..(prefix.len() - MAP_KEY_SEPARATOR.len())]
-                        .as_ref(),
-                );
-            }
+    pub async fn close(&self) -> Result<()> {
+        let mut first = None;
+        let first_res = &mut first;
+        let res = self
+            .close_result
+            .get_or_init(|| async move {
+                let res = self._close().await;
+                let shared = res.as_ref().map(|_| ()).map_err(|e| e.to_string());
+                *first_res = Some(res);
+                shared
+            })
+            .await;
+        //the call that closed the database returns its error as is
+        match first {
+            Some(res) => res,
+            None => res.clone().map_err(|e| StorageError::Backend(e.into())),
         }
-        None
     }
 
-    /// Creates a list prefix
+    /// Stops the background tasks and executes the queued commands before flushing
+    async fn _close(&self) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        self.close_notify.notify_waiters();
+        self.cleanup_handle.stop();
+        let (tx, rx) = oneshot::channel();
+        self.active_count.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self
+            .cmd_tx
+            .send((Instant::now(), Command::DBClose(self.clone(), tx)))
+            .await
+        {
+            self.active_count.fetch_sub(1, Ordering::Relaxed);
+            return Err(StorageError::from(e));
+        }
+        rx.await?
+    }
+
+    /// Checks if the database has been closed
     #[inline]
-    fn make_list_prefix<K>(name: K) -> Key
-    where
-        K: AsRef<[u8]>,
-    {
-        [LIST_NAME_PREFIX, name.as_ref()].concat()
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
     }
 
-    /// Creates a list count key
+    /// Waits until the database is closed
     #[inline]
-    fn make_list_count_key(name: &[u8]) -> Vec<u8> {
-        [LIST_NAME_PREFIX, name, LIST_KEY_COUNT_SUFFIX].concat()
+    pub async fn closed(&self) {
+        loop {
+            let notified = self.close_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.is_closed() {
+                return;
+            }
+            notified.await;
+        }
     }
 
-    /// Extracts list name from count key
+    /// Current time of the configured clock, used for all expiration times
     #[inline]
-    fn list_count_key_to_name(key: &[u8]) -> &[u8] {
-        key[LIST_NAME_PREFIX.len()..key.len() - LIST_KEY_COUNT_SUFFIX.len()].as_ref()
+    pub(crate) fn now(&self) -> TimestampMillis {
+        self.cfg.clock.now()
     }
 
-    /// Checks if a key is a list count key
+    /// Returns the cleanup task statistics
     #[inline]
-    fn is_list_count_key(key: &[u8]) -> bool {
-        key.starts_with(LIST_NAME_PREFIX) && key.ends_with(LIST_KEY_COUNT_SUFFIX)
+    pub fn cleanup_stats(&self) -> &CleanupStats {
+        &self.cleanup_stats
     }
 
-    /// Checks if a key exists for a specific key type
+    /// Returns the handle controlling the cleanup task
     #[inline]
-    fn _contains_key<K: AsRef<[u8]> + Sync + Send>(
-        &self,
-        key: K,
-        key_type: KeyType,
-    ) -> Result<bool> {
-        match key_type {
-            KeyType::KV => Self::_kv_contains_key(&self.kv_tree, key),
-            KeyType::Map => Self::_map_contains_key(&self.map_tree, key),
-            KeyType::List => Self::_list_contains_key(&self.list_tree, key),
-        }
+    pub fn cleanup_handle(&self) -> CleanupHandle {
+        self.cleanup_handle.clone()
     }
 
-    /// Checks if a key exists in key-value store
+    /// Returns the policy of the default cleanup task
     #[inline]
-    fn _kv_contains_key<K: AsRef<[u8]> + Sync + Send>(kv: &Tree, key: K) -> Result<bool> {
-        Ok(kv.contains_key(key.as_ref())?)
+    pub fn cleanup_policy(&self) -> &CleanupPolicy {
+        &self.cfg.cleanup
     }
 
-    /// Checks if a map exists
+    /// Returns the number of commands waiting in the command channel
     #[inline]
-    fn _map_contains_key<K: AsRef<[u8]> + Sync + Send>(tree: &Tree, key: K) -> Result<bool> {
-        let count_key = SledStorageDB::make_map_count_key_name(key.as_ref());
-        Ok(tree.contains_key(count_key)?)
+    pub fn queue_depth(&self) -> usize {
+        self.cmd_tx.max_capacity() - self.cmd_tx.capacity()
     }
 
-    /// Checks if a list exists
+    /// Gets number of maps
     #[inline]
-    fn _list_contains_key<K: AsRef<[u8]> + Sync + Send>(tree: &Tree, name: K) -> Result<bool> {
-        let count_key = SledStorageDB::make_list_count_key(name.as_ref());
-        Ok(tree.contains_key(count_key)?)
+    fn map_size(&self) -> usize {
+        self.map_tree
+            .scan_prefix(MAP_NAME_PREFIX)
+            .keys()
+            .filter(|k| matches!(k, Ok(k) if Self::is_map_count_key(k.as_ref())))
+            .count()
     }
 
-    /// Removes a map
+    /// Gets number of lists
     #[inline]
-    fn _map_remove<K>(&self, key: K) -> Result<()>
-    where
-        K: AsRef<[u8]>,
-    {
-        #[cfg(not(feature = "ttl"))]
-        self._map(key.as_ref())._clear()?;
-        #[cfg(feature = "ttl")]
-        {
-            let map = self._map(key.as_ref());
-            let map_clear_batch = map._make_clear_batch();
-            (&self.map_tree, &self.key_expire_tree, &self.expire_key_tree)
-                .transaction(|(map_tx, key_expire_tx, expire_key_tx)| {
-                    map._tx_clear(map_tx, &map_clear_batch)?;
-                    Self::_tx_remove_expire_key(key_expire_tx, expire_key_tx, key.as_ref())?;
-                    Ok::<(), ConflictableTransactionError<()>>(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
-        Ok(())
+    fn list_size(&self) -> usize {
+        self.list_tree
+            .scan_prefix(LIST_NAME_PREFIX)
+            .keys()
+            .filter(|k| matches!(k, Ok(k) if Self::is_list_count_key(k.as_ref())))
+            .count()
     }
 
-    /// Removes a list
+    /// Takes a snapshot of the command metrics
     #[inline]
-    fn _list_remove<K>(&self, key: K) -> Result<()>
-    where
-        K: AsRef<[u8]>,
-    {
-        #[cfg(not(feature = "ttl"))]
-        self._list(key.as_ref())._clear()?;
-        #[cfg(feature = "ttl")]
-        {
-            let list = self._list(key.as_ref());
-            let list_clear_batch = list._make_clear_batch();
-            (
-                &self.list_tree,
-                &self.key_expire_tree,
-                &self.expire_key_tree,
-            )
-                .transaction(|(list_tx, key_expire_tx, expire_key_tx)| {
-                    SledStorageList::_tx_clear(list_tx, &list_clear_batch)?;
-                    Self::_tx_remove_expire_key(key_expire_tx, expire_key_tx, key.as_ref())?;
-                    Ok::<(), ConflictableTransactionError<()>>(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
-        Ok(())
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics
+            .snapshot(self.active_count(), self.queue_depth())
     }
 
-    /// Removes a key-value pair
+    /// Gets up to `n` slow-operation log entries, newest first
     #[inline]
-    fn _kv_remove<K>(&self, key: K) -> Result<()>
-    where
-        K: AsRef<[u8]>,
-    {
-        #[cfg(not(feature = "ttl"))]
-        self.kv_tree.remove(key.as_ref())?;
-        #[cfg(feature = "ttl")]
-        {
-            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree)
-                .transaction(|(kv_tx, key_expire_tx, expire_key_tx)| {
-                    kv_tx.remove(key.as_ref())?;
-                    Self::_tx_remove_expire_key(key_expire_tx, expire_key_tx, key.as_ref())?;
-                    Ok::<(), ConflictableTransactionError<()>>(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
-        Ok(())
+    pub fn slowlog_get(&self, n: usize) -> Vec<SlowLogEntry> {
+        self.slowlog.get(n)
     }
 
-    /// Removes expiration key (TTL feature)
-    #[cfg(feature = "ttl")]
+    /// Gets the number of slow-operation log entries
     #[inline]
-    fn _remove_expire_key(&self, key: &[u8]) -> Result<()> {
-        if let Some(expire_at_bytes) = self.key_expire_tree.get(key)? {
-            self.key_expire_tree.remove(key)?;
-            let expire_key = [expire_at_bytes.as_ref(), key].concat();
-            self.expire_key_tree.remove(expire_key.as_slice())?;
-        }
-        Ok(())
+    pub fn slowlog_len(&self) -> usize {
+        self.slowlog.len()
     }
 
-    /// Transactionally removes expiration key (TTL feature)
-    #[cfg(feature = "ttl")]
+    /// Clears the slow-operation log
     #[inline]
-    fn _tx_remove_expire_key(
-        key_expire_tx: &TransactionalTree,
-        expire_key_tx: &TransactionalTree,
-        key: &[u8],
-    ) -> ConflictableTransactionResult<()> {
-        if let Some(expire_at_bytes) = key_expire_tx.get(key)? {
-            key_expire_tx.remove(key)?;
-            let expire_key = [expire_at_bytes.as_ref(), key].concat();
-            expire_key_tx.remove(expire_key.as_slice())?;
-        }
-        Ok(())
+    pub fn slowlog_reset(&self) {
+        self.slowlog.reset()
     }
 
-    /// Checks if a key is expired
+    /// Trims streams added to with a `maxlen` down to that length
+    ///
+    /// Called by the cleanup task, at most `limit` streams are trimmed per call.
     #[inline]
-    fn _is_expired<K, F>(&self, _key: K, _contains_key_f: F) -> Result<bool>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-        F: Fn(&[u8]) -> Result<bool>,
-    {
-        #[cfg(feature = "ttl")]
-        {
-            //获取过期时间
-            if let Some((expire_at, _)) = self._ttl_at(_key, _contains_key_f)? {
-                Ok(timestamp_millis() >= expire_at)
-            } else {
-                Ok(true)
-            }
-        }
-        #[cfg(not(feature = "ttl"))]
-        Ok(false)
+    pub async fn trim_streams(&self, limit: usize) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBStreamTrim(self.clone(), limit, tx))
+            .await?;
+        Ok(rx.await?)
     }
 
-    /// Gets time-to-live for a key
+    /// Subscribes to changes of keys, maps and lists of this namespace whose name matches `pattern`
+    ///
+    /// Events are published for the changes made by successful write commands, writes that
+    /// change nothing such as removing a missing key publish none, and for expirations
+    /// applied by the cleanup task.
     #[inline]
-    fn _ttl<K, F>(
-        &self,
-        key: K,
-        contains_key_f: F,
-    ) -> Result<Option<(TimestampMillis, Option<IVec>)>>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-        F: Fn(&[u8]) -> Result<bool>,
-    {
-        Ok(self
-            ._ttl_at(key, contains_key_f)?
-            .map(|(expire_at, at_bytes)| (expire_at - timestamp_millis(), at_bytes)))
+    pub fn subscribe<P: AsRef<[u8]>>(&self, pattern: P) -> KeyspaceSubscriber {
+        self.keyspace
+            .subscribe(pattern.as_ref(), self.namespace_name())
     }
 
-    /// Gets expiration time for a key
+    /// Returns the Pub/Sub message hub
     #[inline]
-    fn _ttl_at<K, F>(
-        &self,
-        c_key: K,
-        contains_key_f: F,
-    ) -> Result<Option<(TimestampMillis, Option<IVec>)>>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-        F: Fn(&[u8]) -> Result<bool>,
-    {
-        let ttl_res = match self.key_expire_tree.get(c_key.as_ref()) {
-            Ok(Some(at_bytes)) => {
-                if contains_key_f(c_key.as_ref())? {
-                    Ok(Some((
-                        TimestampMillis::from_be_bytes(at_bytes.as_ref().try_into()?),
-                        Some(at_bytes),
-                    )))
-                } else {
-                    Ok(None)
-                }
-            }
-            Ok(None) => {
-                if contains_key_f(c_key.as_ref())? {
-                    Ok(Some((TimestampMillis::MAX, None)))
-                } else {
-                    Ok(None)
-                }
-            }
-            Err(e) => Err(anyhow!(e)),
-        }?;
-        Ok(ttl_res)
+    pub(crate) fn pubsub_hub(&self) -> Arc<PubSubHub> {
+        self.pubsub.clone()
     }
 
-    /// Inserts a key-value pair
+    /// Appends a published message to the history of its channel
     #[inline]
-    fn _insert(&self, key: &[u8], val: &[u8]) -> Result<()> {
-        #[cfg(not(feature = "ttl"))]
-        self.kv_tree.insert(key, val)?;
-        #[cfg(feature = "ttl")]
-        {
-            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree)
-                .transaction(|(kv_tx, key_expire_tx, expire_keys_tx)| {
-                    kv_tx.insert(key, val)?;
-                    Self::_tx_remove_expire_key(key_expire_tx, expire_keys_tx, key)?;
-                    Ok::<(), ConflictableTransactionError<()>>(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
-        Ok(())
+    pub(crate) async fn pubsub_append(&self, msg: PubSubMessage) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBPublish(self.clone(), msg, tx))
+            .await?;
+        rx.await?
     }
 
-    /// Gets a value by key
+    /// Gets the retained messages of a channel, oldest first
     #[inline]
-    fn _get(&self, key: &[u8]) -> Result<Option<IVec>> {
-        let res = if self._is_expired(key, |k| Self::_kv_contains_key(&self.kv_tree, k))? {
-            None
-        } else {
-            self.kv_tree.get(key)?
-        };
-        Ok(res)
+    pub(crate) async fn pubsub_history(&self, channel: &[u8]) -> Result<Vec<PubSubMessage>> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBPubSubHistory(
+            self.clone(),
+            IVec::from(channel),
+            tx,
+        ))
+        .await?;
+        rx.await?
     }
 
-    /// Checks if a map key exists
+    /// Removes the retained messages of a channel
     #[inline]
-    fn _self_map_contains_key(&self, key: &[u8]) -> Result<bool> {
-        #[cfg(feature = "ttl")]
-        {
-            if self._is_expired(key, |k| Self::_map_contains_key(&self.map_tree, k))? {
-                Ok(false)
-            } else {
-                //Self::_map_contains_key(&self.map_tree, key)
-                Ok(true)
-            }
-        }
-
-        #[cfg(not(feature = "ttl"))]
-        Self::_map_contains_key(&self.map_tree, key)
+    pub(crate) async fn pubsub_clear_history(&self, channel: &[u8]) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBPubSubClearHistory(
+            self.clone(),
+            IVec::from(channel),
+            tx,
+        ))
+        .await?;
+        rx.await?
     }
 
-    /// Checks if a list key exists
+    /// Collects storage statistics
     #[inline]
-    fn _self_list_contains_key(&self, key: &[u8]) -> Result<bool> {
-        #[cfg(feature = "ttl")]
-        {
-            let this = self;
-            if this._is_expired(key, |k| Self::_list_contains_key(&self.list_tree, k))? {
-                Ok(false)
-            } else {
-                // Self::_list_contains_key(&this.list_tree, key)
-                Ok(true)
-            }
+    fn _storage_info(&self) -> StorageInfo {
+        let commands = CommandKind::ALL
+            .iter()
+            .map(|kind| (kind.as_str().to_string(), self.metrics.count(*kind)))
+            .collect();
+        StorageInfo {
+            storage_engine: "Sled".into(),
+            active_count: self.active_count(),
+            queue_depth: self.queue_depth(),
+            queue_capacity: self.cmd_tx.max_capacity(),
+            namespace: self.namespace_name().map(|name| name.to_vec()),
+            kv_keys: self.kv_tree.len(),
+            maps: self.map_size(),
+            lists: self.list_size(),
+            ttl_keys: self.key_expire_tree.len(),
+            size_on_disk: self.db.size_on_disk().unwrap_or_default(),
+            was_recovered: self.db.was_recovered(),
+            cache_capacity: self.cfg.cache_capacity.as_u64(),
+            commands,
+            cleanup: self.cleanup_stats.snapshot(),
+            eviction: self.eviction.info(),
         }
-
-        #[cfg(not(feature = "ttl"))]
-        Self::_list_contains_key(&self.list_tree, key)
     }
 
-    /// Batch insert key-value pairs
+    /// Gets storage statistics
     #[inline]
-    fn _batch_insert(&self, key_vals: Vec<(Key, IVec)>) -> Result<()> {
-        if key_vals.is_empty() {
-            return Ok(());
-        }
+    pub async fn storage_info(&self) -> Result<StorageInfo> {
+        let this = self.clone();
+        Ok(spawn_blocking(move || this._storage_info()).await?)
+    }
 
-        let mut batch = Batch::default();
-        for (k, v) in key_vals.iter() {
-            batch.insert(k.as_slice(), v.as_ref());
-        }
+    /// Opens a handle on the namespace `name`
+    ///
+    /// Keys, maps, lists and streams of a namespace live in their own trees of the
+    /// same database, so scans, iteration, `len()`, expiration and keyspace events
+    /// only see that namespace. Namespaces are not nested, calling this on a
+    /// namespace handle opens a sibling namespace.
+    #[inline]
+    pub fn namespace<N: AsRef<[u8]>>(&self, name: N) -> Result<Self> {
+        self._namespace(Some(name.as_ref()))
+    }
 
-        let this = self;
-        #[cfg(not(feature = "ttl"))]
-        this.kv_tree.apply_batch(batch)?;
+    /// Opens the trees of a namespace, `None` for the default namespace
+    #[inline]
+    fn _namespace(&self, name: Option<&[u8]>) -> Result<Self> {
+        let open = |tree: &[u8]| match name {
+            Some(name) => self
+                .db
+                .open_tree([Self::encode_name(NAMESPACE_PREFIX, name).as_slice(), tree].concat()),
+            None => self.db.open_tree(tree),
+        };
+        Ok(Self {
+            kv_tree: open(KV_TREE)?,
+            map_tree: open(MAP_TREE)?,
+            list_tree: open(LIST_TREE)?,
+            stream_tree: open(STREAM_TREE)?,
+            expire_key_tree: open(EXPIRE_KEYS_TREE)?,
+            key_expire_tree: open(KEY_EXPIRE_TREE)?,
+            access_tree: open(KV_ACCESS_TREE)?,
+            type_tree: open(TYPE_TREE)?,
+            namespace: name.map(|name| Arc::new(name.to_vec())),
+            quota: Self::namespace_quota(&self.db, &self.quotas, name)?,
+            ..self.clone()
+        })
+    }
 
-        #[cfg(feature = "ttl")]
-        {
-            let mut remove_key_expire_batch = Batch::default();
-            let mut remove_expire_key_batch = Batch::default();
-            for (k, _) in key_vals.iter() {
-                if let Some((expire_at, Some(expire_at_bytes))) =
-                    this._ttl(k.as_slice(), |k| Self::_kv_contains_key(&self.kv_tree, k))?
-                {
-                    if expire_at <= 0 {
-                        remove_key_expire_batch.remove(k.as_slice());
-                        let expire_key = [expire_at_bytes.as_ref(), k.as_slice()].concat();
-                        remove_expire_key_batch.remove(expire_key.as_slice())
-                    }
-                }
-            }
+    /// Returns the namespace of this handle, `None` for the default namespace
+    #[inline]
+    pub fn namespace_name(&self) -> Option<&[u8]> {
+        self.namespace.as_deref().map(|name| name.as_slice())
+    }
 
-            // this.key_expire_tree.apply_batch(remove_key_expire_batch)?;
-            // this.expire_key_tree.apply_batch(remove_expire_key_batch)?;
-            // this.kv_tree.apply_batch(batch)?;
-            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree)
-                .transaction(|(kv_tx, key_expire_tx, expire_key_tx)| {
-                    key_expire_tx.apply_batch(&remove_key_expire_batch)?;
-                    expire_key_tx.apply_batch(&remove_expire_key_batch)?;
-                    kv_tx.apply_batch(&batch)?;
-                    Ok::<(), ConflictableTransactionError<()>>(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
-        Ok(())
+    /// Lists the namespaces that were ever opened, without the default namespace
+    #[inline]
+    pub fn namespace_names(&self) -> Vec<Key> {
+        self.db
+            .tree_names()
+            .iter()
+            .filter_map(|tree| match Self::decode_name(NAMESPACE_PREFIX, tree) {
+                Some((name, rest)) if rest == KV_TREE => Some(name.to_vec()),
+                _ => None,
+            })
+            .collect()
     }
 
-    /// Batch remove keys
+    /// Opens handles on the default namespace followed by all other namespaces
     #[inline]
-    fn _batch_remove(&self, keys: Vec<Key>) -> Result<()> {
-        if keys.is_empty() {
-            return Ok(());
+    pub(crate) fn namespace_handles(&self) -> Result<Vec<Self>> {
+        let mut handles = vec![self._namespace(None)?];
+        for name in self.namespace_names() {
+            handles.push(self._namespace(Some(&name))?);
         }
+        Ok(handles)
+    }
 
-        let mut batch = Batch::default();
-        for k in keys.iter() {
-            batch.remove(k.as_slice());
+    /// Builds the keyspace events of a successful command for the namespace of this handle,
+    /// nothing is built without subscribers
+    #[inline]
+    fn _events<T, F>(&self, notify: bool, res: &Result<T>, events: F) -> Vec<KeyspaceEvent>
+    where
+        F: FnOnce(&T) -> Vec<KeyspaceEvent>,
+    {
+        match res {
+            Ok(v) if notify => self.in_namespace(events(v)),
+            _ => Vec::new(),
         }
-        #[cfg(not(feature = "ttl"))]
-        self.kv_tree.apply_batch(batch)?;
+    }
 
-        #[cfg(feature = "ttl")]
-        {
-            let mut remove_key_expire_batch = Batch::default();
-            let mut remove_expire_key_batch = Batch::default();
-            for k in keys.iter() {
-                if let Some(expire_at_bytes) = self.key_expire_tree.get(k)? {
-                    remove_key_expire_batch.remove(k.as_slice());
-                    let expire_key = [expire_at_bytes.as_ref(), k.as_slice()].concat();
-                    remove_expire_key_batch.remove(expire_key.as_slice())
-                }
+    /// Tags keyspace events with the namespace of this handle
+    #[inline]
+    fn in_namespace(&self, mut events: Vec<KeyspaceEvent>) -> Vec<KeyspaceEvent> {
+        if let Some(name) = self.namespace_name() {
+            for event in events.iter_mut() {
+                event.namespace = Some(name.to_vec());
             }
-            // this.key_expire_tree.apply_batch(remove_key_expire_batch)?;
-            // this.expire_key_tree.apply_batch(remove_expire_key_batch)?;
-            // this.kv_tree.apply_batch(batch)?;
-            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree)
-                .transaction(|(kv_tx, key_expire_tx, expire_key_tx)| {
-                    key_expire_tx.apply_batch(&remove_key_expire_batch)?;
-                    expire_key_tx.apply_batch(&remove_expire_key_batch)?;
-                    kv_tx.apply_batch(&batch)?;
-                    Ok::<(), ConflictableTransactionError<()>>(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
         }
-
+        events
+    }
+
+    /// Removes all keys, maps, lists and streams of this namespace
+    #[inline]
+    fn _flush_namespace(&self) -> Result<()> {
+        for tree in [
+            &self.kv_tree,
+            &self.map_tree,
+            &self.list_tree,
+            &self.stream_tree,
+            &self.expire_key_tree,
+            &self.key_expire_tree,
+            &self.access_tree,
+            &self.type_tree,
+        ] {
+            tree.clear()?;
+        }
+        self.quota.invalidate();
+        //recount the usage, the removed keys no longer count towards the limit
+        if self.eviction.is_enabled() {
+            self.eviction.invalidate();
+            self.evict()?;
+        }
         Ok(())
     }
 
-    /// Increments a counter
+    /// Removes all keys, maps, lists and streams of this handle's namespace
+    ///
+    /// Other namespaces are left untouched, on a default handle only the default
+    /// namespace is flushed.
     #[inline]
-    fn _counter_incr(&self, key: &[u8], increment: isize) -> Result<()> {
-        self.kv_tree.fetch_and_update(key, |old: Option<&[u8]>| {
-            let number = match old {
-                Some(bytes) => {
-                    if let Ok(array) = bytes.try_into() {
-                        let number = isize::from_be_bytes(array);
-                        number + increment
-                    } else {
-                        increment
-                    }
-                }
-                None => increment,
-            };
-            Some(number.to_be_bytes().to_vec())
-        })?;
-        Ok(())
+    pub async fn flush_namespace(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBFlushNamespace(self.clone(), tx))
+            .await?;
+        rx.await?
     }
 
-    /// Decrements a counter
+    /// Counts the keys of this namespace
     #[inline]
-    fn _counter_decr(&self, key: &[u8], decrement: isize) -> Result<()> {
-        self.kv_tree.fetch_and_update(key, |old: Option<&[u8]>| {
-            let number = match old {
-                Some(bytes) => {
-                    if let Ok(array) = bytes.try_into() {
-                        let number = isize::from_be_bytes(array);
-                        number - decrement
-                    } else {
-                        -decrement
-                    }
-                }
-                None => -decrement,
-            };
-            Some(number.to_be_bytes().to_vec())
-        })?;
-        Ok(())
+    fn _namespace_info(&self) -> NamespaceInfo {
+        NamespaceInfo {
+            name: self.namespace_name().map(|name| name.to_vec()),
+            kv_keys: self.kv_tree.len(),
+            maps: self.map_size(),
+            lists: self.list_size(),
+            ttl_keys: self.key_expire_tree.len(),
+            quota: self.quota.get(),
+        }
     }
 
-    /// Gets counter value
+    /// Gets the key counts of the default namespace and all other namespaces
     #[inline]
-    fn _counter_get(&self, key: &[u8]) -> Result<Option<isize>> {
-        let this = self;
-        if this._is_expired(key, |k| Self::_kv_contains_key(&self.kv_tree, k))? {
-            Ok(None)
-        } else if let Some(v) = this.kv_tree.get(key)? {
-            Ok(Some(isize::from_be_bytes(v.as_ref().try_into()?)))
-        } else {
-            Ok(None)
+    pub async fn namespaces(&self) -> Result<Vec<NamespaceInfo>> {
+        let this = self.clone();
+        spawn_blocking(move || {
+            Ok(this
+                .namespace_handles()?
+                .iter()
+                .map(|db| db._namespace_info())
+                .collect())
+        })
+        .await?
+    }
+
+    /// Gets the quota state of a namespace, loading its stored quota when first opened
+    fn namespace_quota(
+        db: &sled::Db,
+        quotas: &Mutex<HashMap<Option<Key>, Arc<NamespaceQuota>>>,
+        name: Option<&[u8]>,
+    ) -> Result<Arc<NamespaceQuota>> {
+        let mut quotas = quotas.lock().unwrap_or_else(|e| e.into_inner());
+        let name = name.map(|name| name.to_vec());
+        if let Some(quota) = quotas.get(&name) {
+            return Ok(quota.clone());
+        }
+        let stored = db
+            .open_tree(META_TREE)?
+            .get(Self::quota_key(name.as_deref()))?
+            .map(|v| bincode::deserialize::<Quota>(v.as_ref()))
+            .transpose()?;
+        let quota = Arc::new(NamespaceQuota::new(name.clone(), stored));
+        quotas.insert(name, quota.clone());
+        Ok(quota)
+    }
+
+    /// Metadata key holding the quota of a namespace
+    #[inline]
+    fn quota_key(name: Option<&[u8]>) -> Key {
+        match name {
+            Some(name) => Self::encode_name(QUOTA_PREFIX, name),
+            None => QUOTA_PREFIX.to_vec(),
         }
     }
 
-    /// Sets counter value
+    /// Sets or removes the quota of this handle's namespace
+    ///
+    /// The quota is stored in the database and applies to all handles on the namespace.
     #[inline]
-    fn _counter_set(&self, key: &[u8], val: isize) -> Result<()> {
-        let val = val.to_be_bytes().to_vec();
-
-        #[cfg(not(feature = "ttl"))]
-        self.kv_tree.insert(key, val.as_slice())?;
-        #[cfg(feature = "ttl")]
-        {
-            // self._remove_expire_key(key)?;
-            // kv_tree.insert(key, val.as_slice())?;
-            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree)
-                .transaction(|(kv_tx, key_expire_tx, expire_key_tx)| {
-                    Self::_tx_remove_expire_key(key_expire_tx, expire_key_tx, key)?;
-                    kv_tx.insert(key, val.as_slice())?;
-                    Ok::<(), ConflictableTransactionError<()>>(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
+    pub fn set_quota(&self, quota: Option<Quota>) -> Result<()> {
+        let meta_tree = self.db.open_tree(META_TREE)?;
+        let key = Self::quota_key(self.namespace_name());
+        match &quota {
+            Some(quota) => meta_tree.insert(key, bincode::serialize(quota)?)?,
+            None => meta_tree.remove(key)?,
+        };
+        self.quota.set(quota);
         Ok(())
     }
 
-    /// Checks if a key exists in key-value store
+    /// Gets the quota of this handle's namespace
     #[inline]
-    fn _self_contains_key(&self, key: &[u8]) -> Result<bool> {
-        #[cfg(feature = "ttl")]
-        {
-            let this = self;
-            if this._is_expired(key, |k| Self::_kv_contains_key(&self.kv_tree, k))? {
-                Ok(false)
-            } else {
-                // this._contains_key(key, KeyType::KV)
-                Ok(true)
+    pub fn quota(&self) -> Option<Quota> {
+        self.quota.get()
+    }
+
+    /// Counts the entries and value bytes of this namespace that quotas apply to
+    fn _quota_count(&self) -> Result<QuotaUsage> {
+        let mut usage = QuotaUsage::default();
+        for item in self.kv_tree.iter() {
+            let (_, val) = item?;
+            usage.keys += 1;
+            usage.bytes += val.len() as u64;
+        }
+        for item in self.map_tree.scan_prefix(MAP_NAME_PREFIX) {
+            let (key, val) = item?;
+            if matches!(Self::decode_name(MAP_NAME_PREFIX, &key), Some((_, rest)) if rest.starts_with(MAP_KEY_SEPARATOR))
+            {
+                usage.keys += 1;
+                usage.bytes += val.len() as u64;
             }
         }
-        #[cfg(not(feature = "ttl"))]
-        Self::_kv_contains_key(&self.kv_tree, key)
+        for item in self.list_tree.scan_prefix(LIST_NAME_PREFIX) {
+            let (key, val) = item?;
+            if matches!(Self::decode_name(LIST_NAME_PREFIX, &key), Some((_, rest)) if rest.starts_with(LIST_KEY_CONTENT_SUFFIX))
+            {
+                usage.keys += 1;
+                usage.bytes += val.len() as u64;
+            }
+        }
+        Ok(usage)
     }
 
-    /// Sets expiration time for a key (TTL feature)
+    /// Checks a write against the namespace quota and reserves its usage
+    ///
+    /// `writes` holds the previous value length, `None` for new entries, and the
+    /// new value length of every written entry. The reservation has to be committed
+    /// once the write succeeded.
     #[inline]
-    #[cfg(feature = "ttl")]
-    fn _expire_at(&self, key: &[u8], at: TimestampMillis, key_type: KeyType) -> Result<bool> {
-        if self._contains_key(key, key_type)? {
-            let res = (&self.key_expire_tree, &self.expire_key_tree)
-                .transaction(|(key_expire_tx, expire_key_tx)| {
-                    Self::_tx_expire_at(key_expire_tx, expire_key_tx, key, at, key_type)
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-            Ok(res)
-        } else {
-            Ok(false)
+    fn _quota_reserve<I>(&self, writes: I) -> Result<QuotaReservation<'_>>
+    where
+        I: IntoIterator<Item = (Option<usize>, usize)>,
+    {
+        let (mut keys, mut bytes, mut value_size) = (0i64, 0i64, 0u64);
+        for (old, new) in writes {
+            keys += old.is_none() as i64;
+            bytes += new as i64 - old.unwrap_or_default() as i64;
+            value_size = value_size.max(new as u64);
         }
+        self.quota
+            .reserve(keys, bytes, value_size, || self._quota_count())
     }
 
-    /// Transactionally sets expiration time (TTL feature)
+    /// Checks a key-value write against the namespace quota and reserves its usage
     #[inline]
-    #[cfg(feature = "ttl")]
-    fn _tx_expire_at(
-        key_expire_tx: &TransactionalTree,
-        expire_key_tx: &TransactionalTree,
-        key: &[u8],
-        at: TimestampMillis,
-        key_type: KeyType,
-    ) -> ConflictableTransactionResult<bool> {
-        let at_bytes = at.to_be_bytes();
-        key_expire_tx.insert(key, at_bytes.as_slice())?;
-        let res = expire_key_tx
-            .insert([at_bytes.as_ref(), key].concat(), key_type.encode())
-            .map(|_| true)?;
-        Ok(res)
+    fn _quota_reserve_kv(&self, key: &[u8], val_len: usize) -> Result<QuotaReservation<'_>> {
+        if !self.quota.is_enabled() {
+            return Ok(QuotaReservation::default());
+        }
+        let old = self.kv_tree.get(key)?.map(|v| v.len());
+        self._quota_reserve([(old, val_len)])
     }
 
-    /// Gets time-to-live for a key (TTL feature)
+    /// Takes a removed value off the namespace usage
     #[inline]
-    #[cfg(feature = "ttl")]
-    fn _self_ttl(&self, key: &[u8]) -> Result<Option<TimestampMillis>> {
-        Ok(self
-            ._ttl(key, |k| Self::_kv_contains_key(&self.kv_tree, k))?
-            .and_then(|(ttl, _)| if ttl > 0 { Some(ttl) } else { None }))
+    fn _quota_release(&self, removed: Option<&IVec>) {
+        if let Some(v) = removed {
+            self.quota.release(QuotaUsage {
+                keys: 1,
+                bytes: v.len() as u64,
+            });
+        }
     }
 
-    /// Creates an iterator for map prefixes
+    /// Gets the entries and value bytes of this namespace that quotas apply to
     #[inline]
-    fn _map_scan_prefix(&self) -> sled::Iter {
-        self.map_tree.scan_prefix(MAP_NAME_PREFIX)
+    pub async fn quota_usage(&self) -> Result<QuotaUsage> {
+        let this = self.clone();
+        spawn_blocking(move || this.quota.current(|| this._quota_count())).await?
     }
 
-    /// Creates an iterator for list prefixes
+    /// Encodes a map, list or stream name behind its length, so it cannot collide with separators
     #[inline]
-    fn _list_scan_prefix(&self) -> sled::Iter {
-        self.list_tree.scan_prefix(LIST_NAME_PREFIX)
+    fn encode_name(prefix: &[u8], name: &[u8]) -> Key {
+        [prefix, (name.len() as u32).to_be_bytes().as_slice(), name].concat()
     }
 
-    /// Creates an iterator for database scan with pattern
+    /// Decodes a length-prefixed name, returns the name and the rest of the key
     #[inline]
-    fn _db_scan_prefix(&self, pattern: Vec<u8>) -> sled::Iter {
-        let mut last_esc_char = false;
-        let mut has_esc_char = false;
-        let start_pattern = pattern
-            .splitn(2, |x| {
-                if !last_esc_char && (*x == b'*' || *x == b'?') {
-                    true
-                } else {
-                    last_esc_char = *x == b'\\';
-                    if last_esc_char && !has_esc_char {
-                        has_esc_char = true;
-                    }
-                    false
-                }
-            })
-            .next();
-        let start_pattern = if has_esc_char {
-            start_pattern.map(|start_pattern| {
-                Cow::Owned(
-                    start_pattern
-                        .replace(b"\\*", b"*")
-                        .as_slice()
-                        .replace(b"\\?", b"?"),
-                )
-            })
-        } else {
-            start_pattern.map(Cow::Borrowed)
-        };
-        let iter = if let Some(start_pattern) = start_pattern {
-            self.kv_tree.scan_prefix(start_pattern.as_ref())
-        } else {
-            self.kv_tree.iter()
-        };
-        iter
+    fn decode_name<'a>(prefix: &[u8], key: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+        let (len, rest) = key.strip_prefix(prefix)?.split_first_chunk::<4>()?;
+        let len = u32::from_be_bytes(*len) as usize;
+        (rest.len() >= len).then(|| rest.split_at(len))
     }
 
-    /// Gets number of key-value pairs
+    /// Creates a map prefix name
     #[inline]
-    fn _kv_len(&self) -> usize {
-        #[cfg(feature = "ttl")]
-        {
-            let limit = 500;
-            loop {
-                if self.cleanup_kvs(limit) < limit {
-                    break;
-                }
-            }
-        }
-        self.kv_tree.len()
-    }
-
+    fn make_map_prefix_name<K>(name: K) -> Key
+    where
+        K: AsRef<[u8]>,
+    {
+        [
+            Self::encode_name(MAP_NAME_PREFIX, name.as_ref()).as_slice(),
+            SEPARATOR,
+        ]
+        .concat()
+    }
+
+    /// Creates a map item prefix name
+    #[inline]
+    fn make_map_item_prefix_name<K>(name: K) -> Key
+    where
+        K: AsRef<[u8]>,
+    {
+        [
+            Self::encode_name(MAP_NAME_PREFIX, name.as_ref()).as_slice(),
+            MAP_KEY_SEPARATOR,
+        ]
+        .concat()
+    }
+
+    /// Creates a map count key name
+    #[inline]
+    fn make_map_count_key_name<K>(name: K) -> Key
+    where
+        K: AsRef<[u8]>,
+    {
+        [
+            Self::encode_name(MAP_NAME_PREFIX, name.as_ref()).as_slice(),
+            MAP_KEY_COUNT_SUFFIX,
+        ]
+        .concat()
+    }
+
+    /// Creates the prefix of map item expiration times
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn make_map_field_expire_prefix_name<K>(name: K) -> Key
+    where
+        K: AsRef<[u8]>,
+    {
+        [
+            Self::encode_name(MAP_NAME_PREFIX, name.as_ref()).as_slice(),
+            MAP_FIELD_EXPIRE_SEPARATOR,
+        ]
+        .concat()
+    }
+
+    /// Creates the key of a map item in the index of expiring items
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn make_map_field_expire_index_key(at: &[u8], name: &[u8], key: &[u8]) -> Key {
+        let prefix = [MAP_FIELD_EXPIRE_PREFIX, at].concat();
+        [Self::encode_name(&prefix, name).as_slice(), key].concat()
+    }
+
+    /// Splits a key of the index of expiring map items into expiration time, map name and item key
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn decode_map_field_expire_index_key(key: &[u8]) -> Option<(TimestampMillis, &[u8], &[u8])> {
+        let prefix = key.get(..MAP_FIELD_EXPIRE_PREFIX.len() + 8)?;
+        let at = TimestampMillis::from_be_bytes(
+            prefix[MAP_FIELD_EXPIRE_PREFIX.len()..].try_into().ok()?,
+        );
+        let (name, key) = Self::decode_name(prefix, key)?;
+        Some((at, name, key))
+    }
+
+    /// Extracts map name from count key
+    #[inline]
+    fn map_count_key_to_name(key: &[u8]) -> &[u8] {
+        Self::decode_name(MAP_NAME_PREFIX, key)
+            .map(|(name, _)| name)
+            .unwrap_or_default()
+    }
+
+    /// Checks if a key is a map count key
+    #[inline]
+    fn is_map_count_key(key: &[u8]) -> bool {
+        matches!(Self::decode_name(MAP_NAME_PREFIX, key), Some((_, rest)) if rest == MAP_KEY_COUNT_SUFFIX)
+    }
+
+    /// Extracts map name from item key
+    #[inline]
+    fn map_item_key_to_name(key: &[u8]) -> Option<&[u8]> {
+        let (name, rest) = Self::decode_name(MAP_NAME_PREFIX, key)?;
+        rest.starts_with(MAP_KEY_SEPARATOR).then_some(name)
+    }
+
+    /// Extracts list name and index from a content key
+    #[inline]
+    fn list_content_key_to_name(key: &[u8]) -> Option<(&[u8], u64)> {
+        let (name, rest) = Self::decode_name(LIST_NAME_PREFIX, key)?;
+        let idx = rest.strip_prefix(LIST_KEY_CONTENT_SUFFIX)?;
+        Some((name, u64::from_be_bytes(idx.try_into().ok()?)))
+    }
+
+    /// Creates a list prefix
+    #[inline]
+    fn make_list_prefix<K>(name: K) -> Key
+    where
+        K: AsRef<[u8]>,
+    {
+        Self::encode_name(LIST_NAME_PREFIX, name.as_ref())
+    }
+
+    /// Creates a stream prefix
+    #[inline]
+    fn make_stream_prefix<K>(name: K) -> Key
+    where
+        K: AsRef<[u8]>,
+    {
+        Self::encode_name(STREAM_NAME_PREFIX, name.as_ref())
+    }
+
+    /// Creates a list count key
+    #[inline]
+    fn make_list_count_key(name: &[u8]) -> Vec<u8> {
+        [
+            Self::make_list_prefix(name).as_slice(),
+            LIST_KEY_COUNT_SUFFIX,
+        ]
+        .concat()
+    }
+
+    /// Extracts list name from count key
+    #[inline]
+    fn list_count_key_to_name(key: &[u8]) -> &[u8] {
+        Self::decode_name(LIST_NAME_PREFIX, key)
+            .map(|(name, _)| name)
+            .unwrap_or_default()
+    }
+
+    /// Checks if a key is a list count key
+    #[inline]
+    fn is_list_count_key(key: &[u8]) -> bool {
+        matches!(Self::decode_name(LIST_NAME_PREFIX, key), Some((_, rest)) if rest == LIST_KEY_COUNT_SUFFIX)
+    }
+
+    /// Migration step rewriting map and list keys from the legacy `prefix@name@suffix` layout
+    ///
+    /// Keys are converted one by one into staging trees. Names containing separators
+    /// are resolved where possible by looking up the legacy count keys, the longest
+    /// name with a count key wins for map items. Unrecognized keys are kept as they are.
+    pub(crate) fn migrate_key_layout(ctx: &mut MigrationContext) -> Result<u64> {
+        use super::SplitSubslice;
+        let map_tree = ctx.db.open_tree(MAP_TREE)?;
+        let list_tree = ctx.db.open_tree(LIST_TREE)?;
+        let mut changed = 0;
+
+        //maps, count keys hold no item separator
+        let is_count_key = |rest: &[u8]| {
+            rest.ends_with(MAP_KEY_COUNT_SUFFIX) && rest.split_subslice(MAP_KEY_SEPARATOR).is_none()
+        };
+        let staging = (!ctx.dry_run)
+            .then(|| ctx.staging_tree(MAP_TREE))
+            .transpose()?;
+        for item in map_tree.iter() {
+            let (key, value) = item?;
+            let new_key = match key.strip_prefix(MAP_NAME_PREFIX) {
+                Some(rest) if is_count_key(rest) => Some(Self::make_map_count_key_name(
+                    &rest[..rest.len() - MAP_KEY_COUNT_SUFFIX.len()],
+                )),
+                Some(rest) => {
+                    let mut name_len = None;
+                    for at in (0..rest.len()).rev() {
+                        if rest[at..].starts_with(MAP_KEY_SEPARATOR)
+                            && is_count_key(&[&rest[..at], MAP_KEY_COUNT_SUFFIX].concat())
+                            && map_tree.contains_key(
+                                [MAP_NAME_PREFIX, &rest[..at], MAP_KEY_COUNT_SUFFIX].concat(),
+                            )?
+                        {
+                            name_len = Some(at);
+                            break;
+                        }
+                    }
+                    match name_len {
+                        Some(at) => {
+                            let field = &rest[at + MAP_KEY_SEPARATOR.len()..];
+                            Some(
+                                [
+                                    Self::make_map_item_prefix_name(&rest[..at]).as_slice(),
+                                    field,
+                                ]
+                                .concat(),
+                            )
+                        }
+                        None => rest.split_subslice(MAP_KEY_SEPARATOR).map(|(name, field)| {
+                            let name = &name[..name.len() - MAP_KEY_SEPARATOR.len()];
+                            [Self::make_map_item_prefix_name(name).as_slice(), field].concat()
+                        }),
+                    }
+                }
+                None => None,
+            };
+            Self::_stage_migrated(ctx, staging.as_ref(), &key, new_key, value, &mut changed)?;
+        }
+
+        //lists, content keys end with a `usize` index of 8 or 4 bytes, depending on the host
+        let staging = (!ctx.dry_run)
+            .then(|| ctx.staging_tree(LIST_TREE))
+            .transpose()?;
+        for item in list_tree.iter() {
+            let (key, value) = item?;
+            let new_key = key.strip_prefix(LIST_NAME_PREFIX).and_then(|rest| {
+                let content_at = [8, 4].into_iter().find_map(|idx_len| {
+                    rest.len()
+                        .checked_sub(LIST_KEY_CONTENT_SUFFIX.len() + idx_len)
+                        .filter(|at| rest[*at..].starts_with(LIST_KEY_CONTENT_SUFFIX))
+                });
+                match content_at {
+                    Some(at) => Some(SledStorageList::make_list_content_prefix(
+                        Self::make_list_prefix(&rest[..at]).as_slice(),
+                        Some(&rest[at + LIST_KEY_CONTENT_SUFFIX.len()..]),
+                    )),
+                    _ if rest.ends_with(LIST_KEY_COUNT_SUFFIX) => Some(Self::make_list_count_key(
+                        &rest[..rest.len() - LIST_KEY_COUNT_SUFFIX.len()],
+                    )),
+                    _ => None,
+                }
+            });
+            Self::_stage_migrated(ctx, staging.as_ref(), &key, new_key, value, &mut changed)?;
+        }
+        Ok(changed)
+    }
+
+    /// Writes a key converted by a migration step into its staging tree, `None` keeps the key
+    ///
+    /// Nothing is written in a dry run, converted keys are only counted.
+    #[inline]
+    fn _stage_migrated(
+        ctx: &mut MigrationContext,
+        staging: Option<&Tree>,
+        key: &[u8],
+        new_key: Option<Key>,
+        value: IVec,
+        changed: &mut u64,
+    ) -> Result<()> {
+        match new_key {
+            Some(new_key) => {
+                *changed += 1;
+                ctx.tick();
+                if let Some(staging) = staging {
+                    staging.insert(new_key, value)?;
+                }
+            }
+            None => {
+                if [MAP_NAME_PREFIX, LIST_NAME_PREFIX, STREAM_NAME_PREFIX]
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix))
+                {
+                    log::warn!("keeping unrecognized key {:?}", key);
+                }
+                if let Some(staging) = staging {
+                    staging.insert(key, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Migration step widening map counts and list indexes written as 4 bytes by 32-bit hosts
+    ///
+    /// List bounds are already stored as two `u64` by bincode. Counters in the key-value
+    /// tree cannot be told apart from other values, they are widened on their next update.
+    /// Keys are rewritten in place in bounded batches, a rewritten key is never matched
+    /// again, so an interrupted run continues where it stopped.
+    pub(crate) fn migrate_fixed_width(ctx: &mut MigrationContext) -> Result<u64> {
+        let map_tree = ctx.db.open_tree(MAP_TREE)?;
+        let list_tree = ctx.db.open_tree(LIST_TREE)?;
+        let mut changed = 0;
+        let mut batch = Batch::default();
+        let mut pending = 0;
+        for item in map_tree.scan_prefix(MAP_NAME_PREFIX) {
+            let (key, value) = item?;
+            ctx.tick();
+            if value.len() == 4
+                && Self::is_map_count_key(&key)
+                && let Some(count) = decode_counter(value.as_ref())
+            {
+                batch.insert(key, count.to_be_bytes().as_slice());
+                changed += 1;
+                pending += 1;
+                if !ctx.dry_run && pending == MIGRATION_BATCH_SIZE {
+                    map_tree.apply_batch(std::mem::take(&mut batch))?;
+                    pending = 0;
+                }
+            }
+        }
+        if !ctx.dry_run {
+            map_tree.apply_batch(std::mem::take(&mut batch))?;
+        }
+        pending = 0;
+        for item in list_tree.scan_prefix(LIST_NAME_PREFIX) {
+            let (key, value) = item?;
+            ctx.tick();
+            if let Some((name, rest)) = Self::decode_name(LIST_NAME_PREFIX, &key)
+                && let Some(idx) = rest.strip_prefix(LIST_KEY_CONTENT_SUFFIX)
+                && let Ok(idx) = <[u8; 4]>::try_from(idx)
+            {
+                let new_key = SledStorageList::make_list_content_prefix(
+                    Self::make_list_prefix(name).as_slice(),
+                    Some((u32::from_be_bytes(idx) as u64).to_be_bytes().as_slice()),
+                );
+                batch.remove(key.as_ref());
+                batch.insert(new_key, value);
+                changed += 1;
+                pending += 1;
+                if !ctx.dry_run && pending == MIGRATION_BATCH_SIZE {
+                    list_tree.apply_batch(std::mem::take(&mut batch))?;
+                    pending = 0;
+                }
+            }
+        }
+        if !ctx.dry_run {
+            list_tree.apply_batch(batch)?;
+        }
+        Ok(changed)
+    }
+
+    /// Migration step keying expiration entries by type and name instead of the bare name
+    ///
+    /// The expiration trees of every namespace are converted entry by entry into staging
+    /// trees. The type comes from the time-ordered entries. Where one type has several,
+    /// the time stored for the name wins, otherwise the latest. Stored times without a
+    /// time-ordered entry go to the type holding the name, or are dropped.
+    pub(crate) fn migrate_expire_keys(ctx: &mut MigrationContext) -> Result<u64> {
+        let mut changed = 0;
+        for prefix in Self::namespace_tree_prefixes(ctx.db, KEY_EXPIRE_TREE) {
+            let open = |tree: &[u8]| ctx.db.open_tree([prefix.as_slice(), tree].concat());
+            let key_expire_tree = open(KEY_EXPIRE_TREE)?;
+            let expire_key_tree = open(EXPIRE_KEYS_TREE)?;
+            let staging = if ctx.dry_run {
+                None
+            } else {
+                Some((
+                    ctx.staging_tree(&[prefix.as_slice(), KEY_EXPIRE_TREE].concat())?,
+                    ctx.staging_tree(&[prefix.as_slice(), EXPIRE_KEYS_TREE].concat())?,
+                ))
+            };
+
+            //typed key => expiration time, from the time-ordered entries
+            for item in expire_key_tree.iter() {
+                let (at_key, key_type) = item?;
+                ctx.tick();
+                let Ok(key_type) = KeyType::decode(key_type.as_ref()) else {
+                    continue;
+                };
+                if at_key.len() < 8 {
+                    continue;
+                }
+                changed += 1;
+                let Some((key_expire_staging, _)) = staging.as_ref() else {
+                    continue;
+                };
+                let (at, name) = at_key.split_at(8);
+                let stored = key_expire_tree.get(name)?;
+                let is_stored = |at: &[u8]| stored.as_deref() == Some(at);
+                let typed_key = Self::make_expire_key(key_type, name);
+                let replace = match key_expire_staging.get(&typed_key)? {
+                    Some(old_at) => !is_stored(&old_at) && (is_stored(at) || at > old_at.as_ref()),
+                    None => true,
+                };
+                if replace {
+                    key_expire_staging.insert(typed_key, at)?;
+                }
+            }
+
+            //stored times without a time-ordered entry
+            let data_trees = [
+                open(KV_TREE)?,
+                open(MAP_TREE)?,
+                open(LIST_TREE)?,
+                open(STREAM_TREE)?,
+            ];
+            for item in key_expire_tree.iter() {
+                let (name, at) = item?;
+                ctx.tick();
+                let has_entry = match staging.as_ref() {
+                    Some((key_expire_staging, _)) => {
+                        KeyType::ALL.iter().try_fold(false, |found, t| {
+                            Ok::<_, StorageError>(
+                                found
+                                    || key_expire_staging
+                                        .contains_key(Self::make_expire_key(*t, &name))?,
+                            )
+                        })?
+                    }
+                    //a dry run has no staged entries, an entry at the stored time stands for them
+                    None => expire_key_tree
+                        .contains_key([ExpireMeta::at_bytes(&at), name.as_ref()].concat())?,
+                };
+                if has_entry {
+                    continue;
+                }
+                for (key_type, tree) in KeyType::ALL.into_iter().zip(data_trees.iter()) {
+                    if Self::_typed_contains_key(tree, &name, key_type)? {
+                        changed += 1;
+                        if let Some((key_expire_staging, _)) = staging.as_ref() {
+                            key_expire_staging
+                                .insert(Self::make_expire_key(key_type, &name), at)?;
+                        }
+                        break;
+                    }
+                }
+            }
+
+            //time-ordered entries follow the chosen times
+            if let Some((key_expire_staging, expire_key_staging)) = staging.as_ref() {
+                for item in key_expire_staging.iter() {
+                    let (typed_key, at) = item?;
+                    let Some(key_type) = typed_key.first() else {
+                        continue;
+                    };
+                    expire_key_staging
+                        .insert([at.as_ref(), typed_key.as_ref()].concat(), &[*key_type])?;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Migration step rewriting stream keys from the legacy `prefix name suffix` layout
+    ///
+    /// Keys are converted one by one into staging trees. Consumer group keys of names
+    /// containing separators are resolved where possible by looking up the legacy
+    /// metadata keys, the longest name with metadata wins. Unrecognized keys are kept.
+    pub(crate) fn migrate_stream_names(ctx: &mut MigrationContext) -> Result<u64> {
+        let mut changed = 0;
+        for prefix in Self::namespace_tree_prefixes(ctx.db, STREAM_TREE) {
+            let tree_name = [prefix.as_slice(), STREAM_TREE].concat();
+            let stream_tree = ctx.db.open_tree(&tree_name)?;
+            let staging = (!ctx.dry_run)
+                .then(|| ctx.staging_tree(&tree_name))
+                .transpose()?;
+            let id_len = StreamId::MAX.to_bytes().len();
+            for item in stream_tree.iter() {
+                let (key, value) = item?;
+                let new_key = match key.strip_prefix(STREAM_NAME_PREFIX) {
+                    Some(rest) if rest.ends_with(STREAM_KEY_META_SUFFIX) => {
+                        let name = &rest[..rest.len() - STREAM_KEY_META_SUFFIX.len()];
+                        Some(
+                            [
+                                Self::make_stream_prefix(name).as_slice(),
+                                STREAM_KEY_META_SUFFIX,
+                            ]
+                            .concat(),
+                        )
+                    }
+                    Some(rest)
+                        if rest.len() >= STREAM_KEY_ENTRY_SUFFIX.len() + id_len
+                            && rest[..rest.len() - id_len].ends_with(STREAM_KEY_ENTRY_SUFFIX) =>
+                    {
+                        let (name, id) = rest.split_at(rest.len() - id_len);
+                        let name = &name[..name.len() - STREAM_KEY_ENTRY_SUFFIX.len()];
+                        Some(
+                            [
+                                Self::make_stream_prefix(name).as_slice(),
+                                STREAM_KEY_ENTRY_SUFFIX,
+                                id,
+                            ]
+                            .concat(),
+                        )
+                    }
+                    Some(rest) => {
+                        let mut found = None;
+                        let mut first = None;
+                        for at in (0..rest.len()).rev() {
+                            if !rest[at..].starts_with(STREAM_KEY_GROUP_SUFFIX) {
+                                continue;
+                            }
+                            first = Some(at);
+                            let meta_key =
+                                [STREAM_NAME_PREFIX, &rest[..at], STREAM_KEY_META_SUFFIX].concat();
+                            if found.is_none() && stream_tree.contains_key(meta_key)? {
+                                found = Some(at);
+                            }
+                        }
+                        found.or(first).map(|at| {
+                            let (name, group) = rest.split_at(at);
+                            [Self::make_stream_prefix(name).as_slice(), group].concat()
+                        })
+                    }
+                    None => None,
+                };
+                Self::_stage_migrated(ctx, staging.as_ref(), &key, new_key, value, &mut changed)?;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Migration step recording the type of every key, map, list and stream in the type index
+    ///
+    /// A name stored by several types is recorded with the first one of `KeyType::ALL`.
+    pub(crate) fn migrate_type_index(ctx: &mut MigrationContext) -> Result<u64> {
+        let mut changed = 0;
+        for prefix in Self::namespace_tree_prefixes(ctx.db, KV_TREE) {
+            let mut data_trees = Vec::new();
+            for tree in [KV_TREE, MAP_TREE, LIST_TREE, STREAM_TREE] {
+                data_trees.push(ctx.db.open_tree([prefix.as_slice(), tree].concat())?);
+            }
+            let staging = (!ctx.dry_run)
+                .then(|| ctx.staging_tree(&[prefix.as_slice(), TYPE_TREE].concat()))
+                .transpose()?;
+            for (i, key_type) in KeyType::ALL.into_iter().enumerate() {
+                let name_prefix = match key_type {
+                    KeyType::KV => &[][..],
+                    KeyType::Map => MAP_NAME_PREFIX,
+                    KeyType::List => LIST_NAME_PREFIX,
+                    KeyType::Stream => STREAM_NAME_PREFIX,
+                };
+                for key in data_trees[i].scan_prefix(name_prefix).keys() {
+                    let key = key?;
+                    ctx.tick();
+                    let name = match key_type {
+                        KeyType::KV => Some(key.as_ref()),
+                        KeyType::Map => {
+                            Self::is_map_count_key(&key).then(|| Self::map_count_key_to_name(&key))
+                        }
+                        KeyType::List => Self::is_list_count_key(&key)
+                            .then(|| Self::list_count_key_to_name(&key)),
+                        KeyType::Stream => match Self::decode_name(STREAM_NAME_PREFIX, &key) {
+                            Some((name, STREAM_KEY_META_SUFFIX)) => Some(name),
+                            _ => None,
+                        },
+                    };
+                    let Some(name) = name else {
+                        continue;
+                    };
+                    let mut held = false;
+                    for (t, tree) in KeyType::ALL[..i].iter().zip(data_trees.iter()) {
+                        held = held || Self::_typed_contains_key(tree, name, *t)?;
+                    }
+                    if held {
+                        continue;
+                    }
+                    changed += 1;
+                    if let Some(staging) = staging.as_ref() {
+                        staging.insert(name, key_type.encode())?;
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Tree name prefixes of the default namespace and of every namespace holding `tree`
+    #[inline]
+    fn namespace_tree_prefixes(db: &sled::Db, tree: &[u8]) -> Vec<Key> {
+        let mut prefixes = vec![Vec::new()];
+        for name in db.tree_names() {
+            if let Some((_, rest)) = Self::decode_name(NAMESPACE_PREFIX, &name)
+                && rest == tree
+            {
+                prefixes.push(name[..name.len() - rest.len()].to_vec());
+            }
+        }
+        prefixes
+    }
+
+    /// Checks if a key exists for a specific key type
+    #[inline]
+    fn _contains_key<K: AsRef<[u8]> + Sync + Send>(
+        &self,
+        key: K,
+        key_type: KeyType,
+    ) -> Result<bool> {
+        let tree = match key_type {
+            KeyType::KV => &self.kv_tree,
+            KeyType::Map => &self.map_tree,
+            KeyType::List => &self.list_tree,
+            KeyType::Stream => &self.stream_tree,
+        };
+        Self::_typed_contains_key(tree, key, key_type)
+    }
+
+    /// Checks if the data tree of `key_type` holds a key
+    #[inline]
+    fn _typed_contains_key<K: AsRef<[u8]> + Sync + Send>(
+        tree: &Tree,
+        key: K,
+        key_type: KeyType,
+    ) -> Result<bool> {
+        match key_type {
+            KeyType::KV => Self::_kv_contains_key(tree, key),
+            KeyType::Map => Self::_map_contains_key(tree, key),
+            KeyType::List => Self::_list_contains_key(tree, key),
+            KeyType::Stream => Self::_stream_contains_key(tree, key),
+        }
+    }
+
+    /// Gets the type the type index records for a name, if that type still stores it
+    ///
+    /// Every write that creates a name records its type first, so a name is held by
+    /// at most one type. Entries left by data removed without unregistering, such as
+    /// a cleared map, are ignored.
+    #[inline]
+    fn _indexed_type(&self, name: &[u8]) -> Result<Option<KeyType>> {
+        let Some(key_type) = self.type_tree.get(name)? else {
+            return Ok(None);
+        };
+        let key_type = KeyType::decode(key_type.as_ref())?;
+        Ok(self._contains_key(name, key_type)?.then_some(key_type))
+    }
+
+    /// Gets the type stored under a name, ignoring expired keys
+    #[inline]
+    fn _type_of(&self, name: &[u8]) -> Result<Option<KeyType>> {
+        let Some(indexed) = self._indexed_type(name)? else {
+            return Ok(None);
+        };
+        if !self._is_expired(name, indexed)? {
+            return Ok(Some(indexed));
+        }
+        //names written by several types before the type index existed keep the others
+        for key_type in KeyType::ALL {
+            if key_type != indexed
+                && self._contains_key(name, key_type)?
+                && !self._is_expired(name, key_type)?
+            {
+                return Ok(Some(key_type));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Refuses to use a name as `expected` while another type holds it, otherwise
+    /// records `expected` as its type
+    ///
+    /// An expired key of another type no longer holds the name, it is removed.
+    #[inline]
+    fn _check_type(&self, name: &[u8], expected: KeyType) -> Result<()> {
+        match self._indexed_type(name)? {
+            Some(actual) if actual == expected => return Ok(()),
+            //names written by several types before the type index existed stay usable as each
+            Some(_) if self._contains_key(name, expected)? => return Ok(()),
+            Some(actual) if !self._is_expired(name, actual)? => {
+                return Err(StorageError::WrongType {
+                    key: name.to_vec(),
+                    expected,
+                    actual,
+                });
+            }
+            Some(actual) => self._remove_typed(name, actual)?,
+            None => {}
+        }
+        self.type_tree.insert(name, expected.encode())?;
+        Ok(())
+    }
+
+    /// Drops the type index entry of a name whose `key_type` data was removed
+    ///
+    /// The entry is only dropped while it still records `key_type`, the cleanup task
+    /// runs beside the command worker.
+    #[inline]
+    fn _type_unset(&self, name: &[u8], key_type: KeyType) -> Result<()> {
+        if !self._contains_key(name, key_type)? {
+            let _ = self.type_tree.compare_and_swap(
+                name,
+                Some(key_type.encode()),
+                None as Option<&[u8]>,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Refuses to read a name as `expected` while another type holds it
+    #[inline]
+    fn _check_read_type(&self, name: &[u8], expected: KeyType) -> Result<()> {
+        match self._type_of(name)? {
+            Some(actual) if actual != expected && !self._contains_key(name, expected)? => {
+                Err(StorageError::WrongType {
+                    key: name.to_vec(),
+                    expected,
+                    actual,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the key, map, list or stream of the given type
+    #[inline]
+    fn _remove_typed(&self, name: &[u8], key_type: KeyType) -> Result<()> {
+        match key_type {
+            KeyType::KV => self._kv_remove(name)?,
+            KeyType::Map => self._map_remove(name)?,
+            KeyType::List => self._list_remove(name)?,
+            KeyType::Stream => self._stream_remove(name)?,
+        };
+        Ok(())
+    }
+
+    /// Checks if a key exists in key-value store
+    #[inline]
+    fn _kv_contains_key<K: AsRef<[u8]> + Sync + Send>(kv: &Tree, key: K) -> Result<bool> {
+        Ok(kv.contains_key(key.as_ref())?)
+    }
+
+    /// Checks if a map exists
+    #[inline]
+    fn _map_contains_key<K: AsRef<[u8]> + Sync + Send>(tree: &Tree, key: K) -> Result<bool> {
+        let count_key = SledStorageDB::make_map_count_key_name(key.as_ref());
+        Ok(tree.contains_key(count_key)?)
+    }
+
+    /// Checks if a list exists
+    #[inline]
+    fn _list_contains_key<K: AsRef<[u8]> + Sync + Send>(tree: &Tree, name: K) -> Result<bool> {
+        let count_key = SledStorageDB::make_list_count_key(name.as_ref());
+        Ok(tree.contains_key(count_key)?)
+    }
+
+    /// Checks if a stream exists
+    #[inline]
+    fn _stream_contains_key<K: AsRef<[u8]> + Sync + Send>(tree: &Tree, name: K) -> Result<bool> {
+        let meta_key = [
+            Self::make_stream_prefix(name.as_ref()).as_slice(),
+            STREAM_KEY_META_SUFFIX,
+        ]
+        .concat();
+        Ok(tree.contains_key(meta_key)?)
+    }
+
+    /// Removes a stream, returns whether it existed
+    #[inline]
+    fn _stream_remove<K>(&self, key: K) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+    {
+        let existed = self._contains_key(key.as_ref(), KeyType::Stream)?;
+        #[cfg(not(feature = "ttl"))]
+        self._stream(key.as_ref())._clear()?;
+        #[cfg(feature = "ttl")]
+        {
+            let stream = self._stream(key.as_ref());
+            let stream_clear_batch = stream._make_clear_batch();
+            (
+                &self.stream_tree,
+                &self.key_expire_tree,
+                &self.expire_key_tree,
+            )
+                .transaction(|(stream_tx, key_expire_tx, expire_key_tx)| {
+                    SledStorageStream::_tx_clear(stream_tx, &stream_clear_batch)?;
+                    Self::_tx_remove_expire_key(
+                        key_expire_tx,
+                        expire_key_tx,
+                        key.as_ref(),
+                        KeyType::Stream,
+                    )?;
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                })?;
+        }
+        self._type_unset(key.as_ref(), KeyType::Stream)?;
+        Ok(existed)
+    }
+
+    /// Checks if a stream exists and is not expired
+    #[inline]
+    fn _self_stream_contains_key(&self, key: &[u8]) -> Result<bool> {
+        #[cfg(feature = "ttl")]
+        {
+            Ok(!self._is_expired(key, KeyType::Stream)?)
+        }
+
+        #[cfg(not(feature = "ttl"))]
+        Self::_stream_contains_key(&self.stream_tree, key)
+    }
+
+    /// Trims streams whose length exceeds the limit given when entries were added
+    #[inline]
+    fn _trim_streams(&self, limit: usize) -> usize {
+        let names = self
+            .stream_tree
+            .scan_prefix(STREAM_TRIM_PREFIX)
+            .keys()
+            .take(limit)
+            .filter_map(|k| match k {
+                Ok(k) => Some(k[STREAM_TRIM_PREFIX.len()..].to_vec()),
+                Err(e) => {
+                    log::warn!("{:?}", e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let count = names.len();
+        for name in names {
+            if let Err(e) = self._stream(name)._trim_to_maxlen() {
+                log::error!("{:?}", e);
+            }
+        }
+        count
+    }
+
+    /// Removes a map, returns whether it existed
+    #[inline]
+    fn _map_remove<K>(&self, key: K) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+    {
+        let existed = self._contains_key(key.as_ref(), KeyType::Map)?;
+        #[cfg(not(feature = "ttl"))]
+        self._map(key.as_ref())._clear()?;
+        #[cfg(feature = "ttl")]
+        {
+            let map = self._map(key.as_ref());
+            let (map_clear_batch, removed) = map._make_clear_batch();
+            (&self.map_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+                |(map_tx, key_expire_tx, expire_key_tx)| {
+                    map._tx_clear(map_tx, &map_clear_batch)?;
+                    Self::_tx_remove_expire_key(
+                        key_expire_tx,
+                        expire_key_tx,
+                        key.as_ref(),
+                        KeyType::Map,
+                    )?;
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                },
+            )?;
+            self.quota.release(removed);
+        }
+        self._type_unset(key.as_ref(), KeyType::Map)?;
+        Ok(existed)
+    }
+
+    /// Removes a list, returns whether it existed
+    #[inline]
+    fn _list_remove<K>(&self, key: K) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+    {
+        let existed = self._contains_key(key.as_ref(), KeyType::List)?;
+        #[cfg(not(feature = "ttl"))]
+        self._list(key.as_ref())._clear()?;
+        #[cfg(feature = "ttl")]
+        {
+            let list = self._list(key.as_ref());
+            let (list_clear_batch, removed) = list._make_clear_batch();
+            (
+                &self.list_tree,
+                &self.key_expire_tree,
+                &self.expire_key_tree,
+            )
+                .transaction(|(list_tx, key_expire_tx, expire_key_tx)| {
+                    SledStorageList::_tx_clear(list_tx, &list_clear_batch)?;
+                    Self::_tx_remove_expire_key(
+                        key_expire_tx,
+                        expire_key_tx,
+                        key.as_ref(),
+                        KeyType::List,
+                    )?;
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                })?;
+            self.quota.release(removed);
+        }
+        self._type_unset(key.as_ref(), KeyType::List)?;
+        Ok(existed)
+    }
+
+    /// Removes a key-value pair, returns whether it existed
+    #[inline]
+    fn _kv_remove<K>(&self, key: K) -> Result<bool>
+    where
+        K: AsRef<[u8]>,
+    {
+        #[cfg(not(feature = "ttl"))]
+        let removed = self.kv_tree.remove(key.as_ref())?;
+        #[cfg(feature = "ttl")]
+        let removed = (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+            |(kv_tx, key_expire_tx, expire_key_tx)| {
+                let removed = kv_tx.remove(key.as_ref())?;
+                Self::_tx_remove_expire_key(
+                    key_expire_tx,
+                    expire_key_tx,
+                    key.as_ref(),
+                    KeyType::KV,
+                )?;
+                Ok::<_, ConflictableTransactionError<()>>(removed)
+            },
+        )?;
+        self._kv_removed(key.as_ref(), removed.as_ref())?;
+        Ok(removed.is_some())
+    }
+
+    /// Removes expiration key (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _remove_expire_key(&self, key: &[u8], key_type: KeyType) -> Result<()> {
+        let typed_key = Self::make_expire_key(key_type, key);
+        if let Some(expire_at_bytes) = self.key_expire_tree.remove(typed_key.as_slice())? {
+            let expire_key =
+                [ExpireMeta::at_bytes(&expire_at_bytes), typed_key.as_slice()].concat();
+            self.expire_key_tree.remove(expire_key.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Transactionally removes expiration key (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _tx_remove_expire_key<E>(
+        key_expire_tx: &TransactionalTree,
+        expire_key_tx: &TransactionalTree,
+        key: &[u8],
+        key_type: KeyType,
+    ) -> ConflictableTransactionResult<(), E> {
+        let typed_key = Self::make_expire_key(key_type, key);
+        if let Some(expire_at_bytes) = key_expire_tx.remove(typed_key.as_slice())? {
+            let expire_key =
+                [ExpireMeta::at_bytes(&expire_at_bytes), typed_key.as_slice()].concat();
+            expire_key_tx.remove(expire_key.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Key of an expiration entry, the name behind its type so each type has its own entries
+    #[inline]
+    fn make_expire_key(key_type: KeyType, key: &[u8]) -> Key {
+        [key_type.encode(), key].concat()
+    }
+
+    /// Splits the key of an expiration entry into type and name
+    #[inline]
+    fn decode_expire_key(typed_key: &[u8]) -> Result<(KeyType, &[u8])> {
+        let (key_type, key) = typed_key
+            .split_first()
+            .ok_or_else(|| StorageError::codec("invalid expiration key"))?;
+        Ok((KeyType::decode(&[*key_type])?, key))
+    }
+
+    /// Checks if a key is expired
+    #[inline]
+    fn _is_expired<K>(&self, _key: K, _key_type: KeyType) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        #[cfg(feature = "ttl")]
+        {
+            //获取过期时间
+            if let Some((expire_at, _)) = self._ttl_at(_key, _key_type)? {
+                Ok(self.now() >= expire_at)
+            } else {
+                Ok(true)
+            }
+        }
+        #[cfg(not(feature = "ttl"))]
+        Ok(false)
+    }
+
+    /// Gets the sliding time-to-live of a key, `None` if its expiration is fixed or unset
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _sliding_ttl(&self, key: &[u8], key_type: KeyType) -> Result<Option<TimestampMillis>> {
+        match self
+            .key_expire_tree
+            .get(Self::make_expire_key(key_type, key))?
+        {
+            Some(meta) => Ok(ExpireMeta::decode(&meta)?.sliding),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads from `tree` and restarts the sliding expiration of a key in the same transaction
+    ///
+    /// The deadline only moves when the read returns a value.
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _read_sliding<T, F>(
+        &self,
+        tree: &Tree,
+        key: &[u8],
+        key_type: KeyType,
+        ttl: TimestampMillis,
+        f: F,
+    ) -> Result<Option<T>>
+    where
+        F: Fn(&TransactionalTree) -> ConflictableTransactionResult<Option<T>, StorageError>,
+    {
+        let res = (tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+            |(tx, key_expire_tx, expire_key_tx)| {
+                let res = f(tx)?;
+                if res.is_some() {
+                    let meta = ExpireMeta::sliding(self.now(), ttl);
+                    Self::_tx_expire_at(key_expire_tx, expire_key_tx, key, meta, key_type)?;
+                }
+                Ok(res)
+            },
+        )?;
+        Ok(res)
+    }
+
+    /// Gets time-to-live for a key
+    #[inline]
+    fn _ttl<K>(&self, key: K, key_type: KeyType) -> Result<Option<(TimestampMillis, Option<IVec>)>>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        Ok(self
+            ._ttl_at(key, key_type)?
+            .map(|(expire_at, at_bytes)| (expire_at - self.now(), at_bytes)))
+    }
+
+    /// Gets expiration time for a key
+    #[inline]
+    fn _ttl_at<K>(
+        &self,
+        c_key: K,
+        key_type: KeyType,
+    ) -> Result<Option<(TimestampMillis, Option<IVec>)>>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        if !self._contains_key(c_key.as_ref(), key_type)? {
+            return Ok(None);
+        }
+        let typed_key = Self::make_expire_key(key_type, c_key.as_ref());
+        match self.key_expire_tree.get(typed_key)? {
+            Some(at_bytes) => Ok(Some((ExpireMeta::decode(&at_bytes)?.at, Some(at_bytes)))),
+            None => Ok(Some((TimestampMillis::MAX, None))),
+        }
+    }
+
+    /// Inserts a key-value pair
+    #[inline]
+    fn _insert(&self, key: &[u8], val: &[u8]) -> Result<()> {
+        self._check_type(key, KeyType::KV)?;
+        let evict_reserved = self.eviction.reserve(self._kv_added(key, val.len())?)?;
+        let reserved = self._quota_reserve_kv(key, val.len())?;
+        self._touch(key)?;
+        #[cfg(not(feature = "ttl"))]
+        self.kv_tree.insert(key, val)?;
+        #[cfg(feature = "ttl")]
+        {
+            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+                |(kv_tx, key_expire_tx, expire_keys_tx)| {
+                    kv_tx.insert(key, val)?;
+                    Self::_tx_remove_expire_key(key_expire_tx, expire_keys_tx, key, KeyType::KV)?;
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                },
+            )?;
+        }
+        reserved.commit();
+        evict_reserved.commit();
+        Ok(())
+    }
+
+    /// Inserts a key-value pair whose time-to-live restarts on every read (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _insert_sliding(&self, key: &[u8], val: &[u8], ttl: TimestampMillis) -> Result<()> {
+        self._check_type(key, KeyType::KV)?;
+        let evict_reserved = self.eviction.reserve(self._kv_added(key, val.len())?)?;
+        let reserved = self._quota_reserve_kv(key, val.len())?;
+        self._touch(key)?;
+        (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+            |(kv_tx, key_expire_tx, expire_keys_tx)| {
+                kv_tx.insert(key, val)?;
+                let meta = ExpireMeta::sliding(self.now(), ttl);
+                Self::_tx_expire_at(key_expire_tx, expire_keys_tx, key, meta, KeyType::KV)?;
+                Ok::<(), ConflictableTransactionError<()>>(())
+            },
+        )?;
+        reserved.commit();
+        evict_reserved.commit();
+        Ok(())
+    }
+
+    /// Gets a value by key
+    #[inline]
+    fn _get(&self, key: &[u8]) -> Result<Option<IVec>> {
+        let res = if self._is_expired(key, KeyType::KV)? {
+            None
+        } else {
+            self._kv_get(key)?
+        };
+        if res.is_some() {
+            self._touch(key)?;
+        } else {
+            self._check_read_type(key, KeyType::KV)?;
+        }
+        Ok(res)
+    }
+
+    /// Reads a value, restarting a sliding expiration of the key
+    #[inline]
+    fn _kv_get(&self, key: &[u8]) -> Result<Option<IVec>> {
+        #[cfg(feature = "ttl")]
+        if let Some(ttl) = self._sliding_ttl(key, KeyType::KV)? {
+            return self._read_sliding(&self.kv_tree, key, KeyType::KV, ttl, |tx| Ok(tx.get(key)?));
+        }
+        Ok(self.kv_tree.get(key)?)
+    }
+
+    /// Checks if a map key exists
+    #[inline]
+    fn _self_map_contains_key(&self, key: &[u8]) -> Result<bool> {
+        #[cfg(feature = "ttl")]
+        {
+            if self._is_expired(key, KeyType::Map)? {
+                Ok(false)
+            } else {
+                //Self::_map_contains_key(&self.map_tree, key)
+                Ok(true)
+            }
+        }
+
+        #[cfg(not(feature = "ttl"))]
+        Self::_map_contains_key(&self.map_tree, key)
+    }
+
+    /// Checks if a list key exists
+    #[inline]
+    fn _self_list_contains_key(&self, key: &[u8]) -> Result<bool> {
+        #[cfg(feature = "ttl")]
+        {
+            let this = self;
+            if this._is_expired(key, KeyType::List)? {
+                Ok(false)
+            } else {
+                // Self::_list_contains_key(&this.list_tree, key)
+                Ok(true)
+            }
+        }
+
+        #[cfg(not(feature = "ttl"))]
+        Self::_list_contains_key(&self.list_tree, key)
+    }
+
+    /// Batch insert key-value pairs
+    #[inline]
+    #[allow(clippy::collapsible_if)]
+    fn _batch_insert(&self, key_vals: Vec<(Key, IVec)>) -> Result<()> {
+        if key_vals.is_empty() {
+            return Ok(());
+        }
+        for (k, _) in key_vals.iter() {
+            self._check_type(k, KeyType::KV)?;
+        }
+
+        let reserved = if self.quota.is_enabled() {
+            let mut writes = Vec::with_capacity(key_vals.len());
+            for (k, v) in key_vals.iter() {
+                writes.push((self.kv_tree.get(k)?.map(|v| v.len()), v.len()));
+            }
+            self._quota_reserve(writes)?
+        } else {
+            QuotaReservation::default()
+        };
+        let mut added = 0;
+        for (k, v) in key_vals.iter() {
+            added += self._kv_added(k, v.len())?;
+        }
+        let evict_reserved = self.eviction.reserve(added)?;
+
+        let mut batch = Batch::default();
+        for (k, v) in key_vals.iter() {
+            batch.insert(k.as_slice(), v.as_ref());
+            self._touch(k)?;
+        }
+
+        let this = self;
+        #[cfg(not(feature = "ttl"))]
+        this.kv_tree.apply_batch(batch)?;
+
+        #[cfg(feature = "ttl")]
+        {
+            let mut remove_key_expire_batch = Batch::default();
+            let mut remove_expire_key_batch = Batch::default();
+            for (k, _) in key_vals.iter() {
+                if let Some((expire_at, Some(expire_at_bytes))) =
+                    this._ttl(k.as_slice(), KeyType::KV)?
+                {
+                    if expire_at <= 0 {
+                        let typed_key = Self::make_expire_key(KeyType::KV, k);
+                        let expire_key =
+                            [ExpireMeta::at_bytes(&expire_at_bytes), typed_key.as_slice()].concat();
+                        remove_key_expire_batch.remove(typed_key);
+                        remove_expire_key_batch.remove(expire_key.as_slice())
+                    }
+                }
+            }
+
+            // this.key_expire_tree.apply_batch(remove_key_expire_batch)?;
+            // this.expire_key_tree.apply_batch(remove_expire_key_batch)?;
+            // this.kv_tree.apply_batch(batch)?;
+            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+                |(kv_tx, key_expire_tx, expire_key_tx)| {
+                    key_expire_tx.apply_batch(&remove_key_expire_batch)?;
+                    expire_key_tx.apply_batch(&remove_expire_key_batch)?;
+                    kv_tx.apply_batch(&batch)?;
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                },
+            )?;
+        }
+        reserved.commit();
+        evict_reserved.commit();
+        Ok(())
+    }
+
+    /// Batch remove keys, returns the keys that existed
+    #[inline]
+    fn _batch_remove(&self, keys: Vec<Key>) -> Result<Vec<Key>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut batch = Batch::default();
+        for k in keys.iter() {
+            batch.remove(k.as_slice());
+        }
+        let mut removed = BTreeMap::new();
+        for k in keys.iter() {
+            if self._kv_tracks_removals() {
+                if let Some(v) = self.kv_tree.get(k)? {
+                    removed.insert(k.as_slice(), Some(v));
+                }
+            } else if self.kv_tree.contains_key(k)? {
+                removed.insert(k.as_slice(), None);
+            }
+        }
+        #[cfg(not(feature = "ttl"))]
+        self.kv_tree.apply_batch(batch)?;
+
+        #[cfg(feature = "ttl")]
+        {
+            let mut remove_key_expire_batch = Batch::default();
+            let mut remove_expire_key_batch = Batch::default();
+            for k in keys.iter() {
+                let typed_key = Self::make_expire_key(KeyType::KV, k);
+                if let Some(expire_at_bytes) = self.key_expire_tree.get(typed_key.as_slice())? {
+                    let expire_key =
+                        [ExpireMeta::at_bytes(&expire_at_bytes), typed_key.as_slice()].concat();
+                    remove_key_expire_batch.remove(typed_key);
+                    remove_expire_key_batch.remove(expire_key.as_slice())
+                }
+            }
+            // this.key_expire_tree.apply_batch(remove_key_expire_batch)?;
+            // this.expire_key_tree.apply_batch(remove_expire_key_batch)?;
+            // this.kv_tree.apply_batch(batch)?;
+            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+                |(kv_tx, key_expire_tx, expire_key_tx)| {
+                    key_expire_tx.apply_batch(&remove_key_expire_batch)?;
+                    expire_key_tx.apply_batch(&remove_expire_key_batch)?;
+                    kv_tx.apply_batch(&batch)?;
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                },
+            )?;
+        }
+
+        for k in keys.iter() {
+            self._kv_removed(k, removed.get(k.as_slice()).and_then(Option::as_ref))?;
+        }
+        Ok(removed.into_keys().map(<[u8]>::to_vec).collect())
+    }
+
+    /// Increments a counter
+    #[inline]
+    fn _counter_incr(&self, key: &[u8], increment: i64) -> Result<()> {
+        self._check_type(key, KeyType::KV)?;
+        let evict_reserved = self.eviction.reserve(self._kv_added(key, 8)?)?;
+        let reserved = self._quota_reserve_kv(key, 8)?;
+        self._touch(key)?;
+        self.kv_tree.fetch_and_update(key, |old: Option<&[u8]>| {
+            let number = old
+                .and_then(decode_counter)
+                .map(|n| n + increment)
+                .unwrap_or(increment);
+            Some(number.to_be_bytes().to_vec())
+        })?;
+        reserved.commit();
+        evict_reserved.commit();
+        Ok(())
+    }
+
+    /// Decrements a counter
+    #[inline]
+    fn _counter_decr(&self, key: &[u8], decrement: i64) -> Result<()> {
+        self._check_type(key, KeyType::KV)?;
+        let evict_reserved = self.eviction.reserve(self._kv_added(key, 8)?)?;
+        let reserved = self._quota_reserve_kv(key, 8)?;
+        self._touch(key)?;
+        self.kv_tree.fetch_and_update(key, |old: Option<&[u8]>| {
+            let number = old
+                .and_then(decode_counter)
+                .map(|n| n - decrement)
+                .unwrap_or(-decrement);
+            Some(number.to_be_bytes().to_vec())
+        })?;
+        reserved.commit();
+        evict_reserved.commit();
+        Ok(())
+    }
+
+    /// Gets counter value
+    #[inline]
+    fn _counter_get(&self, key: &[u8]) -> Result<Option<i64>> {
+        let this = self;
+        if this._is_expired(key, KeyType::KV)? {
+            Ok(None)
+        } else if let Some(v) = this.kv_tree.get(key)? {
+            this._touch(key)?;
+            Ok(Some(decode_counter(v.as_ref()).ok_or_else(|| {
+                StorageError::codec("invalid counter value")
+            })?))
+        } else {
+            this._check_read_type(key, KeyType::KV)?;
+            Ok(None)
+        }
+    }
+
+    /// Sets counter value
+    #[inline]
+    fn _counter_set(&self, key: &[u8], val: i64) -> Result<()> {
+        self._check_type(key, KeyType::KV)?;
+        let evict_reserved = self.eviction.reserve(self._kv_added(key, 8)?)?;
+        let reserved = self._quota_reserve_kv(key, 8)?;
+        self._touch(key)?;
+        let val = val.to_be_bytes().to_vec();
+
+        #[cfg(not(feature = "ttl"))]
+        self.kv_tree.insert(key, val.as_slice())?;
+        #[cfg(feature = "ttl")]
+        {
+            // self._remove_expire_key(key)?;
+            // kv_tree.insert(key, val.as_slice())?;
+            (&self.kv_tree, &self.key_expire_tree, &self.expire_key_tree).transaction(
+                |(kv_tx, key_expire_tx, expire_key_tx)| {
+                    Self::_tx_remove_expire_key(key_expire_tx, expire_key_tx, key, KeyType::KV)?;
+                    kv_tx.insert(key, val.as_slice())?;
+                    Ok::<(), ConflictableTransactionError<()>>(())
+                },
+            )?;
+        }
+        reserved.commit();
+        evict_reserved.commit();
+        Ok(())
+    }
+
+    /// Checks if a key exists in key-value store
+    #[inline]
+    fn _self_contains_key(&self, key: &[u8]) -> Result<bool> {
+        #[cfg(feature = "ttl")]
+        {
+            let this = self;
+            if this._is_expired(key, KeyType::KV)? {
+                Ok(false)
+            } else {
+                // this._contains_key(key, KeyType::KV)
+                Ok(true)
+            }
+        }
+        #[cfg(not(feature = "ttl"))]
+        Self::_kv_contains_key(&self.kv_tree, key)
+    }
+
+    /// Sets expiration time for a key (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    fn _expire_at(&self, key: &[u8], at: TimestampMillis, key_type: KeyType) -> Result<bool> {
+        self._expire(key, ExpireMeta::fixed(at), key_type)
+    }
+
+    /// Sets the expiration metadata of a key (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    fn _expire(&self, key: &[u8], meta: ExpireMeta, key_type: KeyType) -> Result<bool> {
+        if self._contains_key(key, key_type)? {
+            let res = (&self.key_expire_tree, &self.expire_key_tree).transaction(
+                |(key_expire_tx, expire_key_tx)| {
+                    Self::_tx_expire_at::<()>(key_expire_tx, expire_key_tx, key, meta, key_type)
+                },
+            )?;
+            Ok(res)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Transactionally sets expiration time (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    fn _tx_expire_at<E>(
+        key_expire_tx: &TransactionalTree,
+        expire_key_tx: &TransactionalTree,
+        key: &[u8],
+        meta: ExpireMeta,
+        key_type: KeyType,
+    ) -> ConflictableTransactionResult<bool, E> {
+        let typed_key = Self::make_expire_key(key_type, key);
+        let at_bytes = meta.at.to_be_bytes();
+        //drop the entry of the previous expiration time, it would expire the key early
+        if let Some(old_at) = key_expire_tx.insert(typed_key.as_slice(), meta.encode())? {
+            expire_key_tx.remove([ExpireMeta::at_bytes(&old_at), typed_key.as_slice()].concat())?;
+        }
+        let res = expire_key_tx
+            .insert(
+                [at_bytes.as_slice(), typed_key.as_slice()].concat(),
+                key_type.encode(),
+            )
+            .map(|_| true)?;
+        Ok(res)
+    }
+
+    /// Gets time-to-live for a key (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    fn _self_ttl(&self, key: &[u8]) -> Result<Option<TimestampMillis>> {
+        Ok(self
+            ._ttl(key, KeyType::KV)?
+            .and_then(|(ttl, _)| if ttl > 0 { Some(ttl) } else { None }))
+    }
+
+    /// Creates an iterator for map prefixes
+    #[inline]
+    fn _map_scan_prefix(&self) -> sled::Iter {
+        self.map_tree.scan_prefix(MAP_NAME_PREFIX)
+    }
+
+    /// Creates an iterator for list prefixes
+    #[inline]
+    fn _list_scan_prefix(&self) -> sled::Iter {
+        self.list_tree.scan_prefix(LIST_NAME_PREFIX)
+    }
+
+    /// Creates an iterator for database scan with pattern
+    #[inline]
+    #[allow(clippy::let_and_return)]
+    fn _db_scan_prefix(&self, pattern: Vec<u8>) -> sled::Iter {
+        let mut last_esc_char = false;
+        let mut has_esc_char = false;
+        let start_pattern = pattern
+            .splitn(2, |x| {
+                if !last_esc_char && (*x == b'*' || *x == b'?') {
+                    true
+                } else {
+                    last_esc_char = *x == b'\\';
+                    if last_esc_char && !has_esc_char {
+                        has_esc_char = true;
+                    }
+                    false
+                }
+            })
+            .next();
+        let start_pattern = if has_esc_char {
+            start_pattern.map(|start_pattern| {
+                Cow::Owned(
+                    start_pattern
+                        .replace(b"\\*", b"*")
+                        .as_slice()
+                        .replace(b"\\?", b"?"),
+                )
+            })
+        } else {
+            start_pattern.map(Cow::Borrowed)
+        };
+        let iter = if let Some(start_pattern) = start_pattern {
+            self.kv_tree.scan_prefix(start_pattern.as_ref())
+        } else {
+            self.kv_tree.iter()
+        };
+        iter
+    }
+
+    /// Gets number of key-value pairs
+    #[inline]
+    fn _kv_len(&self) -> usize {
+        #[cfg(feature = "ttl")]
+        {
+            let limit = 500;
+            loop {
+                if self.cleanup_kvs(limit) < limit {
+                    break;
+                }
+            }
+        }
+        self.kv_tree.len()
+    }
+
+    /// Gets total database size
+    #[inline]
+    fn _db_size(&self) -> usize {
+        self.db.len()
+            + self.kv_tree.len()
+            + self.map_tree.len()
+            + self.list_tree.len()
+            + self.stream_tree.len()
+    }
+
+    /// Gets the remaining time-to-live of a key that has an expiration time, and its
+    /// sliding time-to-live if the expiration slides
+    #[inline]
+    fn _dump_ttl(
+        &self,
+        _key: &[u8],
+        _key_type: KeyType,
+    ) -> Result<(Option<TimestampMillis>, Option<TimestampMillis>)> {
+        #[cfg(feature = "ttl")]
+        if let Some(meta) = self
+            .key_expire_tree
+            .get(Self::make_expire_key(_key_type, _key))?
+        {
+            let meta = ExpireMeta::decode(&meta)?;
+            return Ok((Some(meta.at - self.now()), meta.sliding));
+        }
+        Ok((None, None))
+    }
+
+    /// Calls `f` with every key, map, list and stream that is not expired
+    #[inline]
+    fn _dump<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(DumpEntry) -> Result<()>,
+    {
+        let expired = |ttl: &Option<TimestampMillis>| matches!(ttl, Some(ttl) if *ttl <= 0);
+        for item in self.kv_tree.iter() {
+            let (key, value) = item?;
+            let (ttl, sliding) = self._dump_ttl(&key, KeyType::KV)?;
+            if expired(&ttl) {
+                continue;
+            }
+            f(DumpEntry::KV {
+                key: key.to_vec(),
+                ttl,
+                sliding,
+                value: value.to_vec(),
+            })?;
+        }
+        for key in self.map_tree.scan_prefix(MAP_NAME_PREFIX).keys() {
+            let key = key?;
+            if !Self::is_map_count_key(&key) {
+                continue;
+            }
+            let name = Self::map_count_key_to_name(&key);
+            let (ttl, sliding) = self._dump_ttl(name, KeyType::Map)?;
+            if expired(&ttl) {
+                continue;
+            }
+            let prefix = Self::make_map_item_prefix_name(name);
+            #[cfg(feature = "ttl")]
+            let map = SledStorageMap::_new(name.to_vec(), self.clone());
+            let mut fields = Vec::new();
+            #[allow(unused_mut)]
+            let mut field_ttls = Vec::new();
+            for item in self.map_tree.scan_prefix(prefix.as_slice()) {
+                let (k, v) = item?;
+                let field = k[prefix.len()..].to_vec();
+                #[cfg(feature = "ttl")]
+                if let Some(at) = self.map_tree.get(map.make_field_expire_key(&field))? {
+                    let ttl = TimestampMillis::from_be_bytes(at.as_ref().try_into()?) - self.now();
+                    if ttl <= 0 {
+                        continue;
+                    }
+                    field_ttls.push((field.clone(), ttl));
+                }
+                fields.push((field, v.to_vec()));
+            }
+            f(DumpEntry::Map {
+                name: name.to_vec(),
+                ttl,
+                sliding,
+                fields,
+                field_ttls,
+            })?;
+        }
+        for key in self.list_tree.scan_prefix(LIST_NAME_PREFIX).keys() {
+            let key = key?;
+            if !Self::is_list_count_key(&key) {
+                continue;
+            }
+            let name = Self::list_count_key_to_name(&key);
+            let (ttl, sliding) = self._dump_ttl(name, KeyType::List)?;
+            if expired(&ttl) {
+                continue;
+            }
+            let values = SledStorageList::_new(name.to_vec(), self.clone())
+                ._all()?
+                .into_iter()
+                .map(|v| v.to_vec())
+                .collect();
+            f(DumpEntry::List {
+                name: name.to_vec(),
+                ttl,
+                sliding,
+                values,
+            })?;
+        }
+        for key in self.stream_tree.scan_prefix(STREAM_NAME_PREFIX).keys() {
+            let key = key?;
+            let Some((name, STREAM_KEY_META_SUFFIX)) = Self::decode_name(STREAM_NAME_PREFIX, &key)
+            else {
+                continue;
+            };
+            let (ttl, sliding) = self._dump_ttl(name, KeyType::Stream)?;
+            if expired(&ttl) {
+                continue;
+            }
+            let stream = SledStorageStream::_new(name.to_vec(), self.clone());
+            let meta = stream._meta()?.unwrap_or_default();
+            let entries = stream
+                ._range(StreamId::MIN, StreamId::MAX, None, false)?
+                .into_iter()
+                .map(|(id, v)| (id, v.to_vec()))
+                .collect();
+            f(DumpEntry::Stream {
+                name: name.to_vec(),
+                ttl,
+                sliding,
+                last_id: meta.last_id,
+                maxlen: meta.maxlen,
+                entries,
+                groups: stream._groups()?,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Writes a dumped key, map or list
+    #[inline]
+    fn _load(&self, entry: DumpEntry) -> Result<()> {
+        //the loaded entry replaces whatever type holds its name
+        if let Some(key_type) = self._indexed_type(entry.name())?
+            && key_type != entry.key_type()
+        {
+            self._remove_typed(entry.name(), key_type)?;
+        }
+        //empty maps and streams are written without a type check
+        self.type_tree
+            .insert(entry.name(), entry.key_type().encode())?;
+        #[cfg(feature = "ttl")]
+        let expire = entry.ttl().map(|ttl| {
+            let meta = ExpireMeta {
+                at: self.now() + ttl,
+                sliding: entry.sliding(),
+            };
+            (entry.name().to_vec(), entry.key_type(), meta)
+        });
+        match entry {
+            DumpEntry::KV { key, value, .. } => self._insert(&key, &value)?,
+            DumpEntry::Map {
+                name,
+                fields,
+                field_ttls: _field_ttls,
+                ..
+            } => {
+                let map = SledStorageMap::_new(name, self.clone());
+                map._clear()?;
+                if fields.is_empty() {
+                    map._counter_init()?;
+                }
+                for (k, v) in fields {
+                    map._insert(IVec::from(k), IVec::from(v))?;
+                }
+                #[cfg(feature = "ttl")]
+                for (k, ttl) in _field_ttls {
+                    map._expire_field_at(IVec::from(k), self.now() + ttl)?;
+                }
+            }
+            DumpEntry::List { name, values, .. } => {
+                let list = SledStorageList::_new(name, self.clone());
+                list._clear()?;
+                list._pushs(values.into_iter().map(IVec::from).collect())?;
+            }
+            DumpEntry::Stream {
+                name,
+                last_id,
+                maxlen,
+                entries,
+                groups,
+                ..
+            } => {
+                let stream = SledStorageStream::_new(name, self.clone());
+                stream._clear()?;
+                stream._load(last_id, maxlen, entries, groups)?;
+            }
+        }
+        #[cfg(feature = "ttl")]
+        if let Some((name, key_type, meta)) = expire {
+            self._expire(&name, meta, key_type)?;
+        }
+        Ok(())
+    }
+
+    /// Checks map, list and expiration metadata, fixing it when `repair` is set
+    #[inline]
+    fn _verify(&self, repair: bool) -> Result<VerifyReport> {
+        let mut report = VerifyReport {
+            repaired: repair,
+            ..Default::default()
+        };
+        self._verify_maps(repair, &mut report)?;
+        self._verify_lists(repair, &mut report)?;
+        self._verify_streams(repair, &mut report)?;
+        #[cfg(feature = "ttl")]
+        self._verify_expiries(repair, &mut report)?;
+        if repair {
+            //repairs remove entries behind the usage's back
+            self.quota.invalidate();
+        }
+        Ok(report)
+    }
+
+    /// Checks map item counts and items without a map
+    #[inline]
+    fn _verify_maps(&self, repair: bool, report: &mut VerifyReport) -> Result<()> {
+        let mut names = BTreeSet::new();
+        for key in self.map_tree.scan_prefix(MAP_NAME_PREFIX).keys() {
+            let key = key?;
+            if Self::map_item_key_to_name(&key).is_none() && Self::is_map_count_key(&key) {
+                names.insert(Self::map_count_key_to_name(&key).to_vec());
+            }
+        }
+        report.maps = names.len();
+
+        let mut counts: BTreeMap<Key, i64> = BTreeMap::new();
+        let mut orphans: BTreeMap<Key, usize> = BTreeMap::new();
+        for key in self.map_tree.scan_prefix(MAP_NAME_PREFIX).keys() {
+            let key = key?;
+            if let Some(name) = Self::map_item_key_to_name(&key) {
+                if names.contains(name) {
+                    *counts.entry(name.to_vec()).or_default() += 1;
+                } else {
+                    *orphans.entry(name.to_vec()).or_default() += 1;
+                }
+            }
+        }
+
+        #[cfg(feature = "map_len")]
+        for name in names.iter() {
+            let count_key = Self::make_map_count_key_name(name);
+            let stored = self
+                .map_tree
+                .get(count_key.as_slice())?
+                .and_then(|v| decode_counter(v.as_ref()))
+                .unwrap_or(-1);
+            let actual = counts.get(name).copied().unwrap_or(0);
+            if stored != actual {
+                if repair {
+                    self.map_tree
+                        .insert(count_key.as_slice(), actual.to_be_bytes().as_slice())?;
+                }
+                report.issues.push(Inconsistency::MapCount {
+                    name: name.clone(),
+                    stored,
+                    actual,
+                });
+            }
+        }
+
+        for (name, count) in orphans {
+            if repair {
+                let mut batch = Batch::default();
+                let prefix = Self::make_map_item_prefix_name(name.as_slice());
+                for key in self.map_tree.scan_prefix(prefix).keys() {
+                    batch.remove(key?);
+                }
+                self.map_tree.apply_batch(batch)?;
+            }
+            report
+                .issues
+                .push(Inconsistency::OrphanMapItems { name, count });
+        }
+        Ok(())
+    }
+
+    /// Checks list bounds against the content keys and content without a list
+    #[inline]
+    fn _verify_lists(&self, repair: bool, report: &mut VerifyReport) -> Result<()> {
+        let mut names = BTreeSet::new();
+        for key in self.list_tree.scan_prefix(LIST_NAME_PREFIX).keys() {
+            let key = key?;
+            if Self::list_content_key_to_name(&key).is_none() && Self::is_list_count_key(&key) {
+                names.insert(Self::list_count_key_to_name(&key).to_vec());
+            }
+        }
+        report.lists = names.len();
+
+        let mut orphans: BTreeMap<Key, usize> = BTreeMap::new();
+        for key in self.list_tree.scan_prefix(LIST_NAME_PREFIX).keys() {
+            let key = key?;
+            if let Some((name, _)) = Self::list_content_key_to_name(&key)
+                && !names.contains(name)
+            {
+                *orphans.entry(name.to_vec()).or_default() += 1;
+            }
+        }
+
+        for name in names {
+            let count_key = Self::make_list_count_key(name.as_slice());
+            let stored = self
+                .list_tree
+                .get(count_key.as_slice())?
+                .and_then(|v| bincode::deserialize::<(u64, u64)>(v.as_ref()).ok());
+            let prefix = SledStorageList::make_list_content_prefix(
+                Self::make_list_prefix(name.as_slice()).as_slice(),
+                None,
+            );
+            let items = self
+                .list_tree
+                .scan_prefix(prefix.as_slice())
+                .map(|item| item.map_err(StorageError::from))
+                .collect::<Result<Vec<_>>>()?;
+            let idx = |key: &IVec| -> Result<u64> {
+                Ok(u64::from_be_bytes(key[prefix.len()..].try_into()?))
+            };
+            let (actual, gaps) = match (items.first(), items.last()) {
+                (Some((first, _)), Some((last, _))) => {
+                    let (first, last) = (idx(first)?, idx(last)?);
+                    let len = items.len() as u64;
+                    match first.checked_sub(1) {
+                        Some(start) => ((start, start + len), last - first + 1 != len),
+                        //content starts at index 1, an item at 0 is renumbered
+                        None => ((0, len), true),
+                    }
+                }
+                _ => {
+                    let start = stored.map(|(start, _)| start).unwrap_or(0);
+                    ((start, start), false)
+                }
+            };
+            if stored == Some(actual) && !gaps {
+                continue;
+            }
+            if repair {
+                let mut batch = Batch::default();
+                if gaps {
+                    for (key, _) in items.iter() {
+                        batch.remove(key.as_ref());
+                    }
+                    for (i, (_, value)) in items.iter().enumerate() {
+                        let key = SledStorageList::make_list_content_prefix(
+                            Self::make_list_prefix(name.as_slice()).as_slice(),
+                            Some((actual.0 + 1 + i as u64).to_be_bytes().as_slice()),
+                        );
+                        batch.insert(key, value.as_ref());
+                    }
+                }
+                batch.insert(count_key, bincode::serialize(&actual)?);
+                self.list_tree.apply_batch(batch)?;
+            }
+            report.issues.push(Inconsistency::ListBounds {
+                name,
+                stored: stored.unwrap_or_default(),
+                actual,
+                gaps,
+            });
+        }
+
+        for (name, count) in orphans {
+            if repair {
+                let mut batch = Batch::default();
+                let prefix = SledStorageList::make_list_content_prefix(
+                    Self::make_list_prefix(name.as_slice()).as_slice(),
+                    None,
+                );
+                for key in self.list_tree.scan_prefix(prefix).keys() {
+                    batch.remove(key?);
+                }
+                self.list_tree.apply_batch(batch)?;
+            }
+            report
+                .issues
+                .push(Inconsistency::OrphanListItems { name, count });
+        }
+        Ok(())
+    }
+
+    /// Checks stream lengths and last IDs against the entries and items without a stream
+    #[inline]
+    fn _verify_streams(&self, repair: bool, report: &mut VerifyReport) -> Result<()> {
+        let mut names = BTreeSet::new();
+        //entry count and last entry ID, entry keys are sorted by ID
+        let mut entries: BTreeMap<Key, (u64, StreamId)> = BTreeMap::new();
+        let mut items: BTreeMap<Key, usize> = BTreeMap::new();
+        for key in self.stream_tree.scan_prefix(STREAM_NAME_PREFIX).keys() {
+            let key = key?;
+            let Some((name, rest)) = Self::decode_name(STREAM_NAME_PREFIX, &key) else {
+                continue;
+            };
+            if rest == STREAM_KEY_META_SUFFIX {
+                names.insert(name.to_vec());
+                continue;
+            }
+            if let Some(id) = rest.strip_prefix(STREAM_KEY_ENTRY_SUFFIX) {
+                let entry = entries.entry(name.to_vec()).or_default();
+                entry.0 += 1;
+                entry.1 = StreamId::from_bytes(id)?;
+            }
+            *items.entry(name.to_vec()).or_default() += 1;
+        }
+        report.streams = names.len();
+
+        for name in names.iter() {
+            let stream = SledStorageStream::_new(name.clone(), self.clone());
+            let mut meta = stream._meta()?.unwrap_or_default();
+            let stored = (meta.len, meta.last_id);
+            let (len, last) = entries.get(name).copied().unwrap_or_default();
+            let actual = (len, meta.last_id.max(last));
+            if stored == actual {
+                continue;
+            }
+            if repair {
+                (meta.len, meta.last_id) = actual;
+                self.stream_tree
+                    .insert(stream.make_meta_key(), bincode::serialize(&meta)?)?;
+            }
+            report.issues.push(Inconsistency::StreamMeta {
+                name: name.clone(),
+                stored,
+                actual,
+            });
+        }
+
+        for (name, count) in items {
+            if names.contains(&name) {
+                continue;
+            }
+            if repair {
+                let mut batch = Batch::default();
+                let prefix = Self::encode_name(STREAM_NAME_PREFIX, name.as_slice());
+                for key in self.stream_tree.scan_prefix(prefix).keys() {
+                    batch.remove(key?);
+                }
+                self.stream_tree.apply_batch(batch)?;
+            }
+            report
+                .issues
+                .push(Inconsistency::OrphanStreamItems { name, count });
+        }
+        Ok(())
+    }
+
+    /// Checks that both expiration trees agree and only refer to existing keys
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _verify_expiries(&self, repair: bool, report: &mut VerifyReport) -> Result<()> {
+        for item in self.expire_key_tree.iter() {
+            let (at_key, _) = item?;
+            report.expiries += 1;
+            let decoded = match at_key.len() {
+                0..8 => None,
+                _ => Self::decode_expire_key(&at_key[8..]).ok(),
+            };
+            let Some((key_type, key)) = decoded else {
+                if repair {
+                    self.expire_key_tree.remove(at_key.as_ref())?;
+                }
+                report.issues.push(Inconsistency::OrphanExpiry {
+                    key: at_key.to_vec(),
+                    key_type: None,
+                });
+                continue;
+            };
+            let (at, typed_key) = at_key.split_at(8);
+            let indexed = self
+                .key_expire_tree
+                .get(typed_key)?
+                .is_some_and(|v| ExpireMeta::at_bytes(&v) == at);
+            if indexed && self._contains_key(key, key_type)? {
+                continue;
+            }
+            if repair {
+                self.expire_key_tree.remove(at_key.as_ref())?;
+                if indexed {
+                    self.key_expire_tree.remove(typed_key)?;
+                }
+            }
+            report.issues.push(Inconsistency::OrphanExpiry {
+                key: key.to_vec(),
+                key_type: Some(key_type),
+            });
+        }
+
+        for item in self.key_expire_tree.iter() {
+            let (typed_key, meta) = item?;
+            let at_key = [ExpireMeta::at_bytes(&meta), typed_key.as_ref()].concat();
+            if self.expire_key_tree.contains_key(at_key.as_slice())? {
+                continue;
+            }
+            match Self::decode_expire_key(&typed_key) {
+                Ok((key_type, key)) if self._contains_key(key, key_type)? => {
+                    if repair {
+                        self.expire_key_tree
+                            .insert(at_key.as_slice(), key_type.encode())?;
+                    }
+                    report.issues.push(Inconsistency::MissingExpiryIndex {
+                        key: key.to_vec(),
+                        key_type,
+                    });
+                }
+                decoded => {
+                    if repair {
+                        self.key_expire_tree.remove(typed_key.as_ref())?;
+                    }
+                    let (key, key_type) = match decoded {
+                        Ok((key_type, key)) => (key, Some(key_type)),
+                        Err(_) => (typed_key.as_ref(), None),
+                    };
+                    report.issues.push(Inconsistency::OrphanExpiry {
+                        key: key.to_vec(),
+                        key_type,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes dirty data to disk
+    #[inline]
+    fn _flush(&self) -> Result<usize> {
+        Ok(self.db.flush()?)
+    }
+
+    /// Sends a command to the background processor
+    #[inline]
+    async fn cmd_send(&self, cmd: Command) -> Result<()> {
+        if self.is_closed() {
+            return Err(StorageError::Closed);
+        }
+        self.active_count.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self.cmd_tx.send((Instant::now(), cmd)).await {
+            self.active_count.fetch_sub(1, Ordering::Relaxed);
+            Err(StorageError::from(e))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Gets a map handle
+    #[inline]
+    fn _map<N: AsRef<[u8]>>(&self, name: N) -> SledStorageMap {
+        SledStorageMap::_new(name.as_ref().to_vec(), self.clone())
+    }
+
+    /// Gets a list handle
+    #[inline]
+    fn _list<V: AsRef<[u8]>>(&self, name: V) -> SledStorageList {
+        SledStorageList::_new(name.as_ref().to_vec(), self.clone())
+    }
+
+    /// Creates a stream handle
+    #[inline]
+    fn _stream<V: AsRef<[u8]>>(&self, name: V) -> SledStorageStream {
+        SledStorageStream::_new(name.as_ref().to_vec(), self.clone())
+    }
+}
+
+#[async_trait]
+impl IStorageDB for SledStorageDB {
+    type MapType = SledStorageMap;
+    type ListType = SledStorageList;
+    type StreamType = SledStorageStream;
+
+    /// Creates or gets a map with optional expiration
+    #[inline]
+    async fn map<N: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: N,
+        expire: Option<TimestampMillis>,
+    ) -> Result<Self::MapType> {
+        SledStorageMap::new_expire(name.as_ref().to_vec(), expire, false, self.clone()).await
+    }
+
+    /// Creates or gets a map with a sliding expiration (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    async fn map_sliding<N: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: N,
+        ttl: TimestampMillis,
+    ) -> Result<Self::MapType> {
+        SledStorageMap::new_expire(name.as_ref().to_vec(), Some(ttl), true, self.clone()).await
+    }
+
+    /// Removes a map
+    #[inline]
+    async fn map_remove<K>(&self, name: K) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBMapRemove(self.clone(), name.as_ref().into(), tx))
+            .await?;
+        rx.await??;
+        Ok(())
+    }
+
+    /// Checks if a map exists
+    #[inline]
+    async fn map_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBMapContainsKey(
+            self.clone(),
+            key.as_ref().into(),
+            tx,
+        ))
+        .await?;
+        Ok(rx.await??)
+    }
+
+    /// Creates or gets a list with optional expiration
+    #[inline]
+    async fn list<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        expire: Option<TimestampMillis>,
+    ) -> Result<Self::ListType> {
+        SledStorageList::new_expire(name.as_ref().to_vec(), expire, false, self.clone()).await
+    }
+
+    /// Creates or gets a list with a sliding expiration (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    async fn list_sliding<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        ttl: TimestampMillis,
+    ) -> Result<Self::ListType> {
+        SledStorageList::new_expire(name.as_ref().to_vec(), Some(ttl), true, self.clone()).await
+    }
+
+    /// Removes a list
+    #[inline]
+    async fn list_remove<K>(&self, name: K) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBListRemove(
+            self.clone(),
+            name.as_ref().into(),
+            tx,
+        ))
+        .await?;
+        rx.await??;
+        Ok(())
+    }
+
+    /// Checks if a list exists
+    #[inline]
+    async fn list_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBListContainsKey(
+            self.clone(),
+            key.as_ref().into(),
+            tx,
+        ))
+        .await?;
+        Ok(rx.await??)
+    }
+
+    /// Creates or gets a stream with optional expiration
+    #[inline]
+    async fn stream<V: AsRef<[u8]> + Sync + Send>(
+        &self,
+        name: V,
+        expire: Option<TimestampMillis>,
+    ) -> Result<Self::StreamType> {
+        SledStorageStream::new_expire(name.as_ref().to_vec(), expire, self.clone()).await
+    }
+
+    /// Removes a stream
+    #[inline]
+    async fn stream_remove<K>(&self, name: K) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBStreamRemove(
+            self.clone(),
+            name.as_ref().into(),
+            tx,
+        ))
+        .await?;
+        rx.await?
+    }
+
+    /// Checks if a stream exists
+    #[inline]
+    async fn stream_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBStreamContainsKey(
+            self.clone(),
+            key.as_ref().into(),
+            tx,
+        ))
+        .await?;
+        rx.await?
+    }
+
+    /// Gets the type of the key, map, list or stream stored under a name
+    ///
+    /// A name holds a single type, using it as another type fails with
+    /// `StorageError::WrongType`.
+    #[inline]
+    async fn type_of<K: AsRef<[u8]> + Sync + Send>(&self, name: K) -> Result<Option<KeyType>> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBTypeOf(self.clone(), name.as_ref().into(), tx))
+            .await?;
+        rx.await?
+    }
+
+    /// Inserts a key-value pair
+    #[inline]
+    async fn insert<K, V>(&self, key: K, val: &V) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+        V: serde::ser::Serialize + Sync + Send,
+    {
+        let val = bincode::serialize(val)?;
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBInsert(
+            self.clone(),
+            key.as_ref().to_vec(),
+            val,
+            tx,
+        ))
+        .await?;
+        rx.await??;
+        Ok(())
+    }
+
+    /// Inserts a key-value pair with a sliding expiration (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    async fn insert_sliding<K, V>(&self, key: K, val: &V, ttl: TimestampMillis) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+        V: serde::ser::Serialize + Sync + Send,
+    {
+        let val = bincode::serialize(val)?;
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBInsertSliding(
+            self.clone(),
+            key.as_ref().to_vec(),
+            val,
+            ttl,
+            tx,
+        ))
+        .await?;
+        rx.await??;
+        Ok(())
+    }
+
+    /// Gets a value by key
+    #[inline]
+    async fn get<K, V>(&self, key: K) -> Result<Option<V>>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBGet(self.clone(), key.as_ref().into(), tx))
+            .await?;
+        match rx.await?? {
+            Some(v) => Ok(Some(bincode::deserialize::<V>(v.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes a key-value pair
+    #[inline]
+    async fn remove<K>(&self, key: K) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBRemove(self.clone(), key.as_ref().into(), tx))
+            .await?;
+        rx.await??;
+        Ok(())
+    }
+
+    /// Batch inserts key-value pairs
+    #[inline]
+    async fn batch_insert<V>(&self, key_vals: Vec<(Key, V)>) -> Result<()>
+    where
+        V: Serialize + Sync + Send,
+    {
+        if key_vals.is_empty() {
+            return Ok(());
+        }
+
+        let key_vals = key_vals
+            .into_iter()
+            .map(|(k, v)| {
+                bincode::serialize(&v)
+                    .map(|v| (k, v.into()))
+                    .map_err(StorageError::from)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBBatchInsert(self.clone(), key_vals, tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Batch removes keys
+    #[inline]
+    async fn batch_remove(&self, keys: Vec<Key>) -> Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBBatchRemove(self.clone(), keys, tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Increments a counter
+    #[inline]
+    async fn counter_incr<K>(&self, key: K, increment: i64) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBCounterIncr(
+            self.clone(),
+            key.as_ref().into(),
+            increment,
+            tx,
+        ))
+        .await?;
+        Ok(rx.await??)
+    }
+
+    /// Decrements a counter
+    #[inline]
+    async fn counter_decr<K>(&self, key: K, decrement: i64) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBCounterDecr(
+            self.clone(),
+            key.as_ref().into(),
+            decrement,
+            tx,
+        ))
+        .await?;
+        Ok(rx.await??)
+    }
+
+    /// Gets counter value
+    #[inline]
+    async fn counter_get<K>(&self, key: K) -> Result<Option<i64>>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBCounterGet(self.clone(), key.as_ref().into(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Sets counter value
+    #[inline]
+    async fn counter_set<K>(&self, key: K, val: i64) -> Result<()>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBCounterSet(
+            self.clone(),
+            key.as_ref().into(),
+            val,
+            tx,
+        ))
+        .await?;
+        Ok(rx.await??)
+    }
+
+    /// Checks if a key exists
+    #[inline]
+    async fn contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBContainsKey(
+            self.clone(),
+            key.as_ref().into(),
+            tx,
+        ))
+        .await?;
+        Ok(rx.await??)
+    }
+
+    /// Gets number of key-value pairs (if enabled)
+    #[inline]
+    #[cfg(feature = "len")]
+    async fn len(&self) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBLen(self.clone(), tx)).await?;
+        Ok(rx.await?)
+    }
+
     /// Gets total database size
     #[inline]
-    fn _db_size(&self) -> usize {
-        self.db.len() + self.kv_tree.len() + self.map_tree.len() + self.list_tree.len()
+    async fn db_size(&self) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBSize(self.clone(), tx)).await?;
+        Ok(rx.await?)
+    }
+
+    /// Sets expiration time for a key (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    async fn expire_at<K>(&self, key: K, at: TimestampMillis) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBExpireAt(
+            self.clone(),
+            key.as_ref().into(),
+            at,
+            tx,
+        ))
+        .await?;
+        Ok(rx.await??)
+    }
+
+    /// Sets time-to-live for a key (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    async fn expire<K>(&self, key: K, dur: TimestampMillis) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let at = self.now() + dur;
+        self.expire_at(key, at).await
+    }
+
+    /// Gets time-to-live for a key (TTL feature)
+    #[inline]
+    #[cfg(feature = "ttl")]
+    async fn ttl<K>(&self, key: K) -> Result<Option<TimestampMillis>>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBTtl(self.clone(), key.as_ref().into(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Iterates over all maps
+    #[inline]
+    async fn map_iter<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn AsyncIterator<Item = Result<StorageMap>> + Send + 'a>> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBMapPrefixIter(self.clone(), tx))
+            .await?;
+        let iter = rx.await?;
+        let iter = Box::new(AsyncMapIter::new(self, iter));
+        Ok(iter)
+    }
+
+    /// Iterates over all lists
+    #[inline]
+    async fn list_iter<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn AsyncIterator<Item = Result<StorageList>> + Send + 'a>> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBListPrefixIter(self.clone(), tx))
+            .await?;
+        let iter = rx.await?;
+        let iter = Box::new(AsyncListIter {
+            db: self,
+            iter: Some(iter),
+        });
+        Ok(iter)
+    }
+
+    /// Scans keys matching pattern
+    async fn scan<'a, P>(
+        &'a mut self,
+        pattern: P,
+    ) -> Result<Box<dyn AsyncIterator<Item = Result<Key>> + Send + 'a>>
+    where
+        P: AsRef<[u8]> + Send + Sync,
+    {
+        let pattern = pattern.as_ref();
+        let (tx, rx) = oneshot::channel();
+        self.cmd_send(Command::DBScanIter(self.clone(), pattern.to_vec(), tx))
+            .await?;
+        let iter = rx.await?;
+        let pattern = Pattern::from(pattern);
+        let iter = Box::new(AsyncDbKeyIter {
+            db: self,
+            pattern,
+            iter: Some(iter),
+        });
+        Ok(iter)
+    }
+
+    /// Gets database information
+    async fn info(&self) -> Result<Value> {
+        Ok(serde_json::to_value(self.storage_info().await?)?)
+    }
+}
+
+/// Map structure for key-value storage within a namespace
+/*
+SET user:1001 "{name: Alice}"  # 用户命名空间
+SET order:1001 "{id: 123}"     # 订单命名空间
+
+KEYS "user:*"    # 查询所有用户数据
+DEL "order:1001" # 删除指定订单
+
+优点​​：减少 Key 数量，适合字段固定的场景
+
+
+完整示例（Map + Namespace）​
+
+# 写入数据（Hash 结构）
+HSET user:profile 1001 name "Alice" age 30
+HSET user:profile 1002 name "Bob" age 25
+
+# 读取数据
+HGETALL user:profile:1001
+# 结果：1) "name" 2) "Alice" 3) "age" 4) "30"
+
+# 删除整个命名空间
+DEL user:profile  # 慎用！会删除所有用户数据
+
+*/
+#[derive(Clone)]
+pub struct SledStorageMap {
+    /// Map name
+    name: Key,
+    /// Prefix for map keys
+    map_prefix_name: Key,
+    /// Prefix for map items
+    map_item_prefix_name: Key,
+    /// Key for map count
+    map_count_key_name: Key,
+    /// Prefix for item expiration times
+    #[cfg(feature = "ttl")]
+    map_field_expire_prefix_name: Key,
+    /// Flag indicating if map is empty
+    empty: Arc<AtomicBool>,
+    /// Database handle
+    pub(crate) db: SledStorageDB,
+}
+
+impl SledStorageMap {
+    /// Creates a new map with optional expiration
+    #[inline]
+    async fn new_expire(
+        name: Key,
+        expire_ms: Option<TimestampMillis>,
+        sliding: bool,
+        db: SledStorageDB,
+    ) -> Result<Self> {
+        let (tx, rx) = oneshot::channel();
+        db.cmd_send(Command::DBMapNew(
+            db.clone(),
+            name.into(),
+            expire_ms,
+            sliding,
+            tx,
+        ))
+        .await?;
+        rx.await?
+    }
+
+    /// Internal method to create map with expiration
+    #[inline]
+    fn _new_expire(
+        name: Key,
+        _expire_ms: Option<TimestampMillis>,
+        _sliding: bool,
+        db: SledStorageDB,
+    ) -> Result<Self> {
+        db._check_type(&name, KeyType::Map)?;
+        let m = Self::_new(name, db);
+        m.empty.store(m._is_empty()?, Ordering::SeqCst);
+        #[cfg(feature = "ttl")]
+        if let Some(expire_ms) = _expire_ms {
+            //a new map needs its count key, the expiration is only set on existing names
+            m._counter_init()?;
+            m.db._expire(
+                m.name.as_slice(),
+                ExpireMeta::new(m.db.now(), expire_ms, _sliding),
+                KeyType::Map,
+            )?;
+        }
+        Ok(m)
+    }
+
+    /// Internal method to create map
+    #[inline]
+    fn _new(name: Key, db: SledStorageDB) -> Self {
+        let map_prefix_name = SledStorageDB::make_map_prefix_name(name.as_slice());
+        let map_item_prefix_name = SledStorageDB::make_map_item_prefix_name(name.as_slice());
+        let map_count_key_name = SledStorageDB::make_map_count_key_name(name.as_slice());
+        #[cfg(feature = "ttl")]
+        let map_field_expire_prefix_name =
+            SledStorageDB::make_map_field_expire_prefix_name(name.as_slice());
+        SledStorageMap {
+            name,
+            map_prefix_name,
+            map_item_prefix_name,
+            map_count_key_name,
+            #[cfg(feature = "ttl")]
+            map_field_expire_prefix_name,
+            empty: Arc::new(AtomicBool::new(true)),
+            db,
+        }
+    }
+
+    /// Gets the underlying tree
+    #[inline]
+    fn tree(&self) -> &sled::Tree {
+        &self.db.map_tree
+    }
+
+    /// Creates a full item key
+    #[inline]
+    fn make_map_item_key<K: AsRef<[u8]>>(&self, key: K) -> Key {
+        [self.map_item_prefix_name.as_ref(), key.as_ref()].concat()
+    }
+
+    /// Creates the key holding the expiration time of an item (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn make_field_expire_key<K: AsRef<[u8]>>(&self, key: K) -> Key {
+        [self.map_field_expire_prefix_name.as_ref(), key.as_ref()].concat()
+    }
+
+    /// Checks if an item has expired
+    #[inline]
+    fn _is_field_expired(&self, _key: &[u8]) -> Result<bool> {
+        #[cfg(feature = "ttl")]
+        if let Some(at) = self.tree().get(self.make_field_expire_key(_key))? {
+            let at = TimestampMillis::from_be_bytes(at.as_ref().try_into()?);
+            return Ok(self.db.now() >= at);
+        }
+        Ok(false)
+    }
+
+    /// Counts the items that have expired but are not yet removed
+    #[cfg(feature = "map_len")]
+    #[inline]
+    fn _expired_field_count(&self) -> Result<i64> {
+        #[allow(unused_mut)]
+        let mut count = 0;
+        #[cfg(feature = "ttl")]
+        {
+            let now = self.db.now();
+            for item in self
+                .tree()
+                .scan_prefix(self.map_field_expire_prefix_name.as_slice())
+                .values()
+            {
+                if TimestampMillis::from_be_bytes(item?.as_ref().try_into()?) <= now {
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Transactionally removes the expiration time of an item, returns true if it had one (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _tx_remove_field_expire<E>(
+        &self,
+        tx: &TransactionalTree,
+        key: &[u8],
+    ) -> ConflictableTransactionResult<bool, E> {
+        if let Some(at) = tx.remove(self.make_field_expire_key(key))? {
+            tx.remove(SledStorageDB::make_map_field_expire_index_key(
+                &at, &self.name, key,
+            ))?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Removes the expiration time of an item, returns true if it had one (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _remove_field_expire(&self, key: &[u8]) -> Result<bool> {
+        if !self.tree().contains_key(self.make_field_expire_key(key))? {
+            return Ok(false);
+        }
+        Ok(self
+            .tree()
+            .transaction(|tx| self._tx_remove_field_expire::<()>(tx, key))?)
+    }
+
+    /// Sets the expiration time of an item, returns false if the item does not exist (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _expire_field_at(&self, key: IVec, at: TimestampMillis) -> Result<bool> {
+        if self._is_expired()? || !self._contains_key(key.clone())? {
+            return Ok(false);
+        }
+        let at_bytes = at.to_be_bytes();
+        let index_key = SledStorageDB::make_map_field_expire_index_key(&at_bytes, &self.name, &key);
+        self.tree().transaction(|tx| {
+            self._tx_remove_field_expire::<()>(tx, &key)?;
+            tx.insert(self.make_field_expire_key(&key), &at_bytes)?;
+            tx.insert(index_key.as_slice(), IVec::default())?;
+            Ok(())
+        })?;
+        Ok(true)
+    }
+
+    /// Gets the remaining time-to-live of an item, `None` if the item does not exist or has none (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _field_ttl(&self, key: IVec) -> Result<Option<TimestampMillis>> {
+        if self._is_expired()? || !self._contains_key(key.clone())? {
+            return Ok(None);
+        }
+        match self.tree().get(self.make_field_expire_key(&key))? {
+            Some(at) => {
+                let at = TimestampMillis::from_be_bytes(at.as_ref().try_into()?);
+                Ok(Some(at - self.db.now()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the expiration time of an item, returns false if the item does not exist or has none (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _persist_field(&self, key: IVec) -> Result<bool> {
+        if self._is_expired()? || !self._contains_key(key.clone())? {
+            return Ok(false);
+        }
+        self._remove_field_expire(&key)
+    }
+
+    /// Removes an item whose expiration time `at` has passed, `index_key` is its entry in the
+    /// index of expiring items (TTL feature)
+    #[cfg(feature = "ttl")]
+    #[inline]
+    fn _remove_expired_field(
+        &self,
+        key: &[u8],
+        at: TimestampMillis,
+        index_key: &[u8],
+    ) -> Result<bool> {
+        let item_key = self.make_map_item_key(key);
+        let expire_key = self.make_field_expire_key(key);
+        let removed = self.tree().transaction(|tx| {
+            tx.remove(index_key)?;
+            //the item got another expiration time or none since it was indexed
+            if tx
+                .get(expire_key.as_slice())?
+                .is_none_or(|v| v.as_ref() != at.to_be_bytes())
+            {
+                return Ok(None);
+            }
+            tx.remove(expire_key.as_slice())?;
+            let removed = tx.remove(item_key.as_slice())?;
+            #[cfg(feature = "map_len")]
+            if removed.is_some() {
+                Self::_tx_counter_dec(tx, self.map_count_key_name.as_slice())?;
+            }
+            Ok::<_, ConflictableTransactionError<()>>(Some(removed))
+        })?;
+        self.db
+            ._quota_release(removed.as_ref().and_then(Option::as_ref));
+        Ok(removed.is_some())
+    }
+
+    /// Advances an item iterator, skipping expired items
+    #[inline]
+    fn _iter_next(&self, iter: &mut sled::Iter) -> Option<Result<(IVec, IVec)>> {
+        for item in iter.by_ref() {
+            let (k, v) = match item {
+                Ok(item) => item,
+                Err(e) => return Some(Err(e.into())),
+            };
+            match self._is_field_expired(&k[self.map_item_prefix_name.len()..]) {
+                Ok(true) => continue,
+                Ok(false) => return Some(Ok((k, v))),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+
+    /// Gets map length (if enabled)
+    #[cfg(feature = "map_len")]
+    #[inline]
+    fn _len_get(&self) -> Result<i64> {
+        self._counter_get(self.map_count_key_name.as_slice())
+    }
+
+    /// Transactionally increments a counter
+    #[inline]
+    fn _tx_counter_inc<K: AsRef<[u8]>>(
+        tx: &TransactionalTree,
+        key: K,
+    ) -> ConflictableTransactionResult<()> {
+        let val = tx
+            .get(key.as_ref())?
+            .and_then(|data| decode_counter(data.as_ref()))
+            .map(|n| n + 1)
+            .unwrap_or(1);
+        tx.insert(key.as_ref(), val.to_be_bytes().as_slice())?;
+        Ok(())
+    }
+
+    /// Transactionally decrements a counter
+    #[inline]
+    fn _tx_counter_dec<K: AsRef<[u8]>>(
+        tx: &TransactionalTree,
+        key: K,
+    ) -> ConflictableTransactionResult<()> {
+        let val = tx
+            .get(key.as_ref())?
+            .and_then(|data| decode_counter(data.as_ref()))
+            .map(|n| n - 1)
+            .unwrap_or(-1);
+        if val > 0 {
+            tx.insert(key.as_ref(), val.to_be_bytes().as_slice())?;
+        } else {
+            tx.remove(key.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Transactionally gets counter value
+    #[inline]
+    fn _tx_counter_get<K: AsRef<[u8]>>(
+        tx: &TransactionalTree,
+        key: K,
+    ) -> ConflictableTransactionResult<i64, StorageError> {
+        if let Some(v) = tx.get(key)? {
+            match decode_counter(v.as_ref()) {
+                Some(c) => Ok(c),
+                None => Err(ConflictableTransactionError::Abort(StorageError::codec(
+                    "invalid counter value",
+                ))),
+            }
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Transactionally sets counter value
+    #[inline]
+    fn _tx_counter_set<K: AsRef<[u8]>, E>(
+        tx: &TransactionalTree,
+        key: K,
+        val: i64,
+    ) -> ConflictableTransactionResult<(), E> {
+        tx.insert(key.as_ref(), val.to_be_bytes().as_slice())?;
+        Ok(())
+    }
+
+    /// Transactionally removes counter
+    #[inline]
+    fn _tx_counter_remove<K: AsRef<[u8]>, E>(
+        tx: &TransactionalTree,
+        key: K,
+    ) -> ConflictableTransactionResult<(), E> {
+        tx.remove(key.as_ref())?;
+        Ok(())
+    }
+
+    /// Gets counter value
+    #[inline]
+    fn _counter_get<K: AsRef<[u8]>>(&self, key: K) -> Result<i64> {
+        if let Some(v) = self.tree().get(key)? {
+            decode_counter(v.as_ref()).ok_or_else(|| StorageError::codec("invalid counter value"))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Initializes counter if not present
+    #[inline]
+    fn _counter_init(&self) -> Result<()> {
+        let tree = self.tree();
+        if !tree.contains_key(self.map_count_key_name.as_slice())? {
+            tree.insert(
+                self.map_count_key_name.as_slice(),
+                0i64.to_be_bytes().as_slice(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Clears the map
+    #[inline]
+    fn _clear(&self) -> Result<()> {
+        let (batch, removed) = self._make_clear_batch();
+        self.tree().transaction(|tx| self._tx_clear(tx, &batch))?;
+        self.db.quota.release(removed);
+        Ok(())
+    }
+
+    /// Transactionally clears the map
+    #[inline]
+    fn _tx_clear(
+        &self,
+        map_tree_tx: &TransactionalTree,
+        batch: &Batch,
+    ) -> ConflictableTransactionResult<()> {
+        map_tree_tx.apply_batch(batch)?;
+        self.empty.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Creates batch for clearing map, along with the usage of the removed items
+    #[inline]
+    fn _make_clear_batch(&self) -> (Batch, QuotaUsage) {
+        let mut batch = Batch::default();
+        let mut removed = QuotaUsage::default();
+        //clear key-value
+        for item in self.tree().scan_prefix(self.map_prefix_name.as_slice()) {
+            match item {
+                Ok((key, val)) => {
+                    if key.starts_with(self.map_item_prefix_name.as_slice()) {
+                        removed.keys += 1;
+                        removed.bytes += val.len() as u64;
+                    }
+                    batch.remove(key);
+                }
+                Err(e) => {
+                    log::warn!("{:?}", e);
+                }
+            }
+        }
+        (batch, removed)
+    }
+
+    /// Inserts a key-value pair into the map
+    #[inline]
+    fn _insert(&self, key: IVec, val: IVec) -> Result<()> {
+        self.db._check_type(&self.name, KeyType::Map)?;
+        let item_key = self.make_map_item_key(key.as_ref());
+        let reserved = if self.db.quota.is_enabled() {
+            let old = self.tree().get(&item_key)?.map(|v| v.len());
+            self.db._quota_reserve([(old, val.len())])?
+        } else {
+            QuotaReservation::default()
+        };
+        let this = self;
+        #[cfg(feature = "map_len")]
+        {
+            let count_key = this.map_count_key_name.as_slice();
+            this.tree().transaction(move |tx| {
+                if tx.insert(item_key.as_slice(), val.as_ref())?.is_none() {
+                    Self::_tx_counter_inc(tx, count_key)?;
+                }
+                //a new value drops the expiration time of the item
+                #[cfg(feature = "ttl")]
+                this._tx_remove_field_expire(tx, &key)?;
+                Ok(())
+            })?;
+        }
+        #[cfg(not(feature = "map_len"))]
+        {
+            if self.empty.load(Ordering::SeqCst) {
+                self._counter_init()?;
+                self.empty.store(false, Ordering::SeqCst)
+            }
+            this.tree().insert(item_key.as_slice(), val.as_ref())?;
+            #[cfg(feature = "ttl")]
+            this._remove_field_expire(&key)?;
+        }
+
+        #[cfg(feature = "ttl")]
+        {
+            //判断是否是ttl 值。
+            // true 为已过期
+            let res = this.db._is_expired(this.name.as_slice(), KeyType::Map)?;
+
+            // this.db._remove_expire_key(this.name.as_slice())?;
+            if res {
+                (&self.db.key_expire_tree, &self.db.expire_key_tree).transaction(
+                    |(key_expire_tx, expire_key_tx)| {
+                        SledStorageDB::_tx_remove_expire_key(
+                            key_expire_tx,
+                            expire_key_tx,
+                            this.name.as_slice(),
+                            KeyType::Map,
+                        )?;
+                        Ok::<(), ConflictableTransactionError<()>>(())
+                    },
+                )?;
+            }
+        }
+
+        reserved.commit();
+        Ok(())
+    }
+
+    /// Gets a value from the map
+    #[inline]
+    fn _get(&self, key: IVec) -> Result<Option<IVec>> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::Map)?;
+        let this = self;
+        let item_key = self.make_map_item_key(key.as_ref());
+        if this.db._is_expired(this.name.as_slice(), KeyType::Map)?
+            || this._is_field_expired(&key)?
+        {
+            return Ok(None);
+        }
+        #[cfg(feature = "ttl")]
+        if let Some(ttl) = this.db._sliding_ttl(this.name.as_slice(), KeyType::Map)? {
+            return this.db._read_sliding(
+                this.tree(),
+                this.name.as_slice(),
+                KeyType::Map,
+                ttl,
+                |tx| Ok(tx.get(item_key.as_slice())?),
+            );
+        }
+        Ok(this.tree().get(item_key)?)
+    }
+
+    /// Removes a key from the map
+    #[inline]
+    fn _remove(&self, key: IVec) -> Result<bool> {
+        let tree = self.tree();
+        let item_key = self.make_map_item_key(key.as_ref());
+
+        #[cfg(feature = "map_len")]
+        let removed = {
+            let count_key = self.map_count_key_name.to_vec();
+            tree.transaction(move |tx| {
+                let removed = tx.remove(item_key.as_slice())?;
+                if removed.is_some() {
+                    Self::_tx_counter_dec(tx, count_key.as_slice())?;
+                }
+                #[cfg(feature = "ttl")]
+                self._tx_remove_field_expire(tx, &key)?;
+                Ok(removed)
+            })?
+        };
+
+        #[cfg(not(feature = "map_len"))]
+        let removed = {
+            let removed = tree.remove(item_key.as_slice())?;
+            #[cfg(feature = "ttl")]
+            self._remove_field_expire(&key)?;
+            removed
+        };
+
+        self.db._quota_release(removed.as_ref());
+        Ok(removed.is_some())
+    }
+
+    /// Checks if key exists in map
+    #[inline]
+    fn _contains_key(&self, key: IVec) -> Result<bool> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::Map)?;
+        let item_key = self.make_map_item_key(key.as_ref());
+        Ok(self.tree().contains_key(item_key)? && !self._is_field_expired(&key)?)
+    }
+
+    /// Gets map length (if enabled)
+    #[cfg(feature = "map_len")]
+    #[inline]
+    fn _len(&self) -> Result<usize> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::Map)?;
+        if self.db._is_expired(self.name.as_slice(), KeyType::Map)? {
+            return Ok(0);
+        }
+        let len = self._len_get()? - self._expired_field_count()?;
+        Ok(len.max(0) as usize)
+    }
+
+    /// Checks if map is empty
+    #[inline]
+    fn _is_empty(&self) -> Result<bool> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::Map)?;
+        let this = self;
+        let res = {
+            if this.db._is_expired(this.name.as_slice(), KeyType::Map)? {
+                true
+            } else {
+                let mut iter = self
+                    .tree()
+                    .scan_prefix(self.map_item_prefix_name.as_slice());
+                self._iter_next(&mut iter).transpose()?.is_none()
+            }
+        };
+        Ok(res)
+    }
+
+    /// Removes and returns a value
+    #[inline]
+    fn _remove_and_fetch(&self, key: IVec) -> Result<Option<IVec>> {
+        let item_key = self.make_map_item_key(key.as_ref());
+        let this = self;
+        let expired = this._is_field_expired(&key)?;
+        let removed = {
+            if this.db._is_expired(this.name.as_slice(), KeyType::Map)? {
+                Ok(None)
+            } else {
+                #[cfg(feature = "map_len")]
+                {
+                    let count_key = this.map_count_key_name.to_vec();
+                    this.tree().transaction(move |tx| {
+                        #[cfg(feature = "ttl")]
+                        this._tx_remove_field_expire(tx, &key)?;
+                        if let Some(removed) = tx.remove(item_key.as_slice())? {
+                            Self::_tx_counter_dec(tx, count_key.as_slice())?;
+                            Ok(Some(removed))
+                        } else {
+                            Ok(None)
+                        }
+                    })
+                }
+                #[cfg(not(feature = "map_len"))]
+                {
+                    let removed = this.tree().remove(item_key.as_slice())?;
+                    #[cfg(feature = "ttl")]
+                    this._remove_field_expire(&key)?;
+                    Ok::<_, TransactionError<()>>(removed)
+                }
+            }
+        }?;
+
+        self.db._quota_release(removed.as_ref());
+        Ok(removed.filter(|_| !expired))
+    }
+
+    /// Removes keys with prefix, returns the number of removed keys
+    #[inline]
+    fn _remove_with_prefix(&self, prefix: IVec) -> Result<u64> {
+        let tree = self.tree();
+        #[cfg(feature = "ttl")]
+        let raw_prefix = prefix.clone();
+        let prefix = [self.map_item_prefix_name.as_slice(), prefix.as_ref()]
+            .concat()
+            .to_vec();
+
+        #[cfg(feature = "map_len")]
+        let map_count_key_name = self.map_count_key_name.to_vec();
+        let mut removed = QuotaUsage::default();
+        {
+            let mut removeds = Batch::default();
+            #[cfg(feature = "map_len")]
+            let mut c = 0;
+            for item in tree.scan_prefix(prefix) {
+                match item {
+                    Ok((k, v)) => {
+                        removed.keys += 1;
+                        removed.bytes += v.len() as u64;
+                        removeds.remove(k.as_ref());
+                        #[cfg(feature = "map_len")]
+                        {
+                            c += 1;
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("{:?}", e);
+                    }
+                }
+            }
+            #[cfg(feature = "ttl")]
+            {
+                let expire_prefix = self.map_field_expire_prefix_name.as_slice();
+                for item in tree.scan_prefix([expire_prefix, raw_prefix.as_ref()].concat()) {
+                    let (k, at) = item?;
+                    removeds.remove(SledStorageDB::make_map_field_expire_index_key(
+                        &at,
+                        &self.name,
+                        &k[expire_prefix.len()..],
+                    ));
+                    removeds.remove(k);
+                }
+            }
+
+            #[cfg(feature = "map_len")]
+            {
+                tree.transaction(move |tx| {
+                    let len = Self::_tx_counter_get(tx, map_count_key_name.as_slice())? - c;
+                    if len > 0 {
+                        Self::_tx_counter_set(tx, map_count_key_name.as_slice(), len)?;
+                    } else {
+                        Self::_tx_counter_remove(tx, map_count_key_name.as_slice())?;
+                    };
+                    tx.apply_batch(&removeds)?;
+                    Ok::<(), ConflictableTransactionError<StorageError>>(())
+                })
+            }
+            #[cfg(not(feature = "map_len"))]
+            {
+                tree.apply_batch(removeds)?;
+                Ok::<(), TransactionError<StorageError>>(())
+            }
+        }?;
+        let count = removed.keys;
+        self.db.quota.release(removed);
+        Ok(count)
+    }
+
+    /// Batch inserts key-value pairs
+    #[inline]
+    fn _batch_insert(&self, key_vals: Vec<(IVec, IVec)>) -> Result<()> {
+        for (k, v) in key_vals {
+            self._insert(k, v)?;
+        }
+        Ok(())
     }
 
-    /// Sends a command to the background processor
+    /// Batch removes keys, returns the keys that existed
     #[inline]
-    async fn cmd_send(&self, cmd: Command) -> Result<()> {
-        self.active_count.fetch_add(1, Ordering::Relaxed);
-        if let Err(e) = self.cmd_tx.send(cmd).await {
-            self.active_count.fetch_sub(1, Ordering::Relaxed);
-            Err(anyhow!(e))
-        } else {
-            Ok(())
+    fn _batch_remove(&self, keys: Vec<IVec>) -> Result<Vec<IVec>> {
+        let mut removed = Vec::new();
+        for k in keys {
+            if self._remove(k.clone())? {
+                removed.push(k);
+            }
         }
+        Ok(removed)
     }
 
-    /// Gets a map handle
+    /// Sets expiration time (TTL feature)
+    #[cfg(feature = "ttl")]
     #[inline]
-    fn _map<N: AsRef<[u8]>>(&self, name: N) -> SledStorageMap {
-        SledStorageMap::_new(name.as_ref().to_vec(), self.clone())
+    fn _expire_at(&self, at: TimestampMillis) -> Result<bool> {
+        self.db._expire_at(self.name.as_slice(), at, KeyType::Map)
     }
 
-    /// Gets a list handle
+    /// Gets time-to-live (TTL feature)
+    #[cfg(feature = "ttl")]
     #[inline]
-    fn _list<V: AsRef<[u8]>>(&self, name: V) -> SledStorageList {
-        SledStorageList::_new(name.as_ref().to_vec(), self.clone())
+    fn _ttl(&self) -> Result<Option<TimestampMillis>> {
+        let res = self
+            .db
+            ._ttl(self.name(), KeyType::Map)?
+            .and_then(|(at, _)| if at > 0 { Some(at) } else { None });
+        Ok(res)
+    }
+
+    /// Checks if map is expired
+    #[inline]
+    fn _is_expired(&self) -> Result<bool> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::Map)?;
+        self.db._is_expired(self.name.as_slice(), KeyType::Map)
+    }
+
+    /// Checks if map is expired (async)
+    #[inline]
+    async fn call_is_expired(&self) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::MapIsExpired(self.clone(), tx))
+            .await?;
+        rx.await?
+    }
+
+    /// Creates prefix iterator
+    #[inline]
+    fn _prefix_iter(&self, prefix: Option<IVec>) -> sled::Iter {
+        if let Some(prefix) = prefix {
+            self.tree()
+                .scan_prefix([self.map_item_prefix_name.as_slice(), prefix.as_ref()].concat())
+        } else {
+            self.tree()
+                .scan_prefix(self.map_item_prefix_name.as_slice())
+        }
+    }
+
+    /// Creates prefix iterator (async)
+    #[inline]
+    async fn call_prefix_iter(&self, prefix: Option<IVec>) -> Result<sled::Iter> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::MapPrefixIter(self.clone(), prefix, tx))
+            .await?;
+        Ok(rx.await?)
     }
 }
 
 #[async_trait]
-impl IStorageDB for SledStorageDB {
-    type MapType = SledStorageMap;
-    type ListType = SledStorageList;
-
-    /// Creates or gets a map with optional expiration
+impl Map for SledStorageMap {
+    /// Gets map name
     #[inline]
-    async fn map<N: AsRef<[u8]> + Sync + Send>(
-        &self,
-        name: N,
-        expire: Option<TimestampMillis>,
-    ) -> Result<Self::MapType> {
-        SledStorageMap::new_expire(name.as_ref().to_vec(), expire, self.clone()).await
+    fn name(&self) -> &[u8] {
+        self.name.as_slice()
     }
 
-    /// Removes a map
+    /// Inserts a key-value pair
     #[inline]
-    async fn map_remove<K>(&self, name: K) -> Result<()>
+    async fn insert<K, V>(&self, key: K, val: &V) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send,
+        V: Serialize + Sync + Send + ?Sized,
     {
+        let val = bincode::serialize(val)?;
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBMapRemove(self.clone(), name.as_ref().into(), tx))
+        self.db
+            .cmd_send(Command::MapInsert(
+                self.clone(),
+                key.as_ref().into(),
+                val.into(),
+                tx,
+            ))
             .await?;
         rx.await??;
         Ok(())
     }
 
-    /// Checks if a map exists
+    /// Gets a value by key
     #[inline]
-    async fn map_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+    async fn get<K, V>(&self, key: K) -> Result<Option<V>>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
+    {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBMapContainsKey(
-            self.clone(),
-            key.as_ref().into(),
-            tx,
-        ))
-        .await?;
-        Ok(rx.await??)
-    }
+        self.db
+            .cmd_send(Command::MapGet(self.clone(), key.as_ref().into(), tx))
+            .await?;
 
-    /// Creates or gets a list with optional expiration
-    #[inline]
-    async fn list<V: AsRef<[u8]> + Sync + Send>(
-        &self,
-        name: V,
-        expire: Option<TimestampMillis>,
-    ) -> Result<Self::ListType> {
-        SledStorageList::new_expire(name.as_ref().to_vec(), expire, self.clone()).await
+        match rx.await?? {
+            Some(v) => Ok(Some(bincode::deserialize::<V>(v.as_ref())?)),
+            None => Ok(None),
+        }
     }
 
-    /// Removes a list
+    /// Removes a key
     #[inline]
-    async fn list_remove<K>(&self, name: K) -> Result<()>
+    async fn remove<K>(&self, key: K) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send,
     {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBListRemove(
-            self.clone(),
-            name.as_ref().into(),
-            tx,
-        ))
-        .await?;
+        self.db
+            .cmd_send(Command::MapRemove(self.clone(), key.as_ref().into(), tx))
+            .await?;
         rx.await??;
         Ok(())
     }
 
-    /// Checks if a list exists
+    /// Checks if key exists
     #[inline]
-    async fn list_contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+    async fn contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBListContainsKey(
-            self.clone(),
-            key.as_ref().into(),
-            tx,
-        ))
-        .await?;
+        self.db
+            .cmd_send(Command::MapContainsKey(
+                self.clone(),
+                key.as_ref().into(),
+                tx,
+            ))
+            .await?;
         Ok(rx.await??)
     }
 
-    /// Inserts a key-value pair
+    /// Gets map length (if enabled)
+    #[cfg(feature = "map_len")]
     #[inline]
-    async fn insert<K, V>(&self, key: K, val: &V) -> Result<()>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-        V: serde::ser::Serialize + Sync + Send,
-    {
-        let val = bincode::serialize(val)?;
+    async fn len(&self) -> Result<usize> {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBInsert(
-            self.clone(),
-            key.as_ref().to_vec(),
-            val,
-            tx,
-        ))
-        .await?;
+        self.db.cmd_send(Command::MapLen(self.clone(), tx)).await?;
+        Ok(rx.await??)
+    }
+
+    /// Checks if map is empty
+    #[inline]
+    async fn is_empty(&self) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::MapIsEmpty(self.clone(), tx))
+            .await?;
+        Ok(rx.await??)
+    }
+
+    /// Clears the map
+    #[inline]
+    async fn clear(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::MapClear(self.clone(), tx))
+            .await?;
         rx.await??;
         Ok(())
     }
 
-    /// Gets a value by key
+    /// Removes and returns a value
     #[inline]
-    async fn get<K, V>(&self, key: K) -> Result<Option<V>>
+    async fn remove_and_fetch<K, V>(&self, key: K) -> Result<Option<V>>
     where
         K: AsRef<[u8]> + Sync + Send,
         V: DeserializeOwned + Sync + Send,
     {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBGet(self.clone(), key.as_ref().into(), tx))
+        self.db
+            .cmd_send(Command::MapRemoveAndFetch(
+                self.clone(),
+                key.as_ref().into(),
+                tx,
+            ))
             .await?;
+
         match rx.await?? {
             Some(v) => Ok(Some(bincode::deserialize::<V>(v.as_ref())?)),
             None => Ok(None),
         }
     }
 
-    /// Removes a key-value pair
+    /// Removes keys with prefix
     #[inline]
-    async fn remove<K>(&self, key: K) -> Result<()>
+    async fn remove_with_prefix<K>(&self, prefix: K) -> Result<()>
     where
         K: AsRef<[u8]> + Sync + Send,
     {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBRemove(self.clone(), key.as_ref().into(), tx))
+        self.db
+            .cmd_send(Command::MapRemoveWithPrefix(
+                self.clone(),
+                prefix.as_ref().into(),
+                tx,
+            ))
             .await?;
         rx.await??;
         Ok(())
@@ -1686,1127 +6829,962 @@ impl IStorageDB for SledStorageDB {
     #[inline]
     async fn batch_insert<V>(&self, key_vals: Vec<(Key, V)>) -> Result<()>
     where
-        V: Serialize + Sync + Send,
-    {
-        if key_vals.is_empty() {
-            return Ok(());
-        }
-
+        V: serde::ser::Serialize + Sync + Send,
+    {
         let key_vals = key_vals
             .into_iter()
             .map(|(k, v)| {
                 bincode::serialize(&v)
-                    .map(|v| (k, v.into()))
-                    .map_err(|e| anyhow!(e))
+                    .map(|v| (k.into(), v.into()))
+                    .map_err(StorageError::from)
             })
-            .collect::<Result<Vec<_>>>()?;
+            .collect::<Result<Vec<(IVec, IVec)>>>()?;
 
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBBatchInsert(self.clone(), key_vals, tx))
+        self.db
+            .cmd_send(Command::MapBatchInsert(self.clone(), key_vals, tx))
             .await?;
-        Ok(rx.await??)
+        rx.await??;
+        Ok(())
     }
 
     /// Batch removes keys
     #[inline]
     async fn batch_remove(&self, keys: Vec<Key>) -> Result<()> {
-        if keys.is_empty() {
-            return Ok(());
-        }
+        let keys = keys.into_iter().map(|k| k.into()).collect::<Vec<IVec>>();
 
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBBatchRemove(self.clone(), keys, tx))
+        self.db
+            .cmd_send(Command::MapBatchRemove(self.clone(), keys, tx))
             .await?;
-        Ok(rx.await??)
-    }
-
-    /// Increments a counter
-    #[inline]
-    async fn counter_incr<K>(&self, key: K, increment: isize) -> Result<()>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-    {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBCounterIncr(
-            self.clone(),
-            key.as_ref().into(),
-            increment,
-            tx,
-        ))
-        .await?;
-        Ok(rx.await??)
+        rx.await??;
+        Ok(())
     }
 
-    /// Decrements a counter
+    /// Iterates over map items
     #[inline]
-    async fn counter_decr<K>(&self, key: K, decrement: isize) -> Result<()>
+    async fn iter<'a, V>(
+        &'a mut self,
+    ) -> Result<Box<dyn AsyncIterator<Item = IterItem<V>> + Send + 'a>>
     where
-        K: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send + 'a + 'static,
     {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBCounterDecr(
-            self.clone(),
-            key.as_ref().into(),
-            decrement,
-            tx,
-        ))
-        .await?;
-        Ok(rx.await??)
+        let this = self;
+        let res = {
+            if this.call_is_expired().await? {
+                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> =
+                    Box::new(AsyncEmptyIter {
+                        _m: std::marker::PhantomData,
+                    });
+                Ok::<_, StorageError>(iter)
+            } else {
+                let tem_prefix_name = this.map_item_prefix_name.len();
+                let iter = this.call_prefix_iter(None).await?;
+                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> = Box::new(AsyncIter {
+                    map: this,
+                    prefix_len: tem_prefix_name,
+                    iter: Some(iter),
+                    _m: std::marker::PhantomData,
+                });
+                Ok::<_, StorageError>(iter)
+            }
+        }?;
+        Ok(res)
     }
 
-    /// Gets counter value
+    /// Iterates over map keys
     #[inline]
-    async fn counter_get<K>(&self, key: K) -> Result<Option<isize>>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-    {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBCounterGet(self.clone(), key.as_ref().into(), tx))
-            .await?;
-        Ok(rx.await??)
+    async fn key_iter<'a>(
+        &'a mut self,
+    ) -> Result<Box<dyn AsyncIterator<Item = Result<Key>> + Send + 'a>> {
+        let this = self;
+        let res = {
+            if this.call_is_expired().await? {
+                let iter: Box<dyn AsyncIterator<Item = Result<Key>> + Send> =
+                    Box::new(AsyncEmptyIter {
+                        _m: std::marker::PhantomData,
+                    });
+                Ok::<_, StorageError>(iter)
+            } else {
+                let iter = this.call_prefix_iter(None).await?;
+                let iter: Box<dyn AsyncIterator<Item = Result<Key>> + Send> =
+                    Box::new(AsyncKeyIter {
+                        map: this,
+                        prefix_len: this.map_item_prefix_name.len(),
+                        iter: Some(iter),
+                    });
+                Ok::<_, StorageError>(iter)
+            }
+        }?;
+        Ok(res)
     }
 
-    /// Sets counter value
+    /// Iterates over items with prefix
     #[inline]
-    async fn counter_set<K>(&self, key: K, val: isize) -> Result<()>
+    async fn prefix_iter<'a, P, V>(
+        &'a mut self,
+        prefix: P,
+    ) -> Result<Box<dyn AsyncIterator<Item = IterItem<V>> + Send + 'a>>
     where
-        K: AsRef<[u8]> + Sync + Send,
+        P: AsRef<[u8]> + Send + Sync,
+        V: DeserializeOwned + Sync + Send + 'a + 'static,
     {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBCounterSet(
-            self.clone(),
-            key.as_ref().into(),
-            val,
-            tx,
-        ))
-        .await?;
-        Ok(rx.await??)
+        let this = self;
+        let res = {
+            if this.call_is_expired().await? {
+                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> =
+                    Box::new(AsyncEmptyIter {
+                        _m: std::marker::PhantomData,
+                    });
+                Ok::<_, StorageError>(iter)
+            } else {
+                let iter = this
+                    .call_prefix_iter(Some(IVec::from(prefix.as_ref())))
+                    .await?;
+                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> = Box::new(AsyncIter {
+                    map: this,
+                    prefix_len: this.map_item_prefix_name.len(),
+                    iter: Some(iter),
+                    _m: std::marker::PhantomData,
+                });
+                Ok::<_, StorageError>(iter)
+            }
+        }?;
+        Ok(res)
     }
 
-    /// Checks if a key exists
-    #[inline]
-    async fn contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+    /// Sets expiration time (TTL feature)
+    #[cfg(feature = "ttl")]
+    async fn expire_at(&self, at: TimestampMillis) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBContainsKey(
-            self.clone(),
-            key.as_ref().into(),
-            tx,
-        ))
-        .await?;
+        self.db
+            .cmd_send(Command::MapExpireAt(self.clone(), at, tx))
+            .await?;
         Ok(rx.await??)
     }
 
-    /// Gets number of key-value pairs (if enabled)
-    #[inline]
-    #[cfg(feature = "len")]
-    async fn len(&self) -> Result<usize> {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBLen(self.clone(), tx)).await?;
-        Ok(rx.await?)
-    }
-
-    /// Gets total database size
-    #[inline]
-    async fn db_size(&self) -> Result<usize> {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBSize(self.clone(), tx)).await?;
-        Ok(rx.await?)
+    /// Sets time-to-live (TTL feature)
+    #[cfg(feature = "ttl")]
+    async fn expire(&self, dur: TimestampMillis) -> Result<bool> {
+        let at = self.db.now() + dur;
+        self.expire_at(at).await
     }
 
-    /// Sets expiration time for a key (TTL feature)
-    #[inline]
+    /// Gets time-to-live (TTL feature)
     #[cfg(feature = "ttl")]
-    async fn expire_at<K>(&self, key: K, at: TimestampMillis) -> Result<bool>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-    {
+    async fn ttl(&self) -> Result<Option<TimestampMillis>> {
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBExpireAt(
-            self.clone(),
-            key.as_ref().into(),
-            at,
-            tx,
-        ))
-        .await?;
+        self.db.cmd_send(Command::MapTTL(self.clone(), tx)).await?;
         Ok(rx.await??)
     }
 
-    /// Sets time-to-live for a key (TTL feature)
-    #[inline]
-    #[cfg(feature = "ttl")]
-    async fn expire<K>(&self, key: K, dur: TimestampMillis) -> Result<bool>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-    {
-        let at = timestamp_millis() + dur;
-        self.expire_at(key, at).await
-    }
-
-    /// Gets time-to-live for a key (TTL feature)
-    #[inline]
+    /// Sets time-to-live of an item (TTL feature)
     #[cfg(feature = "ttl")]
-    async fn ttl<K>(&self, key: K) -> Result<Option<TimestampMillis>>
+    async fn expire_field<K>(&self, key: K, dur: TimestampMillis) -> Result<bool>
     where
         K: AsRef<[u8]> + Sync + Send,
     {
+        let at = self.db.now() + dur;
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBTtl(self.clone(), key.as_ref().into(), tx))
+        self.db
+            .cmd_send(Command::MapExpireField(
+                self.clone(),
+                key.as_ref().into(),
+                at,
+                tx,
+            ))
             .await?;
         Ok(rx.await??)
     }
 
-    /// Iterates over all maps
-    #[inline]
-    async fn map_iter<'a>(
-        &'a mut self,
-    ) -> Result<Box<dyn AsyncIterator<Item = Result<StorageMap>> + Send + 'a>> {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBMapPrefixIter(self.clone(), tx))
-            .await?;
-        let iter = rx.await?;
-        let iter = Box::new(AsyncMapIter::new(self, iter));
-        Ok(iter)
-    }
-
-    /// Iterates over all lists
-    #[inline]
-    async fn list_iter<'a>(
-        &'a mut self,
-    ) -> Result<Box<dyn AsyncIterator<Item = Result<StorageList>> + Send + 'a>> {
-        let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBListPrefixIter(self.clone(), tx))
-            .await?;
-        let iter = rx.await?;
-        let iter = Box::new(AsyncListIter {
-            db: self,
-            iter: Some(iter),
-        });
-        Ok(iter)
-    }
-
-    /// Scans keys matching pattern
-    async fn scan<'a, P>(
-        &'a mut self,
-        pattern: P,
-    ) -> Result<Box<dyn AsyncIterator<Item = Result<Key>> + Send + 'a>>
+    /// Gets time-to-live of an item (TTL feature)
+    #[cfg(feature = "ttl")]
+    async fn field_ttl<K>(&self, key: K) -> Result<Option<TimestampMillis>>
     where
-        P: AsRef<[u8]> + Send + Sync,
+        K: AsRef<[u8]> + Sync + Send,
     {
-        let pattern = pattern.as_ref();
         let (tx, rx) = oneshot::channel();
-        self.cmd_send(Command::DBScanIter(self.clone(), pattern.to_vec(), tx))
+        self.db
+            .cmd_send(Command::MapFieldTTL(self.clone(), key.as_ref().into(), tx))
             .await?;
-        let iter = rx.await?;
-        let pattern = Pattern::from(pattern);
-        let iter = Box::new(AsyncDbKeyIter {
-            db: self,
-            pattern,
-            iter: Some(iter),
-        });
-        Ok(iter)
+        Ok(rx.await??)
     }
 
-    /// Gets database information   
-    async fn info(&self) -> Result<Value> {
-        let active_count = self.active_count.load(Ordering::Relaxed);
-        Ok(serde_json::json!({
-            "active_count": active_count,
-            // "db_size": db_size,
-            // "map_size": map_size,
-            // "list_size": list_size,
-            // "size_on_disk": size_on_disk,
-            // "db_keys": db_keys,
-            // "map_names": map_names,
-            // "list_names": list_names,
-        }))
-    }
-
-    // async fn _info(&self) -> Result<Value> {
-    //     let active_count = self.active_count.load(Ordering::Relaxed);
-    //     // let this = self.clone();
-    //     Ok(spawn_blocking(move || {
-    //         // let size_on_disk = this.db.size_on_disk().unwrap_or_default();
-    //         // let db_size = this.db_size();
-    //         // let map_size = this.map_size();
-    //         // let list_size = this.list_size();
-
-    //         // let limit = 20;
-
-    //         // let mut db_keys = Vec::new();
-    //         // for (i, key) in this.db.iter().keys().enumerate() {
-    //         //     let key = key
-    //         //         .map(|k| String::from_utf8_lossy(k.as_ref()).to_string())
-    //         //         .unwrap_or_else(|e| e.to_string());
-    //         //     db_keys.push(key);
-    //         //     if i > limit {
-    //         //         break;
-    //         //     }
-    //         // }
-
-    //         // let mut map_names = Vec::new();
-    //         // for (i, key) in this.map_tree.iter().keys().enumerate() {
-    //         //     let key = key
-    //         //         .map(|k| String::from_utf8_lossy(k.as_ref()).to_string())
-    //         //         .unwrap_or_else(|e| e.to_string());
-    //         //     map_names.push(key);
-    //         //     if i > limit {
-    //         //         break;
-    //         //     }
-    //         // }
-
-    //         // let mut list_names = Vec::new();
-    //         // for (i, key) in this.list_tree.iter().keys().enumerate() {
-    //         //     let key = key
-    //         //         .map(|k| String::from_utf8_lossy(k.as_ref()).to_string())
-    //         //         .unwrap_or_else(|e| e.to_string());
-    //         //     list_names.push(key);
-    //         //     if i > limit {
-    //         //         break;
-    //         //     }
-    //         // }
-
-    //         serde_json::json!({
-    //             "storage_engine": "Sled",
-    //             "active_count": active_count,
-    //             // "db_size": db_size,
-    //             // "map_size": map_size,
-    //             // "list_size": list_size,
-    //             // "size_on_disk": size_on_disk,
-    //             // "db_keys": db_keys,
-    //             // "map_names": map_names,
-    //             // "list_names": list_names,
-    //         })
-    //     })
-    //     .await?)
-    // }
+    /// Removes time-to-live of an item (TTL feature)
+    #[cfg(feature = "ttl")]
+    async fn persist_field<K>(&self, key: K) -> Result<bool>
+    where
+        K: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::MapPersistField(
+                self.clone(),
+                key.as_ref().into(),
+                tx,
+            ))
+            .await?;
+        Ok(rx.await??)
+    }
 }
 
-/// Map structure for key-value storage within a namespace
-/*
-SET user:1001 "{name: Alice}"  # 用户命名空间
-SET order:1001 "{id: 123}"     # 订单命名空间
-
-KEYS "user:*"    # 查询所有用户数据
-DEL "order:1001" # 删除指定订单
-
-优点​​：减少 Key 数量，适合字段固定的场景
-
-
-完整示例（Map + Namespace）​
-
-# 写入数据（Hash 结构）
-HSET user:profile 1001 name "Alice" age 30
-HSET user:profile 1002 name "Bob" age 25
-
-# 读取数据
-HGETALL user:profile:1001
-# 结果：1) "name" 2) "Alice" 3) "age" 4) "30"
-
-# 删除整个命名空间
-DEL user:profile  # 慎用！会删除所有用户数据
-
-*/
+/// List structure for queue-like storage within a namespace
 #[derive(Clone)]
-pub struct SledStorageMap {
-    /// Map name
+pub struct SledStorageList {
+    /// List name
     name: Key,
-    /// Prefix for map keys
-    map_prefix_name: Key,
-    /// Prefix for map items
-    map_item_prefix_name: Key,
-    /// Key for map count
-    map_count_key_name: Key,
-    /// Flag indicating if map is empty
-    empty: Arc<AtomicBool>,
+    /// Prefix for list keys
+    prefix_name: Key,
     /// Database handle
     pub(crate) db: SledStorageDB,
 }
 
-impl SledStorageMap {
-    /// Creates a new map with optional expiration
+impl SledStorageList {
+    /// Creates a new list with optional expiration
     #[inline]
     async fn new_expire(
         name: Key,
         expire_ms: Option<TimestampMillis>,
+        sliding: bool,
         db: SledStorageDB,
     ) -> Result<Self> {
         let (tx, rx) = oneshot::channel();
-        db.cmd_send(Command::DBMapNew(db.clone(), name.into(), expire_ms, tx))
-            .await?;
+        db.cmd_send(Command::DBListNew(
+            db.clone(),
+            name.into(),
+            expire_ms,
+            sliding,
+            tx,
+        ))
+        .await?;
         rx.await?
     }
 
-    /// Internal method to create map with expiration
+    /// Internal method to create list with expiration
     #[inline]
     fn _new_expire(
         name: Key,
         _expire_ms: Option<TimestampMillis>,
+        _sliding: bool,
         db: SledStorageDB,
     ) -> Result<Self> {
-        let m = Self::_new(name, db);
-        m.empty.store(m._is_empty()?, Ordering::SeqCst);
+        db._check_type(&name, KeyType::List)?;
+        let l = Self::_new(name, db);
         #[cfg(feature = "ttl")]
-        if let Some(expire_ms) = _expire_ms.as_ref() {
-            m._expire_at(timestamp_millis() + *expire_ms)?;
+        if let Some(expire_ms) = _expire_ms {
+            //a new list needs its count key, the expiration is only set on existing names
+            let count_key = l.make_list_count_key();
+            if !l.tree().contains_key(&count_key)? {
+                l.tree()
+                    .insert(count_key, bincode::serialize(&(0u64, 0u64))?)?;
+            }
+            l.db._expire(
+                l.name.as_slice(),
+                ExpireMeta::new(l.db.now(), expire_ms, _sliding),
+                KeyType::List,
+            )?;
         }
-        Ok(m)
+        Ok(l)
     }
 
-    /// Internal method to create map
+    /// Internal method to create list
     #[inline]
     fn _new(name: Key, db: SledStorageDB) -> Self {
-        let map_prefix_name = SledStorageDB::make_map_prefix_name(name.as_slice());
-        let map_item_prefix_name = SledStorageDB::make_map_item_prefix_name(name.as_slice());
-        let map_count_key_name = SledStorageDB::make_map_count_key_name(name.as_slice());
-        SledStorageMap {
+        let prefix_name = SledStorageDB::make_list_prefix(name.as_slice());
+        SledStorageList {
             name,
-            map_prefix_name,
-            map_item_prefix_name,
-            map_count_key_name,
-            empty: Arc::new(AtomicBool::new(true)),
+            prefix_name,
             db,
         }
     }
 
+    /// Gets list name
+    #[inline]
+    pub(crate) fn name(&self) -> &[u8] {
+        self.name.as_slice()
+    }
+
     /// Gets the underlying tree
     #[inline]
-    fn tree(&self) -> &sled::Tree {
-        &self.db.map_tree
+    pub(crate) fn tree(&self) -> &sled::Tree {
+        &self.db.list_tree
     }
 
-    /// Creates a full item key
+    /// Creates list count key
     #[inline]
-    fn make_map_item_key<K: AsRef<[u8]>>(&self, key: K) -> Key {
-        [self.map_item_prefix_name.as_ref(), key.as_ref()].concat()
+    #[allow(clippy::let_and_return)]
+    fn make_list_count_key(&self) -> Vec<u8> {
+        let list_count_key = [self.prefix_name.as_ref(), LIST_KEY_COUNT_SUFFIX].concat();
+        list_count_key
     }
 
-    /// Gets map length (if enabled)
-    #[cfg(feature = "map_len")]
+    /// Creates list content prefix
     #[inline]
-    fn _len_get(&self) -> Result<isize> {
-        self._counter_get(self.map_count_key_name.as_slice())
+    fn make_list_content_prefix(prefix_name: &[u8], idx: Option<&[u8]>) -> Vec<u8> {
+        if let Some(idx) = idx {
+            [prefix_name, LIST_KEY_CONTENT_SUFFIX, idx].concat()
+        } else {
+            [prefix_name, LIST_KEY_CONTENT_SUFFIX].concat()
+        }
     }
 
-    /// Transactionally increments a counter
+    /// Creates list content key
     #[inline]
-    fn _tx_counter_inc<K: AsRef<[u8]>>(
-        tx: &TransactionalTree,
-        key: K,
-    ) -> ConflictableTransactionResult<()> {
-        let val = match tx.get(key.as_ref())? {
-            Some(data) => {
-                if let Ok(array) = data.as_ref().try_into() {
-                    let number = isize::from_be_bytes(array);
-                    number + 1
-                } else {
-                    1
-                }
-            }
-            None => 1,
-        };
-        tx.insert(key.as_ref(), val.to_be_bytes().as_slice())?;
-        Ok(())
+    fn make_list_content_key(&self, idx: u64) -> Vec<u8> {
+        Self::make_list_content_prefix(
+            self.prefix_name.as_ref(),
+            Some(idx.to_be_bytes().as_slice()),
+        )
     }
 
-    /// Transactionally decrements a counter
+    /// Creates batch of list content keys
     #[inline]
-    fn _tx_counter_dec<K: AsRef<[u8]>>(
-        tx: &TransactionalTree,
-        key: K,
-    ) -> ConflictableTransactionResult<()> {
-        let val = match tx.get(key.as_ref())? {
-            Some(data) => {
-                if let Ok(array) = data.as_ref().try_into() {
-                    let number = isize::from_be_bytes(array);
-                    number - 1
-                } else {
-                    -1
-                }
-            }
-            None => -1,
-        };
-        if val > 0 {
-            tx.insert(key.as_ref(), val.to_be_bytes().as_slice())?;
-        } else {
-            tx.remove(key.as_ref())?;
-        }
-        Ok(())
+    fn make_list_content_keys(&self, start: u64, end: u64) -> Vec<Vec<u8>> {
+        (start..end)
+            .map(|idx| self.make_list_content_key(idx))
+            .collect()
     }
 
-    /// Transactionally gets counter value
+    /// Transactionally gets list count
     #[inline]
-    fn _tx_counter_get<K: AsRef<[u8]>, E>(
+    fn tx_list_count_get<K>(
         tx: &TransactionalTree,
-        key: K,
-    ) -> ConflictableTransactionResult<isize, E> {
-        if let Some(v) = tx.get(key)? {
-            let c = match v.as_ref().try_into() {
-                Ok(c) => c,
-                Err(e) => {
-                    return Err(ConflictableTransactionError::Storage(sled::Error::Io(
-                        io::Error::new(ErrorKind::InvalidData, e),
-                    )));
-                }
-            };
-            Ok(isize::from_be_bytes(c))
+        list_count_key: K,
+    ) -> ConflictableTransactionResult<(u64, u64), StorageError>
+    where
+        K: AsRef<[u8]>,
+    {
+        if let Some(v) = tx.get(list_count_key.as_ref())? {
+            let (start, end) = bincode::deserialize::<(u64, u64)>(v.as_ref())
+                .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+            Ok((start, end))
         } else {
-            Ok(0)
+            Ok((0, 0))
         }
     }
 
-    /// Transactionally sets counter value
+    /// Transactionally sets list count
     #[inline]
-    fn _tx_counter_set<K: AsRef<[u8]>, E>(
+    fn tx_list_count_set<K>(
         tx: &TransactionalTree,
-        key: K,
-        val: isize,
-    ) -> ConflictableTransactionResult<(), E> {
-        tx.insert(key.as_ref(), val.to_be_bytes().as_slice())?;
+        key_count: K,
+        start: u64,
+        end: u64,
+    ) -> ConflictableTransactionResult<(), StorageError>
+    where
+        K: AsRef<[u8]>,
+    {
+        let count_bytes = bincode::serialize(&(start, end))
+            .map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+        tx.insert(key_count.as_ref(), count_bytes.as_slice())?;
         Ok(())
     }
 
-    /// Transactionally removes counter
+    /// Transactionally sets list content
     #[inline]
-    fn _tx_counter_remove<K: AsRef<[u8]>, E>(
+    fn tx_list_content_set<K, V, E>(
         tx: &TransactionalTree,
-        key: K,
-    ) -> ConflictableTransactionResult<(), E> {
-        tx.remove(key.as_ref())?;
+        key_content: K,
+        data: V,
+    ) -> ConflictableTransactionResult<(), E>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        tx.insert(key_content.as_ref(), data.as_ref())?;
         Ok(())
     }
 
-    /// Gets counter value
-    #[inline]
-    fn _counter_get<K: AsRef<[u8]>>(&self, key: K) -> Result<isize> {
-        if let Some(v) = self.tree().get(key)? {
-            Ok(isize::from_be_bytes(v.as_ref().try_into()?))
-        } else {
-            Ok(0)
-        }
-    }
-
-    /// Initializes counter if not present
+    /// Transactionally sets batch list content
     #[inline]
-    fn _counter_init(&self) -> Result<()> {
-        let tree = self.tree();
-        if !tree.contains_key(self.map_count_key_name.as_slice())? {
-            tree.insert(
-                self.map_count_key_name.as_slice(),
-                0isize.to_be_bytes().as_slice(),
-            )?;
+    fn tx_list_content_batch_set<K, V, E>(
+        tx: &TransactionalTree,
+        key_contents: Vec<(K, V)>,
+    ) -> ConflictableTransactionResult<(), E>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut batch = Batch::default();
+        for (k, v) in key_contents {
+            batch.insert(k.as_ref(), v.as_ref());
         }
+        tx.apply_batch(&batch)?;
         Ok(())
     }
 
-    /// Clears the map
+    /// Clears the list
     #[inline]
     fn _clear(&self) -> Result<()> {
-        let batch = self._make_clear_batch();
-        self.tree()
-            .transaction(|tx| self._tx_clear(tx, &batch))
-            .map_err(|e| anyhow!(format!("{:?}", e)))?;
+        let (batch, removed) = self._make_clear_batch();
+        self.tree().transaction(|tx| {
+            tx.apply_batch(&batch)?;
+            Ok::<_, ConflictableTransactionError<()>>(())
+        })?;
+        self.db.quota.release(removed);
         Ok(())
     }
 
-    /// Transactionally clears the map
+    /// Transactionally clears the list
     #[inline]
     fn _tx_clear(
-        &self,
-        map_tree_tx: &TransactionalTree,
+        list_tree_tx: &TransactionalTree,
         batch: &Batch,
     ) -> ConflictableTransactionResult<()> {
-        map_tree_tx.apply_batch(batch)?;
-        self.empty.store(true, Ordering::SeqCst);
+        list_tree_tx.apply_batch(batch)?;
         Ok(())
     }
 
-    /// Creates batch for clearing map
+    /// Creates batch for clearing list, along with the usage of the removed values
     #[inline]
-    fn _make_clear_batch(&self) -> Batch {
+    fn _make_clear_batch(&self) -> (Batch, QuotaUsage) {
         let mut batch = Batch::default();
-        //clear key-value
-        for item in self.tree().scan_prefix(self.map_prefix_name.as_slice()) {
+        let mut removed = QuotaUsage::default();
+        let list_count_key = self.make_list_count_key();
+        batch.remove(list_count_key);
+        let list_content_prefix = Self::make_list_content_prefix(self.prefix_name.as_slice(), None);
+        for item in self.tree().scan_prefix(list_content_prefix) {
             match item {
-                Ok((key, _)) => {
-                    batch.remove(key);
+                Ok((k, v)) => {
+                    removed.keys += 1;
+                    removed.bytes += v.len() as u64;
+                    batch.remove(k);
                 }
                 Err(e) => {
                     log::warn!("{:?}", e);
                 }
             }
         }
-        batch
+        (batch, removed)
     }
 
-    /// Inserts a key-value pair into the map
+    /// Pushes value to list
     #[inline]
-    fn _insert(&self, key: IVec, val: IVec) -> Result<()> {
-        let item_key = self.make_map_item_key(key.as_ref());
-        let this = self;
-        #[cfg(feature = "map_len")]
-        {
-            let count_key = this.map_count_key_name.as_slice();
-            this.tree()
-                .transaction(move |tx| {
-                    if tx.insert(item_key.as_slice(), val.as_ref())?.is_none() {
-                        Self::_tx_counter_inc(tx, count_key)?;
-                    }
-                    Ok(())
-                })
-                .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
-        #[cfg(not(feature = "map_len"))]
-        {
-            if self.empty.load(Ordering::SeqCst) {
-                self._counter_init()?;
-                self.empty.store(false, Ordering::SeqCst)
-            }
-            this.tree().insert(item_key.as_slice(), val.as_ref())?;
-        }
+    fn _push(&self, data: IVec) -> Result<()> {
+        self.db._check_type(&self.name, KeyType::List)?;
+        let reserved = self.db._quota_reserve([(None, data.len())])?;
+        let this = self;
+        this.tree().transaction(move |tx| {
+            let list_count_key = this.make_list_count_key();
+            let (start, mut end) = Self::tx_list_count_get(tx, list_count_key.as_slice())?;
+            end += 1;
+            Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
+
+            let list_content_key = this.make_list_content_key(end);
+            Self::tx_list_content_set(tx, list_content_key.as_slice(), data.as_ref())?;
+            Ok(())
+        })?;
 
         #[cfg(feature = "ttl")]
         {
-            //判断是否是ttl 值。
-            // true 为已过期
-            let res = this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_map_contains_key(this.tree(), k)
-            })?;
-
-            // this.db._remove_expire_key(this.name.as_slice())?;
-            if res {
-                (&self.db.key_expire_tree, &self.db.expire_key_tree)
-                    .transaction(|(key_expire_tx, expire_key_tx)| {
+            if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
+                // this.db._remove_expire_key(this.name.as_slice())?;
+                (&self.db.key_expire_tree, &self.db.expire_key_tree).transaction(
+                    |(key_expire_tx, expire_key_tx)| {
                         SledStorageDB::_tx_remove_expire_key(
                             key_expire_tx,
                             expire_key_tx,
                             this.name.as_slice(),
+                            KeyType::List,
                         )?;
                         Ok::<(), ConflictableTransactionError<()>>(())
-                    })
-                    .map_err(|e| anyhow!(format!("{:?}", e)))?;
+                    },
+                )?;
             }
         }
 
+        reserved.commit();
         Ok(())
     }
 
-    /// Gets a value from the map
+    /// Pushes multiple values to list
     #[inline]
-    fn _get(&self, key: IVec) -> Result<Option<IVec>> {
+    fn _pushs(&self, vals: Vec<IVec>) -> Result<()> {
+        if vals.is_empty() {
+            return Ok(());
+        }
+        self.db._check_type(&self.name, KeyType::List)?;
+        let reserved = self
+            .db
+            ._quota_reserve(vals.iter().map(|val| (None, val.len())))?;
+        let tree = self.tree();
         let this = self;
-        let item_key = self.make_map_item_key(key.as_ref());
-        let res = if !this.db._is_expired(this.name.as_slice(), |k| {
-            SledStorageDB::_map_contains_key(this.tree(), k)
-        })? {
-            this.tree().get(item_key).map_err(|e| anyhow!(e))?
-        } else {
-            None
-        };
-        Ok(res)
-    }
 
-    /// Removes a key from the map
-    #[inline]
-    fn _remove(&self, key: IVec) -> Result<()> {
-        let tree = self.tree();
-        let key = self.make_map_item_key(key.as_ref());
+        tree.transaction(move |tx| {
+            let list_count_key = this.make_list_count_key();
+            let (start, mut end) = Self::tx_list_count_get(tx, list_count_key.as_slice())?;
 
-        #[cfg(feature = "map_len")]
-        {
-            let count_key = self.map_count_key_name.to_vec();
-            tree.transaction(move |tx| {
-                if tx.remove(key.as_slice())?.is_some() {
-                    Self::_tx_counter_dec(tx, count_key.as_slice())?;
-                }
-                Ok(())
-            })
-            .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        }
+            let mut list_content_keys =
+                this.make_list_content_keys(end + 1, end + vals.len() as u64 + 1);
+            //assert_eq!(vals.len(), list_content_keys.len());
+            end += vals.len() as u64;
+            Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
 
-        #[cfg(not(feature = "map_len"))]
+            let list_contents = vals
+                .iter()
+                .map(|val| (list_content_keys.remove(0), val))
+                .collect::<Vec<_>>();
+            Self::tx_list_content_batch_set(tx, list_contents)?;
+            Ok(())
+        })?;
+
+        #[cfg(feature = "ttl")]
         {
-            tree.remove(key.as_slice())?;
+            if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
+                // this.db._remove_expire_key(this.name.as_slice())?;
+                (&self.db.key_expire_tree, &self.db.expire_key_tree).transaction(
+                    |(key_expire_tx, expire_key_tx)| {
+                        SledStorageDB::_tx_remove_expire_key(
+                            key_expire_tx,
+                            expire_key_tx,
+                            this.name.as_slice(),
+                            KeyType::List,
+                        )?;
+                        Ok::<(), ConflictableTransactionError<()>>(())
+                    },
+                )?;
+            }
         }
-
+        reserved.commit();
         Ok(())
     }
 
-    /// Checks if key exists in map
+    /// Pushes value with limit
     #[inline]
-    fn _contains_key(&self, key: IVec) -> Result<bool> {
-        let key = self.make_map_item_key(key.as_ref());
-        Ok(self.tree().contains_key(key)?)
+    fn _push_limit(
+        &self,
+        data: IVec,
+        limit: usize,
+        pop_front_if_limited: bool,
+    ) -> Result<Option<IVec>> {
+        self.db._check_type(&self.name, KeyType::List)?;
+        let reserved = self.db._quota_reserve([(None, data.len())])?;
+        let tree = self.tree();
+        let this = self;
+        let removed = {
+            let res = tree.transaction(move |tx| {
+                let list_count_key = this.make_list_count_key();
+                let (mut start, mut end) = Self::tx_list_count_get(tx, list_count_key.as_slice())?;
+                let count = end - start;
+
+                if count < limit as u64 {
+                    end += 1;
+                    Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
+                    let list_content_key = this.make_list_content_key(end);
+                    Self::tx_list_content_set(tx, list_content_key.as_slice(), data.as_ref())?;
+                    Ok(None)
+                } else if pop_front_if_limited {
+                    let mut removed = None;
+                    let removed_content_key = this.make_list_content_key(start + 1);
+                    if let Some(v) = tx.remove(removed_content_key)? {
+                        removed = Some(v);
+                        start += 1;
+                    }
+                    end += 1;
+                    Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
+                    let list_content_key = this.make_list_content_key(end);
+                    Self::tx_list_content_set(tx, list_content_key.as_slice(), data.as_ref())?;
+                    Ok(removed)
+                } else {
+                    Err(ConflictableTransactionError::Abort(StorageError::ListFull))
+                }
+            });
+
+            #[cfg(feature = "ttl")]
+            {
+                if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
+                    // this.db._remove_expire_key(this.name.as_slice())?;
+                    (&self.db.key_expire_tree, &self.db.expire_key_tree).transaction(
+                        |(key_expire_tx, expire_key_tx)| {
+                            SledStorageDB::_tx_remove_expire_key(
+                                key_expire_tx,
+                                expire_key_tx,
+                                this.name.as_slice(),
+                                KeyType::List,
+                            )?;
+                            Ok::<(), ConflictableTransactionError<()>>(())
+                        },
+                    )?;
+                }
+            }
+
+            Ok::<_, TransactionError<()>>(res)
+        }??;
+        reserved.commit();
+        self.db._quota_release(removed.as_ref());
+        Ok(removed)
     }
 
-    /// Gets map length (if enabled)
-    #[cfg(feature = "map_len")]
+    /// Pops value from list
     #[inline]
-    fn _len(&self) -> Result<usize> {
+    fn _pop(&self) -> Result<Option<IVec>> {
         let this = self;
-        let len = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_map_contains_key(this.tree(), k)
-            })? {
-                Ok(0)
-            } else {
-                this._len_get()
+        if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
+            return Ok(None);
+        }
+        let pop = |tx: &TransactionalTree| {
+            let list_count_key = this.make_list_count_key();
+            let (start, end) = Self::tx_list_count_get(tx, list_count_key.as_slice())?;
+
+            let mut removed = None;
+            if (end - start) > 0 {
+                let removed_content_key = this.make_list_content_key(start + 1);
+                if let Some(v) = tx.remove(removed_content_key)? {
+                    removed = Some(v);
+                    Self::tx_list_count_set(tx, list_count_key.as_slice(), start + 1, end)?;
+                }
             }
-        }?;
-        Ok(len as usize)
+            Ok::<_, ConflictableTransactionError<StorageError>>(removed)
+        };
+        #[cfg(feature = "ttl")]
+        let removed = match this.db._sliding_ttl(this.name.as_slice(), KeyType::List)? {
+            Some(ttl) => {
+                this.db
+                    ._read_sliding(this.tree(), this.name.as_slice(), KeyType::List, ttl, pop)?
+            }
+            None => this.tree().transaction(pop)?,
+        };
+        #[cfg(not(feature = "ttl"))]
+        let removed = this.tree().transaction(pop)?;
+        this.db._quota_release(removed.as_ref());
+        Ok(removed)
     }
 
-    /// Checks if map is empty
+    /// Gets all values in list
     #[inline]
-    fn _is_empty(&self) -> Result<bool> {
+    fn _all(&self) -> Result<Vec<IVec>> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::List)?;
         let this = self;
         let res = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_map_contains_key(this.tree(), k)
-            })? {
-                true
+            if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
+                Ok(vec![])
             } else {
-                self.tree()
-                    .scan_prefix(self.map_item_prefix_name.as_slice())
-                    .next()
-                    .is_none()
+                let key_content_prefix =
+                    Self::make_list_content_prefix(this.prefix_name.as_slice(), None);
+                this.tree()
+                    .scan_prefix(key_content_prefix)
+                    .values()
+                    .map(|item| item.map_err(StorageError::from))
+                    .collect::<Result<Vec<_>>>()
             }
-        };
+        }?;
         Ok(res)
     }
 
-    /// Removes and returns a value
+    /// Gets value by index
     #[inline]
-    fn _remove_and_fetch(&self, key: IVec) -> Result<Option<IVec>> {
-        let key = self.make_map_item_key(key.as_ref());
+    fn _get_index(&self, idx: usize) -> Result<Option<IVec>> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::List)?;
         let this = self;
-        let removed = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_map_contains_key(this.tree(), k)
-            })? {
+        let res = {
+            if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
                 Ok(None)
             } else {
-                #[cfg(feature = "map_len")]
-                {
-                    let count_key = this.map_count_key_name.to_vec();
-                    this.tree().transaction(move |tx| {
-                        if let Some(removed) = tx.remove(key.as_slice())? {
-                            Self::_tx_counter_dec(tx, count_key.as_slice())?;
-                            Ok(Some(removed))
+                this.tree().transaction(move |tx| {
+                    let list_count_key = this.make_list_count_key();
+                    let (start, end) = Self::tx_list_count_get(tx, list_count_key.as_slice())?;
+                    if (idx as u64) < (end - start) {
+                        let list_content_key = this.make_list_content_key(start + idx as u64 + 1);
+                        if let Some(v) = tx.get(list_content_key)? {
+                            Ok(Some(v))
                         } else {
                             Ok(None)
                         }
-                    })
-                }
-                #[cfg(not(feature = "map_len"))]
-                {
-                    let removed = this.tree().remove(key.as_slice())?;
-                    Ok::<_, TransactionError<()>>(removed)
-                }
-            }
-        }
-        .map_err(|e| anyhow!(format!("{:?}", e)))?;
-
-        Ok(removed)
-    }
-
-    /// Removes keys with prefix
-    #[inline]
-    fn _remove_with_prefix(&self, prefix: IVec) -> Result<()> {
-        let tree = self.tree();
-        let prefix = [self.map_item_prefix_name.as_slice(), prefix.as_ref()]
-            .concat()
-            .to_vec();
-
-        #[cfg(feature = "map_len")]
-        let map_count_key_name = self.map_count_key_name.to_vec();
-        {
-            let mut removeds = Batch::default();
-            #[cfg(feature = "map_len")]
-            let mut c = 0;
-            for item in tree.scan_prefix(prefix) {
-                match item {
-                    Ok((k, _v)) => {
-                        removeds.remove(k.as_ref());
-                        #[cfg(feature = "map_len")]
-                        {
-                            c += 1;
-                        }
-                    }
-                    Err(e) => {
-                        log::warn!("{:?}", e);
-                    }
-                }
-            }
-
-            #[cfg(feature = "map_len")]
-            {
-                tree.transaction(move |tx| {
-                    let len = Self::_tx_counter_get(tx, map_count_key_name.as_slice())? - c;
-                    if len > 0 {
-                        Self::_tx_counter_set(tx, map_count_key_name.as_slice(), len)?;
                     } else {
-                        Self::_tx_counter_remove(tx, map_count_key_name.as_slice())?;
-                    };
-                    tx.apply_batch(&removeds)?;
-                    Ok::<(), ConflictableTransactionError<sled::Error>>(())
+                        Ok(None)
+                    }
                 })
             }
-            #[cfg(not(feature = "map_len"))]
-            {
-                tree.apply_batch(removeds)?;
-                Ok::<(), ConflictableTransactionError<sled::Error>>(())
-            }
         }?;
-        Ok(())
+        Ok(res)
     }
 
-    /// Batch inserts key-value pairs
+    /// Gets list length
     #[inline]
-    fn _batch_insert(&self, key_vals: Vec<(IVec, IVec)>) -> Result<()> {
-        for (k, v) in key_vals {
-            self._insert(k, v)?;
-        }
-        Ok(())
+    fn _len(&self) -> Result<usize> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::List)?;
+        let this = self;
+        let res = {
+            if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
+                Ok::<usize, StorageError>(0)
+            } else {
+                let list_count_key = this.make_list_count_key();
+                if let Some(v) = this.tree().get(list_count_key.as_slice())? {
+                    let (start, end) = bincode::deserialize::<(u64, u64)>(v.as_ref())?;
+                    Ok((end - start) as usize)
+                } else {
+                    Ok(0)
+                }
+            }
+        }?;
+        Ok(res)
     }
 
-    /// Batch removes keys
+    /// Checks if list is empty
     #[inline]
-    fn _batch_remove(&self, keys: Vec<IVec>) -> Result<()> {
-        for k in keys {
-            self._remove(k)?;
-        }
-        Ok(())
+    fn _is_empty(&self) -> Result<bool> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::List)?;
+        let this = self;
+        let res = {
+            if this.db._is_expired(this.name.as_slice(), KeyType::List)? {
+                Ok::<bool, StorageError>(true)
+            } else {
+                let list_content_prefix =
+                    Self::make_list_content_prefix(this.prefix_name.as_slice(), None);
+                Ok(this
+                    .tree()
+                    .scan_prefix(list_content_prefix)
+                    .keys()
+                    .next()
+                    .is_none())
+            }
+        }?;
+        Ok(res)
     }
 
     /// Sets expiration time (TTL feature)
     #[cfg(feature = "ttl")]
     #[inline]
     fn _expire_at(&self, at: TimestampMillis) -> Result<bool> {
-        self.db._expire_at(self.name.as_slice(), at, KeyType::Map)
+        self.db._expire_at(self.name.as_slice(), at, KeyType::List)
     }
 
     /// Gets time-to-live (TTL feature)
     #[cfg(feature = "ttl")]
     #[inline]
     fn _ttl(&self) -> Result<Option<TimestampMillis>> {
-        let res = self
+        Ok(self
             .db
-            ._ttl(self.name(), |k| {
-                SledStorageDB::_map_contains_key(self.tree(), k)
-            })?
-            .and_then(|(at, _)| if at > 0 { Some(at) } else { None });
-        Ok(res)
+            ._ttl(self.name(), KeyType::List)?
+            .and_then(|(at, _)| if at > 0 { Some(at) } else { None }))
     }
 
-    /// Checks if map is expired
+    /// Checks if list is expired
     #[inline]
     fn _is_expired(&self) -> Result<bool> {
-        self.db._is_expired(self.name.as_slice(), |k| {
-            SledStorageDB::_map_contains_key(self.tree(), k)
-        })
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::List)?;
+        self.db._is_expired(self.name.as_slice(), KeyType::List)
     }
 
-    /// Checks if map is expired (async)
+    /// Checks if list is expired (async)
     #[inline]
     async fn call_is_expired(&self) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::MapIsExpired(self.clone(), tx))
+            .cmd_send(Command::ListIsExpired(self.clone(), tx))
             .await?;
         rx.await?
     }
 
     /// Creates prefix iterator
     #[inline]
-    fn _prefix_iter(&self, prefix: Option<IVec>) -> sled::Iter {
-        if let Some(prefix) = prefix {
-            self.tree()
-                .scan_prefix([self.map_item_prefix_name.as_slice(), prefix.as_ref()].concat())
-        } else {
-            self.tree()
-                .scan_prefix(self.map_item_prefix_name.as_slice())
-        }
+    fn _prefix_iter(&self) -> sled::Iter {
+        let list_content_prefix = Self::make_list_content_prefix(self.prefix_name.as_slice(), None);
+        self.tree().scan_prefix(list_content_prefix)
     }
 
     /// Creates prefix iterator (async)
     #[inline]
-    async fn call_prefix_iter(&self, prefix: Option<IVec>) -> Result<sled::Iter> {
+    async fn call_prefix_iter(&self) -> Result<sled::Iter> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::MapPrefixIter(self.clone(), prefix, tx))
+            .cmd_send(Command::ListPrefixIter(self.clone(), tx))
             .await?;
         Ok(rx.await?)
     }
 }
 
 #[async_trait]
-impl Map for SledStorageMap {
-    /// Gets map name
+impl List for SledStorageList {
+    /// Gets list name
     #[inline]
     fn name(&self) -> &[u8] {
         self.name.as_slice()
     }
 
-    /// Inserts a key-value pair
+    /// Pushes value to list
     #[inline]
-    async fn insert<K, V>(&self, key: K, val: &V) -> Result<()>
+    async fn push<V>(&self, val: &V) -> Result<()>
     where
-        K: AsRef<[u8]> + Sync + Send,
-        V: Serialize + Sync + Send + ?Sized,
+        V: serde::ser::Serialize + Sync + Send,
     {
         let val = bincode::serialize(val)?;
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::MapInsert(
-                self.clone(),
-                key.as_ref().into(),
-                val.into(),
-                tx,
-            ))
+            .cmd_send(Command::ListPush(self.clone(), val.into(), tx))
             .await?;
         rx.await??;
         Ok(())
     }
 
-    /// Gets a value by key
+    /// Pushes multiple values to list
     #[inline]
-    async fn get<K, V>(&self, key: K) -> Result<Option<V>>
+    async fn pushs<V>(&self, vals: Vec<V>) -> Result<()>
     where
-        K: AsRef<[u8]> + Sync + Send,
-        V: DeserializeOwned + Sync + Send,
+        V: Serialize + Sync + Send,
     {
-        let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::MapGet(self.clone(), key.as_ref().into(), tx))
-            .await?;
-
-        match rx.await?? {
-            Some(v) => Ok(Some(bincode::deserialize::<V>(v.as_ref())?)),
-            None => Ok(None),
+        if vals.is_empty() {
+            return Ok(());
         }
-    }
 
-    /// Removes a key
-    #[inline]
-    async fn remove<K>(&self, key: K) -> Result<()>
-    where
-        K: AsRef<[u8]> + Sync + Send,
-    {
+        let vals = vals
+            .into_iter()
+            .map(|v| {
+                bincode::serialize(&v)
+                    .map(|v| v.into())
+                    .map_err(StorageError::from)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::MapRemove(self.clone(), key.as_ref().into(), tx))
+            .cmd_send(Command::ListPushs(self.clone(), vals, tx))
             .await?;
         rx.await??;
         Ok(())
     }
 
-    /// Checks if key exists
+    /// Pushes value with limit
     #[inline]
-    async fn contains_key<K: AsRef<[u8]> + Sync + Send>(&self, key: K) -> Result<bool> {
+    async fn push_limit<V>(
+        &self,
+        val: &V,
+        limit: usize,
+        pop_front_if_limited: bool,
+    ) -> Result<Option<V>>
+    where
+        V: serde::ser::Serialize + Sync + Send,
+        V: DeserializeOwned,
+    {
+        let data = bincode::serialize(val)?;
+
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::MapContainsKey(
+            .cmd_send(Command::ListPushLimit(
                 self.clone(),
-                key.as_ref().into(),
+                data.into(),
+                limit,
+                pop_front_if_limited,
                 tx,
             ))
             .await?;
-        Ok(rx.await??)
-    }
-
-    /// Gets map length (if enabled)
-    #[cfg(feature = "map_len")]
-    #[inline]
-    async fn len(&self) -> Result<usize> {
-        let (tx, rx) = oneshot::channel();
-        self.db.cmd_send(Command::MapLen(self.clone(), tx)).await?;
-        Ok(rx.await??)
-    }
-
-    /// Checks if map is empty
-    #[inline]
-    async fn is_empty(&self) -> Result<bool> {
-        let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::MapIsEmpty(self.clone(), tx))
-            .await?;
-        Ok(rx.await??)
-    }
 
-    /// Clears the map
-    #[inline]
-    async fn clear(&self) -> Result<()> {
-        let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::MapClear(self.clone(), tx))
-            .await?;
-        rx.await??;
-        Ok(())
+        let removed = if let Some(removed) = rx.await?? {
+            Some(bincode::deserialize::<V>(removed.as_ref()).map_err(StorageError::from)?)
+        } else {
+            None
+        };
+        Ok(removed)
     }
 
-    /// Removes and returns a value
+    /// Pops value from list
     #[inline]
-    async fn remove_and_fetch<K, V>(&self, key: K) -> Result<Option<V>>
+    async fn pop<V>(&self) -> Result<Option<V>>
     where
-        K: AsRef<[u8]> + Sync + Send,
         V: DeserializeOwned + Sync + Send,
     {
         let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::MapRemoveAndFetch(
-                self.clone(),
-                key.as_ref().into(),
-                tx,
-            ))
-            .await?;
+        self.db.cmd_send(Command::ListPop(self.clone(), tx)).await?;
 
-        match rx.await?? {
-            Some(v) => Ok(Some(bincode::deserialize::<V>(v.as_ref())?)),
-            None => Ok(None),
-        }
+        let removed = if let Some(removed) = rx.await?? {
+            Some(bincode::deserialize::<V>(removed.as_ref()).map_err(StorageError::from)?)
+        } else {
+            None
+        };
+        Ok(removed)
     }
 
-    /// Removes keys with prefix
+    /// Gets all values in list
     #[inline]
-    async fn remove_with_prefix<K>(&self, prefix: K) -> Result<()>
+    async fn all<V>(&self) -> Result<Vec<V>>
     where
-        K: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
     {
         let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::MapRemoveWithPrefix(
-                self.clone(),
-                prefix.as_ref().into(),
-                tx,
-            ))
-            .await?;
-        rx.await??;
-        Ok(())
+        self.db.cmd_send(Command::ListAll(self.clone(), tx)).await?;
+
+        rx.await??
+            .iter()
+            .map(|v| bincode::deserialize::<V>(v.as_ref()).map_err(StorageError::from))
+            .collect::<Result<Vec<_>>>()
     }
 
-    /// Batch inserts key-value pairs
+    /// Gets value by index
     #[inline]
-    async fn batch_insert<V>(&self, key_vals: Vec<(Key, V)>) -> Result<()>
+    async fn get_index<V>(&self, idx: usize) -> Result<Option<V>>
     where
-        V: serde::ser::Serialize + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
     {
-        let key_vals = key_vals
-            .into_iter()
-            .map(|(k, v)| {
-                bincode::serialize(&v)
-                    .map(|v| (k.into(), v.into()))
-                    .map_err(|e| anyhow!(e))
-            })
-            .collect::<Result<Vec<(IVec, IVec)>>>()?;
-
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::MapBatchInsert(self.clone(), key_vals, tx))
+            .cmd_send(Command::ListGetIndex(self.clone(), idx, tx))
             .await?;
-        rx.await??;
-        Ok(())
+
+        Ok(if let Some(res) = rx.await?? {
+            Some(bincode::deserialize::<V>(res.as_ref()).map_err(StorageError::from)?)
+        } else {
+            None
+        })
     }
 
-    /// Batch removes keys
+    /// Gets list length
     #[inline]
-    async fn batch_remove(&self, keys: Vec<Key>) -> Result<()> {
-        let keys = keys.into_iter().map(|k| k.into()).collect::<Vec<IVec>>();
-
+    async fn len(&self) -> Result<usize> {
         let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::MapBatchRemove(self.clone(), keys, tx))
-            .await?;
-        rx.await??;
-        Ok(())
+        self.db.cmd_send(Command::ListLen(self.clone(), tx)).await?;
+        Ok(rx.await??)
     }
 
-    /// Iterates over map items
-    #[inline]
-    async fn iter<'a, V>(
-        &'a mut self,
-    ) -> Result<Box<dyn AsyncIterator<Item = IterItem<V>> + Send + 'a>>
-    where
-        V: DeserializeOwned + Sync + Send + 'a + 'static,
-    {
-        let this = self;
-        let res = {
-            if this.call_is_expired().await? {
-                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> =
-                    Box::new(AsyncEmptyIter {
-                        _m: std::marker::PhantomData,
-                    });
-                Ok::<_, anyhow::Error>(iter)
-            } else {
-                let tem_prefix_name = this.map_item_prefix_name.len();
-                let iter = this.call_prefix_iter(None).await?;
-                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> = Box::new(AsyncIter {
-                    db: &this.db,
-                    prefix_len: tem_prefix_name,
-                    iter: Some(iter),
-                    _m: std::marker::PhantomData,
-                });
-                Ok::<_, anyhow::Error>(iter)
-            }
-        }?;
-        Ok(res)
+    /// Checks if list is empty
+    #[inline]
+    async fn is_empty(&self) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::ListIsEmpty(self.clone(), tx))
+            .await?;
+        Ok(rx.await??)
     }
 
-    /// Iterates over map keys
+    /// Clears the list
     #[inline]
-    async fn key_iter<'a>(
-        &'a mut self,
-    ) -> Result<Box<dyn AsyncIterator<Item = Result<Key>> + Send + 'a>> {
-        let this = self;
-        let res = {
-            if this.call_is_expired().await? {
-                let iter: Box<dyn AsyncIterator<Item = Result<Key>> + Send> =
-                    Box::new(AsyncEmptyIter {
-                        _m: std::marker::PhantomData,
-                    });
-                Ok::<_, anyhow::Error>(iter)
-            } else {
-                let iter = this.call_prefix_iter(None).await?;
-                let iter: Box<dyn AsyncIterator<Item = Result<Key>> + Send> =
-                    Box::new(AsyncKeyIter {
-                        db: &this.db,
-                        prefix_len: this.map_item_prefix_name.len(),
-                        iter: Some(iter),
-                    });
-                Ok::<_, anyhow::Error>(iter)
-            }
-        }?;
-        Ok(res)
+    async fn clear(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::ListClear(self.clone(), tx))
+            .await?;
+        Ok(rx.await??)
     }
 
-    /// Iterates over items with prefix
+    /// Iterates over list values
     #[inline]
-    async fn prefix_iter<'a, P, V>(
+    async fn iter<'a, V>(
         &'a mut self,
-        prefix: P,
-    ) -> Result<Box<dyn AsyncIterator<Item = IterItem<V>> + Send + 'a>>
+    ) -> Result<Box<dyn AsyncIterator<Item = Result<V>> + Send + 'a>>
     where
-        P: AsRef<[u8]> + Send + Sync,
         V: DeserializeOwned + Sync + Send + 'a + 'static,
     {
         let this = self;
         let res = {
             if this.call_is_expired().await? {
-                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> =
+                let iter: Box<dyn AsyncIterator<Item = Result<V>> + Send> =
                     Box::new(AsyncEmptyIter {
                         _m: std::marker::PhantomData,
                     });
-                Ok::<_, anyhow::Error>(iter)
+                Ok::<_, StorageError>(iter)
             } else {
-                let iter = this
-                    .call_prefix_iter(Some(IVec::from(prefix.as_ref())))
-                    .await?;
-                let iter: Box<dyn AsyncIterator<Item = IterItem<V>> + Send> = Box::new(AsyncIter {
-                    db: &this.db,
-                    prefix_len: this.map_item_prefix_name.len(),
-                    iter: Some(iter),
-                    _m: std::marker::PhantomData,
-                });
-                Ok::<_, anyhow::Error>(iter)
+                let iter = this.call_prefix_iter().await?;
+                let iter: Box<dyn AsyncIterator<Item = Result<V>> + Send> =
+                    Box::new(AsyncListValIter {
+                        db: &this.db,
+                        iter: Some(iter),
+                        _m: std::marker::PhantomData,
+                    });
+                Ok::<_, StorageError>(iter)
             }
         }?;
         Ok(res)
@@ -2817,7 +7795,7 @@ impl Map for SledStorageMap {
     async fn expire_at(&self, at: TimestampMillis) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::MapExpireAt(self.clone(), at, tx))
+            .cmd_send(Command::ListExpireAt(self.clone(), at, tx))
             .await?;
         Ok(rx.await??)
     }
@@ -2825,7 +7803,7 @@ impl Map for SledStorageMap {
     /// Sets time-to-live (TTL feature)
     #[cfg(feature = "ttl")]
     async fn expire(&self, dur: TimestampMillis) -> Result<bool> {
-        let at = timestamp_millis() + dur;
+        let at = self.db.now() + dur;
         self.expire_at(at).await
     }
 
@@ -2833,24 +7811,155 @@ impl Map for SledStorageMap {
     #[cfg(feature = "ttl")]
     async fn ttl(&self) -> Result<Option<TimestampMillis>> {
         let (tx, rx) = oneshot::channel();
-        self.db.cmd_send(Command::MapTTL(self.clone(), tx)).await?;
+        self.db.cmd_send(Command::ListTTL(self.clone(), tx)).await?;
         Ok(rx.await??)
     }
 }
 
-/// List structure for queue-like storage within a namespace
+/// Stream entry ID, formatted as `<ms>-<seq>`
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct StreamId {
+    /// Milliseconds part, the time the entry was added for generated IDs
+    pub ms: u64,
+    /// Sequence number within the millisecond
+    pub seq: u64,
+}
+
+impl StreamId {
+    /// Smallest possible ID
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    /// Greatest possible ID
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// Creates an ID
+    #[inline]
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// Gets the smallest ID greater than this one
+    #[inline]
+    pub fn next(&self) -> Self {
+        match self.seq.checked_add(1) {
+            Some(seq) => Self::new(self.ms, seq),
+            None => Self::new(self.ms.saturating_add(1), 0),
+        }
+    }
+
+    /// Encodes the ID so that byte order equals ID order
+    #[inline]
+    fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.ms.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.seq.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes an ID encoded with `to_bytes`
+    #[inline]
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != 16 {
+            return Err(StorageError::invalid("invalid stream id"));
+        }
+        let (ms, seq) = bytes.split_at(8);
+        Ok(Self::new(
+            u64::from_be_bytes(ms.try_into()?),
+            u64::from_be_bytes(seq.try_into()?),
+        ))
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = StorageError;
+
+    /// Parses `<ms>-<seq>` or `<ms>`, which is read as `<ms>-0`
+    fn from_str(s: &str) -> Result<Self> {
+        let (ms, seq) = s.split_once('-').unwrap_or((s, "0"));
+        let parse = |n: &str| {
+            n.parse()
+                .map_err(|_| StorageError::invalid("invalid stream id"))
+        };
+        Ok(Self::new(parse(ms)?, parse(seq)?))
+    }
+}
+
+/// Stream entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamEntry<V> {
+    /// Entry ID
+    pub id: StreamId,
+    /// Entry value
+    pub value: V,
+}
+
+/// Entry delivered to a consumer of a group but not yet acknowledged
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamPendingEntry {
+    /// Entry ID
+    pub id: StreamId,
+    /// Consumer the entry was delivered to
+    pub consumer: Key,
+    /// Time of the last delivery
+    pub delivered_at: TimestampMillis,
+    /// Number of deliveries
+    pub delivery_count: u64,
+}
+
+/// Stream entries as stored, ID and encoded value
+type StreamItems = Vec<(StreamId, IVec)>;
+
+/// Stream metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StreamMeta {
+    /// ID of the last added entry
+    last_id: StreamId,
+    /// Number of entries
+    len: u64,
+    /// Length the cleanup task trims the stream to
+    maxlen: Option<u64>,
+}
+
+/// Consumer group state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StreamGroup {
+    /// ID of the last entry delivered to the group
+    last_delivered: StreamId,
+    /// Delivered but not acknowledged entries
+    pending: BTreeMap<StreamId, StreamPending>,
+}
+
+/// Pending entry of a consumer group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StreamPending {
+    consumer: Key,
+    delivered_at: TimestampMillis,
+    delivery_count: u64,
+}
+
+/// Sled-based stream implementation
 #[derive(Clone)]
-pub struct SledStorageList {
-    /// List name
+pub struct SledStorageStream {
+    /// Stream name
     name: Key,
-    /// Prefix for list keys
+    /// Prefix for stream keys
     prefix_name: Key,
     /// Database handle
     pub(crate) db: SledStorageDB,
 }
 
-impl SledStorageList {
-    /// Creates a new list with optional expiration
+impl SledStorageStream {
+    /// Creates a new stream with optional expiration
     #[inline]
     async fn new_expire(
         name: Key,
@@ -2858,510 +7967,526 @@ impl SledStorageList {
         db: SledStorageDB,
     ) -> Result<Self> {
         let (tx, rx) = oneshot::channel();
-        db.cmd_send(Command::DBListNew(db.clone(), name.into(), expire_ms, tx))
+        db.cmd_send(Command::DBStreamNew(db.clone(), name.into(), expire_ms, tx))
             .await?;
         rx.await?
     }
 
-    /// Internal method to create list with expiration
+    /// Internal method to create stream with expiration
     #[inline]
     fn _new_expire(
         name: Key,
         _expire_ms: Option<TimestampMillis>,
         db: SledStorageDB,
     ) -> Result<Self> {
-        let l = Self::_new(name, db);
+        db._check_type(&name, KeyType::Stream)?;
+        let s = Self::_new(name, db);
         #[cfg(feature = "ttl")]
         if let Some(expire_ms) = _expire_ms {
-            l._expire_at(timestamp_millis() + expire_ms)?;
+            s._expire_at(s.db.now() + expire_ms)?;
         }
-        Ok(l)
+        Ok(s)
     }
 
-    /// Internal method to create list
+    /// Internal method to create stream
     #[inline]
     fn _new(name: Key, db: SledStorageDB) -> Self {
-        let prefix_name = SledStorageDB::make_list_prefix(name.as_slice());
-        SledStorageList {
+        let prefix_name = SledStorageDB::make_stream_prefix(name.as_slice());
+        SledStorageStream {
             name,
             prefix_name,
             db,
         }
     }
 
-    /// Gets list name
+    /// Gets the underlying tree
     #[inline]
-    pub(crate) fn name(&self) -> &[u8] {
-        self.name.as_slice()
+    fn tree(&self) -> &sled::Tree {
+        &self.db.stream_tree
     }
 
-    /// Gets the underlying tree
+    /// Creates metadata key
     #[inline]
-    pub(crate) fn tree(&self) -> &sled::Tree {
-        &self.db.list_tree
+    fn make_meta_key(&self) -> Key {
+        [self.prefix_name.as_slice(), STREAM_KEY_META_SUFFIX].concat()
     }
 
-    /// Creates list count key
+    /// Creates entry key prefix
     #[inline]
-    fn make_list_count_key(&self) -> Vec<u8> {
-        let list_count_key = [self.prefix_name.as_ref(), LIST_KEY_COUNT_SUFFIX].concat();
-        list_count_key
+    fn make_entry_prefix(&self) -> Key {
+        [self.prefix_name.as_slice(), STREAM_KEY_ENTRY_SUFFIX].concat()
     }
 
-    /// Creates list content prefix
+    /// Creates entry key
     #[inline]
-    fn make_list_content_prefix(prefix_name: &[u8], idx: Option<&[u8]>) -> Vec<u8> {
-        if let Some(idx) = idx {
-            [prefix_name, LIST_KEY_CONTENT_SUFFIX, idx].concat()
-        } else {
-            [prefix_name, LIST_KEY_CONTENT_SUFFIX].concat()
-        }
+    fn make_entry_key(&self, id: StreamId) -> Key {
+        [
+            self.prefix_name.as_slice(),
+            STREAM_KEY_ENTRY_SUFFIX,
+            id.to_bytes().as_slice(),
+        ]
+        .concat()
     }
 
-    /// Creates list content key
+    /// Creates consumer group key prefix
     #[inline]
-    fn make_list_content_key(&self, idx: usize) -> Vec<u8> {
-        Self::make_list_content_prefix(
-            self.prefix_name.as_ref(),
-            Some(idx.to_be_bytes().as_slice()),
-        )
+    fn make_group_prefix(&self) -> Key {
+        [self.prefix_name.as_slice(), STREAM_KEY_GROUP_SUFFIX].concat()
     }
 
-    /// Creates batch of list content keys
+    /// Creates consumer group key
     #[inline]
-    fn make_list_content_keys(&self, start: usize, end: usize) -> Vec<Vec<u8>> {
-        (start..end)
-            .map(|idx| self.make_list_content_key(idx))
-            .collect()
+    fn make_group_key(&self, group: &[u8]) -> Key {
+        [self.prefix_name.as_slice(), STREAM_KEY_GROUP_SUFFIX, group].concat()
     }
 
-    /// Transactionally gets list count
+    /// Creates the key marking the stream for trimming by the cleanup task
     #[inline]
-    fn tx_list_count_get<K, E>(
-        tx: &TransactionalTree,
-        list_count_key: K,
-    ) -> ConflictableTransactionResult<(usize, usize), E>
-    where
-        K: AsRef<[u8]>,
-    {
-        if let Some(v) = tx.get(list_count_key.as_ref())? {
-            let (start, end) = bincode::deserialize::<(usize, usize)>(v.as_ref()).map_err(|e| {
-                ConflictableTransactionError::Storage(sled::Error::Io(io::Error::new(
-                    ErrorKind::InvalidData,
-                    e,
-                )))
-            })?;
-            Ok((start, end))
-        } else {
-            Ok((0, 0))
+    fn make_trim_key(&self) -> Key {
+        [STREAM_TRIM_PREFIX, self.name.as_slice()].concat()
+    }
+
+    /// Gets stream metadata
+    #[inline]
+    fn _meta(&self) -> Result<Option<StreamMeta>> {
+        match self.tree().get(self.make_meta_key())? {
+            Some(v) => Ok(Some(bincode::deserialize(v.as_ref())?)),
+            None => Ok(None),
         }
     }
 
-    /// Transactionally sets list count
+    /// Gets consumer group state
     #[inline]
-    fn tx_list_count_set<K, E>(
-        tx: &TransactionalTree,
-        key_count: K,
-        start: usize,
-        end: usize,
-    ) -> ConflictableTransactionResult<(), E>
-    where
-        K: AsRef<[u8]>,
-    {
-        let count_bytes = bincode::serialize(&(start, end)).map_err(|e| {
-            ConflictableTransactionError::Storage(sled::Error::Io(io::Error::new(
-                ErrorKind::InvalidData,
-                e,
-            )))
-        })?;
-        tx.insert(key_count.as_ref(), count_bytes.as_slice())?;
-        Ok(())
+    fn _group(&self, group: &[u8]) -> Result<StreamGroup> {
+        match self.tree().get(self.make_group_key(group))? {
+            Some(v) => Ok(bincode::deserialize(v.as_ref())?),
+            None => Err(StorageError::NotFound(format!(
+                "consumer group {}",
+                String::from_utf8_lossy(group)
+            ))),
+        }
     }
 
-    /// Transactionally sets list content
+    /// Saves consumer group state
     #[inline]
-    fn tx_list_content_set<K, V, E>(
-        tx: &TransactionalTree,
-        key_content: K,
-        data: V,
-    ) -> ConflictableTransactionResult<(), E>
-    where
-        K: AsRef<[u8]>,
-        V: AsRef<[u8]>,
-    {
-        tx.insert(key_content.as_ref(), data.as_ref())?;
+    fn _group_set(&self, group: &[u8], g: &StreamGroup) -> Result<()> {
+        self.tree()
+            .insert(self.make_group_key(group), bincode::serialize(g)?)?;
         Ok(())
     }
 
-    /// Transactionally sets batch list content
+    /// Gets all consumer groups for a dump
+    #[inline]
+    fn _groups(&self) -> Result<Vec<DumpGroup>> {
+        let prefix = self.make_group_prefix();
+        let mut groups = Vec::new();
+        for item in self.tree().scan_prefix(prefix.as_slice()) {
+            let (k, v) = item?;
+            let g: StreamGroup = bincode::deserialize(v.as_ref())?;
+            groups.push(DumpGroup {
+                name: k[prefix.len()..].to_vec(),
+                last_delivered: g.last_delivered,
+                pending: g
+                    .pending
+                    .into_iter()
+                    .map(|(id, p)| StreamPendingEntry {
+                        id,
+                        consumer: p.consumer,
+                        delivered_at: p.delivered_at,
+                        delivery_count: p.delivery_count,
+                    })
+                    .collect(),
+            });
+        }
+        Ok(groups)
+    }
+
+    /// Writes the entries, metadata and consumer groups of a dumped stream
     #[inline]
-    fn tx_list_content_batch_set<K, V, E>(
-        tx: &TransactionalTree,
-        key_contents: Vec<(K, V)>,
-    ) -> ConflictableTransactionResult<(), E>
-    where
-        K: AsRef<[u8]>,
-        V: AsRef<[u8]>,
-    {
+    fn _load(
+        &self,
+        last_id: StreamId,
+        maxlen: Option<u64>,
+        entries: Vec<(StreamId, Vec<u8>)>,
+        groups: Vec<DumpGroup>,
+    ) -> Result<()> {
+        let meta = StreamMeta {
+            last_id,
+            len: entries.len() as u64,
+            maxlen,
+        };
         let mut batch = Batch::default();
-        for (k, v) in key_contents {
-            batch.insert(k.as_ref(), v.as_ref());
+        for (id, data) in entries {
+            batch.insert(self.make_entry_key(id), data);
         }
-        tx.apply_batch(&batch)?;
+        batch.insert(self.make_meta_key(), bincode::serialize(&meta)?);
+        for g in groups {
+            let pending = g
+                .pending
+                .into_iter()
+                .map(|p| {
+                    let pending = StreamPending {
+                        consumer: p.consumer,
+                        delivered_at: p.delivered_at,
+                        delivery_count: p.delivery_count,
+                    };
+                    (p.id, pending)
+                })
+                .collect();
+            let group = StreamGroup {
+                last_delivered: g.last_delivered,
+                pending,
+            };
+            batch.insert(self.make_group_key(&g.name), bincode::serialize(&group)?);
+        }
+        self.tree().apply_batch(batch)?;
         Ok(())
     }
 
-    /// Clears the list
+    /// Decodes the entry ID from an entry key
     #[inline]
-    fn _clear(&self) -> Result<()> {
-        let mut batch = Batch::default();
-        let list_count_key = self.make_list_count_key();
-        batch.remove(list_count_key);
-        let list_content_prefix = Self::make_list_content_prefix(self.prefix_name.as_slice(), None);
-        for item in self.tree().scan_prefix(list_content_prefix).keys() {
-            match item {
-                Ok(k) => {
-                    batch.remove(k);
-                }
-                Err(e) => {
-                    log::warn!("{:?}", e);
+    fn entry_key_to_id(&self, key: &[u8]) -> Result<StreamId> {
+        StreamId::from_bytes(&key[self.prefix_name.len() + STREAM_KEY_ENTRY_SUFFIX.len()..])
+    }
+
+    /// Removes the stream if its expiration time has passed
+    #[inline]
+    fn _remove_if_expired(&self) -> Result<()> {
+        #[cfg(feature = "ttl")]
+        if let Some((at, Some(_))) = self.db._ttl_at(self.name.as_slice(), KeyType::Stream)?
+            && at <= self.db.now()
+        {
+            self.db._stream_remove(self.name.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Appends an entry
+    #[inline]
+    fn _add(&self, id: Option<StreamId>, data: IVec, maxlen: Option<usize>) -> Result<StreamId> {
+        self._remove_if_expired()?;
+        self.db._check_type(&self.name, KeyType::Stream)?;
+        let mut meta = self._meta()?.unwrap_or_default();
+        let id = match id {
+            Some(id) if id <= meta.last_id => {
+                return Err(StorageError::invalid(format!(
+                    "stream id {} is equal or smaller than the last id {}",
+                    id, meta.last_id
+                )));
+            }
+            Some(id) => id,
+            None => {
+                let now = self.db.now().max(0) as u64;
+                if now > meta.last_id.ms {
+                    StreamId::new(now, 0)
+                } else {
+                    meta.last_id.next()
                 }
             }
+        };
+        meta.last_id = id;
+        meta.len += 1;
+        meta.maxlen = maxlen.map(|m| m as u64);
+
+        let mut batch = Batch::default();
+        batch.insert(self.make_entry_key(id), data);
+        batch.insert(self.make_meta_key(), bincode::serialize(&meta)?);
+        if matches!(meta.maxlen, Some(maxlen) if meta.len > maxlen) {
+            batch.insert(self.make_trim_key(), &[]);
+        }
+        self.tree().apply_batch(batch)?;
+        self.db.stream_notify.notify_waiters();
+        Ok(id)
+    }
+
+    /// Gets entries with IDs in `start..=end`
+    #[inline]
+    fn _range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Result<StreamItems> {
+        if start > end || self._is_expired()? {
+            return Ok(Vec::new());
+        }
+        let iter = self
+            .tree()
+            .range(self.make_entry_key(start)..=self.make_entry_key(end));
+        let count = count.unwrap_or(usize::MAX);
+        let items = if rev {
+            iter.rev().take(count).collect::<sled::Result<Vec<_>>>()?
+        } else {
+            iter.take(count).collect::<sled::Result<Vec<_>>>()?
+        };
+        items
+            .into_iter()
+            .map(|(k, v)| Ok((self.entry_key_to_id(k.as_ref())?, v)))
+            .collect()
+    }
+
+    /// Gets number of entries
+    #[inline]
+    fn _len(&self) -> Result<usize> {
+        if self._is_expired()? {
+            return Ok(0);
+        }
+        Ok(self._meta()?.map(|m| m.len as usize).unwrap_or_default())
+    }
+
+    /// Gets the ID of the last added entry
+    #[inline]
+    fn _last_id(&self) -> Result<Option<StreamId>> {
+        if self._is_expired()? {
+            return Ok(None);
+        }
+        Ok(self._meta()?.map(|m| m.last_id))
+    }
+
+    /// Removes entries by ID
+    #[inline]
+    fn _delete(&self, mut ids: Vec<StreamId>) -> Result<usize> {
+        ids.sort();
+        ids.dedup();
+        let mut meta = match self._meta()? {
+            Some(meta) => meta,
+            None => return Ok(0),
+        };
+        let mut batch = Batch::default();
+        let mut removed = 0;
+        for id in ids {
+            let key = self.make_entry_key(id);
+            if self.tree().contains_key(&key)? {
+                batch.remove(key);
+                removed += 1;
+            }
         }
-        self.tree()
-            .transaction(|tx| {
-                tx.apply_batch(&batch)?;
-                Ok::<_, ConflictableTransactionError<()>>(())
-            })
-            .map_err(|e| anyhow!(format!("{:?}", e)))?;
-        Ok(())
+        meta.len = meta.len.saturating_sub(removed);
+        batch.insert(self.make_meta_key(), bincode::serialize(&meta)?);
+        self.tree().apply_batch(batch)?;
+        Ok(removed as usize)
     }
 
-    /// Transactionally clears the list
+    /// Keyspace event of deleted or trimmed entries, none if nothing was removed
     #[inline]
-    fn _tx_clear(
-        list_tree_tx: &TransactionalTree,
-        batch: &Batch,
-    ) -> ConflictableTransactionResult<()> {
-        list_tree_tx.apply_batch(batch)?;
-        Ok(())
+    fn _removed_events(&self, notify: bool, res: &Result<usize>) -> Vec<KeyspaceEvent> {
+        self.db._events(notify, res, |removed| {
+            (*removed > 0)
+                .then(|| {
+                    KeyspaceEvent::new(KeyspaceEventKind::Removed, KeyType::Stream, &self.name)
+                })
+                .into_iter()
+                .collect()
+        })
     }
 
-    /// Creates batch for clearing list
+    /// Removes the oldest entries down to `maxlen`
     #[inline]
-    fn _make_clear_batch(&self) -> Batch {
+    fn _trim(&self, maxlen: usize) -> Result<usize> {
         let mut batch = Batch::default();
-        let list_count_key = self.make_list_count_key();
-        batch.remove(list_count_key);
-        let list_content_prefix = Self::make_list_content_prefix(self.prefix_name.as_slice(), None);
-        for item in self.tree().scan_prefix(list_content_prefix).keys() {
-            match item {
-                Ok(k) => {
-                    batch.remove(k);
-                }
-                Err(e) => {
-                    log::warn!("{:?}", e);
-                }
+        batch.remove(self.make_trim_key());
+        let mut meta = match self._meta()? {
+            Some(meta) => meta,
+            None => {
+                self.tree().apply_batch(batch)?;
+                return Ok(0);
             }
+        };
+        let excess = (meta.len as usize).saturating_sub(maxlen);
+        let mut removed = 0;
+        for key in self
+            .tree()
+            .scan_prefix(self.make_entry_prefix())
+            .keys()
+            .take(excess)
+        {
+            batch.remove(key?);
+            removed += 1;
         }
-        batch
+        meta.len -= removed as u64;
+        batch.insert(self.make_meta_key(), bincode::serialize(&meta)?);
+        self.tree().apply_batch(batch)?;
+        Ok(removed)
     }
 
-    /// Pushes value to list
+    /// Trims the stream to the length given with the most recent add
     #[inline]
-    fn _push(&self, data: IVec) -> Result<()> {
-        let this = self;
-        this.tree().transaction(move |tx| {
-            let list_count_key = this.make_list_count_key();
-            let (start, mut end) = Self::tx_list_count_get::<
-                _,
-                ConflictableTransactionError<sled::Error>,
-            >(tx, list_count_key.as_slice())?;
-            end += 1;
-            Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
-
-            let list_content_key = this.make_list_content_key(end);
-            Self::tx_list_content_set(tx, list_content_key.as_slice(), data.as_ref())?;
-            Ok(())
-        })?;
-
-        #[cfg(feature = "ttl")]
-        {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_list_contains_key(this.tree(), k)
-            })? {
-                // this.db._remove_expire_key(this.name.as_slice())?;
-                (&self.db.key_expire_tree, &self.db.expire_key_tree)
-                    .transaction(|(key_expire_tx, expire_key_tx)| {
-                        SledStorageDB::_tx_remove_expire_key(
-                            key_expire_tx,
-                            expire_key_tx,
-                            this.name.as_slice(),
-                        )?;
-                        Ok::<(), ConflictableTransactionError<()>>(())
-                    })
-                    .map_err(|e| anyhow!(format!("{:?}", e)))?;
+    fn _trim_to_maxlen(&self) -> Result<usize> {
+        match self._meta()?.and_then(|m| m.maxlen) {
+            Some(maxlen) => self._trim(maxlen as usize),
+            None => {
+                self.tree().remove(self.make_trim_key())?;
+                Ok(0)
             }
         }
+    }
 
+    /// Clears the stream
+    #[inline]
+    fn _clear(&self) -> Result<()> {
+        self.tree().apply_batch(self._make_clear_batch())?;
         Ok(())
     }
 
-    /// Pushes multiple values to list
+    /// Transactionally clears the stream
     #[inline]
-    fn _pushs(&self, vals: Vec<IVec>) -> Result<()> {
-        if vals.is_empty() {
-            return Ok(());
-        }
-        let tree = self.tree();
-        let this = self;
-
-        tree.transaction(move |tx| {
-            let list_count_key = this.make_list_count_key();
-            let (start, mut end) = Self::tx_list_count_get::<
-                _,
-                ConflictableTransactionError<sled::Error>,
-            >(tx, list_count_key.as_slice())?;
-
-            let mut list_content_keys = this.make_list_content_keys(end + 1, end + vals.len() + 1);
-            //assert_eq!(vals.len(), list_content_keys.len());
-            end += vals.len();
-            Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
-
-            let list_contents = vals
-                .iter()
-                .map(|val| (list_content_keys.remove(0), val))
-                .collect::<Vec<_>>();
-            Self::tx_list_content_batch_set(tx, list_contents)?;
-            Ok(())
-        })?;
-
-        #[cfg(feature = "ttl")]
-        {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_list_contains_key(this.tree(), k)
-            })? {
-                // this.db._remove_expire_key(this.name.as_slice())?;
-                (&self.db.key_expire_tree, &self.db.expire_key_tree)
-                    .transaction(|(key_expire_tx, expire_key_tx)| {
-                        SledStorageDB::_tx_remove_expire_key(
-                            key_expire_tx,
-                            expire_key_tx,
-                            this.name.as_slice(),
-                        )?;
-                        Ok::<(), ConflictableTransactionError<()>>(())
-                    })
-                    .map_err(|e| anyhow!(format!("{:?}", e)))?;
-            }
-        }
+    fn _tx_clear(
+        stream_tree_tx: &TransactionalTree,
+        batch: &Batch,
+    ) -> ConflictableTransactionResult<()> {
+        stream_tree_tx.apply_batch(batch)?;
         Ok(())
     }
 
-    /// Pushes value with limit
+    /// Creates batch for clearing the stream
     #[inline]
-    fn _push_limit(
-        &self,
-        data: IVec,
-        limit: usize,
-        pop_front_if_limited: bool,
-    ) -> Result<Option<IVec>> {
-        let tree = self.tree();
-        let this = self;
-        let removed = {
-            let res = tree.transaction(move |tx| {
-                let list_count_key = this.make_list_count_key();
-                let (mut start, mut end) = Self::tx_list_count_get::<
-                    _,
-                    ConflictableTransactionError<sled::Error>,
-                >(tx, list_count_key.as_slice())?;
-                let count = end - start;
-
-                if count < limit {
-                    end += 1;
-                    Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
-                    let list_content_key = this.make_list_content_key(end);
-                    Self::tx_list_content_set(tx, list_content_key.as_slice(), data.as_ref())?;
-                    Ok(None)
-                } else if pop_front_if_limited {
-                    let mut removed = None;
-                    let removed_content_key = this.make_list_content_key(start + 1);
-                    if let Some(v) = tx.remove(removed_content_key)? {
-                        removed = Some(v);
-                        start += 1;
+    fn _make_clear_batch(&self) -> Batch {
+        let mut batch = Batch::default();
+        batch.remove(self.make_meta_key());
+        batch.remove(self.make_trim_key());
+        for prefix in [self.make_entry_prefix(), self.make_group_prefix()] {
+            for item in self.tree().scan_prefix(prefix).keys() {
+                match item {
+                    Ok(k) => {
+                        batch.remove(k);
+                    }
+                    Err(e) => {
+                        log::warn!("{:?}", e);
                     }
-                    end += 1;
-                    Self::tx_list_count_set(tx, list_count_key.as_slice(), start, end)?;
-                    let list_content_key = this.make_list_content_key(end);
-                    Self::tx_list_content_set(tx, list_content_key.as_slice(), data.as_ref())?;
-                    Ok(removed)
-                } else {
-                    Err(ConflictableTransactionError::Storage(sled::Error::Io(
-                        io::Error::new(ErrorKind::InvalidData, "Is full"),
-                    )))
-                }
-            });
-
-            #[cfg(feature = "ttl")]
-            {
-                if this.db._is_expired(this.name.as_slice(), |k| {
-                    SledStorageDB::_list_contains_key(this.tree(), k)
-                })? {
-                    // this.db._remove_expire_key(this.name.as_slice())?;
-                    (&self.db.key_expire_tree, &self.db.expire_key_tree)
-                        .transaction(|(key_expire_tx, expire_key_tx)| {
-                            SledStorageDB::_tx_remove_expire_key(
-                                key_expire_tx,
-                                expire_key_tx,
-                                this.name.as_slice(),
-                            )?;
-                            Ok::<(), ConflictableTransactionError<()>>(())
-                        })
-                        .map_err(|e| anyhow!(format!("{:?}", e)))?;
                 }
             }
-
-            Ok::<_, TransactionError<()>>(res)
         }
-        .map_err(|e| anyhow!(format!("{:?}", e)))??;
-
-        Ok(removed)
+        batch
     }
 
-    /// Pops value from list
+    /// Creates a consumer group
     #[inline]
-    fn _pop(&self) -> Result<Option<IVec>> {
-        let this = self;
-        let removed = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_list_contains_key(this.tree(), k)
-            })? {
-                Ok(None)
-            } else {
-                let removed = this.tree().transaction(move |tx| {
-                    let list_count_key = this.make_list_count_key();
-                    let (start, end) = Self::tx_list_count_get(tx, list_count_key.as_slice())?;
-
-                    let mut removed = None;
-                    if (end - start) > 0 {
-                        let removed_content_key = this.make_list_content_key(start + 1);
-                        if let Some(v) = tx.remove(removed_content_key)? {
-                            removed = Some(v);
-                            Self::tx_list_count_set(tx, list_count_key.as_slice(), start + 1, end)?;
-                        }
-                    }
-                    Ok::<_, ConflictableTransactionError<sled::Error>>(removed)
-                });
-                removed
-            }
-        }?;
+    fn _group_create(&self, group: &[u8], start: Option<StreamId>) -> Result<bool> {
+        if self.tree().contains_key(self.make_group_key(group))? {
+            return Ok(false);
+        }
+        let last_delivered = match start {
+            Some(start) => start,
+            None => self._last_id()?.unwrap_or_default(),
+        };
+        let g = StreamGroup {
+            last_delivered,
+            pending: BTreeMap::new(),
+        };
+        self._group_set(group, &g)?;
+        Ok(true)
+    }
 
-        Ok(removed)
+    /// Removes a consumer group
+    #[inline]
+    fn _group_destroy(&self, group: &[u8]) -> Result<bool> {
+        Ok(self.tree().remove(self.make_group_key(group))?.is_some())
     }
 
-    /// Gets all values in list
+    /// Delivers new entries to a consumer of a group
     #[inline]
-    fn _all(&self) -> Result<Vec<IVec>> {
-        let this = self;
-        let res = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_list_contains_key(this.tree(), k)
-            })? {
-                Ok(vec![])
-            } else {
-                let key_content_prefix =
-                    Self::make_list_content_prefix(this.prefix_name.as_slice(), None);
-                this.tree()
-                    .scan_prefix(key_content_prefix)
-                    .values()
-                    .map(|item| item.map_err(anyhow::Error::new))
-                    .collect::<Result<Vec<_>>>()
+    fn _read_group(
+        &self,
+        group: &[u8],
+        consumer: &[u8],
+        count: Option<usize>,
+    ) -> Result<StreamItems> {
+        let mut g = self._group(group)?;
+        let items = self._range(g.last_delivered.next(), StreamId::MAX, count, false)?;
+        if let Some((last, _)) = items.last() {
+            let now = self.db.now();
+            g.last_delivered = *last;
+            for (id, _) in items.iter() {
+                g.pending.insert(
+                    *id,
+                    StreamPending {
+                        consumer: consumer.to_vec(),
+                        delivered_at: now,
+                        delivery_count: 1,
+                    },
+                );
             }
-        }?;
-        Ok(res)
+            self._group_set(group, &g)?;
+        }
+        Ok(items)
     }
 
-    /// Gets value by index
+    /// Acknowledges pending entries of a group
     #[inline]
-    fn _get_index(&self, idx: usize) -> Result<Option<IVec>> {
-        let this = self;
-        let res = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_list_contains_key(this.tree(), k)
-            })? {
-                Ok(None)
-            } else {
-                this.tree().transaction(move |tx| {
-                    let list_count_key = this.make_list_count_key();
-                    let (start, end) = Self::tx_list_count_get::<
-                        _,
-                        ConflictableTransactionError<sled::Error>,
-                    >(tx, list_count_key.as_slice())?;
-                    if idx < (end - start) {
-                        let list_content_key = this.make_list_content_key(start + idx + 1);
-                        if let Some(v) = tx.get(list_content_key)? {
-                            Ok(Some(v))
-                        } else {
-                            Ok(None)
-                        }
-                    } else {
-                        Ok(None)
-                    }
-                })
-            }
-        }?;
-        Ok(res)
+    fn _ack(&self, group: &[u8], ids: Vec<StreamId>) -> Result<usize> {
+        let mut g = self._group(group)?;
+        let acked = ids
+            .iter()
+            .filter(|id| g.pending.remove(id).is_some())
+            .count();
+        if acked > 0 {
+            self._group_set(group, &g)?;
+        }
+        Ok(acked)
     }
 
-    /// Gets list length
+    /// Gets the pending entries of a group
     #[inline]
-    fn _len(&self) -> Result<usize> {
-        let this = self;
-        let res = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_list_contains_key(this.tree(), k)
-            })? {
-                Ok::<usize, anyhow::Error>(0)
-            } else {
-                let list_count_key = this.make_list_count_key();
-                if let Some(v) = this.tree().get(list_count_key.as_slice())? {
-                    let (start, end) = bincode::deserialize::<(usize, usize)>(v.as_ref())?;
-                    Ok(end - start)
-                } else {
-                    Ok(0)
-                }
-            }
-        }?;
-        Ok(res)
+    fn _pending(&self, group: &[u8]) -> Result<Vec<StreamPendingEntry>> {
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::Stream)?;
+        Ok(self
+            ._group(group)?
+            .pending
+            .into_iter()
+            .map(|(id, p)| StreamPendingEntry {
+                id,
+                consumer: p.consumer,
+                delivered_at: p.delivered_at,
+                delivery_count: p.delivery_count,
+            })
+            .collect())
     }
 
-    /// Checks if list is empty
+    /// Transfers idle pending entries to another consumer
+    ///
+    /// Pending entries whose stream entry was removed are dropped.
     #[inline]
-    fn _is_empty(&self) -> Result<bool> {
-        let this = self;
-        let res = {
-            if this.db._is_expired(this.name.as_slice(), |k| {
-                SledStorageDB::_list_contains_key(this.tree(), k)
-            })? {
-                Ok::<bool, anyhow::Error>(true)
-            } else {
-                let list_content_prefix =
-                    Self::make_list_content_prefix(this.prefix_name.as_slice(), None);
-                Ok(this
-                    .tree()
-                    .scan_prefix(list_content_prefix)
-                    .keys()
-                    .next()
-                    .is_none())
+    fn _claim(
+        &self,
+        group: &[u8],
+        consumer: &[u8],
+        min_idle: TimestampMillis,
+        ids: Vec<StreamId>,
+    ) -> Result<StreamItems> {
+        let mut g = self._group(group)?;
+        let now = self.db.now();
+        let mut items = Vec::new();
+        for id in ids {
+            let Some(p) = g.pending.get_mut(&id) else {
+                continue;
+            };
+            if now - p.delivered_at < min_idle {
+                continue;
             }
-        }?;
-        Ok(res)
+            match self.tree().get(self.make_entry_key(id))? {
+                Some(v) => {
+                    p.consumer = consumer.to_vec();
+                    p.delivered_at = now;
+                    p.delivery_count += 1;
+                    items.push((id, v));
+                }
+                None => {
+                    g.pending.remove(&id);
+                }
+            }
+        }
+        self._group_set(group, &g)?;
+        Ok(items)
     }
 
     /// Sets expiration time (TTL feature)
     #[cfg(feature = "ttl")]
     #[inline]
     fn _expire_at(&self, at: TimestampMillis) -> Result<bool> {
-        self.db._expire_at(self.name.as_slice(), at, KeyType::List)
+        self.db
+            ._expire_at(self.name.as_slice(), at, KeyType::Stream)
     }
 
     /// Gets time-to-live (TTL feature)
@@ -3370,243 +8495,377 @@ impl SledStorageList {
     fn _ttl(&self) -> Result<Option<TimestampMillis>> {
         Ok(self
             .db
-            ._ttl(self.name(), |k| {
-                SledStorageDB::_list_contains_key(self.tree(), k)
-            })?
+            ._ttl(self.name.as_slice(), KeyType::Stream)?
             .and_then(|(at, _)| if at > 0 { Some(at) } else { None }))
     }
 
-    /// Checks if list is expired
+    /// Checks if stream is expired
     #[inline]
     fn _is_expired(&self) -> Result<bool> {
-        self.db._is_expired(self.name.as_slice(), |k| {
-            SledStorageDB::_list_contains_key(self.tree(), k)
-        })
+        self.db
+            ._check_read_type(self.name.as_slice(), KeyType::Stream)?;
+        self.db._is_expired(self.name.as_slice(), KeyType::Stream)
+    }
+
+    /// Gets entries with IDs in `start..=end` (async)
+    #[inline]
+    async fn call_range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Result<StreamItems> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::StreamRange(
+                self.clone(),
+                start,
+                end,
+                count,
+                rev,
+                tx,
+            ))
+            .await?;
+        rx.await?
     }
 
-    /// Checks if list is expired (async)
+    /// Delivers new entries to a consumer of a group (async)
     #[inline]
-    async fn call_is_expired(&self) -> Result<bool> {
+    async fn call_read_group(
+        &self,
+        group: &[u8],
+        consumer: &[u8],
+        count: Option<usize>,
+    ) -> Result<StreamItems> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::ListIsExpired(self.clone(), tx))
+            .cmd_send(Command::StreamReadGroup(
+                self.clone(),
+                group.to_vec(),
+                consumer.to_vec(),
+                count,
+                tx,
+            ))
             .await?;
         rx.await?
     }
 
-    /// Creates prefix iterator
+    /// Calls `f` until it returns entries, waiting for added entries up to `block`
     #[inline]
-    fn _prefix_iter(&self) -> sled::Iter {
-        let list_content_prefix = Self::make_list_content_prefix(self.prefix_name.as_slice(), None);
-        self.tree().scan_prefix(list_content_prefix)
+    async fn call_blocking<'a, F, Fut>(
+        &'a self,
+        block: Option<Duration>,
+        f: F,
+    ) -> Result<StreamItems>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<StreamItems>> + 'a,
+    {
+        let deadline = block.map(|block| tokio::time::Instant::now().checked_add(block));
+        loop {
+            let notified = self.db.stream_notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let items = f().await?;
+            if !items.is_empty() {
+                return Ok(items);
+            }
+            match deadline {
+                None => return Ok(items),
+                Some(None) => notified.await,
+                Some(Some(deadline)) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        return Ok(items);
+                    }
+                }
+            }
+        }
     }
 
-    /// Creates prefix iterator (async)
+    /// Decodes stored entries
     #[inline]
-    async fn call_prefix_iter(&self) -> Result<sled::Iter> {
-        let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::ListPrefixIter(self.clone(), tx))
-            .await?;
-        Ok(rx.await?)
+    fn decode_items<V: DeserializeOwned>(items: StreamItems) -> Result<Vec<StreamEntry<V>>> {
+        items
+            .into_iter()
+            .map(|(id, v)| {
+                Ok(StreamEntry {
+                    id,
+                    value: bincode::deserialize::<V>(v.as_ref())?,
+                })
+            })
+            .collect()
     }
 }
 
 #[async_trait]
-impl List for SledStorageList {
-    /// Gets list name
+impl Stream for SledStorageStream {
+    /// Gets stream name
     #[inline]
     fn name(&self) -> &[u8] {
         self.name.as_slice()
     }
 
-    /// Pushes value to list
+    /// Appends a value with an auto-generated ID
     #[inline]
-    async fn push<V>(&self, val: &V) -> Result<()>
+    async fn add<V>(&self, val: &V, maxlen: Option<usize>) -> Result<StreamId>
     where
         V: serde::ser::Serialize + Sync + Send,
     {
-        let val = bincode::serialize(val)?;
-        let (tx, rx) = oneshot::channel();
-        self.db
-            .cmd_send(Command::ListPush(self.clone(), val.into(), tx))
-            .await?;
-        rx.await??;
-        Ok(())
-    }
-
-    /// Pushes multiple values to list
-    #[inline]
-    async fn pushs<V>(&self, vals: Vec<V>) -> Result<()>
-    where
-        V: Serialize + Sync + Send,
-    {
-        if vals.is_empty() {
-            return Ok(());
-        }
-
-        let vals = vals
-            .into_iter()
-            .map(|v| {
-                bincode::serialize(&v)
-                    .map(|v| v.into())
-                    .map_err(|e| anyhow!(e))
-            })
-            .collect::<Result<Vec<_>>>()?;
-
+        let data = bincode::serialize(val)?;
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::ListPushs(self.clone(), vals, tx))
+            .cmd_send(Command::StreamAdd(
+                self.clone(),
+                None,
+                data.into(),
+                maxlen,
+                tx,
+            ))
             .await?;
-        rx.await??;
-        Ok(())
+        rx.await?
     }
 
-    /// Pushes value with limit
+    /// Appends a value with an explicit ID
     #[inline]
-    async fn push_limit<V>(
-        &self,
-        val: &V,
-        limit: usize,
-        pop_front_if_limited: bool,
-    ) -> Result<Option<V>>
+    async fn add_with_id<V>(&self, id: StreamId, val: &V, maxlen: Option<usize>) -> Result<StreamId>
     where
         V: serde::ser::Serialize + Sync + Send,
-        V: DeserializeOwned,
     {
         let data = bincode::serialize(val)?;
-
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::ListPushLimit(
+            .cmd_send(Command::StreamAdd(
                 self.clone(),
+                Some(id),
                 data.into(),
-                limit,
-                pop_front_if_limited,
+                maxlen,
                 tx,
             ))
             .await?;
-
-        let removed = if let Some(removed) = rx.await?? {
-            Some(
-                bincode::deserialize::<V>(removed.as_ref())
-                    .map_err(|e| sled::Error::Io(io::Error::new(ErrorKind::InvalidData, e)))?,
-            )
-        } else {
-            None
-        };
-        Ok(removed)
+        rx.await?
     }
 
-    /// Pops value from list
+    /// Gets entries with IDs in `start..=end`, oldest first
     #[inline]
-    async fn pop<V>(&self) -> Result<Option<V>>
+    async fn range<V>(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry<V>>>
     where
         V: DeserializeOwned + Sync + Send,
     {
-        let (tx, rx) = oneshot::channel();
-        self.db.cmd_send(Command::ListPop(self.clone(), tx)).await?;
-
-        let removed = if let Some(removed) = rx.await?? {
-            Some(
-                bincode::deserialize::<V>(removed.as_ref())
-                    .map_err(|e| sled::Error::Io(io::Error::new(ErrorKind::InvalidData, e)))?,
-            )
-        } else {
-            None
-        };
-        Ok(removed)
+        Self::decode_items(self.call_range(start, end, count, false).await?)
     }
 
-    /// Gets all values in list
+    /// Gets entries with IDs in `start..=end`, newest first
     #[inline]
-    async fn all<V>(&self) -> Result<Vec<V>>
+    async fn rev_range<V>(
+        &self,
+        end: StreamId,
+        start: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry<V>>>
     where
         V: DeserializeOwned + Sync + Send,
     {
-        let (tx, rx) = oneshot::channel();
-        self.db.cmd_send(Command::ListAll(self.clone(), tx)).await?;
-
-        rx.await??
-            .iter()
-            .map(|v| bincode::deserialize::<V>(v.as_ref()).map_err(|e| anyhow!(e)))
-            .collect::<Result<Vec<_>>>()
+        Self::decode_items(self.call_range(start, end, count, true).await?)
     }
 
-    /// Gets value by index
+    /// Gets entries with IDs greater than `after`, optionally waiting for new entries
     #[inline]
-    async fn get_index<V>(&self, idx: usize) -> Result<Option<V>>
+    async fn read<V>(
+        &self,
+        after: StreamId,
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> Result<Vec<StreamEntry<V>>>
     where
         V: DeserializeOwned + Sync + Send,
     {
+        let items = self
+            .call_blocking(block, || {
+                self.call_range(after.next(), StreamId::MAX, count, false)
+            })
+            .await?;
+        Self::decode_items(items)
+    }
+
+    /// Gets number of entries
+    #[inline]
+    async fn len(&self) -> Result<usize> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::ListGetIndex(self.clone(), idx, tx))
+            .cmd_send(Command::StreamLen(self.clone(), tx))
             .await?;
+        rx.await?
+    }
 
-        Ok(if let Some(res) = rx.await?? {
-            Some(bincode::deserialize::<V>(res.as_ref()).map_err(|e| anyhow!(e))?)
-        } else {
-            None
-        })
+    /// Checks if stream is empty
+    #[inline]
+    async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
     }
 
-    /// Gets list length
+    /// Gets the ID of the last added entry
     #[inline]
-    async fn len(&self) -> Result<usize> {
+    async fn last_id(&self) -> Result<Option<StreamId>> {
         let (tx, rx) = oneshot::channel();
-        self.db.cmd_send(Command::ListLen(self.clone(), tx)).await?;
-        Ok(rx.await??)
+        self.db
+            .cmd_send(Command::StreamLastId(self.clone(), tx))
+            .await?;
+        rx.await?
     }
 
-    /// Checks if list is empty
+    /// Removes entries by ID
     #[inline]
-    async fn is_empty(&self) -> Result<bool> {
+    async fn delete(&self, ids: Vec<StreamId>) -> Result<usize> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::ListIsEmpty(self.clone(), tx))
+            .cmd_send(Command::StreamDelete(self.clone(), ids, tx))
             .await?;
-        Ok(rx.await??)
+        rx.await?
     }
 
-    /// Clears the list
+    /// Removes the oldest entries down to `maxlen`
+    #[inline]
+    async fn trim(&self, maxlen: usize) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::StreamTrim(self.clone(), maxlen, tx))
+            .await?;
+        rx.await?
+    }
+
+    /// Removes all entries and consumer groups
     #[inline]
     async fn clear(&self) -> Result<()> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::ListClear(self.clone(), tx))
+            .cmd_send(Command::StreamClear(self.clone(), tx))
             .await?;
-        Ok(rx.await??)
+        rx.await?
     }
 
-    /// Iterates over list values
+    /// Creates a consumer group
     #[inline]
-    async fn iter<'a, V>(
-        &'a mut self,
-    ) -> Result<Box<dyn AsyncIterator<Item = Result<V>> + Send + 'a>>
+    async fn group_create<G>(&self, group: G, start: Option<StreamId>) -> Result<bool>
     where
-        V: DeserializeOwned + Sync + Send + 'a + 'static,
+        G: AsRef<[u8]> + Sync + Send,
     {
-        let this = self;
-        let res = {
-            if this.call_is_expired().await? {
-                let iter: Box<dyn AsyncIterator<Item = Result<V>> + Send> =
-                    Box::new(AsyncEmptyIter {
-                        _m: std::marker::PhantomData,
-                    });
-                Ok::<_, anyhow::Error>(iter)
-            } else {
-                let iter = this.call_prefix_iter().await?;
-                let iter: Box<dyn AsyncIterator<Item = Result<V>> + Send> =
-                    Box::new(AsyncListValIter {
-                        db: &this.db,
-                        iter: Some(iter),
-                        _m: std::marker::PhantomData,
-                    });
-                Ok::<_, anyhow::Error>(iter)
-            }
-        }?;
-        Ok(res)
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::StreamGroupCreate(
+                self.clone(),
+                group.as_ref().to_vec(),
+                start,
+                tx,
+            ))
+            .await?;
+        rx.await?
+    }
+
+    /// Removes a consumer group
+    #[inline]
+    async fn group_destroy<G>(&self, group: G) -> Result<bool>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::StreamGroupDestroy(
+                self.clone(),
+                group.as_ref().to_vec(),
+                tx,
+            ))
+            .await?;
+        rx.await?
+    }
+
+    /// Delivers new entries to a consumer of a group, optionally waiting for new entries
+    #[inline]
+    async fn read_group<G, C, V>(
+        &self,
+        group: G,
+        consumer: C,
+        count: Option<usize>,
+        block: Option<Duration>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+        C: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let (group, consumer) = (group.as_ref(), consumer.as_ref());
+        let items = self
+            .call_blocking(block, || self.call_read_group(group, consumer, count))
+            .await?;
+        Self::decode_items(items)
+    }
+
+    /// Acknowledges pending entries of a group
+    #[inline]
+    async fn ack<G>(&self, group: G, ids: Vec<StreamId>) -> Result<usize>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::StreamAck(
+                self.clone(),
+                group.as_ref().to_vec(),
+                ids,
+                tx,
+            ))
+            .await?;
+        rx.await?
+    }
+
+    /// Gets the pending entries of a group
+    #[inline]
+    async fn pending<G>(&self, group: G) -> Result<Vec<StreamPendingEntry>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::StreamPending(
+                self.clone(),
+                group.as_ref().to_vec(),
+                tx,
+            ))
+            .await?;
+        rx.await?
+    }
+
+    /// Transfers idle pending entries to another consumer
+    #[inline]
+    async fn claim<G, C, V>(
+        &self,
+        group: G,
+        consumer: C,
+        min_idle: TimestampMillis,
+        ids: Vec<StreamId>,
+    ) -> Result<Vec<StreamEntry<V>>>
+    where
+        G: AsRef<[u8]> + Sync + Send,
+        C: AsRef<[u8]> + Sync + Send,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.db
+            .cmd_send(Command::StreamClaim(
+                self.clone(),
+                group.as_ref().to_vec(),
+                consumer.as_ref().to_vec(),
+                min_idle,
+                ids,
+                tx,
+            ))
+            .await?;
+        Self::decode_items(rx.await??)
     }
 
     /// Sets expiration time (TTL feature)
@@ -3614,15 +8873,15 @@ impl List for SledStorageList {
     async fn expire_at(&self, at: TimestampMillis) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
         self.db
-            .cmd_send(Command::ListExpireAt(self.clone(), at, tx))
+            .cmd_send(Command::StreamExpireAt(self.clone(), at, tx))
             .await?;
-        Ok(rx.await??)
+        rx.await?
     }
 
     /// Sets time-to-live (TTL feature)
     #[cfg(feature = "ttl")]
     async fn expire(&self, dur: TimestampMillis) -> Result<bool> {
-        let at = timestamp_millis() + dur;
+        let at = self.db.now() + dur;
         self.expire_at(at).await
     }
 
@@ -3630,14 +8889,16 @@ impl List for SledStorageList {
     #[cfg(feature = "ttl")]
     async fn ttl(&self) -> Result<Option<TimestampMillis>> {
         let (tx, rx) = oneshot::channel();
-        self.db.cmd_send(Command::ListTTL(self.clone(), tx)).await?;
-        Ok(rx.await??)
+        self.db
+            .cmd_send(Command::StreamTTL(self.clone(), tx))
+            .await?;
+        rx.await?
     }
 }
 
 /// Async iterator for map items
 pub struct AsyncIter<'a, V> {
-    db: &'a SledStorageDB,
+    map: &'a SledStorageMap,
     prefix_len: usize,
     iter: Option<sled::Iter>,
     _m: std::marker::PhantomData<V>,
@@ -3659,12 +8920,13 @@ where
     async fn next(&mut self) -> Option<Self::Item> {
         let mut iter = self.iter.take()?;
         let (tx, rx) = oneshot::channel();
-        if let Err(e) = self.db.cmd_send(Command::IterNext(iter, tx)).await {
+        let cmd = Command::MapIterNext(self.map.clone(), iter, tx);
+        if let Err(e) = self.map.db.cmd_send(cmd).await {
             return Some(Err(e));
         }
         let item = match rx.await {
             Err(e) => {
-                return Some(Err(anyhow::Error::new(e)));
+                return Some(Err(StorageError::from(e)));
             }
             Ok((it, item)) => {
                 iter = it;
@@ -3674,7 +8936,7 @@ where
 
         match item {
             None => None,
-            Some(Err(e)) => Some(Err(anyhow::Error::new(e))),
+            Some(Err(e)) => Some(Err(e)),
             Some(Ok((k, v))) => {
                 let name = k.as_ref()[self.prefix_len..].to_vec();
                 match bincode::deserialize::<V>(v.as_ref()) {
@@ -3682,7 +8944,7 @@ where
                         self.iter = Some(iter);
                         Some(Ok((name, v)))
                     }
-                    Err(e) => Some(Err(anyhow::Error::new(e))),
+                    Err(e) => Some(Err(StorageError::from(e))),
                 }
             }
         }
@@ -3691,7 +8953,7 @@ where
 
 /// Async iterator for map keys
 pub struct AsyncKeyIter<'a> {
-    db: &'a SledStorageDB,
+    map: &'a SledStorageMap,
     prefix_len: usize,
     iter: Option<sled::Iter>,
 }
@@ -3709,12 +8971,13 @@ impl AsyncIterator for AsyncKeyIter<'_> {
     async fn next(&mut self) -> Option<Self::Item> {
         let mut iter = self.iter.take()?;
         let (tx, rx) = oneshot::channel();
-        if let Err(e) = self.db.cmd_send(Command::IterNext(iter, tx)).await {
+        let cmd = Command::MapIterNext(self.map.clone(), iter, tx);
+        if let Err(e) = self.map.db.cmd_send(cmd).await {
             return Some(Err(e));
         }
         let item = match rx.await {
             Err(e) => {
-                return Some(Err(anyhow::Error::new(e)));
+                return Some(Err(StorageError::from(e)));
             }
             Ok((it, item)) => {
                 iter = it;
@@ -3724,7 +8987,7 @@ impl AsyncIterator for AsyncKeyIter<'_> {
 
         return match item {
             None => None,
-            Some(Err(e)) => Some(Err(anyhow::Error::new(e))),
+            Some(Err(e)) => Some(Err(e)),
             Some(Ok((k, _))) => {
                 self.iter = Some(iter);
                 let name = k.as_ref()[self.prefix_len..].to_vec();
@@ -3762,7 +9025,7 @@ where
         }
         let item = match rx.await {
             Err(e) => {
-                return Some(Err(anyhow::Error::new(e)));
+                return Some(Err(StorageError::from(e)));
             }
             Ok((it, item)) => {
                 iter = it;
@@ -3772,10 +9035,10 @@ where
 
         match item {
             None => None,
-            Some(Err(e)) => Some(Err(anyhow::Error::new(e))),
+            Some(Err(e)) => Some(Err(StorageError::from(e))),
             Some(Ok((_k, v))) => {
                 self.iter = Some(iter);
-                Some(bincode::deserialize::<V>(v.as_ref()).map_err(|e| anyhow!(e)))
+                Some(bincode::deserialize::<V>(v.as_ref()).map_err(StorageError::from))
             }
         }
     }
@@ -3838,7 +9101,7 @@ impl AsyncIterator for AsyncMapIter<'_> {
             }
             let item = match rx.await {
                 Err(e) => {
-                    return Some(Err(anyhow::Error::new(e)));
+                    return Some(Err(StorageError::from(e)));
                 }
                 Ok((it, item)) => {
                     iter = it;
@@ -3848,7 +9111,7 @@ impl AsyncIterator for AsyncMapIter<'_> {
 
             match item {
                 None => return None,
-                Some(Err(e)) => return Some(Err(anyhow::Error::new(e))),
+                Some(Err(e)) => return Some(Err(StorageError::from(e))),
                 Some(Ok((k, _))) => {
                     if !SledStorageDB::is_map_count_key(k.as_ref()) {
                         continue;
@@ -3887,7 +9150,7 @@ impl AsyncIterator for AsyncListIter<'_> {
             }
             let item = match rx.await {
                 Err(e) => {
-                    return Some(Err(anyhow::Error::new(e)));
+                    return Some(Err(StorageError::from(e)));
                 }
                 Ok((it, item)) => {
                     iter = it;
@@ -3896,7 +9159,7 @@ impl AsyncIterator for AsyncListIter<'_> {
             };
             return match item {
                 None => None,
-                Some(Err(e)) => Some(Err(anyhow::Error::new(e))),
+                Some(Err(e)) => Some(Err(StorageError::from(e))),
                 Some(Ok((k, _))) => {
                     if !SledStorageDB::is_list_count_key(k.as_ref()) {
                         continue;
@@ -3936,7 +9199,7 @@ impl AsyncIterator for AsyncDbKeyIter<'_> {
             }
             let item = match rx.await {
                 Err(e) => {
-                    return Some(Err(anyhow::Error::new(e)));
+                    return Some(Err(StorageError::from(e)));
                 }
                 Ok((it, item)) => {
                     iter = it;
@@ -3946,7 +9209,7 @@ impl AsyncIterator for AsyncDbKeyIter<'_> {
 
             return match item {
                 None => None,
-                Some(Err(e)) => Some(Err(anyhow::Error::new(e))),
+                Some(Err(e)) => Some(Err(StorageError::from(e))),
                 Some(Ok((k, _))) => {
                     if !is_match(self.pattern.clone(), k.as_ref()) {
                         continue;
@@ -0,0 +1,119 @@
+//! Slow-operation log
+//!
+//! A SLOWLOG-style ring buffer of commands whose execution in the background
+//! processor exceeded a configurable threshold.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::sled_storage::CommandKind;
+use super::{Key, TimestampMillis, timestamp_millis};
+
+/// Maximum number of key bytes kept per entry
+const SLOWLOG_KEY_MAX_LEN: usize = 128;
+
+/// A command that exceeded the slow-operation threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlowLogEntry {
+    /// Unique, increasing entry id
+    pub id: u64,
+    /// Time the command finished
+    pub timestamp: TimestampMillis,
+    /// Command execution time
+    pub duration: Duration,
+    /// Command kind
+    pub kind: CommandKind,
+    /// Key, map name or list name, truncated to 128 bytes
+    pub key: Option<Key>,
+    /// Length of the key before truncation
+    pub key_len: usize,
+}
+
+/// Ring buffer of slow commands
+pub(crate) struct SlowLog {
+    /// Threshold in microseconds, negative disables the log
+    slower_than: i64,
+    /// Maximum number of entries kept
+    max_len: usize,
+    /// Id of the next entry
+    next_id: AtomicU64,
+    /// Entries, newest first
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+}
+
+impl SlowLog {
+    /// Creates a slow log
+    #[inline]
+    pub(crate) fn new(slower_than: i64, max_len: usize) -> Self {
+        Self {
+            slower_than,
+            max_len,
+            next_id: AtomicU64::new(0),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Checks if commands are recorded at all
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.slower_than >= 0 && self.max_len > 0
+    }
+
+    /// Truncates a key to the length kept in the log
+    #[inline]
+    pub(crate) fn truncate_key(key: &[u8]) -> (Key, usize) {
+        (
+            key[..key.len().min(SLOWLOG_KEY_MAX_LEN)].to_vec(),
+            key.len(),
+        )
+    }
+
+    /// Records a command if it exceeded the threshold
+    #[inline]
+    pub(crate) fn record(&self, kind: CommandKind, key: Option<(Key, usize)>, dur: Duration) {
+        if !self.is_enabled() || (dur.as_micros() as i64) < self.slower_than {
+            return;
+        }
+        let (key, key_len) = match key {
+            Some((key, len)) => (Some(key), len),
+            None => (None, 0),
+        };
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: timestamp_millis(),
+            duration: dur,
+            kind,
+            key,
+            key_len,
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push_front(entry);
+        entries.truncate(self.max_len);
+    }
+
+    /// Gets up to `n` entries, newest first
+    #[inline]
+    pub(crate) fn get(&self, n: usize) -> Vec<SlowLogEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().take(n).cloned().collect()
+    }
+
+    /// Gets the number of entries
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Removes all entries
+    #[inline]
+    pub(crate) fn reset(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
@@ -11,8 +11,8 @@ mod tests {
             path: format!("./db/list/{}", name),
             ..Default::default()
         };
-        let db = init_db(&cfg).await.unwrap();
-        db
+
+        init_db(&cfg).await.unwrap()
     }
 
     #[tokio::main]
@@ -45,6 +45,19 @@ mod tests {
         assert_eq!(db.counter_get("incr3").await.unwrap(), Some(110));
 
         assert_eq!(db.counter_get("incr4").await.unwrap(), None);
+
+        //a counter written as a 4 byte isize by a 32-bit host is widened on update
+        let StorageDB::Sled(s) = &db;
+        s.kv_tree
+            .insert("incr5", 5i32.to_be_bytes().as_slice())
+            .unwrap();
+        assert_eq!(db.counter_get("incr5").await.unwrap(), Some(5));
+        db.counter_incr("incr5", i64::from(i32::MAX)).await.unwrap();
+        assert_eq!(
+            db.counter_get("incr5").await.unwrap(),
+            Some(i64::from(i32::MAX) + 5)
+        );
+        assert_eq!(s.kv_tree.get("incr5").unwrap().unwrap().len(), 8);
     }
 
     #[tokio::main]
@@ -122,6 +135,248 @@ mod tests {
         println!("test_db_size db_size: {:?}", db.db_size().await);
     }
 
+    #[tokio::main]
+    #[test]
+    async fn test_info() {
+        let mut db = get_db("info").await;
+        let iter = db.scan("*").await.unwrap();
+        for item in collect(iter).await {
+            db.remove(item).await.unwrap();
+        }
+        db.map_remove("info_map").await.unwrap();
+        db.list_remove("info_list").await.unwrap();
+
+        db.insert("info_k1", &1).await.unwrap();
+        db.insert("info_k2", &2).await.unwrap();
+        db.counter_incr("info_c1", 1).await.unwrap();
+        db.expire("info_k2", 60 * 1000).await.unwrap();
+        let m = db.map("info_map", None).await.unwrap();
+        m.insert("mk1", &1).await.unwrap();
+        let l = db.list("info_list", None).await.unwrap();
+        l.push(&1).await.unwrap();
+
+        let info = db.storage_info().await.unwrap();
+        println!("test_info info: {:?}", info);
+        assert_eq!(info.storage_engine, "Sled");
+        assert_eq!(info.kv_keys, 3);
+        assert_eq!(info.maps, 1);
+        assert_eq!(info.lists, 1);
+        assert_eq!(info.ttl_keys, 1);
+        assert_eq!(info.commands.get("DBInsert"), Some(&2));
+        assert_eq!(info.commands.get("DBCounterIncr"), Some(&1));
+        assert_eq!(info.commands.len(), CommandKind::COUNT);
+        //every kind is listed once, at the index of its discriminant
+        for (i, kind) in CommandKind::ALL.iter().enumerate() {
+            assert_eq!(*kind as usize, i);
+        }
+
+        let val = db.info().await.unwrap();
+        assert_eq!(val["kv_keys"], 3);
+        assert_eq!(val["commands"]["MapInsert"], 1);
+        assert!(val["cleanup"]["rounds"].is_u64());
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_metrics() {
+        let db = get_db("metrics").await;
+        db.insert("metrics_k1", &"abc").await.unwrap();
+        db.insert("metrics_k2", &"def").await.unwrap();
+        assert_eq!(
+            db.get::<_, String>("metrics_k1").await.unwrap(),
+            Some("abc".into())
+        );
+        //not a counter value
+        assert!(db.counter_get("metrics_k1").await.is_err());
+
+        //metrics are recorded after the result is sent back
+        sleep(Duration::from_millis(50)).await;
+        let snapshot = db.metrics();
+        let insert = snapshot.command(CommandKind::DBInsert);
+        assert_eq!(insert.count, 2);
+        assert_eq!(insert.errors, 0);
+        assert_eq!(insert.latency.count, 2);
+        assert_eq!(insert.latency.counts.iter().sum::<u64>(), 2);
+        assert!(insert.latency.quantile(0.5).is_some());
+        let counter_get = snapshot.command(CommandKind::DBCounterGet);
+        assert_eq!(counter_get.count, 1);
+        assert_eq!(counter_get.errors, 1);
+        assert!(snapshot.queue_wait.count >= 4);
+
+        let text = snapshot.to_prometheus();
+        println!("{}", text);
+        assert!(text.contains("# TYPE kv_storage_commands_total counter"));
+        assert!(text.contains("kv_storage_commands_total{command=\"DBInsert\"} 2"));
+        assert!(text.contains("kv_storage_command_errors_total{command=\"DBCounterGet\"} 1"));
+        assert!(text.contains(
+            "kv_storage_command_duration_seconds_bucket{command=\"DBInsert\",le=\"+Inf\"} 2"
+        ));
+        assert!(text.contains("kv_storage_queue_wait_seconds_count "));
+        assert!(!text.contains("command=\"ListPop\""));
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_slowlog() {
+        let cfg = Config {
+            path: "./db/list/slowlog".into(),
+            slowlog_slower_than: 0,
+            slowlog_max_len: 3,
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+        let long_key = "k".repeat(200);
+        db.insert(long_key.as_str(), &1).await.unwrap();
+        let m = db.map("slowlog_map", None).await.unwrap();
+        m.insert("f1", &1).await.unwrap();
+        db.insert("slowlog_k1", &1).await.unwrap();
+        db.get::<_, i32>("slowlog_k1").await.unwrap();
+
+        //entries are recorded after the result is sent back
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(db.slowlog_len(), 3);
+        let entries = db.slowlog_get(10);
+        println!("test_slowlog entries: {:?}", entries);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].kind, CommandKind::DBGet);
+        assert_eq!(entries[0].key.as_deref(), Some(&b"slowlog_k1"[..]));
+        assert!(entries[0].id > entries[1].id);
+        assert_eq!(entries[2].kind, CommandKind::MapInsert);
+        assert_eq!(entries[2].key.as_deref(), Some(&b"slowlog_map"[..]));
+        assert_eq!(db.slowlog_get(1).len(), 1);
+
+        db.slowlog_reset();
+        assert_eq!(db.slowlog_len(), 0);
+
+        db.insert(long_key.as_str(), &2).await.unwrap();
+        sleep(Duration::from_millis(50)).await;
+        let entries = db.slowlog_get(10);
+        assert_eq!(entries[0].key.as_ref().map(|k| k.len()), Some(128));
+        assert_eq!(entries[0].key_len, 200);
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_subscribe() {
+        let _ = std::fs::remove_dir_all("./db/list/subscribe");
+        let db = get_db("subscribe").await;
+        let mut sub = db.subscribe("ks_*");
+        db.insert("other_k1", &1).await.unwrap();
+        db.insert("ks_k1", &1).await.unwrap();
+        db.remove("ks_k1").await.unwrap();
+        let m = db.map("ks_map", None).await.unwrap();
+        m.insert("f1", &1).await.unwrap();
+        m.clear().await.unwrap();
+        let l = db.list("ks_list", None).await.unwrap();
+        l.push(&1).await.unwrap();
+        l.pop::<i32>().await.unwrap();
+
+        //commands that change nothing publish nothing
+        db.remove("ks_k1").await.unwrap();
+        db.batch_remove(vec![b"ks_k1".to_vec()]).await.unwrap();
+        db.map_remove("ks_none").await.unwrap();
+        m.remove("f1").await.unwrap();
+        l.pop::<i32>().await.unwrap();
+        l.push_limit(&1, 1, true).await.unwrap();
+        //a push past the limit pops the front element
+        l.push_limit(&2, 1, true).await.unwrap();
+
+        let mut events = Vec::new();
+        for _ in 0..9 {
+            events.push(sub.next().await.unwrap().unwrap());
+        }
+        println!("test_subscribe events: {:?}", events);
+        use KeyspaceEventKind::*;
+        let summary = events
+            .iter()
+            .map(|e| (e.kind, e.key_type, e.key.as_slice(), e.field.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (Inserted, KeyType::KV, &b"ks_k1"[..], None),
+                (Removed, KeyType::KV, &b"ks_k1"[..], None),
+                (Inserted, KeyType::Map, &b"ks_map"[..], Some(&b"f1"[..])),
+                (Cleared, KeyType::Map, &b"ks_map"[..], None),
+                (Inserted, KeyType::List, &b"ks_list"[..], None),
+                (Removed, KeyType::List, &b"ks_list"[..], None),
+                (Inserted, KeyType::List, &b"ks_list"[..], None),
+                (Inserted, KeyType::List, &b"ks_list"[..], None),
+                (Removed, KeyType::List, &b"ks_list"[..], None),
+            ]
+        );
+
+        #[cfg(feature = "ttl")]
+        {
+            db.insert("ks_k2", &2).await.unwrap();
+            db.expire("ks_k2", 10).await.unwrap();
+            let event = sub.next().await.unwrap().unwrap();
+            assert_eq!(event.kind, Inserted);
+            sleep(Duration::from_millis(20)).await;
+            let StorageDB::Sled(sled_db) = &db;
+            sled_db.cleanup(100);
+            let event = sub.next().await.unwrap().unwrap();
+            assert_eq!(event.kind, Expired);
+            assert_eq!(event.key, b"ks_k2".to_vec());
+        }
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_pubsub() {
+        let clock = ManualClock::new(1_000_000);
+        let cfg = Config {
+            path: "./db/list/pubsub".into(),
+            pubsub_history_len: 2,
+            clock: std::sync::Arc::new(clock.clone()),
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+        let pubsub = db.pubsub();
+        pubsub.clear_history("news/a").await.unwrap();
+
+        let mut sub = pubsub.subscribe("news/a");
+        let mut psub = pubsub.psubscribe("news/*");
+        let other = pubsub.subscribe("sports");
+        //only subscribers whose channel or pattern matches are counted
+        assert_eq!(pubsub.publish("news/b", &"b1").await.unwrap(), 1);
+        assert_eq!(pubsub.publish("news/a", &"a1").await.unwrap(), 2);
+        assert_eq!(pubsub.publish("sports", &"s1").await.unwrap(), 1);
+        drop(other);
+        assert_eq!(pubsub.publish("sports", &"s2").await.unwrap(), 0);
+        clock.advance(Duration::from_secs(1));
+        pubsub.publish("news/a", &"a2").await.unwrap();
+        pubsub.publish("news/a", &"a3").await.unwrap();
+
+        let msg = sub.next().await.unwrap().unwrap();
+        assert_eq!(msg.channel, b"news/a".to_vec());
+        assert_eq!(msg.value::<String>().unwrap(), "a1");
+        assert_eq!(msg.timestamp, 1_000_000);
+        let msg = psub.next().await.unwrap().unwrap();
+        assert_eq!(msg.channel, b"news/b".to_vec());
+        assert_eq!(msg.value::<String>().unwrap(), "b1");
+        let msg = psub.next().await.unwrap().unwrap();
+        assert_eq!(msg.value::<String>().unwrap(), "a1");
+
+        let history = pubsub.history("news/a").await.unwrap();
+        let vals = history
+            .iter()
+            .map(|m| m.value::<String>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vals, vec!["a2", "a3"]);
+        assert_eq!(history[1].timestamp, 1_001_000);
+
+        //the history is kept out of the user-visible lists
+        let mut db = db;
+        let mut lists = db.list_iter().await.unwrap();
+        while let Some(l) = lists.next().await {
+            assert!(!l.unwrap().name().starts_with(b"__pubsub"));
+        }
+        drop(lists);
+        pubsub.clear_history("news/a").await.unwrap();
+        assert!(pubsub.history("news/a").await.unwrap().is_empty());
+    }
+
     async fn collect(mut iter: Box<dyn AsyncIterator<Item = Result<Key>> + Send + '_>) -> Vec<Key> {
         let mut data = Vec::new();
         while let Some(key) = iter.next().await {
@@ -130,6 +385,1405 @@ mod tests {
         data
     }
 
+    #[tokio::main]
+    #[test]
+    async fn test_flush_close() {
+        let cfg = Config {
+            path: "./db/list/flush_close".into(),
+            durability: Durability::PerWrite,
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+        db.insert("close/1", &1).await.unwrap();
+        db.flush().await.unwrap();
+
+        let writer = db.clone();
+        let pending = tokio::spawn(async move { writer.insert("close/2", &2).await });
+        tokio::task::yield_now().await;
+        db.close().await.unwrap();
+        assert!(db.is_closed());
+        //closing twice is a no-op
+        db.close().await.unwrap();
+        assert!(db.insert("close/3", &3).await.is_err());
+        let pending_res = pending.await.unwrap();
+        drop(db);
+        sleep(Duration::from_millis(100)).await;
+
+        let db = init_db(&cfg).await.unwrap();
+        assert_eq!(db.get::<_, i32>("close/1").await.unwrap(), Some(1));
+        if pending_res.is_ok() {
+            assert_eq!(db.get::<_, i32>("close/2").await.unwrap(), Some(2));
+        }
+        assert_eq!(db.get::<_, i32>("close/3").await.unwrap(), None);
+
+        //a concurrent close returns once the first one completed
+        let writer = db.clone();
+        let write = tokio::spawn(async move {
+            let key_vals = (0..20_000)
+                .map(|i| (format!("close/w{}", i).into_bytes(), i))
+                .collect::<Vec<_>>();
+            writer.batch_insert(key_vals).await
+        });
+        sleep(Duration::from_millis(5)).await;
+        let closer = db.clone();
+        let first = tokio::spawn(async move { closer.close().await });
+        sleep(Duration::from_millis(1)).await;
+        db.close().await.unwrap();
+        let StorageDB::Sled(s) = &db;
+        let written = s.kv_tree.contains_key("close/w19999").unwrap();
+        if write.await.unwrap().is_ok() {
+            assert!(written);
+        }
+        first.await.unwrap().unwrap();
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_config_tuning() {
+        let cfg = Config {
+            path: "./db/list/config_tuning".into(),
+            mode: StorageMode::HighThroughput,
+            segment_size: 1024 * 1024,
+            temporary: true,
+            command_channel_capacity: 16,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&cfg).unwrap();
+        let cfg2: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(cfg2.mode, StorageMode::HighThroughput);
+        assert_eq!(cfg2.segment_size, 1024 * 1024);
+        assert_eq!(cfg2.command_channel_capacity, 16);
+
+        //fields missing from a config file fall back to the defaults
+        let mut value = serde_json::to_value(&cfg).unwrap();
+        let obj = value.as_object_mut().unwrap();
+        for field in [
+            "mode",
+            "segment_size",
+            "command_channel_capacity",
+            "durability",
+        ] {
+            obj.remove(field);
+        }
+        let cfg3: Config = serde_json::from_value(value).unwrap();
+        assert_eq!(cfg3.mode, StorageMode::LowSpace);
+        assert_eq!(cfg3.segment_size, 512 * 1024);
+        assert_eq!(cfg3.command_channel_capacity, 300_000);
+        assert_eq!(cfg3.durability, Durability::Periodic { interval_ms: 3000 });
+
+        let db = init_db(&cfg).await.unwrap();
+        for i in 0..100 {
+            db.insert(format!("tuning/{}", i), &i).await.unwrap();
+        }
+        assert_eq!(db.get::<_, i32>("tuning/99").await.unwrap(), Some(99));
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_compression() {
+        let cfg = Config {
+            path: "./db/list/compression".into(),
+            use_compression: true,
+            compression_factor: 3,
+            temporary: true,
+            ..Default::default()
+        };
+        #[cfg(not(feature = "compression"))]
+        assert!(matches!(init_db(&cfg).await, Err(StorageError::Invalid(_))));
+        #[cfg(feature = "compression")]
+        {
+            let db = init_db(&cfg).await.unwrap();
+            db.insert("compressed", &"a".repeat(1000)).await.unwrap();
+            assert_eq!(
+                db.get::<_, String>("compressed").await.unwrap(),
+                Some("a".repeat(1000))
+            );
+        }
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_backup_restore() {
+        let _ = std::fs::remove_dir_all("./db/list/backup_restored");
+        std::fs::create_dir_all("./db/list/backup_archives").unwrap();
+        let db = get_db("backup").await;
+        db.insert("backup/k1", &1).await.unwrap();
+        db.insert("backup/k2", &2).await.unwrap();
+        db.expire("backup/k2", 60_000).await.unwrap();
+        let m = db.map("backup_map", None).await.unwrap();
+        m.insert("f1", &"v1").await.unwrap();
+        let l = db.list("backup_list", None).await.unwrap();
+        l.clear().await.unwrap();
+        l.push(&10).await.unwrap();
+        l.push(&20).await.unwrap();
+
+        let info = db.backup("./db/list/backup_archives").await.unwrap();
+        assert!(info.path.starts_with("./db/list/backup_archives"));
+        assert!(info.entries > 0);
+        let verified = verify_backup(&info.path).unwrap();
+        assert_eq!(verified.checksum, info.checksum);
+        assert_eq!(verified.entries, info.entries);
+
+        let cfg = Config {
+            path: "./db/list/backup_restored".into(),
+            ..Default::default()
+        };
+        let restored = restore_db(&info.path, &cfg).await.unwrap();
+        assert_eq!(restored.get::<_, i32>("backup/k1").await.unwrap(), Some(1));
+        assert_eq!(restored.get::<_, i32>("backup/k2").await.unwrap(), Some(2));
+        assert!(restored.ttl("backup/k2").await.unwrap().is_some());
+        let m = restored.map("backup_map", None).await.unwrap();
+        assert_eq!(
+            m.get::<_, String>("f1").await.unwrap(),
+            Some("v1".to_string())
+        );
+        let l = restored.list("backup_list", None).await.unwrap();
+        assert_eq!(l.all::<i32>().await.unwrap(), vec![10, 20]);
+
+        //the target must be empty
+        restored.close().await.unwrap();
+        drop((m, l, restored));
+        sleep(Duration::from_millis(100)).await;
+        let err = restore_db(&info.path, &cfg).await.err().unwrap();
+        assert!(err.to_string().contains("not empty"));
+
+        //a corrupted archive is rejected before anything is written
+        let mut data = std::fs::read(&info.path).unwrap();
+        let mid = data.len() / 2;
+        data[mid] ^= 0xff;
+        let corrupted = info.path.with_extension("corrupted");
+        std::fs::write(&corrupted, data).unwrap();
+        assert!(verify_backup(&corrupted).is_err());
+
+        //a corrupt length is bounded by the bytes left in the archive
+        let mut data = std::fs::read(&info.path).unwrap();
+        data[21..25].copy_from_slice(&u32::MAX.to_be_bytes());
+        data.truncate(64);
+        std::fs::write(&corrupted, data).unwrap();
+        let err = verify_backup(&corrupted).err().unwrap();
+        assert!(err.to_string().contains("truncated"));
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_verify_repair() {
+        let db = get_db("verify_repair").await;
+        let m1 = db.map("verify_m1", None).await.unwrap();
+        m1.clear().await.unwrap();
+        for i in 0..3 {
+            m1.insert(format!("f{}", i), &i).await.unwrap();
+        }
+        let l1 = db.list("verify_l1", None).await.unwrap();
+        l1.clear().await.unwrap();
+        for i in 0..3 {
+            l1.push(&i).await.unwrap();
+        }
+        assert!(db.verify().await.unwrap().is_consistent());
+
+        let StorageDB::Sled(s) = &db;
+        let enc = |prefix: &str, name: &str| {
+            [
+                prefix.as_bytes(),
+                (name.len() as u32).to_be_bytes().as_slice(),
+                name.as_bytes(),
+            ]
+            .concat()
+        };
+        //corrupt the map count and add items without a map
+        s.map_tree
+            .insert(
+                [enc("__map@", "verify_m1"), b"@__count@".to_vec()].concat(),
+                7i64.to_be_bytes().as_slice(),
+            )
+            .unwrap();
+        s.map_tree
+            .insert(
+                [enc("__map@", "verify_ghost"), b"@__item@x".to_vec()].concat(),
+                b"1",
+            )
+            .unwrap();
+        //remove the middle list item and add content without a list
+        let content = |name: &str, idx: usize| {
+            [
+                enc("__list@", name).as_slice(),
+                b"@__content@",
+                idx.to_be_bytes().as_slice(),
+            ]
+            .concat()
+        };
+        s.list_tree.remove(content("verify_l1", 2)).unwrap();
+        s.list_tree
+            .insert(content("verify_ghost", 1), b"1")
+            .unwrap();
+        //an expiration time for a key that does not exist
+        s.key_expire_tree
+            .insert(b"verify_nokey", i64::MAX.to_be_bytes().as_slice())
+            .unwrap();
+
+        let report = db.verify().await.unwrap();
+        assert!(!report.repaired);
+        assert_eq!(report.issues.len(), 5);
+        assert!(report.issues.contains(&Inconsistency::MapCount {
+            name: b"verify_m1".to_vec(),
+            stored: 7,
+            actual: 3,
+        }));
+        assert!(report.issues.contains(&Inconsistency::ListBounds {
+            name: b"verify_l1".to_vec(),
+            stored: (0, 3),
+            actual: (0, 2),
+            gaps: true,
+        }));
+        assert!(report.issues.contains(&Inconsistency::OrphanMapItems {
+            name: b"verify_ghost".to_vec(),
+            count: 1,
+        }));
+        assert!(report.issues.contains(&Inconsistency::OrphanListItems {
+            name: b"verify_ghost".to_vec(),
+            count: 1,
+        }));
+        assert!(report.issues.contains(&Inconsistency::OrphanExpiry {
+            key: b"verify_nokey".to_vec(),
+            key_type: None,
+        }));
+
+        let report = db.repair().await.unwrap();
+        assert!(report.repaired);
+        assert_eq!(report.issues.len(), 5);
+        assert!(db.verify().await.unwrap().is_consistent());
+        assert_eq!(m1.len().await.unwrap(), 3);
+        assert_eq!(l1.all::<i32>().await.unwrap(), vec![0, 2]);
+        assert_eq!(l1.len().await.unwrap(), 2);
+        l1.push(&3).await.unwrap();
+        assert_eq!(l1.all::<i32>().await.unwrap(), vec![0, 2, 3]);
+
+        //list content at index 0 is reported and renumbered instead of underflowing
+        s.list_tree
+            .insert(
+                content("verify_l1", 0),
+                s.list_tree.get(content("verify_l1", 1)).unwrap().unwrap(),
+            )
+            .unwrap();
+        let report = db.repair().await.unwrap();
+        assert!(report.issues.contains(&Inconsistency::ListBounds {
+            name: b"verify_l1".to_vec(),
+            stored: (0, 3),
+            actual: (0, 4),
+            gaps: true,
+        }));
+        assert!(db.verify().await.unwrap().is_consistent());
+        assert_eq!(l1.all::<i32>().await.unwrap(), vec![0, 0, 2, 3]);
+
+        //a stream entry the length does not count and entries without a stream
+        let st = db.stream("verify_s1", None).await.unwrap();
+        st.clear().await.unwrap();
+        st.add(&1, None).await.unwrap();
+        let last = st.add(&2, None).await.unwrap();
+        let late = StreamId {
+            ms: last.ms + 1,
+            seq: 0,
+        };
+        let entry = |name: &str, id: StreamId| {
+            let mut id_bytes = id.ms.to_be_bytes().to_vec();
+            id_bytes.extend_from_slice(&id.seq.to_be_bytes());
+            [enc("__stream@", name), b"@__entry@".to_vec(), id_bytes].concat()
+        };
+        let value = bincode::serialize(&3).unwrap();
+        s.stream_tree
+            .insert(entry("verify_s1", late), value.as_slice())
+            .unwrap();
+        s.stream_tree
+            .insert(entry("verify_ghost", late), value.as_slice())
+            .unwrap();
+        let report = db.verify().await.unwrap();
+        assert_eq!(report.streams, 1);
+        assert_eq!(report.issues.len(), 2);
+        assert!(report.issues.contains(&Inconsistency::StreamMeta {
+            name: b"verify_s1".to_vec(),
+            stored: (2, last),
+            actual: (3, late),
+        }));
+        assert!(report.issues.contains(&Inconsistency::OrphanStreamItems {
+            name: b"verify_ghost".to_vec(),
+            count: 1,
+        }));
+        db.repair().await.unwrap();
+        assert!(db.verify().await.unwrap().is_consistent());
+        assert_eq!(st.len().await.unwrap(), 3);
+        assert!(st.add(&4, None).await.unwrap() > late);
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_key_layout_migration() {
+        let path = "./db/list/key_layout_migration";
+        let _ = std::fs::remove_dir_all(path);
+        {
+            //write a database with the legacy `prefix@name@suffix` key layout, m1 and l1
+            //as a 64-bit host would, m32 and l32 with the 4 byte isize of a 32-bit host
+            let db = sled::open(path).unwrap();
+            let map_tree = db.open_tree("__map_tree@").unwrap();
+            map_tree
+                .insert("__map@m1@__count@", 2i64.to_be_bytes().as_slice())
+                .unwrap();
+            map_tree
+                .insert("__map@m32@__count@", 1i32.to_be_bytes().as_slice())
+                .unwrap();
+            map_tree
+                .insert("__map@m32@__item@f", bincode::serialize(&3i32).unwrap())
+                .unwrap();
+            for (field, v) in [("f1", 1i32), ("f2", 2)] {
+                map_tree
+                    .insert(
+                        format!("__map@m1@__item@{}", field),
+                        bincode::serialize(&v).unwrap(),
+                    )
+                    .unwrap();
+            }
+            let list_tree = db.open_tree("__list_tree@").unwrap();
+            list_tree
+                .insert(
+                    "__list@l1@__count@",
+                    bincode::serialize(&(0usize, 2usize)).unwrap(),
+                )
+                .unwrap();
+            for (idx, v) in [(1u64, 10i32), (2, 20)] {
+                list_tree
+                    .insert(
+                        [b"__list@l1@__content@".as_slice(), &idx.to_be_bytes()].concat(),
+                        bincode::serialize(&v).unwrap(),
+                    )
+                    .unwrap();
+            }
+            list_tree
+                .insert(
+                    "__list@l32@__count@",
+                    bincode::serialize(&(0u64, 1u64)).unwrap(),
+                )
+                .unwrap();
+            list_tree
+                .insert(
+                    [b"__list@l32@__content@".as_slice(), &1u32.to_be_bytes()].concat(),
+                    bincode::serialize(&7i32).unwrap(),
+                )
+                .unwrap();
+            //left over by an interrupted migration, dropped before the steps run again
+            db.open_tree("__migrate@__map_tree@")
+                .unwrap()
+                .insert("junk", "junk")
+                .unwrap();
+            db.flush().unwrap();
+        }
+
+        let cfg = Config {
+            path: path.into(),
+            ..Default::default()
+        };
+        let mut progress = Vec::new();
+        let report = migrate_db(&cfg, true, |p| progress.push(p.clone()))
+            .await
+            .unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.from_version, 0);
+        //later steps depend on the keys the first one rewrites
+        assert_eq!(report.to_version, 1);
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(report.uncounted.len(), 4);
+        assert_eq!(report.steps[0].changed, 10);
+        assert_eq!(progress[0].processed, 10);
+
+        //a dry run changes nothing, opening the database migrates it
+        let db = init_db(&cfg).await.unwrap();
+        let m1 = db.map("m1", None).await.unwrap();
+        assert_eq!(m1.len().await.unwrap(), 2);
+        assert_eq!(m1.get::<_, i32>("f2").await.unwrap(), Some(2));
+        let l1 = db.list("l1", None).await.unwrap();
+        assert_eq!(l1.all::<i32>().await.unwrap(), vec![10, 20]);
+        let m32 = db.map("m32", None).await.unwrap();
+        assert_eq!(m32.len().await.unwrap(), 1);
+        let l32 = db.list("l32", None).await.unwrap();
+        assert_eq!(l32.all::<i32>().await.unwrap(), vec![7]);
+        assert_eq!(l32.get_index::<i32>(0).await.unwrap(), Some(7));
+        assert!(db.verify().await.unwrap().is_consistent());
+        let StorageDB::Sled(s) = &db;
+        assert!(
+            !s.db
+                .tree_names()
+                .iter()
+                .any(|name| name.starts_with(b"__migrate@"))
+        );
+        assert_eq!(m1.len().await.unwrap(), 2);
+        db.close().await.unwrap();
+        drop((m1, l1, m32, l32, db));
+        sleep(Duration::from_millis(100)).await;
+
+        let report = migrate_db(&cfg, false, |_| {}).await.unwrap();
+        assert_eq!(report.from_version, FORMAT_VERSION);
+        assert!(report.steps.is_empty());
+
+        //formats newer than this release are refused
+        {
+            let db = sled::open(path).unwrap();
+            let meta_tree = db.open_tree("__meta_tree@").unwrap();
+            meta_tree
+                .insert(
+                    "format_version",
+                    (FORMAT_VERSION + 1).to_be_bytes().as_slice(),
+                )
+                .unwrap();
+            db.flush().unwrap();
+        }
+        assert!(init_db(&cfg).await.is_err());
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_expire_key_migration() {
+        let path = "./db/list/expire_key_migration";
+        let _ = std::fs::remove_dir_all(path);
+        let now = timestamp_millis();
+        {
+            //a version 2 database, key-value `a` and map `a` share the expiration time stored
+            //for the bare name, namespace key `b` lost its time-ordered entry
+            let db = sled::open(path).unwrap();
+            let enc = |prefix: &str, name: &str| {
+                [
+                    prefix.as_bytes(),
+                    (name.len() as u32).to_be_bytes().as_slice(),
+                    name.as_bytes(),
+                ]
+                .concat()
+            };
+            db.open_tree("__meta_tree@")
+                .unwrap()
+                .insert("format_version", 2u32.to_be_bytes().as_slice())
+                .unwrap();
+            db.open_tree("__kv_tree@")
+                .unwrap()
+                .insert("a", bincode::serialize(&1i32).unwrap())
+                .unwrap();
+            let map_tree = db.open_tree("__map_tree@").unwrap();
+            map_tree
+                .insert(
+                    [enc("__map@", "a"), b"@__count@".to_vec()].concat(),
+                    1i64.to_be_bytes().as_slice(),
+                )
+                .unwrap();
+            map_tree
+                .insert(
+                    [enc("__map@", "a"), b"@__item@f".to_vec()].concat(),
+                    bincode::serialize(&2i32).unwrap(),
+                )
+                .unwrap();
+            let (kv_at, map_at) = (now + 100_000, now + 200_000);
+            let expire_key_tree = db.open_tree("__expire_key_tree@").unwrap();
+            expire_key_tree
+                .insert([kv_at.to_be_bytes().as_slice(), b"a"].concat(), &[1u8])
+                .unwrap();
+            expire_key_tree
+                .insert([map_at.to_be_bytes().as_slice(), b"a"].concat(), &[2u8])
+                .unwrap();
+            db.open_tree("__key_expire_tree@")
+                .unwrap()
+                .insert("a", map_at.to_be_bytes().as_slice())
+                .unwrap();
+            let ns = enc("__ns@", "ns");
+            db.open_tree([ns.as_slice(), b"__kv_tree@"].concat())
+                .unwrap()
+                .insert("b", bincode::serialize(&3i32).unwrap())
+                .unwrap();
+            db.open_tree([ns.as_slice(), b"__key_expire_tree@"].concat())
+                .unwrap()
+                .insert("b", (now + 300_000).to_be_bytes().as_slice())
+                .unwrap();
+            db.flush().unwrap();
+        }
+        sleep(Duration::from_millis(100)).await;
+
+        let cfg = Config {
+            path: path.into(),
+            ..Default::default()
+        };
+        let report = migrate_db(&cfg, true, |_| {}).await.unwrap();
+        assert_eq!(report.from_version, 2);
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(report.steps[0].changed, 3);
+        assert_eq!(report.uncounted.len(), 2);
+        sleep(Duration::from_millis(100)).await;
+
+        let report = migrate_db(&cfg, false, |_| {}).await.unwrap();
+        assert_eq!(report.steps.len(), 3);
+        assert_eq!(report.steps[0].changed, 3);
+        //map `a` shares its name with key-value `a`, which is recorded
+        assert_eq!(report.steps[2].changed, 2);
+        sleep(Duration::from_millis(100)).await;
+
+        let db = init_db(&cfg).await.unwrap();
+        let in_range = |ttl: Option<TimestampMillis>, max: TimestampMillis| matches!(ttl, Some(ttl) if ttl > max - 10_000 && ttl <= max);
+        assert!(in_range(db.ttl("a").await.unwrap(), 100_000));
+        let m = db.map("a", None).await.unwrap();
+        assert!(in_range(m.ttl().await.unwrap(), 200_000));
+        assert_eq!(m.get::<_, i32>("f").await.unwrap(), Some(2));
+        let ns = db.namespace("ns").unwrap();
+        assert!(in_range(ns.ttl("b").await.unwrap(), 300_000));
+        assert!(db.verify().await.unwrap().is_consistent());
+        assert!(ns.verify().await.unwrap().is_consistent());
+
+        //expiring one type leaves the other alone
+        db.expire("a", 0).await.unwrap();
+        assert_eq!(db.type_of("a").await.unwrap(), Some(KeyType::Map));
+        assert!(in_range(m.ttl().await.unwrap(), 200_000));
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_eviction() {
+        let open = |name: &str, max_memory: MaxMemory, eviction_policy: EvictionPolicy| {
+            let path = format!("./db/list/{}", name);
+            let _ = std::fs::remove_dir_all(&path);
+            let cfg = Config {
+                path,
+                max_memory,
+                eviction_policy,
+                ..Default::default()
+            };
+            async move { init_db(&cfg).await.unwrap() }
+        };
+
+        //least recently used keys go first
+        let db = open(
+            "evict_lru",
+            MaxMemory::Keys { limit: 3 },
+            EvictionPolicy::AllkeysLru,
+        )
+        .await;
+        for i in 0..5 {
+            db.insert(format!("k{}", i), &i).await.unwrap();
+            sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(db.get::<_, i32>("k0").await.unwrap(), Some(0));
+        let StorageDB::Sled(s) = &db;
+        assert_eq!(s.evict().unwrap(), 2);
+        for (key, exists) in [("k0", true), ("k1", false), ("k2", false), ("k3", true)] {
+            assert_eq!(db.contains_key(key).await.unwrap(), exists, "{}", key);
+        }
+        let info = db.storage_info().await.unwrap().eviction;
+        assert_eq!((info.used, info.evicted), (3, 2));
+
+        //more keys than a sample holds, the latest key is never the best ranked
+        let db = open(
+            "evict_sampled",
+            MaxMemory::Keys { limit: 10 },
+            EvictionPolicy::AllkeysLru,
+        )
+        .await;
+        for i in 0..40 {
+            db.insert(format!("k{:02}", i), &i).await.unwrap();
+            sleep(Duration::from_millis(2)).await;
+        }
+        let StorageDB::Sled(s) = &db;
+        assert_eq!(s.evict_batch(10).unwrap(), 10);
+        assert_eq!(s.evict().unwrap(), 20);
+        assert_eq!(s.evict().unwrap(), 0);
+        assert_eq!(db.len().await.unwrap(), 10);
+        assert!(db.contains_key("k39").await.unwrap());
+        assert_eq!(db.storage_info().await.unwrap().eviction.used, 10);
+
+        //least frequently used keys go first
+        let db = open(
+            "evict_lfu",
+            MaxMemory::Keys { limit: 2 },
+            EvictionPolicy::AllkeysLfu,
+        )
+        .await;
+        for key in ["a", "b", "c"] {
+            db.insert(key, &1).await.unwrap();
+        }
+        for key in ["a", "a", "c"] {
+            db.get::<_, i32>(key).await.unwrap();
+        }
+        let StorageDB::Sled(s) = &db;
+        assert_eq!(s.evict().unwrap(), 1);
+        assert!(!db.contains_key("b").await.unwrap());
+
+        //keys expiring soonest go first, keys without a TTL are kept
+        let db = open(
+            "evict_volatile_ttl",
+            MaxMemory::Keys { limit: 2 },
+            EvictionPolicy::VolatileTtl,
+        )
+        .await;
+        for key in ["t1", "t2", "p"] {
+            db.insert(key, &1).await.unwrap();
+        }
+        db.expire("t1", 60_000).await.unwrap();
+        db.expire("t2", 30_000).await.unwrap();
+        let StorageDB::Sled(s) = &db;
+        assert_eq!(s.evict().unwrap(), 1);
+        assert!(!db.contains_key("t2").await.unwrap());
+        assert!(db.contains_key("t1").await.unwrap());
+        for key in ["q", "r"] {
+            db.insert(key, &1).await.unwrap();
+        }
+        assert_eq!(s.evict().unwrap(), 1);
+        assert_eq!(s.evict().unwrap(), 0);
+        assert_eq!(db.storage_info().await.unwrap().eviction.used, 3);
+
+        //writes adding keys are refused once the limit is reached
+        let db = open(
+            "evict_noeviction",
+            MaxMemory::Keys { limit: 2 },
+            EvictionPolicy::NoEviction,
+        )
+        .await;
+        db.insert("x", &1).await.unwrap();
+        db.counter_incr("y", 1).await.unwrap();
+        let err = db.insert("z", &1).await.unwrap_err();
+        assert!(matches!(
+            err,
+            StorageError::MaxMemory(MaxMemoryError { used: 2, limit: 2 })
+        ));
+        assert!(db.counter_incr("z", 1).await.is_err());
+        db.insert("x", &2).await.unwrap();
+        db.counter_incr("y", 1).await.unwrap();
+        db.remove("x").await.unwrap();
+        let StorageDB::Sled(s) = &db;
+        assert_eq!(s.evict().unwrap(), 0);
+        db.insert("z", &1).await.unwrap();
+
+        //overwrites only count the change in size
+        let db = open(
+            "evict_bytes",
+            MaxMemory::Bytes {
+                limit: convert::Bytesize::from(20),
+            },
+            EvictionPolicy::NoEviction,
+        )
+        .await;
+        db.insert("x", &"abcdefghij".to_string()).await.unwrap();
+        db.insert("x", &"jihgfedcba".to_string()).await.unwrap();
+        assert!(db.insert("y", &1).await.is_err());
+        db.insert("x", &"ab".to_string()).await.unwrap();
+        db.insert("y", &1).await.unwrap();
+        let info = db.storage_info().await.unwrap().eviction;
+        assert_eq!(info.used, 16);
+
+        //failed writes give their reserved usage back
+        let db = open(
+            "evict_release",
+            MaxMemory::Bytes {
+                limit: convert::Bytesize::from(1000),
+            },
+            EvictionPolicy::NoEviction,
+        )
+        .await;
+        db.set_quota(Some(Quota {
+            max_keys: None,
+            max_bytes: None,
+            max_value_size: Some(8),
+        }))
+        .unwrap();
+        assert!(matches!(
+            db.insert("x", &"abcdefghij".to_string()).await,
+            Err(StorageError::QuotaExceeded(_))
+        ));
+        assert_eq!(db.storage_info().await.unwrap().eviction.used, 0);
+        db.counter_set("y", 1).await.unwrap();
+        let used = db.storage_info().await.unwrap().eviction.used;
+        assert!(db.insert("y", &"abcdefghij".to_string()).await.is_err());
+        assert_eq!(db.storage_info().await.unwrap().eviction.used, used);
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_namespaces() {
+        let path = "./db/list/namespaces".to_string();
+        let _ = std::fs::remove_dir_all(&path);
+        let cfg = Config {
+            path,
+            ..Default::default()
+        };
+        let mut db = init_db(&cfg).await.unwrap();
+        let mut a = db.namespace("a").unwrap();
+        let b = db.namespace("b").unwrap();
+        assert_eq!(a.namespace_name(), Some(b"a".as_slice()));
+        assert_eq!(db.namespace_name(), None);
+
+        db.insert("k", &0).await.unwrap();
+        a.insert("k", &1).await.unwrap();
+        a.insert("only_a", &1).await.unwrap();
+        assert_eq!(db.get::<_, i32>("k").await.unwrap(), Some(0));
+        assert_eq!(a.get::<_, i32>("k").await.unwrap(), Some(1));
+        assert_eq!(b.get::<_, i32>("k").await.unwrap(), None);
+        assert_eq!(db.len().await.unwrap(), 1);
+        assert_eq!(a.len().await.unwrap(), 2);
+
+        a.map("m", None)
+            .await
+            .unwrap()
+            .insert("f", &1)
+            .await
+            .unwrap();
+        a.list("l", None).await.unwrap().push(&1).await.unwrap();
+        assert!(a.map_contains_key("m").await.unwrap());
+        assert!(!db.map_contains_key("m").await.unwrap());
+        assert!(!b.list_contains_key("l").await.unwrap());
+        {
+            let mut iter = db.map_iter().await.unwrap();
+            assert!(iter.next().await.is_none());
+        }
+        let mut keys = Vec::new();
+        {
+            let mut iter = a.scan("*").await.unwrap();
+            while let Some(key) = iter.next().await {
+                keys.push(key.unwrap());
+            }
+        }
+        assert_eq!(keys, vec![b"k".to_vec(), b"only_a".to_vec()]);
+
+        //keyspace events are only delivered to subscribers of the same namespace
+        let mut sub = a.subscribe("*");
+        db.insert("root_only", &1).await.unwrap();
+        a.insert("a_only", &1).await.unwrap();
+        let event = sub.next().await.unwrap().unwrap();
+        assert_eq!(event.key, b"a_only".to_vec());
+        assert_eq!(event.namespace, Some(b"a".to_vec()));
+
+        //expired keys are cleaned up per namespace
+        a.insert("t", &1).await.unwrap();
+        a.expire("t", 1).await.unwrap();
+        sleep(Duration::from_millis(10)).await;
+        let StorageDB::Sled(s) = &db;
+        let removed: usize = s
+            .namespace_handles()
+            .unwrap()
+            .iter()
+            .map(|h| h.cleanup(100))
+            .sum();
+        assert_eq!(removed, 1);
+
+        assert_eq!(db.namespace_names(), vec![b"a".to_vec(), b"b".to_vec()]);
+        let stats = db.namespaces().await.unwrap();
+        let counts = stats
+            .iter()
+            .map(|ns| (ns.name.clone(), ns.kv_keys, ns.maps, ns.lists, ns.ttl_keys))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                (None, 2, 0, 0, 0),
+                (Some(b"a".to_vec()), 3, 1, 1, 0),
+                (Some(b"b".to_vec()), 0, 0, 0, 0),
+            ]
+        );
+        assert_eq!(
+            a.storage_info().await.unwrap().namespace,
+            Some(b"a".to_vec())
+        );
+
+        //flushing a namespace leaves the others untouched
+        a.flush_namespace().await.unwrap();
+        assert_eq!(a.len().await.unwrap(), 0);
+        assert!(!a.map_contains_key("m").await.unwrap());
+        assert_eq!(db.get::<_, i32>("k").await.unwrap(), Some(0));
+        assert_eq!(db.len().await.unwrap(), 2);
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_quotas() {
+        let path = "./db/list/quotas".to_string();
+        let _ = std::fs::remove_dir_all(&path);
+        let cfg = Config {
+            path,
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+        let t = db.namespace("tenant").unwrap();
+        let quota = Quota {
+            max_keys: Some(3),
+            max_bytes: None,
+            max_value_size: Some(40),
+        };
+        t.set_quota(Some(quota)).unwrap();
+
+        let quota_err = |e: StorageError| match e {
+            StorageError::QuotaExceeded(e) => e,
+            e => panic!("unexpected error: {:?}", e),
+        };
+        t.insert("k1", &1).await.unwrap();
+        t.insert("k2", &2).await.unwrap();
+        let m = t.map("m", None).await.unwrap();
+        m.insert("f", &3).await.unwrap();
+        let e = quota_err(t.insert("k3", &4).await.unwrap_err());
+        assert_eq!(
+            (e.namespace.as_deref(), e.limit, e.used, e.max),
+            (Some(b"tenant".as_slice()), QuotaLimit::Keys, 3, 3)
+        );
+        let l = t.list("l", None).await.unwrap();
+        assert!(quota_err(l.push(&1).await.unwrap_err()).limit == QuotaLimit::Keys);
+        assert!(t.batch_insert(vec![(b"k4".to_vec(), 1)]).await.is_err());
+        assert!(t.counter_incr("c", 1).await.is_err());
+        //overwrites do not add keys, other namespaces are not limited
+        t.insert("k1", &5).await.unwrap();
+        m.insert("f", &6).await.unwrap();
+        for i in 0..5 {
+            db.insert(format!("k{}", i), &i).await.unwrap();
+        }
+
+        //removed entries free up the quota
+        t.remove("k2").await.unwrap();
+        l.push(&1).await.unwrap();
+        assert_eq!(t.quota_usage().await.unwrap().keys, 3);
+
+        //failed writes give their reservation back, removals free it without a recount
+        m.remove("f").await.unwrap();
+        assert!(matches!(
+            l.push_limit(&2, 1, false).await,
+            Err(StorageError::ListFull)
+        ));
+        t.insert("k2", &2).await.unwrap();
+        assert!(t.insert("k3", &3).await.is_err());
+        assert_eq!(l.pop::<i32>().await.unwrap(), Some(1));
+        t.insert("k3", &3).await.unwrap();
+        assert!(t.insert("k4", &4).await.is_err());
+        assert_eq!(t.quota_usage().await.unwrap().keys, 3);
+
+        let e = quota_err(m.insert("big", &vec![0u8; 64]).await.unwrap_err());
+        assert_eq!((e.limit, e.used, e.max), (QuotaLimit::ValueSize, 72, 40));
+
+        let usage = t.quota_usage().await.unwrap();
+        t.set_quota(Some(Quota {
+            max_bytes: Some(usage.bytes + 6),
+            ..Default::default()
+        }))
+        .unwrap();
+        let e = quota_err(t.insert("s", &"abc".to_string()).await.unwrap_err());
+        assert_eq!(e.limit, QuotaLimit::Bytes);
+        t.insert("n", &7).await.unwrap();
+
+        //quotas are stored in the database
+        db.close().await.unwrap();
+        drop((t, m, l, db));
+        let db = init_db(&cfg).await.unwrap();
+        let t = db.namespace("tenant").unwrap();
+        assert_eq!(t.quota().unwrap().max_bytes, Some(usage.bytes + 6));
+        assert_eq!(db.quota(), None);
+        assert!(t.insert("s", &"abc".to_string()).await.is_err());
+        t.set_quota(None).unwrap();
+        t.insert("s", &"abc".to_string()).await.unwrap();
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_wrong_type() {
+        let _ = std::fs::remove_dir_all("./db/list/wrong_type");
+        let db = get_db("wrong_type").await;
+        let wrong_type = |res: Result<()>, expected: KeyType, actual: KeyType| {
+            assert!(
+                matches!(res, Err(StorageError::WrongType { expected: e, actual: a, .. }) if e == expected && a == actual)
+            )
+        };
+
+        db.insert("k", &1).await.unwrap();
+        let m = db.map("m", None).await.unwrap();
+        m.insert("f", &1).await.unwrap();
+        let l = db.list("l", None).await.unwrap();
+        l.push(&1).await.unwrap();
+        assert_eq!(db.type_of("k").await.unwrap(), Some(KeyType::KV));
+        assert_eq!(db.type_of("m").await.unwrap(), Some(KeyType::Map));
+        assert_eq!(db.type_of("l").await.unwrap(), Some(KeyType::List));
+        assert_eq!(db.type_of("none").await.unwrap(), None);
+
+        //a name holds one type
+        wrong_type(db.insert("m", &1).await, KeyType::KV, KeyType::Map);
+        wrong_type(db.counter_incr("l", 1).await, KeyType::KV, KeyType::List);
+        wrong_type(
+            db.get::<_, i32>("m").await.map(|_| ()),
+            KeyType::KV,
+            KeyType::Map,
+        );
+        wrong_type(
+            db.map("k", None).await.map(|_| ()),
+            KeyType::Map,
+            KeyType::KV,
+        );
+        wrong_type(
+            db.list("m", None).await.map(|_| ()),
+            KeyType::List,
+            KeyType::Map,
+        );
+        wrong_type(
+            db.stream("l", None).await.map(|_| ()),
+            KeyType::Stream,
+            KeyType::List,
+        );
+        wrong_type(
+            db.batch_insert(vec![(b"k2".to_vec(), 2), (b"l".to_vec(), 2)])
+                .await,
+            KeyType::KV,
+            KeyType::List,
+        );
+        assert_eq!(db.get::<_, i32>("k2").await.unwrap(), None);
+
+        //handles opened on a free name check again when writing
+        let mut m2 = db.map("k2", None).await.unwrap();
+        db.insert("k2", &2).await.unwrap();
+        wrong_type(m2.insert("f", &1).await, KeyType::Map, KeyType::KV);
+
+        //and when reading
+        wrong_type(
+            m2.get::<_, i32>("f").await.map(|_| ()),
+            KeyType::Map,
+            KeyType::KV,
+        );
+        wrong_type(m2.is_empty().await.map(|_| ()), KeyType::Map, KeyType::KV);
+        wrong_type(
+            m2.iter::<i32>().await.map(|_| ()),
+            KeyType::Map,
+            KeyType::KV,
+        );
+        let l2 = db.list("k3", None).await.unwrap();
+        let s2 = db.stream("k3", None).await.unwrap();
+        db.insert("k3", &3).await.unwrap();
+        wrong_type(
+            l2.all::<i32>().await.map(|_| ()),
+            KeyType::List,
+            KeyType::KV,
+        );
+        wrong_type(l2.len().await.map(|_| ()), KeyType::List, KeyType::KV);
+        wrong_type(
+            s2.range::<i32>(StreamId::MIN, StreamId::MAX, None)
+                .await
+                .map(|_| ()),
+            KeyType::Stream,
+            KeyType::KV,
+        );
+        wrong_type(s2.len().await.map(|_| ()), KeyType::Stream, KeyType::KV);
+
+        //a removed or expired name is free again
+        db.remove("k").await.unwrap();
+        db.list("k", None).await.unwrap().push(&1).await.unwrap();
+        assert_eq!(db.type_of("k").await.unwrap(), Some(KeyType::List));
+        db.expire("k2", 0).await.unwrap();
+        assert_eq!(db.type_of("k2").await.unwrap(), None);
+        m2.insert("f", &1).await.unwrap();
+        assert_eq!(db.type_of("k2").await.unwrap(), Some(KeyType::Map));
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_sliding_ttl() {
+        let _ = std::fs::remove_dir_all("./db/list/sliding_ttl");
+        let db = get_db("sliding_ttl").await;
+
+        db.insert_sliding("session", &1, 400).await.unwrap();
+        let m = db.map_sliding("m", 400).await.unwrap();
+        m.insert("f", &1).await.unwrap();
+        let l = db.list_sliding("l", 400).await.unwrap();
+        for i in 0..10 {
+            l.push(&i).await.unwrap();
+        }
+        db.insert_sliding("idle", &1, 400).await.unwrap();
+
+        //reads restart the time-to-live, well past the first deadline
+        for _ in 0..5 {
+            sleep(Duration::from_millis(200)).await;
+            assert_eq!(db.get::<_, i32>("session").await.unwrap(), Some(1));
+            assert_eq!(m.get::<_, i32>("f").await.unwrap(), Some(1));
+            assert!(l.pop::<i32>().await.unwrap().is_some());
+            assert!(db.ttl("session").await.unwrap().unwrap() > 300);
+        }
+        assert_eq!(db.get::<_, i32>("idle").await.unwrap(), None);
+
+        //without reads they expire
+        sleep(Duration::from_millis(600)).await;
+        assert_eq!(db.get::<_, i32>("session").await.unwrap(), None);
+        assert_eq!(m.get::<_, i32>("f").await.unwrap(), None);
+        assert_eq!(l.pop::<i32>().await.unwrap(), None);
+
+        //a fixed expiration replaces the sliding one
+        db.insert_sliding("session", &2, 400).await.unwrap();
+        db.expire("session", 300).await.unwrap();
+        for _ in 0..2 {
+            sleep(Duration::from_millis(200)).await;
+            db.get::<_, i32>("session").await.unwrap();
+        }
+        assert_eq!(db.get::<_, i32>("session").await.unwrap(), None);
+    }
+
+    #[cfg(feature = "ttl")]
+    #[tokio::main]
+    #[test]
+    async fn test_expire_hooks() {
+        use crate::ExpiredValue;
+        let _ = std::fs::remove_dir_all("./db/list/expire_hooks");
+        let db = get_db("expire_hooks").await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        db.on_expire("hooked/*", move |key, key_type, value| {
+            let tx = tx.clone();
+            async move {
+                tx.send((key, key_type, value)).unwrap();
+            }
+        });
+
+        db.insert("hooked/k", &1).await.unwrap();
+        db.expire("hooked/k", 100).await.unwrap();
+        let m = db.map("hooked/m", Some(100)).await.unwrap();
+        m.insert("f", &2).await.unwrap();
+        db.insert("other", &3).await.unwrap();
+        db.expire("other", 100).await.unwrap();
+        sleep(Duration::from_millis(200)).await;
+
+        //the plain cleanup leaves hooked entries to the hooks
+        let StorageDB::Sled(sled_db) = &db;
+        assert_eq!(sled_db.cleanup(100), 1);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(sled_db.run_expire_hooks(100).await, 2);
+
+        let mut expired = [rx.try_recv().unwrap(), rx.try_recv().unwrap()];
+        expired.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(expired[0].0, b"hooked/k".to_vec());
+        assert_eq!(expired[0].1, KeyType::KV);
+        assert_eq!(
+            expired[0].2,
+            Some(ExpiredValue::KV(bincode::serialize(&1).unwrap()))
+        );
+        assert_eq!(expired[1].0, b"hooked/m".to_vec());
+        assert_eq!(expired[1].1, KeyType::Map);
+        assert_eq!(
+            expired[1].2,
+            Some(ExpiredValue::Map(vec![(
+                b"f".to_vec(),
+                bincode::serialize(&2).unwrap()
+            )]))
+        );
+
+        assert_eq!(sled_db.run_expire_hooks(100).await, 0);
+        assert!(!db.contains_key("hooked/k").await.unwrap());
+        assert!(!db.map_contains_key("hooked/m").await.unwrap());
+    }
+
+    #[cfg(feature = "ttl")]
+    #[tokio::main]
+    #[test]
+    async fn test_expire_hook_retry() {
+        let _ = std::fs::remove_dir_all("./db/list/expire_hook_retry");
+        let clock = ManualClock::new(1_000_000);
+        let cfg = Config {
+            path: "./db/list/expire_hook_retry".into(),
+            clock: std::sync::Arc::new(clock.clone()),
+            cleanup: CleanupPolicy {
+                enabled: false,
+                hook_timeout_ms: 50,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let calls1 = calls.clone();
+        db.on_expire("bad/*", move |_, _, _| {
+            calls1.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move { panic!("hook failed") }
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        db.on_expire("good/*", move |key, _, _| {
+            let tx = tx.clone();
+            async move {
+                tx.send(key).unwrap();
+            }
+        });
+
+        //the failing entry expires first
+        db.insert("bad/k", &1).await.unwrap();
+        db.expire("bad/k", 100).await.unwrap();
+        for key in ["good/1", "good/2"] {
+            db.insert(key, &1).await.unwrap();
+            db.expire(key, 200).await.unwrap();
+        }
+        clock.advance(Duration::from_secs(1));
+
+        //it waits behind the healthy entries instead of taking every batch
+        let StorageDB::Sled(sled_db) = &db;
+        assert_eq!(sled_db.run_expire_hooks(1).await, 0);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(sled_db.run_expire_hooks(1).await, 1);
+        assert_eq!(sled_db.run_expire_hooks(1).await, 1);
+        assert_eq!(rx.try_recv().unwrap(), b"good/1".to_vec());
+        assert_eq!(rx.try_recv().unwrap(), b"good/2".to_vec());
+        assert_eq!(sled_db.run_expire_hooks(1).await, 0);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        //and is retried once its delay passed, the data is kept until delivered
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(sled_db.run_expire_hooks(1).await, 0);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(sled_db.cleanup(100), 0);
+        assert!(sled_db.kv_tree.contains_key("bad/k").unwrap());
+
+        //a hook that does not complete in time fails the same way
+        let slow_calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let slow_calls1 = slow_calls.clone();
+        db.on_expire("slow/*", move |_, _, _| {
+            slow_calls1.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            std::future::pending::<()>()
+        });
+        db.insert("slow/k", &1).await.unwrap();
+        db.expire("slow/k", 100).await.unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sled_db.run_expire_hooks(10).await, 0);
+        assert_eq!(sled_db.run_expire_hooks(10).await, 0);
+        assert_eq!(slow_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(sled_db.kv_tree.contains_key("slow/k").unwrap());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sled_db.run_expire_hooks(10).await, 0);
+        assert_eq!(slow_calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[cfg(feature = "ttl")]
+    #[tokio::main]
+    #[test]
+    async fn test_cleanup_policy() {
+        let _ = std::fs::remove_dir_all("./db/list/cleanup_policy");
+        let cfg = Config {
+            path: "./db/list/cleanup_policy".into(),
+            cleanup: CleanupPolicy {
+                enabled: false,
+                interval_ms: 50,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+        let StorageDB::Sled(sled_db) = &db;
+        let handle = db.cleanup_handle();
+        assert!(handle.is_paused());
+
+        db.insert("k", &1).await.unwrap();
+        db.expire("k", 50).await.unwrap();
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(sled_db.cleanup_stats().snapshot().rounds, 0);
+        assert_eq!(sled_db.key_expire_tree.len(), 1);
+
+        //a triggered round runs while paused
+        handle.trigger();
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(sled_db.cleanup_stats().snapshot().rounds, 1);
+        assert_eq!(sled_db.key_expire_tree.len(), 0);
+
+        handle.resume();
+        sleep(Duration::from_millis(200)).await;
+        assert!(sled_db.cleanup_stats().snapshot().rounds > 2);
+        handle.stop();
+        sleep(Duration::from_millis(100)).await;
+        let rounds = sled_db.cleanup_stats().snapshot().rounds;
+        handle.trigger();
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(sled_db.cleanup_stats().snapshot().rounds, rounds);
+        db.close().await.unwrap();
+
+        //custom tasks get the database and the handle
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = std::sync::Mutex::new(tx);
+        let cfg = Config {
+            path: "./db/list/cleanup_custom".into(),
+            cleanup_f: CleanupFun::from_fn(move |db, handle| {
+                tx.lock()
+                    .unwrap()
+                    .send((db.namespace_name().is_none(), handle.is_paused()))
+                    .unwrap();
+            }),
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+        assert_eq!(rx.try_recv().unwrap(), (true, false));
+        db.close().await.unwrap();
+    }
+
+    #[cfg(feature = "ttl")]
+    #[tokio::main]
+    #[test]
+    async fn test_manual_clock() {
+        let _ = std::fs::remove_dir_all("./db/list/manual_clock");
+        let clock = ManualClock::new(1_000_000);
+        let cfg = Config {
+            path: "./db/list/manual_clock".into(),
+            clock: std::sync::Arc::new(clock.clone()),
+            ..Default::default()
+        };
+        let db = init_db(&cfg).await.unwrap();
+
+        db.insert("k", &1).await.unwrap();
+        db.expire("k", 60_000).await.unwrap();
+        let m = db.map("m", Some(30_000)).await.unwrap();
+        m.insert("f", &1).await.unwrap();
+        m.insert("g", &2).await.unwrap();
+        m.expire_field("g", 10_000).await.unwrap();
+        db.insert_sliding("s", &1, 20_000).await.unwrap();
+        assert_eq!(db.ttl("k").await.unwrap(), Some(60_000));
+
+        clock.advance(Duration::from_secs(15));
+        assert_eq!(db.ttl("k").await.unwrap(), Some(45_000));
+        assert_eq!(m.get::<_, i32>("g").await.unwrap(), None);
+        assert_eq!(m.get::<_, i32>("f").await.unwrap(), Some(1));
+        assert_eq!(db.get::<_, i32>("s").await.unwrap(), Some(1));
+
+        clock.advance(Duration::from_secs(19));
+        assert_eq!(m.get::<_, i32>("f").await.unwrap(), None);
+        assert_eq!(db.get::<_, i32>("s").await.unwrap(), Some(1));
+        assert_eq!(db.get::<_, i32>("k").await.unwrap(), Some(1));
+
+        //cleanup removes what the clock says has expired, the map field included
+        clock.advance(Duration::from_secs(30));
+        let StorageDB::Sled(sled_db) = &db;
+        //each pass handles at most `limit` entries
+        assert_eq!(sled_db.cleanup(2), 2);
+        assert_eq!(sled_db.cleanup(2), 2);
+        assert_eq!(sled_db.cleanup(2), 0);
+        assert!(!db.contains_key("k").await.unwrap());
+        assert!(!db.contains_key("s").await.unwrap());
+        assert!(!db.map_contains_key("m").await.unwrap());
+
+        //stream IDs and consumer group delivery times follow the clock too
+        let now = 1_000_000 + 64_000;
+        let s = db.stream("clock_stream", None).await.unwrap();
+        let id = s.add(&1, None).await.unwrap();
+        assert_eq!(id, StreamId::new(now as u64, 0));
+        s.group_create("g", Some(StreamId::MIN)).await.unwrap();
+        s.read_group::<_, _, i32>("g", "c1", None, None)
+            .await
+            .unwrap();
+        assert_eq!(s.pending("g").await.unwrap()[0].delivered_at, now);
+        let claimed = s
+            .claim::<_, _, i32>("g", "c2", 5_000, vec![id])
+            .await
+            .unwrap();
+        assert!(claimed.is_empty());
+        clock.advance(Duration::from_secs(5));
+        let claimed = s
+            .claim::<_, _, i32>("g", "c2", 5_000, vec![id])
+            .await
+            .unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(s.pending("g").await.unwrap()[0].delivered_at, now + 5_000);
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_dump_load_jsonl() {
+        let src = get_db("dump_src").await;
+        src.insert("dump/k1", &"hello".to_string()).await.unwrap();
+        src.insert(vec![0u8, 159, 146, 150], &7u64).await.unwrap();
+        src.counter_set("dump/counter", 42).await.unwrap();
+        src.insert("dump/ttl", &"t".to_string()).await.unwrap();
+        src.expire("dump/ttl", 60_000).await.unwrap();
+        src.insert_sliding("dump/sliding", &"s".to_string(), 60_000)
+            .await
+            .unwrap();
+        let m = src.map("dump_map", None).await.unwrap();
+        m.clear().await.unwrap();
+        m.insert("f1", &"one".to_string()).await.unwrap();
+        m.insert("f2", &"two".to_string()).await.unwrap();
+        m.insert("f3", &"three".to_string()).await.unwrap();
+        m.expire_field("f2", 60_000).await.unwrap();
+        m.expire_field("f3", 1).await.unwrap();
+        let l = src.list("dump_list", None).await.unwrap();
+        l.clear().await.unwrap();
+        l.push(&"a".to_string()).await.unwrap();
+        l.push(&"b".to_string()).await.unwrap();
+        let s = src.stream("dump_stream", None).await.unwrap();
+        s.clear().await.unwrap();
+        let id1 = s.add(&"e1".to_string(), Some(10)).await.unwrap();
+        let id2 = s.add(&"e2".to_string(), Some(10)).await.unwrap();
+        s.group_create("g", Some(StreamId::MIN)).await.unwrap();
+        s.read_group::<_, _, String>("g", "c", Some(1), None)
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(5)).await;
+
+        let path = "./db/list/dump.jsonl";
+        let file = std::fs::File::create(path).unwrap();
+        let lines = src.dump_jsonl::<String, _>(file).await.unwrap();
+        let text = std::fs::read_to_string(path).unwrap();
+        assert_eq!(text.lines().count() as u64, lines);
+        for line in text.lines() {
+            serde_json::from_str::<serde_json::Value>(line).unwrap();
+        }
+        //values of the dumped type are decoded, expired fields are left out
+        assert!(text.contains(r#""type":"map","name":"dump_map""#));
+        assert!(text.contains(r#"["f1",{"value":"one"}]"#));
+        assert!(!text.contains("three"));
+        assert!(text.contains(r#"{"type":"kv","key":"dump/k1","value":{"value":"hello"}}"#));
+        assert!(text.contains(r#""sliding":60000"#));
+
+        let dst = get_db("dump_dst").await;
+        dst.list_remove("dump_list").await.unwrap();
+        dst.stream_remove("dump_stream").await.unwrap();
+        let reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+        assert_eq!(dst.load_jsonl::<String, _>(reader).await.unwrap(), lines);
+        assert_eq!(
+            dst.get::<_, String>("dump/k1").await.unwrap(),
+            Some("hello".to_string())
+        );
+        assert_eq!(
+            dst.get::<_, u64>(vec![0u8, 159, 146, 150]).await.unwrap(),
+            Some(7)
+        );
+        assert_eq!(dst.counter_get("dump/counter").await.unwrap(), Some(42));
+        assert!(dst.ttl("dump/ttl").await.unwrap().unwrap() <= 60_000);
+        //a sliding expiration is restarted by reads after loading
+        sleep(Duration::from_millis(50)).await;
+        dst.get::<_, String>("dump/sliding").await.unwrap();
+        assert!(dst.ttl("dump/sliding").await.unwrap().unwrap() > 59_990);
+        let m = dst.map("dump_map", None).await.unwrap();
+        assert_eq!(
+            m.get::<_, String>("f2").await.unwrap(),
+            Some("two".to_string())
+        );
+        assert!(m.field_ttl("f2").await.unwrap().unwrap() <= 60_000);
+        assert_eq!(m.field_ttl("f1").await.unwrap(), None);
+        assert_eq!(m.get::<_, String>("f3").await.unwrap(), None);
+        let l = dst.list("dump_list", None).await.unwrap();
+        assert_eq!(
+            l.all::<String>().await.unwrap(),
+            vec!["a".to_string(), "b".to_string()]
+        );
+        let s = dst.stream("dump_stream", None).await.unwrap();
+        let entries = s
+            .range::<String>(StreamId::MIN, StreamId::MAX, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.id, e.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(id1, "e1"), (id2, "e2")]
+        );
+        assert_eq!(s.last_id().await.unwrap(), Some(id2));
+        let pending = s.pending("g").await.unwrap();
+        assert_eq!(
+            pending
+                .iter()
+                .map(|p| (p.id, p.consumer.as_slice()))
+                .collect::<Vec<_>>(),
+            vec![(id1, b"c".as_slice())]
+        );
+        let next = s
+            .read_group::<_, _, String>("g", "c", None, None)
+            .await
+            .unwrap();
+        assert_eq!(next.iter().map(|e| e.id).collect::<Vec<_>>(), vec![id2]);
+
+        let bad = "{\"type\":\"kv\",\"key\":\"x\"}\n";
+        assert!(
+            dst.load_jsonl::<String, _>(std::io::Cursor::new(bad))
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::main]
     // #[test]
     async fn test_len() {
@@ -149,7 +1803,7 @@ mod tests {
         db.insert("foo/len/3", &3).await.unwrap();
         db.insert("foo/len/4", &4).await.unwrap();
 
-        db.expire_at("foo/len/3", timestamp_millis() + 1 * 1000)
+        db.expire_at("foo/len/3", timestamp_millis() + 1000)
             .await
             .unwrap();
         db.expire("foo/len/4", 1000 * 2).await.unwrap();
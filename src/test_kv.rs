@@ -1,5 +1,5 @@
 #[cfg(test)]
-#[allow(unused_imports)]
+#[allow(unused_imports, clippy::let_and_return)]
 mod tests {
     use super::super::sled_storage::{SledStorageDB, SledStorageList, SledStorageMap};
     use super::super::*;
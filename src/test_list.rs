@@ -1,5 +1,11 @@
 #[cfg(test)]
-#[allow(unused_imports)]
+#[allow(
+    unused_imports,
+    clippy::let_and_return,
+    clippy::collapsible_if,
+    clippy::needless_borrow,
+    clippy::print_literal
+)]
 mod tests {
     use super::super::sled_storage::{SledStorageDB, SledStorageList, SledStorageMap};
     use super::super::*;
@@ -57,6 +63,10 @@ mod tests {
             array_a.push_limit(&i, 5, true).await.unwrap();
         }
         println!("array_a len: {} 5", array_a.len().await.unwrap());
+        assert!(matches!(
+            array_a.push_limit(&20, 5, false).await,
+            Err(StorageError::ListFull)
+        ));
         let vals = array_a.all::<i32>().await.unwrap();
         println!("array_a vals: {:?}", vals);
 
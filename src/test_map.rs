@@ -1,5 +1,5 @@
 #[cfg(test)]
-#[allow(unused_imports)]
+#[allow(unused_imports, clippy::let_and_return)]
 mod tests {
     use super::super::sled_storage::{SledStorageDB, SledStorageList, SledStorageMap};
     use super::super::*;
@@ -200,4 +200,98 @@ mod tests {
             }
         }
     }
+
+    #[tokio::main]
+    #[test]
+    async fn test_map_name_separators() {
+        let mut db = get_db("map_name_separators").await;
+        let mut a = db.map("a", None).await.unwrap();
+        let b = db.map("a@__item@b", None).await.unwrap();
+        a.clear().await.unwrap();
+        b.clear().await.unwrap();
+        a.insert("x@__count@", &1).await.unwrap();
+        a.insert("y", &2).await.unwrap();
+        b.insert("z", &3).await.unwrap();
+
+        assert_eq!(a.len().await.unwrap(), 2);
+        assert_eq!(b.len().await.unwrap(), 1);
+        assert_eq!(a.get::<_, i32>("x@__count@").await.unwrap(), Some(1));
+        assert_eq!(a.get::<_, i32>("b@__item@z").await.unwrap(), None);
+        assert_eq!(b.get::<_, i32>("z").await.unwrap(), Some(3));
+
+        let mut keys = Vec::new();
+        let mut iter = a.iter::<i32>().await.unwrap();
+        while let Some(item) = iter.next().await {
+            keys.push(item.unwrap().0);
+        }
+        drop(iter);
+        assert_eq!(keys, vec![b"x@__count@".to_vec(), b"y".to_vec()]);
+
+        let mut names = Vec::new();
+        let mut map_iter = db.map_iter().await.unwrap();
+        while let Some(map) = map_iter.next().await {
+            names.push(map.unwrap().name().to_vec());
+        }
+        names.sort();
+        assert_eq!(names, vec![b"a".to_vec(), b"a@__item@b".to_vec()]);
+    }
+
+    #[cfg(feature = "ttl")]
+    #[tokio::main]
+    #[test]
+    async fn test_map_field_expire() {
+        let _ = std::fs::remove_dir_all("./db/map/map_field_expire");
+        let db = get_db("map_field_expire").await;
+        let mut m = db.map("tokens", None).await.unwrap();
+        m.insert("phone", &1).await.unwrap();
+        m.insert("laptop", &2).await.unwrap();
+        m.insert("tablet", &3).await.unwrap();
+
+        assert!(!m.expire_field("none", 100).await.unwrap());
+        assert!(m.expire_field("phone", 100).await.unwrap());
+        assert!(m.expire_field("tablet", 100).await.unwrap());
+        assert!(m.field_ttl("phone").await.unwrap().unwrap() <= 100);
+        assert_eq!(m.field_ttl("laptop").await.unwrap(), None);
+        assert!(m.persist_field("tablet").await.unwrap());
+        assert!(!m.persist_field("tablet").await.unwrap());
+        assert_eq!(m.field_ttl("tablet").await.unwrap(), None);
+
+        sleep(Duration::from_millis(150)).await;
+        assert_eq!(m.get::<_, i32>("phone").await.unwrap(), None);
+        assert!(!m.contains_key("phone").await.unwrap());
+        assert_eq!(m.field_ttl("phone").await.unwrap(), None);
+        assert_eq!(m.len().await.unwrap(), 2);
+        let mut keys = Vec::new();
+        let mut iter = m.key_iter().await.unwrap();
+        while let Some(key) = iter.next().await {
+            keys.push(key.unwrap());
+        }
+        drop(iter);
+        assert_eq!(keys, vec![b"laptop".to_vec(), b"tablet".to_vec()]);
+        let mut items = Vec::new();
+        let mut iter = m.prefix_iter::<_, i32>("ph").await.unwrap();
+        while let Some(item) = iter.next().await {
+            items.push(item.unwrap());
+        }
+        drop(iter);
+        assert!(items.is_empty());
+
+        //a new value drops the expiration
+        m.insert("laptop", &4).await.unwrap();
+        m.expire_field("laptop", 50).await.unwrap();
+        m.insert("laptop", &5).await.unwrap();
+        assert_eq!(m.field_ttl("laptop").await.unwrap(), None);
+
+        //cleanup reclaims expired items
+        let StorageDB::Sled(sled_db) = &db;
+        assert_eq!(sled_db.cleanup(100), 1);
+        assert_eq!(m.len().await.unwrap(), 2);
+        assert!(!m.is_empty().await.unwrap());
+        m.expire_field("laptop", 10).await.unwrap();
+        m.expire_field("tablet", 10).await.unwrap();
+        sleep(Duration::from_millis(50)).await;
+        assert!(m.is_empty().await.unwrap());
+        assert_eq!(sled_db.cleanup(100), 2);
+        assert_eq!(m.len().await.unwrap(), 0);
+    }
 }
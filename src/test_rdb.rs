@@ -0,0 +1,216 @@
+#[cfg(test)]
+#[allow(unused_imports)]
+mod tests {
+    use super::super::rdb::crc64;
+    use super::super::*;
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    async fn get_db(name: &str) -> StorageDB {
+        let cfg = Config {
+            path: format!("./db/rdb/{}", name),
+            ..Default::default()
+        };
+
+        init_db(&cfg).await.unwrap()
+    }
+
+    /// Minimal RDB writer for building test files
+    struct RdbWriter(Vec<u8>);
+
+    impl RdbWriter {
+        fn new(version: u32) -> Self {
+            RdbWriter(format!("REDIS{:04}", version).into_bytes())
+        }
+
+        fn byte(&mut self, b: u8) -> &mut Self {
+            self.0.push(b);
+            self
+        }
+
+        fn raw(&mut self, data: &[u8]) -> &mut Self {
+            self.0.extend_from_slice(data);
+            self
+        }
+
+        fn len(&mut self, n: usize) -> &mut Self {
+            if n < 64 {
+                self.byte(n as u8)
+            } else if n < 16384 {
+                self.byte(0x40 | (n >> 8) as u8).byte(n as u8)
+            } else {
+                self.byte(0x80).raw(&(n as u32).to_be_bytes())
+            }
+        }
+
+        fn string(&mut self, s: &[u8]) -> &mut Self {
+            self.len(s.len()).raw(s)
+        }
+
+        fn key(&mut self, value_type: u8, key: &str) -> &mut Self {
+            self.byte(value_type).string(key.as_bytes())
+        }
+
+        fn finish(&mut self) -> Vec<u8> {
+            self.byte(0xff);
+            let crc = crc64(0, &self.0);
+            self.raw(&crc.to_le_bytes());
+            self.0.clone()
+        }
+    }
+
+    /// Builds a listpack from encoded entries
+    fn listpack(entries: &[&[u8]]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for e in entries {
+            body.extend_from_slice(e);
+            //backlen, all test entries are short
+            body.push(e.len() as u8);
+        }
+        body.push(0xff);
+        let mut out = ((body.len() + 6) as u32).to_le_bytes().to_vec();
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend(body);
+        out
+    }
+
+    /// Encodes a short listpack string entry
+    fn lp_str(s: &str) -> Vec<u8> {
+        [&[0x80 | s.len() as u8][..], s.as_bytes()].concat()
+    }
+
+    async fn dump(db: &StorageDB, path: &str) -> BTreeMap<String, serde_json::Value> {
+        let file = std::fs::File::create(path).unwrap();
+        db.dump_jsonl::<Vec<u8>, _>(file).await.unwrap();
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let v: serde_json::Value = serde_json::from_str(line).unwrap();
+                let name = v.get("key").or_else(|| v.get("name")).unwrap();
+                (name.as_str().unwrap().to_string(), v)
+            })
+            .collect()
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_rdb_import() {
+        let now = chrono::Local::now().timestamp_millis();
+        let mut w = RdbWriter::new(11);
+        w.byte(0xfa).string(b"redis-ver").string(b"7.2.0");
+        w.byte(0xfe).len(0).byte(0xfb).len(16).len(1);
+        w.key(0, "s1").string(b"hello");
+        //int8 encoded string
+        w.key(0, "n1").byte(0xc0).byte(123);
+        w.byte(0xfc).raw(&(now + 60_000).to_le_bytes());
+        w.key(0, "ttl1").string(b"soon");
+        w.byte(0xfc).raw(&(now - 1).to_le_bytes());
+        w.key(0, "gone").string(b"late");
+        //lzf compressed "abcabcabc"
+        w.key(0, "lzf")
+            .byte(0xc3)
+            .len(6)
+            .len(9)
+            .raw(&[2, b'a', b'b', b'c', 128, 2]);
+        w.key(1, "l1").len(2).string(b"a").string(b"b");
+        w.key(2, "set1").len(2).string(b"x").string(b"y");
+        w.key(5, "z1")
+            .len(1)
+            .string(b"m1")
+            .raw(&1.5f64.to_le_bytes());
+        w.key(4, "h1").len(1).string(b"f").string(b"v");
+        let lp = listpack(&[&lp_str("f1"), &lp_str("v1"), &lp_str("f2"), &[7]]);
+        w.key(16, "h2").string(&lp);
+        //13 bit int -5 in a packed node, then a plain node
+        let lp = listpack(&[&lp_str("p"), &[0xdf, 0xfb]]);
+        w.key(18, "l2")
+            .len(2)
+            .len(2)
+            .string(&lp)
+            .len(1)
+            .string(b"plain");
+        let mut intset = 2u32.to_le_bytes().to_vec();
+        intset.extend_from_slice(&2u32.to_le_bytes());
+        intset.extend_from_slice(&1i16.to_le_bytes());
+        intset.extend_from_slice(&2i16.to_le_bytes());
+        w.key(11, "is").string(&intset);
+        //ziplist with a string and the immediate integer 5
+        let mut zl = vec![0u8; 10];
+        zl.extend_from_slice(&[0, 2, b'z', b'z', 4, 0xf6, 0xff]);
+        w.key(10, "zl").string(&zl);
+        //empty stream without consumer groups
+        w.key(21, "st").len(0).len(0).len(0).len(0);
+        w.len(0).len(0).len(0).len(0).len(0).len(0);
+        let data = w.finish();
+
+        let db = get_db("import").await;
+        let report = db.import_rdb(Cursor::new(data.clone())).await.unwrap();
+        assert_eq!(report.version, 11);
+        assert_eq!(report.strings, 4);
+        assert_eq!(report.lists, 3);
+        assert_eq!(report.sets, 2);
+        assert_eq!(report.sorted_sets, 1);
+        assert_eq!(report.hashes, 2);
+        assert_eq!(report.expired, 1);
+        assert_eq!(report.unsupported.get("stream"), Some(&1));
+        assert_eq!(report.imported(), 12);
+
+        let dumped = dump(&db, "./db/rdb/import.jsonl").await;
+        assert_eq!(dumped["s1"]["value"], "hello");
+        assert_eq!(dumped["n1"]["value"], "123");
+        assert_eq!(dumped["lzf"]["value"], "abcabcabc");
+        assert!(dumped["ttl1"]["ttl"].as_i64().unwrap() > 0);
+        assert!(!dumped.contains_key("gone"));
+        assert!(!dumped.contains_key("st"));
+        assert_eq!(dumped["l1"]["values"], serde_json::json!(["a", "b"]));
+        assert_eq!(
+            dumped["l2"]["values"],
+            serde_json::json!(["p", "-5", "plain"])
+        );
+        assert_eq!(dumped["zl"]["values"], serde_json::json!(["zz", "5"]));
+        assert_eq!(
+            dumped["h2"]["fields"],
+            serde_json::json!([["f1", "v1"], ["f2", "7"]])
+        );
+        assert_eq!(
+            dumped["is"]["fields"],
+            serde_json::json!([["1", ""], ["2", ""]])
+        );
+
+        let z1 = db.map("z1", None).await.unwrap();
+        assert_eq!(z1.get::<_, f64>("m1").await.unwrap(), Some(1.5));
+        let set1 = db.map("set1", None).await.unwrap();
+        assert!(set1.contains_key("y").await.unwrap());
+
+        //a corrupted checksum is reported
+        let mut bad = data.clone();
+        let last = bad.len() - 1;
+        bad[last] ^= 0xff;
+        assert!(db.import_rdb(Cursor::new(bad)).await.is_err());
+        let mut w = RdbWriter::new(12);
+        assert!(db.import_rdb(Cursor::new(w.finish())).await.is_err());
+
+        //a key of a later database overwrites the earlier one and is reported
+        let mut w = RdbWriter::new(11);
+        w.byte(0xfe).len(0);
+        w.key(0, "dup").string(b"db0");
+        w.key(0, "db0_only").string(b"x");
+        w.byte(0xfe).len(1);
+        w.key(0, "dup").string(b"db1");
+        let report = db.import_rdb(Cursor::new(w.finish())).await.unwrap();
+        assert_eq!(report.strings, 3);
+        assert_eq!(report.merged, 1);
+        let dumped = dump(&db, "./db/rdb/merged.jsonl").await;
+        assert_eq!(dumped["dup"]["value"], "db1");
+
+        //a corrupt length fails without allocating it
+        let mut w = RdbWriter::new(11);
+        w.byte(0)
+            .string(b"big")
+            .byte(0x81)
+            .raw(&u64::MAX.to_be_bytes());
+        let err = db.import_rdb(Cursor::new(w.0)).await.err().unwrap();
+        assert!(err.to_string().contains("truncated"));
+    }
+}
@@ -0,0 +1,267 @@
+#[cfg(test)]
+#[allow(unused_imports)]
+mod tests {
+    use super::super::*;
+    use std::time::Duration;
+    use tokio::time::sleep;
+
+    async fn get_db(name: &str) -> StorageDB {
+        let cfg = Config {
+            path: format!("./db/stream/{}", name),
+            ..Default::default()
+        };
+
+        init_db(&cfg).await.unwrap()
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_stream_add_range() {
+        let db = get_db("add_range").await;
+        let s = db.stream("s_1", None).await.unwrap();
+        s.clear().await.unwrap();
+
+        let mut ids = Vec::new();
+        for i in 0..5 {
+            ids.push(s.add(&i, None).await.unwrap());
+        }
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(s.len().await.unwrap(), 5);
+        assert_eq!(s.last_id().await.unwrap(), Some(ids[4]));
+        assert!(db.stream_contains_key("s_1").await.unwrap());
+
+        let all = s
+            .range::<i32>(StreamId::MIN, StreamId::MAX, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            all.iter().map(|e| e.value).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(all[2].id, ids[2]);
+
+        let part = s.range::<i32>(ids[1], ids[3], Some(2)).await.unwrap();
+        assert_eq!(part.iter().map(|e| e.value).collect::<Vec<_>>(), vec![1, 2]);
+
+        let rev = s
+            .rev_range::<i32>(StreamId::MAX, ids[2], None)
+            .await
+            .unwrap();
+        assert_eq!(
+            rev.iter().map(|e| e.value).collect::<Vec<_>>(),
+            vec![4, 3, 2]
+        );
+
+        let id = ids[4].to_string();
+        assert_eq!(id.parse::<StreamId>().unwrap(), ids[4]);
+        assert!(s.add_with_id(ids[4], &5, None).await.is_err());
+        let next = StreamId::new(ids[4].ms + 1, 7);
+        assert_eq!(s.add_with_id(next, &5, None).await.unwrap(), next);
+
+        assert_eq!(s.delete(vec![ids[0], ids[0]]).await.unwrap(), 1);
+        assert_eq!(s.trim(3).await.unwrap(), 2);
+        let all = s
+            .range::<i32>(StreamId::MIN, StreamId::MAX, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            all.iter().map(|e| e.value).collect::<Vec<_>>(),
+            vec![3, 4, 5]
+        );
+
+        db.stream_remove("s_1").await.unwrap();
+        assert!(!db.stream_contains_key("s_1").await.unwrap());
+        assert_eq!(s.len().await.unwrap(), 0);
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_stream_read_blocking() {
+        let db = get_db("read_blocking").await;
+        let s = db.stream("s_block", None).await.unwrap();
+        s.clear().await.unwrap();
+        let last = s.add(&"a", None).await.unwrap();
+
+        let empty = s
+            .read::<String>(last, None, Some(Duration::from_millis(50)))
+            .await
+            .unwrap();
+        assert!(empty.is_empty());
+
+        let writer = s.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(100)).await;
+            writer.add(&"b", None).await.unwrap();
+        });
+        let items = s
+            .read::<String>(last, Some(10), Some(Duration::from_secs(5)))
+            .await
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].value, "b");
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_stream_groups() {
+        let db = get_db("groups").await;
+        let s = db.stream("s_group", None).await.unwrap();
+        s.clear().await.unwrap();
+        for i in 0..4 {
+            s.add(&i, None).await.unwrap();
+        }
+        assert!(s.group_create("g1", Some(StreamId::MIN)).await.unwrap());
+        assert!(!s.group_create("g1", None).await.unwrap());
+        assert!(s.group_create("g2", None).await.unwrap());
+        assert!(
+            s.read_group::<_, _, i32>("g3", "c1", None, None)
+                .await
+                .is_err()
+        );
+
+        let a = s
+            .read_group::<_, _, i32>("g1", "c1", Some(3), None)
+            .await
+            .unwrap();
+        assert_eq!(a.iter().map(|e| e.value).collect::<Vec<_>>(), vec![0, 1, 2]);
+        let b = s
+            .read_group::<_, _, i32>("g1", "c2", None, None)
+            .await
+            .unwrap();
+        assert_eq!(b.iter().map(|e| e.value).collect::<Vec<_>>(), vec![3]);
+        //g2 only sees entries added after it was created
+        assert!(
+            s.read_group::<_, _, i32>("g2", "c1", None, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        assert_eq!(s.ack("g1", vec![a[0].id, a[1].id]).await.unwrap(), 2);
+        let pending = s.pending("g1").await.unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].id, a[2].id);
+        assert_eq!(pending[0].consumer, b"c1".to_vec());
+
+        //not idle long enough
+        let claimed = s
+            .claim::<_, _, i32>("g1", "c2", 60_000, vec![a[2].id])
+            .await
+            .unwrap();
+        assert!(claimed.is_empty());
+        let claimed = s
+            .claim::<_, _, i32>("g1", "c2", 0, vec![a[2].id])
+            .await
+            .unwrap();
+        assert_eq!(claimed[0].value, 2);
+        let pending = s.pending("g1").await.unwrap();
+        assert_eq!(pending[0].consumer, b"c2".to_vec());
+        assert_eq!(pending[0].delivery_count, 2);
+
+        assert!(s.group_destroy("g1").await.unwrap());
+        assert!(s.pending("g1").await.is_err());
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_stream_maxlen_cleanup() {
+        let db = get_db("maxlen").await;
+        let s = db.stream("s_maxlen", None).await.unwrap();
+        s.clear().await.unwrap();
+        for i in 0..10 {
+            s.add(&i, Some(3)).await.unwrap();
+        }
+        assert_eq!(s.len().await.unwrap(), 10);
+
+        let StorageDB::Sled(sled_db) = &db;
+        assert!(sled_db.trim_streams(100).await.unwrap() >= 1);
+        let all = s
+            .range::<i32>(StreamId::MIN, StreamId::MAX, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            all.iter().map(|e| e.value).collect::<Vec<_>>(),
+            vec![7, 8, 9]
+        );
+
+        #[cfg(feature = "ttl")]
+        {
+            s.expire(10).await.unwrap();
+            assert!(s.ttl().await.unwrap().is_some());
+            sleep(Duration::from_millis(20)).await;
+            assert_eq!(s.len().await.unwrap(), 0);
+            sled_db.cleanup(100);
+            assert!(!db.stream_contains_key("s_maxlen").await.unwrap());
+        }
+    }
+
+    #[tokio::main]
+    #[test]
+    async fn test_stream_name_separators() {
+        let path = "./db/stream/name_separators";
+        let _ = std::fs::remove_dir_all(path);
+        let cfg = Config {
+            path: path.into(),
+            ..Default::default()
+        };
+        {
+            let db = init_db(&cfg).await.unwrap();
+            let s = db.stream("s", None).await.unwrap();
+            let other = db.stream("s@__entry@x", None).await.unwrap();
+            s.add(&1, None).await.unwrap();
+            other.add(&2, None).await.unwrap();
+            other.group_create("g", Some(StreamId::MIN)).await.unwrap();
+            let all = s
+                .range::<i32>(StreamId::MIN, StreamId::MAX, None)
+                .await
+                .unwrap();
+            assert_eq!(all.iter().map(|e| e.value).collect::<Vec<_>>(), vec![1]);
+            s.clear().await.unwrap();
+            assert_eq!(other.len().await.unwrap(), 1);
+            s.add(&3, None).await.unwrap();
+            db.close().await.unwrap();
+        }
+        sleep(Duration::from_millis(100)).await;
+
+        //rewrite the keys into the legacy `prefix name suffix` layout of version 3
+        {
+            let db = sled::open(path).unwrap();
+            let tree = db.open_tree("__stream_tree@").unwrap();
+            for item in tree.iter() {
+                let (key, value) = item.unwrap();
+                let Some(rest) = key.strip_prefix(b"__stream@".as_slice()) else {
+                    continue;
+                };
+                let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+                let legacy =
+                    [b"__stream@".as_slice(), &rest[4..4 + len], &rest[4 + len..]].concat();
+                tree.remove(&key).unwrap();
+                tree.insert(legacy, value).unwrap();
+            }
+            db.open_tree("__meta_tree@")
+                .unwrap()
+                .insert("format_version", 3u32.to_be_bytes().as_slice())
+                .unwrap();
+            db.flush().unwrap();
+        }
+        sleep(Duration::from_millis(100)).await;
+
+        let report = migrate_db(&cfg, false, |_| {}).await.unwrap();
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(report.steps[0].changed, 5);
+        assert_eq!(report.steps[1].changed, 2);
+        let db = init_db(&cfg).await.unwrap();
+        let s = db.stream("s", None).await.unwrap();
+        let other = db.stream("s@__entry@x", None).await.unwrap();
+        let all = s
+            .range::<i32>(StreamId::MIN, StreamId::MAX, None)
+            .await
+            .unwrap();
+        assert_eq!(all.iter().map(|e| e.value).collect::<Vec<_>>(), vec![3]);
+        let read = other
+            .read_group::<_, _, i32>("g", "c", None, None)
+            .await
+            .unwrap();
+        assert_eq!(read.iter().map(|e| e.value).collect::<Vec<_>>(), vec![2]);
+    }
+}
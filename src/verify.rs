@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use super::Key;
+use super::sled_storage::{KeyType, StreamId};
+
+/// A metadata inconsistency found by `verify`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Inconsistency {
+    /// A map's stored item count differs from its number of items
+    MapCount { name: Key, stored: i64, actual: i64 },
+    /// A list's stored `(start, end)` bounds do not match its content keys
+    ListBounds {
+        name: Key,
+        stored: (u64, u64),
+        actual: (u64, u64),
+        /// Whether the content indexes have gaps and must be renumbered
+        gaps: bool,
+    },
+    /// Map items whose map does not exist
+    OrphanMapItems { name: Key, count: usize },
+    /// List content whose list does not exist
+    OrphanListItems { name: Key, count: usize },
+    /// A stream's stored `(len, last_id)` does not match its entries
+    StreamMeta {
+        name: Key,
+        stored: (u64, StreamId),
+        actual: (u64, StreamId),
+    },
+    /// Stream entries and consumer groups whose stream does not exist
+    OrphanStreamItems { name: Key, count: usize },
+    /// An expiration entry for a key that does not exist
+    OrphanExpiry { key: Key, key_type: Option<KeyType> },
+    /// An expiration time without its entry in the expiration index
+    MissingExpiryIndex { key: Key, key_type: KeyType },
+}
+
+/// Result of `verify` or `repair`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifyReport {
+    /// Number of maps checked
+    pub maps: usize,
+    /// Number of lists checked
+    pub lists: usize,
+    /// Number of streams checked
+    pub streams: usize,
+    /// Number of expiration entries checked
+    pub expiries: usize,
+    /// Inconsistencies found
+    pub issues: Vec<Inconsistency>,
+    /// Whether the inconsistencies were repaired
+    pub repaired: bool,
+}
+
+impl VerifyReport {
+    /// Checks if no inconsistencies were found
+    #[inline]
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+}